@@ -0,0 +1,540 @@
+use crate::color::Colors;
+use bitcoin_script_analyzer::{
+    expr::Labels,
+    util::{
+        decode_hex_in_place_easy,
+        locktime::{self, LocktimeFormat},
+    },
+    ChainTip, ReportOptions, ScriptContext, ScriptRules, ScriptVersion, TxTimelocks,
+};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::{
+    fmt, fs,
+    io::{self, Read},
+    path::PathBuf,
+};
+
+/// Analyzes a Bitcoin script and prints the ways it can be spent.
+#[derive(Debug, Parser)]
+#[command(
+    subcommand_negates_reqs = true,
+    after_help = "Exit status of analyze: 0 if the script can be spent, 2 if it is unspendable, \
+                  3 if it can't be parsed, 4 if it contains a disabled opcode, 1 on other errors"
+)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Without a subcommand, the script is analyzed
+    #[command(flatten)]
+    pub analyze: AnalyzeArgs,
+
+    /// Rules to check, consensus rules only or also standardness (policy) rules
+    #[arg(long, value_enum, default_value_t = Rules::All, global = true)]
+    pub rules: Rules,
+
+    /// Network the script is used on, prints the P2SH or P2WSH address of the script and the
+    /// estimated date of block height timelocks
+    #[arg(long, value_enum, global = true)]
+    pub network: Option<Network>,
+
+    /// nLockTime of the spending transaction, reports whether it satisfies the locktime
+    /// requirement of every path
+    #[arg(long, value_name = "N", global = true)]
+    pub lock_time: Option<u32>,
+
+    /// nSequence of the input spending the script, reports whether it satisfies the sequence
+    /// requirement of every path. Defaults to 0xffffffff (final) if only --lock-time is given
+    #[arg(long, value_name = "N", value_parser = parse_u32, global = true)]
+    pub sequence: Option<u32>,
+
+    /// Version of the spending transaction, used with --lock-time and --sequence
+    #[arg(long, value_name = "N", default_value_t = 2, global = true)]
+    pub tx_version: u32,
+
+    /// Flavor of asm scripts, of the input and of the output of decode
+    #[arg(long, value_enum, default_value_t = AsmDialect::Analyzer, global = true)]
+    pub asm_dialect: AsmDialect,
+
+    /// How the times and durations of timelocks are written
+    #[arg(long, value_enum, default_value_t = TimeFormat::Human, global = true)]
+    pub time_format: TimeFormat,
+
+    /// Also show relative block height timelocks as an approximate duration, assuming a block
+    /// every this many seconds (600 on mainnet)
+    #[arg(long, value_name = "SECONDS", global = true)]
+    pub block_interval: Option<u32>,
+
+    /// Height and median time past of the chain tip, reports when every path can be spent
+    #[arg(long, value_name = "HEIGHT:MTP", value_parser = parse_height_time, global = true)]
+    pub tip: Option<(u32, u32)>,
+
+    /// Height of the block that confirmed the spent output and the median time past of the block
+    /// before it, used with --tip for relative timelocks. Without it, the output is assumed to
+    /// confirm in the next block
+    #[arg(long, value_name = "HEIGHT:MTP", value_parser = parse_height_time, global = true)]
+    pub confirmed_at: Option<(u32, u32)>,
+
+    /// Amount of threads to analyze paths on, 0 analyzes on the main thread
+    #[arg(long, default_value_t = 0, global = true)]
+    pub threads: usize,
+
+    /// Analyze with a known initial stack item, 0 is the top of the stack (the last witness
+    /// item). Can be given multiple times
+    #[arg(long, value_name = "POS=HEX", value_parser = parse_stack_item, global = true)]
+    pub stack_item: Vec<(u32, Vec<u8>)>,
+
+    /// List the sets of keys that can sign a k-of-n multisig, for multisigs with at most this
+    /// many sets
+    #[arg(long, value_name = "MAX", global = true)]
+    pub key_sets: Option<usize>,
+
+    /// Name a public key, hash or other constant in the text report, for example
+    /// `02ab..=Alice`. Can be given multiple times
+    #[arg(long = "label", value_name = "HEX=NAME", value_parser = parse_label, global = true)]
+    pub labels: Vec<(Vec<u8>, String)>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Text, global = true)]
+    pub format: Format,
+
+    /// Also print statistics of the analysis
+    #[arg(short, long, global = true, conflicts_with = "quiet")]
+    pub verbose: bool,
+
+    /// Only print the requirements of the spending paths, in infix notation
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Also suggest shorter ways to write parts of the script that keep its spending paths
+    #[arg(long, global = true)]
+    pub suggest: bool,
+
+    /// Also report parts of the script that are never executed
+    #[arg(long, global = true)]
+    pub dead_code: bool,
+
+    /// When to color the text output
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto, global = true)]
+    pub color: ColorChoice,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Analyze a script (the default)
+    Analyze(AnalyzeArgs),
+    /// Decode a hex script to asm
+    Decode(ScriptInput),
+    /// Encode an asm script to hex
+    Assemble(ScriptInput),
+    /// Start an interactive session to analyze scripts and step through their execution
+    Repl(ReplArgs),
+    /// Compare the spending paths of two scripts, fails if they differ
+    Diff(DiffArgs),
+    /// Fetch a script from a Bitcoin Core node and analyze it, the script version is detected
+    Import(ImportArgs),
+    /// Fetch a script from an esplora API, like mempool.space, and analyze it, the script version
+    /// is detected
+    Fetch(FetchArgs),
+}
+
+/// Where to read a script from.
+#[derive(Debug, clap::Args)]
+#[group(required = true, multiple = false)]
+pub struct ScriptInput {
+    /// The script, or - to read it from stdin
+    pub script: Option<String>,
+
+    /// Read the script from a file
+    #[arg(long, value_name = "PATH")]
+    pub file: Option<PathBuf>,
+}
+
+impl ScriptInput {
+    /// Reads the script from the argument, stdin or the file.
+    pub fn read(&self) -> io::Result<String> {
+        match (&self.script, &self.file) {
+            (Some(script), _) if script == "-" => {
+                let mut input = String::new();
+                io::stdin().read_to_string(&mut input)?;
+                Ok(input)
+            }
+            (Some(script), _) => Ok(script.clone()),
+            (None, Some(path)) => fs::read_to_string(path),
+            (None, None) => unreachable!("input is a required group"),
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct AnalyzeArgs {
+    #[command(flatten)]
+    pub input: ScriptInput,
+
+    /// Analyze the script in a file again every time the file changes
+    #[arg(long, value_name = "PATH", group = "ScriptInput")]
+    pub watch: Option<PathBuf>,
+
+    /// Analyze every line of the input as a separate script and print a summary of each
+    #[arg(long)]
+    pub batch: bool,
+
+    /// Encoding of the script
+    #[arg(long, value_enum, default_value_t = InputType::Hex)]
+    pub input_type: InputType,
+
+    /// Script version, determines which opcodes and limits apply
+    #[arg(long, value_enum, default_value_t = Version::SegwitV0)]
+    pub version: Version,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ReplArgs {
+    /// Initial script version, can be changed in the session with :version
+    #[arg(long, value_enum, default_value_t = Version::SegwitV0)]
+    pub version: Version,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct DiffArgs {
+    /// The original script
+    pub old: String,
+
+    /// The script to compare it with
+    pub new: String,
+
+    /// Encoding of the scripts
+    #[arg(long, value_enum, default_value_t = InputType::Auto)]
+    pub input_type: InputType,
+
+    /// Script version, determines which opcodes and limits apply
+    #[arg(long, value_enum, default_value_t = Version::SegwitV0)]
+    pub version: Version,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ImportArgs {
+    /// Output as txid:vout, or an address. The script of P2SH, P2WSH and P2TR outputs is taken
+    /// from the input spending it, if it is in the mempool, or from the wallet of the node
+    pub target: String,
+
+    /// URL of the JSON-RPC server of the node
+    #[arg(long, default_value = "http://127.0.0.1:8332")]
+    pub rpc_url: String,
+
+    /// Cookie file to authenticate with, usually .cookie in the data directory
+    #[arg(long, value_name = "PATH")]
+    pub rpc_cookie: Option<PathBuf>,
+
+    /// Get the chain tip from the node and report when every path can be spent, like --tip
+    #[arg(long, conflicts_with = "tip")]
+    pub fetch_tip: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct FetchArgs {
+    /// Output as txid:vout, or an address of which the most recent spend is used. The script of
+    /// P2SH, P2WSH and P2TR outputs is taken from the input spending it
+    pub target: String,
+
+    /// Base URL of the esplora instance
+    #[arg(long, default_value = "https://mempool.space")]
+    pub api_url: String,
+
+    /// Get the chain tip from the API and report when every path can be spent, like --tip
+    #[arg(long, conflicts_with = "tip")]
+    pub fetch_tip: bool,
+}
+
+impl Args {
+    pub fn script_context(&self, version: ScriptVersion) -> ScriptContext {
+        let ctx = ScriptContext::new(version, self.rules.into());
+        match self.network {
+            Some(network) => ctx.with_network(network.into()),
+            None => ctx,
+        }
+    }
+
+    pub fn labels(&self) -> Labels {
+        let mut labels = Labels::new();
+        for (bytes, label) in &self.labels {
+            labels.insert(bytes, label.as_str());
+        }
+        labels
+    }
+
+    pub fn report_options(&self) -> ReportOptions<'static> {
+        let options = if self.verbose {
+            ReportOptions::verbose()
+        } else if self.quiet {
+            ReportOptions::quiet()
+        } else {
+            ReportOptions::default()
+        };
+        ReportOptions {
+            network: self.network.map(Into::into),
+            locktime_format: LocktimeFormat {
+                time: self.time_format.into(),
+                seconds_per_block: self.block_interval,
+            },
+            tx_timelocks: self.tx_timelocks(),
+            chain_tip: self.chain_tip(),
+            ..options
+        }
+    }
+
+    /// The timelock fields of the spending transaction, if --lock-time or --sequence is given.
+    pub fn tx_timelocks(&self) -> Option<TxTimelocks> {
+        if self.lock_time.is_none() && self.sequence.is_none() {
+            return None;
+        }
+        let default = TxTimelocks::default();
+        Some(TxTimelocks {
+            version: self.tx_version,
+            lock_time: self.lock_time.unwrap_or(default.lock_time),
+            sequence: self.sequence.unwrap_or(default.sequence),
+        })
+    }
+
+    /// The chain tip of --tip and --confirmed-at.
+    pub fn chain_tip(&self) -> Option<ChainTip> {
+        self.tip.map(|(height, median_time_past)| ChainTip {
+            height,
+            median_time_past,
+            confirmed_at: self.confirmed_at,
+        })
+    }
+
+    pub fn stdout_colors(&self) -> Colors {
+        Colors::new(self.color, io::stdout())
+    }
+
+    pub fn stderr_colors(&self) -> Colors {
+        Colors::new(self.color, io::stderr())
+    }
+}
+
+/// A decimal or 0x prefixed hexadecimal number.
+fn parse_u32(s: &str) -> Result<u32, String> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|err| err.to_string())
+}
+
+fn parse_height_time(s: &str) -> Result<(u32, u32), String> {
+    let (height, time) = s
+        .split_once(':')
+        .ok_or_else(|| "expected HEIGHT:MTP".to_owned())?;
+    let height = height
+        .parse()
+        .map_err(|err| format!("invalid height {height:?}: {err}"))?;
+    let time = time
+        .parse()
+        .map_err(|err| format!("invalid median time past {time:?}: {err}"))?;
+    Ok((height, time))
+}
+
+fn parse_stack_item(s: &str) -> Result<(u32, Vec<u8>), String> {
+    let (pos, hex) = s
+        .split_once('=')
+        .ok_or_else(|| "expected POS=HEX".to_owned())?;
+    let pos = pos
+        .parse()
+        .map_err(|err| format!("invalid position {pos:?}: {err}"))?;
+    let bytes = decode_hex_in_place_easy(hex.to_owned()).map_err(|err| err.to_string())?;
+    Ok((pos, bytes))
+}
+
+fn parse_label(s: &str) -> Result<(Vec<u8>, String), String> {
+    let (hex, label) = s
+        .split_once('=')
+        .ok_or_else(|| "expected HEX=NAME".to_owned())?;
+    let bytes = decode_hex_in_place_easy(hex.to_owned()).map_err(|err| err.to_string())?;
+    Ok((bytes, label.to_owned()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum InputType {
+    Hex,
+    Asm,
+    /// Hex if the input only contains hex digits and whitespace, asm otherwise
+    Auto,
+}
+
+impl InputType {
+    /// Resolves [`InputType::Auto`] for `input`.
+    pub fn detect(self, input: &str) -> Self {
+        match self {
+            Self::Auto
+                if input
+                    .chars()
+                    .all(|c| c.is_ascii_hexdigit() || c.is_ascii_whitespace()) =>
+            {
+                Self::Hex
+            }
+            Self::Auto => Self::Asm,
+            input_type => input_type,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Version {
+    /// Output script or P2SH redeem script
+    Legacy,
+    /// P2WSH witness script
+    SegwitV0,
+    /// P2TR tapscript
+    Tapscript,
+}
+
+impl From<Version> for ScriptVersion {
+    fn from(version: Version) -> Self {
+        match version {
+            Version::Legacy => Self::Legacy,
+            Version::SegwitV0 => Self::SegwitV0,
+            Version::Tapscript => Self::SegwitV1,
+        }
+    }
+}
+
+impl From<ScriptVersion> for Version {
+    fn from(version: ScriptVersion) -> Self {
+        match version {
+            ScriptVersion::Legacy => Self::Legacy,
+            ScriptVersion::SegwitV0 => Self::SegwitV0,
+            ScriptVersion::SegwitV1 => Self::Tapscript,
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(
+            self.to_possible_value()
+                .expect("no skipped variants")
+                .get_name(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Rules {
+    /// Consensus and policy rules
+    All,
+    /// Consensus rules only
+    Consensus,
+}
+
+impl From<Rules> for ScriptRules {
+    fn from(rules: Rules) -> Self {
+        match rules {
+            Rules::All => Self::All,
+            Rules::Consensus => Self::ConsensusOnly,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Signet,
+    Regtest,
+}
+
+impl From<Network> for bitcoin_script_analyzer::Network {
+    fn from(network: Network) -> Self {
+        match network {
+            Network::Mainnet => Self::Mainnet,
+            Network::Testnet => Self::Testnet,
+            Network::Signet => Self::Signet,
+            Network::Regtest => Self::Regtest,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AsmDialect {
+    /// Data pushes like <0102>, numbers in decimal
+    Analyzer,
+    /// Like decodescript of Bitcoin Core: data pushes in bare hex, small pushes as numbers
+    Core,
+    /// Like rust-bitcoin: data pushes after their opcode, like OP_PUSHBYTES_2 0102
+    RustBitcoin,
+}
+
+impl From<AsmDialect> for bitcoin_script_analyzer::AsmDialect {
+    fn from(dialect: AsmDialect) -> Self {
+        match dialect {
+            AsmDialect::Analyzer => Self::Analyzer,
+            AsmDialect::Core => Self::Core,
+            AsmDialect::RustBitcoin => Self::RustBitcoin,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum TimeFormat {
+    /// Dates with the unix timestamp, durations like 1d 2h 3m 4s
+    Human,
+    /// ISO 8601 dates and durations
+    Iso8601,
+    /// Unix timestamps and durations in seconds
+    Seconds,
+}
+
+impl From<TimeFormat> for locktime::TimeFormat {
+    fn from(format: TimeFormat) -> Self {
+        match format {
+            TimeFormat::Human => Self::Human,
+            TimeFormat::Iso8601 => Self::Iso8601,
+            TimeFormat::Seconds => Self::Seconds,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Human readable report
+    Text,
+    /// One JSON object with the script and all spending paths
+    Json,
+    /// One JSON object per spending path, one per line
+    Ndjson,
+    /// Graphviz graph of the branches taken by the spending paths
+    Dot,
+    /// Standalone HTML report
+    Html,
+    /// Markdown report with a table of the spending paths
+    Markdown,
+    /// SMT-LIB 2 script of the spending conditions of every path, for a solver like Z3 or cvc5
+    Smtlib,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ColorChoice {
+    /// Color if the output is a terminal and NO_COLOR is not set
+    Auto,
+    Always,
+    Never,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InputType;
+
+    #[test]
+    fn test_detect_input_type() {
+        assert_eq!(InputType::Auto.detect("51b2"), InputType::Hex);
+        assert_eq!(InputType::Auto.detect("51 b2\n"), InputType::Hex);
+        assert_eq!(InputType::Auto.detect(""), InputType::Hex);
+        assert_eq!(InputType::Auto.detect("1 OP_CSV"), InputType::Asm);
+        // also valid asm, but hex wins
+        assert_eq!(InputType::Auto.detect("1"), InputType::Hex);
+        assert_eq!(InputType::Asm.detect("51"), InputType::Asm);
+        assert_eq!(InputType::Hex.detect("OP_1"), InputType::Hex);
+    }
+}
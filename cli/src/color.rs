@@ -0,0 +1,112 @@
+//! ANSI colors for the text output.
+
+use crate::args::ColorChoice;
+use bitcoin_script_analyzer::{Opcode, OpcodeType};
+use std::{env, fmt::Write, io::IsTerminal};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Colors {
+    enabled: bool,
+}
+
+impl Colors {
+    /// With [`ColorChoice::Auto`], colors are enabled if `stream` is a terminal and `NO_COLOR`
+    /// is not set.
+    pub fn new(choice: ColorChoice, stream: impl IsTerminal) -> Self {
+        let enabled = match choice {
+            ColorChoice::Auto => {
+                stream.is_terminal() && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        };
+        Self { enabled }
+    }
+
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text.to_owned()
+        }
+    }
+
+    pub fn bold(&self, text: &str) -> String {
+        self.paint("1", text)
+    }
+
+    pub fn red(&self, text: &str) -> String {
+        self.paint("31", text)
+    }
+
+    pub fn yellow(&self, text: &str) -> String {
+        self.paint("33", text)
+    }
+
+    /// Highlights a script as displayed by `Script`'s `Display` impl, one element per line.
+    pub fn script(&self, script: &str) -> String {
+        let mut out = String::new();
+        for (i, line) in script.lines().enumerate() {
+            if i != 0 {
+                out.push('\n');
+            }
+            let elem = line.trim_start();
+            out.push_str(&line[..line.len() - elem.len()]);
+
+            let code = if elem.starts_with('<') {
+                Some("2")
+            } else {
+                Opcode::from_name(elem).map(|opcode| opcode_color(opcode.opcode_type()))
+            };
+            match code {
+                Some(code) => out.push_str(&self.paint(code, elem)),
+                None => out.push_str(elem),
+            }
+        }
+        out
+    }
+
+    /// Highlights an analysis report: section and spending path headers are bold, warnings
+    /// yellow.
+    pub fn analysis(&self, analysis: &str) -> String {
+        let mut out = String::new();
+        let mut in_paths = false;
+        let mut prev_empty = false;
+        for line in analysis.lines() {
+            let is_section = line.starts_with("Summary:")
+                || line.starts_with("Template:")
+                || line.starts_with("Spending paths:")
+                || line.starts_with("Failed paths:");
+            if is_section {
+                in_paths = line.ends_with("paths:");
+            }
+
+            if line.starts_with("WARNING:") {
+                out.push_str(&self.yellow(line));
+            } else if is_section || (in_paths && prev_empty && !line.is_empty()) {
+                out.push_str(&self.bold(line));
+            } else {
+                out.push_str(line);
+            }
+            writeln!(out).unwrap();
+            prev_empty = line.is_empty();
+        }
+        out.pop();
+        out
+    }
+}
+
+/// SGR code for an opcode category.
+fn opcode_color(opcode_type: OpcodeType) -> &'static str {
+    match opcode_type {
+        OpcodeType::Data => "2",
+        OpcodeType::Number | OpcodeType::Constant => "33",
+        OpcodeType::Flow => "35",
+        OpcodeType::Stack => "34",
+        OpcodeType::Splice | OpcodeType::Bitwise => "36",
+        OpcodeType::Arithmetic => "32",
+        OpcodeType::Crypto => "1;32",
+        OpcodeType::Locktime => "1;35",
+        OpcodeType::Disabled | OpcodeType::Invalid => "1;31",
+    }
+}
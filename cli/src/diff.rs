@@ -0,0 +1,39 @@
+//! JSON output of the `diff` subcommand, the comparison itself is done by the library.
+
+use bitcoin_script_analyzer::{expr::Expr, ItemRange, ScriptComparison};
+use serde_json::{json, Value};
+
+/// Ranges first, like in the text report.
+fn requirements(ranges: &[ItemRange], conditions: &[Expr]) -> Vec<String> {
+    ranges
+        .iter()
+        .map(|r| r.to_string())
+        .chain(conditions.iter().map(|c| c.to_string()))
+        .collect()
+}
+
+pub fn to_json(cmp: &ScriptComparison) -> Value {
+    json!({
+        "equivalent": cmp.is_equivalent(),
+        "unchanged": cmp
+            .unchanged
+            .iter()
+            .map(|&(old, new)| json!({ "old": old, "new": new }))
+            .collect::<Vec<_>>(),
+        "changed": cmp
+            .changed
+            .iter()
+            .map(|c| json!({
+                "old": c.old,
+                "new": c.new,
+                "removed_conditions": requirements(&c.removed_ranges, &c.removed_conditions),
+                "added_conditions": requirements(&c.added_ranges, &c.added_conditions),
+                "locktime": c.locktime.as_ref().map(|(a, b)| json!({ "old": a, "new": b })),
+                "sequence": c.sequence.as_ref().map(|(a, b)| json!({ "old": a, "new": b })),
+                "stack_size": c.stack_size.map(|(a, b)| json!({ "old": a, "new": b })),
+            }))
+            .collect::<Vec<_>>(),
+        "removed": cmp.removed,
+        "added": cmp.added,
+    })
+}
@@ -0,0 +1,88 @@
+//! Imports scripts from an esplora (mempool.space) API, used by the `fetch` subcommand.
+
+use crate::import::{chain_tip, hex, revealed_script, ImportedScript, SpendingInput};
+use bitcoin_script_analyzer::ChainTip;
+use serde_json::Value;
+
+pub struct Esplora {
+    api_url: String,
+}
+
+impl Esplora {
+    pub fn new(api_url: &str) -> Self {
+        Self {
+            api_url: api_url.trim_end_matches('/').to_owned(),
+        }
+    }
+
+    fn get(&self, path: &str) -> Result<Value, String> {
+        let url = format!("{}/api/{path}", self.api_url);
+        ureq::get(&url)
+            .call()
+            .map_err(|err| format!("request to {url} failed: {err}"))?
+            .into_json()
+            .map_err(|err| format!("invalid response from {url}: {err}"))
+    }
+
+    /// Height and median time past of the newest block.
+    pub fn chain_tip(&self) -> Result<ChainTip, String> {
+        let blocks = self.get("blocks")?;
+        chain_tip(&blocks[0]["height"], &blocks[0]["mediantime"])
+    }
+
+    /// Finds the script of the output `txid:vout`, or the script of an address, of which the
+    /// most recent spend is used.
+    pub fn fetch(&self, target: &str) -> Result<ImportedScript, String> {
+        match target.split_once(':') {
+            Some((txid, vout)) => {
+                let vout = vout
+                    .parse()
+                    .map_err(|_| format!("invalid output index {vout}"))?;
+                self.fetch_output(txid, vout)
+            }
+            None => self.fetch_address(target),
+        }
+    }
+
+    fn fetch_output(&self, txid: &str, vout: u32) -> Result<ImportedScript, String> {
+        let outspend = self.get(&format!("tx/{txid}/outspend/{vout}"))?;
+        if let (Some(spending_txid), Some(vin)) =
+            (outspend["txid"].as_str(), outspend["vin"].as_u64())
+        {
+            let tx = self.get(&format!("tx/{spending_txid}"))?;
+            return input_script(&tx["vin"][vin as usize]);
+        }
+
+        let tx = self.get(&format!("tx/{txid}"))?;
+        let output = &tx["vout"][vout as usize];
+        if output.is_null() {
+            return Err(format!("transaction has no output {vout}"));
+        }
+        revealed_script(&hex(&output["scriptpubkey"])?, None)
+    }
+
+    fn fetch_address(&self, address: &str) -> Result<ImportedScript, String> {
+        let txs = self.get(&format!("address/{address}/txs"))?;
+        let input = txs
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|tx| tx["vin"].as_array())
+            .flatten()
+            .find(|input| input["prevout"]["scriptpubkey_address"] == address)
+            .ok_or("no transaction spending from this address found")?;
+        input_script(input)
+    }
+}
+
+/// The script executed when spending the input `vin` (esplora format).
+fn input_script(vin: &Value) -> Result<ImportedScript, String> {
+    let input = SpendingInput {
+        script_sig: hex(&vin["scriptsig"])?,
+        witness: match vin["witness"].as_array() {
+            Some(items) => items.iter().map(hex).collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        },
+    };
+    revealed_script(&hex(&vin["prevout"]["scriptpubkey"])?, Some(&input))
+}
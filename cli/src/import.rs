@@ -0,0 +1,121 @@
+//! Finds the script that is executed when spending an output.
+
+use bitcoin_script_analyzer::{
+    util::decode_hex_in_place_easy, ChainTip, OutputKind, RevealedScriptError, ScriptVersion,
+};
+use serde_json::Value;
+
+pub struct ImportedScript {
+    pub bytes: Vec<u8>,
+    pub version: ScriptVersion,
+}
+
+/// The input that spends an output.
+pub struct SpendingInput {
+    pub script_sig: Vec<u8>,
+    pub witness: Vec<Vec<u8>>,
+}
+
+/// Returns the script that is executed when spending an output with `script_pubkey`. For P2SH,
+/// P2WSH and P2TR outputs this script is only revealed by the input that spends it.
+pub fn revealed_script(
+    script_pubkey: &[u8],
+    input: Option<&SpendingInput>,
+) -> Result<ImportedScript, String> {
+    let (script_sig, witness) = match input {
+        Some(input) => (
+            &input.script_sig[..],
+            input.witness.iter().map(Vec::as_slice).collect(),
+        ),
+        None => (&[][..], Vec::new()),
+    };
+
+    match bitcoin_script_analyzer::revealed_script(script_pubkey, script_sig, &witness) {
+        Ok(revealed) => Ok(ImportedScript {
+            bytes: revealed.script.to_vec(),
+            version: revealed.version,
+        }),
+        Err(RevealedScriptError::NoScript(OutputKind::P2wpkh { .. })) => {
+            Err("P2WPKH outputs have no script".to_owned())
+        }
+        Err(RevealedScriptError::NoScript(OutputKind::P2trKeyPath)) => {
+            Err("spent with the key path, no script is revealed".to_owned())
+        }
+        Err(RevealedScriptError::MissingScript) if input.is_none() => {
+            Err("the script of this output is only revealed when it is spent".to_owned())
+        }
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// The chain tip with a `height` and `median_time_past` from an API response.
+pub fn chain_tip(height: &Value, median_time_past: &Value) -> Result<ChainTip, String> {
+    let number = |value: &Value| {
+        value
+            .as_u64()
+            .and_then(|n| u32::try_from(n).ok())
+            .ok_or("invalid chain tip in response")
+    };
+    Ok(ChainTip {
+        height: number(height)?,
+        median_time_past: number(median_time_past)?,
+        confirmed_at: None,
+    })
+}
+
+/// Decodes a hex string of a JSON response.
+pub fn hex(value: &Value) -> Result<Vec<u8>, String> {
+    let hex = value.as_str().ok_or("missing hex string in response")?;
+    decode_hex_in_place_easy(hex.to_owned()).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{revealed_script, SpendingInput};
+    use bitcoin_script_analyzer::ScriptVersion;
+
+    fn spend(script_pubkey: &[u8], witness: &[&[u8]]) -> Result<(Vec<u8>, ScriptVersion), String> {
+        let input = SpendingInput {
+            script_sig: Vec::new(),
+            witness: witness.iter().map(|item| item.to_vec()).collect(),
+        };
+        revealed_script(script_pubkey, Some(&input)).map(|script| (script.bytes, script.version))
+    }
+
+    #[test]
+    fn test_p2tr() {
+        let p2tr = [[0x51, 0x20].as_slice(), &[0x11; 32]].concat();
+        let control_block = [[0xc0].as_slice(), &[0x22; 32]].concat();
+        let annex = [0x50, 0x01];
+        let sig = [0x33; 64];
+        let leaf_script = [0x51];
+
+        assert_eq!(
+            spend(&p2tr, &[&leaf_script, &control_block]),
+            Ok((leaf_script.to_vec(), ScriptVersion::SegwitV1))
+        );
+        assert_eq!(
+            spend(&p2tr, &[&sig, &leaf_script, &control_block, &annex]),
+            Ok((leaf_script.to_vec(), ScriptVersion::SegwitV1))
+        );
+
+        let key_path = Err("spent with the key path, no script is revealed".to_owned());
+        assert_eq!(spend(&p2tr, &[&sig]), key_path);
+        // one item besides the annex is enough for it to be an annex
+        assert_eq!(spend(&p2tr, &[&sig, &annex]), key_path);
+
+        assert_eq!(
+            revealed_script(&p2tr, None).err(),
+            Some("the script of this output is only revealed when it is spent".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_p2wpkh() {
+        let p2wpkh = [[0x00, 0x14].as_slice(), &[0x11; 20]].concat();
+        assert_eq!(
+            spend(&p2wpkh, &[&[0x33; 72], &[0x02; 33]]),
+            Err("P2WPKH outputs have no script".to_owned())
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use bitcoin_script_analyzer::{
+    expr::Expr, Analysis, DeadCode, LocktimeRequirement, MultisigKeySets, SighashRequirement,
+    SpendingPath, Suggestion,
+};
+use serde_json::{json, Value};
+
+/// The whole analysis as one object.
+pub fn analysis(analysis: &Analysis) -> Value {
+    json!({
+        "template": analysis.template.map(|t| t.to_string()),
+        "paths": analysis.paths.iter().map(path).collect::<Vec<_>>(),
+    })
+}
+
+pub fn path(path: &SpendingPath) -> Value {
+    json!({
+        "stack_size": path.stack_size,
+        "exact_stack_size": path.exact_stack_size,
+        "stack_size_limit": path.stack_size_limit,
+        "conditions": path.spending_conditions.iter().map(|c| c.to_string()).collect::<Vec<_>>(),
+        "ranges": path.ranges.iter().map(|r| r.to_string()).collect::<Vec<_>>(),
+        "items": path
+            .items
+            .iter()
+            .map(|i| json!({
+                "role": i.role.map(|r| r.to_string()),
+                "min_size": i.min_size,
+                "max_size": i.max_size,
+                "low_s": i.low_s,
+                "multisig": i.multisig.map(|(index, count)| json!({ "index": index, "count": count })),
+            }))
+            .collect::<Vec<_>>(),
+        "locktime": locktime(&path.locktime_req),
+        "sequence": locktime(&path.sequence_req),
+        "min_tx_version": path.min_tx_version(),
+        "vsize": path.size.vsize,
+        "script_sig_size": path.size.script_sig_size,
+        "witness_size": path.size.witness_size,
+        "sigops": path.sigops.count(),
+        "branches": path
+            .branches
+            .iter()
+            .map(|b| json!({
+                "opcode_index": b.origin.index,
+                "condition": b.condition,
+                "pick_index": b.pick_index,
+            }))
+            .collect::<Vec<_>>(),
+        "key_sets": path.key_sets.iter().map(key_sets).collect::<Vec<_>>(),
+        "sighash": path.sighash.iter().map(sighash).collect::<Vec<_>>(),
+        "script_codes": path
+            .script_codes
+            .iter()
+            .map(|c| json!({
+                "opcode_index": c.checksig.index,
+                "start": c.start(),
+                "code_separator_index": c.code_separator.map(|sep| sep.index),
+            }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+fn sighash(req: &SighashRequirement) -> Value {
+    json!({
+        "signature": req.sig.to_string(),
+        "public_key": req.key.as_ref().map(|k| k.to_string()),
+        "types": req.types.to_string(),
+        "warning": req.warning(),
+    })
+}
+
+fn key_sets(sets: &MultisigKeySets) -> Value {
+    let strings = |exprs: &[Expr]| exprs.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+    json!({
+        "opcode_index": sets.origin.map(|o| o.index),
+        "signatures": strings(&sets.sigs),
+        "key_sets": sets.key_sets.iter().map(|keys| strings(keys)).collect::<Vec<_>>(),
+    })
+}
+
+/// `null` if there is no requirement.
+fn locktime(req: &LocktimeRequirement) -> Value {
+    if !req.is_required() {
+        return Value::Null;
+    }
+    json!({
+        "min": req.req,
+        "stack_items": req.exprs.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+        "stack_item_ranges": req
+            .expr_ranges
+            .iter()
+            .map(|r| r.map(|r| json!({ "min": r.min, "max": r.max })))
+            .collect::<Vec<_>>(),
+    })
+}
+
+pub fn dead_code(dead: &DeadCode) -> Value {
+    json!({
+        "start": dead.bytes.start,
+        "end": dead.bytes.end,
+        "reason": dead.reason.to_string(),
+    })
+}
+
+pub fn suggestion(suggestion: &Suggestion) -> Value {
+    json!({
+        "offset": suggestion.offset,
+        "original": suggestion.original,
+        "replacement": suggestion.replacement.iter().map(|op| op.to_string()).collect::<Vec<_>>(),
+        "savings": suggestion.savings,
+    })
+}
+
+pub fn error(error: impl ToString) -> Value {
+    json!({ "error": error.to_string() })
+}
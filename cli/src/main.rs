@@ -1,29 +1,630 @@
+mod args;
+mod color;
+mod diff;
+mod esplora;
+mod import;
+mod json;
+mod repl;
+mod rpc;
+
+use crate::{
+    args::{AnalyzeArgs, Args, Command, DiffArgs, Format, InputType, ScriptInput},
+    esplora::Esplora,
+    import::ImportedScript,
+    rpc::Rpc,
+};
 use bitcoin_script_analyzer::{
-    analyze_script, util::decode_hex_in_place, OwnedScript, ScriptContext, ScriptRules,
-    ScriptVersion,
+    expr::Expr,
+    find_dead_code, suggest_optimizations,
+    util::{decode_hex_in_place_ignore_whitespace, encode_hex_easy},
+    Analysis, AnalysisOptions, AnalyzeError, AsmDialect, ChainTip, OwnedScript, ParseScriptError,
+    ParseWarning, ReportOptions, ScriptAnalyzer, ScriptComparison, ScriptContext, VerifyFlags,
+};
+use clap::Parser;
+use std::{
+    fmt, fs,
+    io::{self, IsTerminal},
+    path::Path,
+    process::ExitCode,
+    thread,
+    time::Duration,
 };
 
-fn unwrap_both<T>(res: Result<T, T>) -> T {
+/// Exit code if the script can't be spent.
+const EXIT_UNSPENDABLE: u8 = 2;
+/// Exit code if the input is not a valid script.
+const EXIT_PARSE_ERROR: u8 = 3;
+/// Exit code if the script contains a disabled opcode.
+const EXIT_DISABLED_OPCODE: u8 = 4;
+
+/// How often `--watch` checks if the file was modified.
+const WATCH_INTERVAL_MS: u64 = 500;
+
+pub fn main() -> ExitCode {
+    let args = Args::parse();
+
+    match &args.command {
+        None => analyze(&args, &args.analyze),
+        Some(Command::Analyze(analyze_args)) => analyze(&args, analyze_args),
+        Some(Command::Decode(input)) => convert(&args, input, InputType::Hex),
+        Some(Command::Assemble(input)) => convert(&args, input, InputType::Asm),
+        Some(Command::Repl(repl_args)) => repl::run(&args, repl_args),
+        Some(Command::Diff(diff_args)) => diff(&args, diff_args),
+        Some(Command::Import(import)) => analyze_imported(
+            &args,
+            Rpc::new(&import.rpc_url, import.rpc_cookie.as_deref()).and_then(|rpc| {
+                let tip = import.fetch_tip.then(|| rpc.chain_tip()).transpose()?;
+                Ok((rpc.import(&import.target)?, tip))
+            }),
+        ),
+        Some(Command::Fetch(fetch)) => {
+            let esplora = Esplora::new(&fetch.api_url);
+            analyze_imported(
+                &args,
+                fetch
+                    .fetch_tip
+                    .then(|| esplora.chain_tip())
+                    .transpose()
+                    .and_then(|tip| Ok((esplora.fetch(&fetch.target)?, tip))),
+            )
+        }
+    }
+}
+
+fn print_error(args: &Args, err: impl fmt::Display) {
+    match args.format {
+        Format::Text | Format::Dot | Format::Html | Format::Markdown | Format::Smtlib => {
+            eprintln!("{}", args.stderr_colors().red(&format!("error: {err}")))
+        }
+        Format::Json | Format::Ndjson => println!("{}", json::error(err)),
+    }
+}
+
+fn read_input(args: &Args, input: &ScriptInput) -> Result<String, ExitCode> {
+    input.read().map_err(|err| {
+        print_error(args, format_args!("can't read script: {err}"));
+        ExitCode::FAILURE
+    })
+}
+
+fn analyze(args: &Args, analyze_args: &AnalyzeArgs) -> ExitCode {
+    if let Some(path) = &analyze_args.watch {
+        return watch(args, analyze_args, path);
+    }
+
+    match read_input(args, &analyze_args.input) {
+        Ok(input) => analyze_input(args, analyze_args, &input),
+        Err(code) => code,
+    }
+}
+
+/// Analyzes the script in the file at `path` every time the file is modified, until interrupted.
+fn watch(args: &Args, analyze_args: &AnalyzeArgs, path: &Path) -> ExitCode {
+    let mut last_modified = None;
+    let mut failed = false;
+    loop {
+        match fs::metadata(path).and_then(|m| m.modified()) {
+            Ok(modified) if last_modified != Some(modified) => {
+                last_modified = Some(modified);
+                failed = false;
+                if io::stdout().is_terminal() {
+                    // clear the screen
+                    print!("\x1b[2J\x1b[H");
+                }
+                match fs::read_to_string(path) {
+                    Ok(input) => {
+                        analyze_input(args, analyze_args, &input);
+                    }
+                    Err(err) => {
+                        print_error(args, format_args!("can't read {}: {err}", path.display()))
+                    }
+                }
+            }
+            Ok(_) => {}
+            // report an error only once, until the file can be read again
+            Err(err) if !failed => {
+                last_modified = None;
+                failed = true;
+                print_error(args, format_args!("can't read {}: {err}", path.display()));
+            }
+            Err(_) => {}
+        }
+
+        thread::sleep(Duration::from_millis(WATCH_INTERVAL_MS));
+    }
+}
+
+fn analyze_input(args: &Args, analyze_args: &AnalyzeArgs, input: &str) -> ExitCode {
+    if analyze_args.batch {
+        run_batch(args, analyze_args, input)
+    } else {
+        run(
+            args,
+            input,
+            analyze_args.input_type.detect(input),
+            args.script_context(analyze_args.version.into()),
+            args.chain_tip(),
+        )
+    }
+}
+
+/// `tip` is the fetched chain tip, if any.
+fn analyze_imported(
+    args: &Args,
+    imported: Result<(ImportedScript, Option<ChainTip>), String>,
+) -> ExitCode {
+    let (imported, tip) = match imported {
+        Ok(imported) => imported,
+        Err(err) => {
+            print_error(args, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    run(
+        args,
+        &encode_hex_easy(&imported.bytes),
+        InputType::Hex,
+        args.script_context(imported.version),
+        tip.or(args.chain_tip()),
+    )
+}
+
+/// Decodes (hex to asm) or assembles (asm to hex) a script without analyzing it.
+fn convert(args: &Args, input: &ScriptInput, input_type: InputType) -> ExitCode {
+    let input = match read_input(args, input) {
+        Ok(input) => input,
+        Err(code) => return code,
+    };
+
+    let mut buf = input.into_bytes();
+    // non-minimal pushes are warnings
+    let dialect: AsmDialect = args.asm_dialect.into();
+    let (script_hex, script, warnings) =
+        match parse_script(&mut buf, input_type, dialect, VerifyFlags::NONE) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                print_error(args, err);
+                return ExitCode::from(EXIT_PARSE_ERROR);
+            }
+        };
+
+    let asm = match dialect {
+        AsmDialect::Analyzer => script.to_string(),
+        dialect => script.to_asm(dialect),
+    };
+    match args.format {
+        Format::Text | Format::Dot | Format::Html | Format::Markdown | Format::Smtlib => {
+            match input_type {
+                InputType::Asm => println!("{script_hex}"),
+                _ => println!("{}", args.stdout_colors().script(&asm)),
+            }
+            let colors = args.stderr_colors();
+            for warning in warnings {
+                eprintln!("{}", colors.yellow(&format!("warning: {warning}")));
+            }
+        }
+        Format::Json | Format::Ndjson => {
+            let value = serde_json::json!({
+                "hex": script_hex,
+                "script": asm,
+                "warnings": warnings.iter().map(|w| w.to_string()).collect::<Vec<_>>(),
+            });
+            println!("{value}");
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Compares the spending paths of two scripts.
+fn diff(args: &Args, diff_args: &DiffArgs) -> ExitCode {
+    let ctx = args.script_context(diff_args.version.into());
+
+    let mut old_buf = diff_args.old.as_bytes().to_vec();
+    let mut new_buf = diff_args.new.as_bytes().to_vec();
+    let analyses = [
+        ("old", &diff_args.old, &mut old_buf),
+        ("new", &diff_args.new, &mut new_buf),
+    ]
+    .map(|(name, input, buf)| {
+        let input_type = diff_args.input_type.detect(input);
+        parse_script(buf, input_type, args.asm_dialect.into(), ctx.flags)
+            .and_then(|(_, script, _)| {
+                run_analysis(args, &script, ctx).map_err(|err| err.to_string())
+            })
+            .map_err(|err| format!("{name} script: {err}"))
+    });
+    let [old, new] = match analyses {
+        [Ok(old), Ok(new)] => [old, new],
+        [Err(err), _] | [_, Err(err)] => {
+            print_error(args, err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let cmp = ScriptComparison::new(old, new);
+    match args.format {
+        Format::Text | Format::Dot | Format::Html | Format::Markdown | Format::Smtlib => {
+            println!("{cmp}")
+        }
+        Format::Json | Format::Ndjson => println!("{}", diff::to_json(&cmp)),
+    }
+
+    if cmp.is_equivalent() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Parses `input` (replacing it with the script bytes) and returns the script with its hex
+/// encoding and parse warnings.
+fn parse_script(
+    input: &mut Vec<u8>,
+    input_type: InputType,
+    dialect: AsmDialect,
+    flags: VerifyFlags,
+) -> Result<(String, OwnedScript<'_>, Vec<ParseWarning>), String> {
+    let bytes = match input_type {
+        InputType::Asm => {
+            // not in place, defines can make the script longer than the asm
+            let asm = std::str::from_utf8(input).map_err(|err| err.to_string())?;
+            let (bytes, _) = OwnedScript::parse_from_asm_with_dialect(asm, dialect)
+                .map_err(|err| err.to_string())?;
+            *input = bytes;
+            &input[..]
+        }
+        _ => decode_hex_in_place_ignore_whitespace(input).map_err(|err| err.to_string())?,
+    };
+    // unknown opcodes are rejected by the analysis, so decode can still show them
+    let (script, warnings) =
+        OwnedScript::parse_from_bytes_lenient(bytes).map_err(|err| err.to_string())?;
+    if flags.contains(VerifyFlags::MINIMALDATA) {
+        if let Some(&ParseWarning::NonMinimalPush(offset)) = warnings
+            .iter()
+            .find(|w| matches!(w, ParseWarning::NonMinimalPush(_)))
+        {
+            return Err(ParseScriptError::NonMinimalPush(offset).to_string());
+        }
+    }
+    Ok((encode_hex_easy(bytes), script, warnings))
+}
+
+/// Analyzes `script` with the stack items given with `--stack-item`.
+fn run_analysis(
+    args: &Args,
+    script: &OwnedScript<'_>,
+    ctx: ScriptContext,
+) -> Result<Analysis, AnalyzeError> {
+    let mut builder = ScriptAnalyzer::builder(script);
+    for (pos, value) in &args.stack_item {
+        builder = builder.stack_item(*pos, Expr::bytes(value));
+    }
+    builder.build().analyze(ctx, analysis_options(args))
+}
+
+fn analysis_options(args: &Args) -> AnalysisOptions {
+    AnalysisOptions {
+        worker_threads: args.threads,
+        max_key_sets: args.key_sets,
+        ..Default::default()
+    }
+}
+
+/// `chain_tip` overrides the one of the arguments, see [`Args::chain_tip`].
+fn run(
+    args: &Args,
+    input: &str,
+    input_type: InputType,
+    ctx: ScriptContext,
+    chain_tip: Option<ChainTip>,
+) -> ExitCode {
+    let mut buf = input.as_bytes().to_vec();
+    let (script_hex, script, warnings) =
+        match parse_script(&mut buf, input_type, args.asm_dialect.into(), ctx.flags) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                print_error(args, err);
+                return ExitCode::from(EXIT_PARSE_ERROR);
+            }
+        };
+
+    let address = args.network.and_then(|_| ctx.address(&script));
+    if args.format == Format::Text {
+        if matches!(args.command, Some(Command::Import(_) | Command::Fetch(_))) {
+            println!("version: {}", args::Version::from(ctx.version));
+        }
+        if args.report_options().show_hex {
+            println!("hex: {script_hex}");
+        }
+        if let Some(address) = &address {
+            println!("address: {address}");
+        }
+    }
+
+    let res = run_analysis(args, &script, ctx);
+    let code = exit_code(&res);
+    let suggestions = match &res {
+        Ok(_) if args.suggest => {
+            suggest_optimizations(&script, ctx, analysis_options(args)).unwrap_or_default()
+        }
+        _ => Vec::new(),
+    };
+    let dead_code = if args.dead_code {
+        find_dead_code(&script, ctx, analysis_options(args)).unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+
+    match args.format {
+        Format::Text => {
+            let colors = args.stdout_colors();
+            if !args.quiet {
+                println!("script:\n{}", colors.script(&script.to_string()));
+                println!();
+            }
+            for warning in warnings {
+                println!("{}", colors.yellow(&format!("warning: {warning}")));
+            }
+            match res {
+                Ok(analysis) => {
+                    let labels = args.labels();
+                    let options = ReportOptions {
+                        labels: Some(&labels),
+                        chain_tip,
+                        ..args.report_options()
+                    };
+                    println!("{}", colors.analysis(&analysis.report(options).to_string()))
+                }
+                Err(err) => println!("{}", colors.red(&err.to_string())),
+            }
+            if !dead_code.is_empty() {
+                println!("\nDead code:");
+                for dead in &dead_code {
+                    println!("{}", colors.yellow(&dead.to_string()));
+                }
+            }
+            if !suggestions.is_empty() {
+                println!("\nSuggestions:");
+                for suggestion in &suggestions {
+                    println!("{suggestion}");
+                }
+            }
+        }
+        Format::Json => {
+            let mut value = match &res {
+                Ok(analysis) => json::analysis(analysis),
+                Err(err) => json::error(err),
+            };
+            value["hex"] = script_hex.into();
+            value["script"] = script.to_string().into();
+            if let Some(address) = &address {
+                value["address"] = address.as_str().into();
+            }
+            if let (Ok(analysis), Some(tx)) = (&res, args.tx_timelocks()) {
+                for (value, path) in value["paths"]
+                    .as_array_mut()
+                    .unwrap()
+                    .iter_mut()
+                    .zip(&analysis.paths)
+                {
+                    value["timelock_mismatches"] = path
+                        .check_timelocks(tx)
+                        .iter()
+                        .map(|m| m.to_string())
+                        .collect();
+                }
+            }
+            if let (Ok(analysis), Some(tip)) = (&res, chain_tip) {
+                for (value, path) in value["paths"]
+                    .as_array_mut()
+                    .unwrap()
+                    .iter_mut()
+                    .zip(&analysis.paths)
+                {
+                    let spendable = path.spendable_at(tip);
+                    value["spendable_in"] = serde_json::json!({
+                        "blocks": spendable.blocks,
+                        "seconds": spendable.seconds,
+                    });
+                }
+            }
+            value["warnings"] = warnings.iter().map(|w| w.to_string()).collect();
+            if args.dead_code {
+                value["dead_code"] = dead_code.iter().map(json::dead_code).collect();
+            }
+            if args.suggest {
+                value["suggestions"] = suggestions.iter().map(json::suggestion).collect();
+            }
+            println!("{value}");
+        }
+        Format::Ndjson => match res {
+            Ok(analysis) => {
+                for path in &analysis.paths {
+                    println!("{}", json::path(path));
+                }
+            }
+            Err(err) => println!("{}", json::error(err)),
+        },
+        Format::Dot => match res {
+            Ok(analysis) => print!("{}", analysis.to_dot()),
+            Err(err) => print_error(args, err),
+        },
+        Format::Html => match res {
+            Ok(analysis) => print!("{}", analysis.to_html(&script)),
+            Err(err) => print_error(args, err),
+        },
+        Format::Markdown => match res {
+            Ok(analysis) => print!("{}", analysis.to_markdown()),
+            Err(err) => print_error(args, err),
+        },
+        Format::Smtlib => match res {
+            Ok(analysis) => print!("{}", analysis.to_smtlib()),
+            Err(err) => print_error(args, err),
+        },
+    }
+
+    ExitCode::from(code)
+}
+
+/// 0 if the script can be spent, or one of the `EXIT_*` codes.
+fn exit_code(res: &Result<Analysis, AnalyzeError>) -> u8 {
     match res {
-        Ok(v) | Err(v) => v,
-    }
-}
-
-pub fn main() {
-    let script_hex = std::env::args()
-        .nth(1)
-        .expect("missing argument \"script\"");
-
-    println!("hex: {script_hex}");
-    let mut script_hex = script_hex.into_bytes();
-    let script_bytes = decode_hex_in_place(&mut script_hex).unwrap();
-    let script = OwnedScript::parse_from_bytes(script_bytes).unwrap();
-    println!("script:\n{script}");
-    println!();
-    let res = analyze_script(
-        &script,
-        ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All),
-        0,
-    );
-    println!("{}", unwrap_both(res));
+        Ok(_) => 0,
+        Err(AnalyzeError::DisabledOpcode { .. }) => EXIT_DISABLED_OPCODE,
+        // an error of the parser without parse_from_bytes_lenient
+        Err(AnalyzeError::UnknownOpcode { .. }) => EXIT_PARSE_ERROR,
+        Err(
+            AnalyzeError::ScriptSize
+            | AnalyzeError::PushSize { .. }
+            | AnalyzeError::OpCount
+            | AnalyzeError::Output(_)
+            | AnalyzeError::Unspendable { .. },
+        ) => EXIT_UNSPENDABLE,
+        // the analysis did not complete
+        Err(AnalyzeError::ThreadingUnavailable | AnalyzeError::LimitExceeded(_)) => 1,
+    }
+}
+
+/// Analyzes every line of `input` as a script and prints a summary per script. Empty lines and
+/// lines starting with `#` are skipped. Exits with the exit code of the first script that can't
+/// be parsed or spent, if any.
+fn run_batch(args: &Args, analyze_args: &AnalyzeArgs, input: &str) -> ExitCode {
+    if args.format == Format::Html {
+        print_error(args, "--format html is not supported in batch mode");
+        return ExitCode::FAILURE;
+    }
+    let ctx = args.script_context(analyze_args.version.into());
+
+    let mut code = 0;
+    let mut results = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line_number = i + 1;
+
+        let mut buf = line.as_bytes().to_vec();
+        let input_type = analyze_args.input_type.detect(line);
+        let res = parse_script(&mut buf, input_type, args.asm_dialect.into(), ctx.flags)
+            .map_err(|err| (EXIT_PARSE_ERROR, err))
+            .and_then(|(script_hex, script, _)| {
+                let res = run_analysis(args, &script, ctx);
+                let line_code = exit_code(&res);
+                res.map(|analysis| (script_hex, analysis))
+                    .map_err(|err| (line_code, err.to_string()))
+            });
+        if code == 0 {
+            if let Err((line_code, _)) = &res {
+                code = *line_code;
+            }
+        }
+
+        match args.format {
+            Format::Text => match &res {
+                Ok((_, analysis)) => {
+                    print!(
+                        "line {line_number}: {} spending paths",
+                        analysis.paths.len()
+                    );
+                    if let Some(template) = analysis.template {
+                        print!(" ({template})");
+                    }
+                    println!();
+                }
+                Err((_, err)) => println!(
+                    "{}",
+                    args.stdout_colors()
+                        .red(&format!("line {line_number}: {err}"))
+                ),
+            },
+            // one graph per script
+            Format::Dot => match &res {
+                Ok((_, analysis)) => print!("{}", analysis.to_dot()),
+                Err((_, err)) => print_error(args, format_args!("line {line_number}: {err}")),
+            },
+            Format::Markdown => match &res {
+                Ok((_, analysis)) => {
+                    print!("# Line {line_number}\n\n{}\n", analysis.to_markdown())
+                }
+                Err((_, err)) => println!("# Line {line_number}\n\n{err}\n"),
+            },
+            Format::Smtlib => match &res {
+                Ok((_, analysis)) => {
+                    print!("; line {line_number}\n{}(reset)\n", analysis.to_smtlib())
+                }
+                Err((_, err)) => println!("; line {line_number}: {err}"),
+            },
+            Format::Html => unreachable!("checked above"),
+            Format::Json | Format::Ndjson => {
+                let mut value = match &res {
+                    Ok((script_hex, analysis)) => {
+                        let mut value = json::analysis(analysis);
+                        value["hex"] = script_hex.as_str().into();
+                        value
+                    }
+                    Err((_, err)) => json::error(err),
+                };
+                value["line"] = line_number.into();
+                if args.format == Format::Ndjson {
+                    println!("{value}");
+                } else {
+                    results.push(value);
+                }
+            }
+        }
+    }
+
+    if args.format == Format::Json {
+        println!("{}", serde_json::Value::Array(results));
+    }
+
+    ExitCode::from(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        exit_code, parse_script, EXIT_DISABLED_OPCODE, EXIT_PARSE_ERROR, EXIT_UNSPENDABLE,
+    };
+    use crate::args::InputType;
+    use bitcoin_script_analyzer::{
+        analyze_script, AnalysisOptions, AsmDialect, ScriptContext, ScriptRules, ScriptVersion,
+    };
+
+    /// The exit code of analyzing `input` like `run` does.
+    fn code(input: &str, opts: AnalysisOptions) -> u8 {
+        let ctx = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All);
+        let mut buf = input.as_bytes().to_vec();
+        let input_type = InputType::Auto.detect(input);
+        match parse_script(&mut buf, input_type, AsmDialect::default(), ctx.flags) {
+            Ok((_, script, _)) => exit_code(&analyze_script(&script, ctx, opts)),
+            Err(_) => EXIT_PARSE_ERROR,
+        }
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(code("OP_1 OP_CHECKSEQUENCEVERIFY", Default::default()), 0);
+        assert_eq!(code("OP_RETURN", Default::default()), EXIT_UNSPENDABLE);
+        assert_eq!(
+            code("OP_1 OP_1 OP_CAT", Default::default()),
+            EXIT_DISABLED_OPCODE
+        );
+
+        // truncated push
+        assert_eq!(code("4c", Default::default()), EXIT_PARSE_ERROR);
+        // non-minimal push, standardness rules require minimal pushes
+        assert_eq!(code("0101", Default::default()), EXIT_PARSE_ERROR);
+        // parsed leniently, rejected by the analysis
+        assert_eq!(code("51c0", Default::default()), EXIT_PARSE_ERROR);
+        assert_eq!(code("OP_NOTANOPCODE", Default::default()), EXIT_PARSE_ERROR);
+
+        let opts = AnalysisOptions {
+            max_paths: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(code("OP_IF OP_ENDIF OP_1", opts), 1);
+    }
 }
@@ -0,0 +1,152 @@
+//! Interactive session, used by the `repl` subcommand.
+
+use crate::{
+    args::{Args, InputType, ReplArgs, Rules, Version},
+    parse_script,
+};
+use bitcoin_script_analyzer::{AsmDialect, ScriptContext, ScriptDebugger};
+use clap::ValueEnum;
+use std::{
+    io::{self, BufRead, Write},
+    process::ExitCode,
+};
+
+const HELP: &str = "\
+Enter a script (hex or asm) to analyze it, or a command:
+  :version <legacy|segwit-v0|tapscript>  set the script version
+  :rules <all|consensus>                 set the rules to check
+  :step <script>                         step through the execution of a script
+  :help                                  show this help
+  :quit                                  end the session";
+
+const STEP_HELP: &str = "\
+Enter to execute the next element, c to run to the end of the path, q to stop";
+
+pub fn run(args: &Args, repl_args: &ReplArgs) -> ExitCode {
+    let mut version = repl_args.version;
+    let mut rules = args.rules;
+    let mut lines = io::stdin().lock().lines();
+
+    println!("{HELP}");
+    loop {
+        let Some(line) = prompt(&mut lines, "> ") else {
+            return ExitCode::SUCCESS;
+        };
+        let line = line.trim();
+        let ctx = ScriptContext::new(version.into(), rules.into());
+
+        let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let arg = arg.trim();
+        match command {
+            "" => {}
+            ":q" | ":quit" | ":exit" => return ExitCode::SUCCESS,
+            ":h" | ":help" => println!("{HELP}"),
+            ":version" if arg.is_empty() => println!("version: {version}"),
+            ":version" => match Version::from_str(arg, true) {
+                Ok(v) => version = v,
+                Err(_) => {
+                    println!("unknown version {arg:?}, expected legacy, segwit-v0 or tapscript")
+                }
+            },
+            ":rules" => match Rules::from_str(arg, true) {
+                Ok(r) => rules = r,
+                Err(_) => println!("unknown rules {arg:?}, expected all or consensus"),
+            },
+            ":step" => step(arg, ctx, args.asm_dialect.into(), &mut lines),
+            _ if command.starts_with(':') => println!("unknown command {command}, try :help"),
+            _ => {
+                crate::run(
+                    args,
+                    line,
+                    InputType::Auto.detect(line),
+                    ctx,
+                    args.chain_tip(),
+                );
+            }
+        }
+    }
+}
+
+/// Prints `prompt` and reads a line, `None` at the end of the input.
+fn prompt(lines: &mut impl Iterator<Item = io::Result<String>>, prompt: &str) -> Option<String> {
+    print!("{prompt}");
+    io::stdout().flush().ok()?;
+    lines.next()?.ok()
+}
+
+/// Steps through the execution of `input`. Every time execution forks, the other path is queued
+/// and continued when the current path ends.
+fn step(
+    input: &str,
+    ctx: ScriptContext,
+    dialect: AsmDialect,
+    lines: &mut impl Iterator<Item = io::Result<String>>,
+) {
+    let mut buf = input.as_bytes().to_vec();
+    let script = match parse_script(&mut buf, InputType::Auto.detect(input), dialect, ctx.flags) {
+        Ok((_, script, _)) => script,
+        Err(err) => {
+            println!("error: {err}");
+            return;
+        }
+    };
+
+    println!("{STEP_HELP}");
+    let mut paths = vec![ScriptDebugger::new(&script, ctx)];
+    while let Some(mut debugger) = paths.pop() {
+        let mut run_to_end = false;
+        while let Some(res) = debugger.step() {
+            // finish returns the error
+            let Ok(step) = res else {
+                break;
+            };
+
+            print!("#{} {}", step.index, step.elem);
+            if !step.executed {
+                print!(" (not executed)");
+            }
+            println!();
+            println!("  stack: {}", join(&step.stack));
+            if !step.altstack.is_empty() {
+                println!("  altstack: {}", join(&step.altstack));
+            }
+            if let Some(fork) = step.fork {
+                println!("  forked, the other branch is continued after this path");
+                paths.push(fork);
+            }
+
+            if !run_to_end {
+                match prompt(lines, "step> ").as_deref().map(str::trim) {
+                    Some("c") => run_to_end = true,
+                    Some("q") | None => return,
+                    _ => {}
+                }
+            }
+        }
+
+        match debugger.finish() {
+            Ok(conditions) if conditions.is_empty() => println!("path spendable, no conditions"),
+            Ok(conditions) => {
+                println!("path spendable if:");
+                for condition in conditions {
+                    println!("  {condition}");
+                }
+            }
+            Err(err) => println!("path fails: {err}"),
+        }
+        if !paths.is_empty() {
+            println!("continuing with the other branch of the last fork");
+        }
+    }
+}
+
+fn join<T: ToString>(items: &[T]) -> String {
+    if items.is_empty() {
+        return "(empty)".to_owned();
+    }
+    items
+        .iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
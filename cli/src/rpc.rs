@@ -0,0 +1,146 @@
+//! Minimal Bitcoin Core JSON-RPC client, used by the `import` subcommand.
+
+use crate::import::{chain_tip, hex, revealed_script, ImportedScript, SpendingInput};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bitcoin_script_analyzer::{ChainTip, ScriptVersion};
+use serde_json::{json, Value};
+use std::{fs, path::Path};
+
+pub struct Rpc {
+    url: String,
+    /// Value of the `Authorization` header.
+    auth: Option<String>,
+}
+
+impl Rpc {
+    /// `cookie` is the `.cookie` file in the data directory of the node.
+    pub fn new(url: &str, cookie: Option<&Path>) -> Result<Self, String> {
+        let auth = match cookie {
+            Some(path) => {
+                let cookie = fs::read_to_string(path)
+                    .map_err(|err| format!("can't read {}: {err}", path.display()))?;
+                Some(format!("Basic {}", STANDARD.encode(cookie.trim())))
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            url: url.to_owned(),
+            auth,
+        })
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value, String> {
+        let mut request = ureq::post(&self.url);
+        if let Some(auth) = &self.auth {
+            request = request.set("Authorization", auth);
+        }
+        let body = json!({ "jsonrpc": "1.0", "id": "bsa", "method": method, "params": params });
+
+        let mut response: Value = match request.send_json(body) {
+            Ok(response) => response.into_json(),
+            // errors of the call itself have a status code too
+            Err(ureq::Error::Status(_, response)) => response.into_json(),
+            Err(err) => return Err(format!("RPC request failed: {err}")),
+        }
+        .map_err(|err| format!("invalid RPC response: {err}"))?;
+
+        match response["error"]["message"].as_str() {
+            Some(message) => Err(format!("{method}: {message}")),
+            None => Ok(response["result"].take()),
+        }
+    }
+
+    /// Height and median time past of the best block.
+    pub fn chain_tip(&self) -> Result<ChainTip, String> {
+        let info = self.call("getblockchaininfo", json!([]))?;
+        chain_tip(&info["blocks"], &info["mediantime"])
+    }
+
+    /// Finds the script of the output `txid:vout` or of an address.
+    pub fn import(&self, target: &str) -> Result<ImportedScript, String> {
+        match target.split_once(':') {
+            Some((txid, vout)) => {
+                let vout = vout
+                    .parse()
+                    .map_err(|_| format!("invalid output index {vout}"))?;
+                self.import_output(txid, vout)
+            }
+            None => self.import_address(target),
+        }
+    }
+
+    fn import_output(&self, txid: &str, vout: u32) -> Result<ImportedScript, String> {
+        let tx = self.call("getrawtransaction", json!([txid, true]))?;
+        let output = tx["vout"]
+            .as_array()
+            .and_then(|outputs| outputs.iter().find(|output| output["n"] == vout))
+            .ok_or_else(|| format!("transaction has no output {vout}"))?;
+        let script_pubkey = hex(&output["scriptPubKey"]["hex"])?;
+
+        revealed_script(&script_pubkey, self.spending_input(txid, vout)?.as_ref())
+    }
+
+    /// The input spending `txid:vout`. Only spends in the mempool are found, Bitcoin Core has no
+    /// index of spent outputs.
+    fn spending_input(&self, txid: &str, vout: u32) -> Result<Option<SpendingInput>, String> {
+        // not available before Bitcoin Core 24
+        let Ok(spends) = self.call(
+            "gettxspendingprevout",
+            json!([[{ "txid": txid, "vout": vout }]]),
+        ) else {
+            return Ok(None);
+        };
+        let Some(spending_txid) = spends[0]["spendingtxid"].as_str() else {
+            return Ok(None);
+        };
+
+        let tx = self.call("getrawtransaction", json!([spending_txid, true]))?;
+        let input = tx["vin"]
+            .as_array()
+            .and_then(|inputs| {
+                inputs
+                    .iter()
+                    .find(|input| input["txid"] == txid && input["vout"] == vout)
+            })
+            .ok_or("spending transaction does not spend the output")?;
+
+        Ok(Some(SpendingInput {
+            script_sig: hex(&input["scriptSig"]["hex"])?,
+            witness: match input["txinwitness"].as_array() {
+                Some(items) => items.iter().map(hex).collect::<Result<_, _>>()?,
+                None => Vec::new(),
+            },
+        }))
+    }
+
+    fn import_address(&self, address: &str) -> Result<ImportedScript, String> {
+        // getaddressinfo needs a wallet, but also knows the scripts of addresses of the wallet
+        let info = self
+            .call("getaddressinfo", json!([address]))
+            .or_else(|_| self.call("validateaddress", json!([address])))?;
+        if info["isvalid"] == false {
+            return Err(format!("invalid address {address}"));
+        }
+
+        if info["embedded"]["hex"].is_string() {
+            // P2SH wrapped P2WSH
+            return Ok(ImportedScript {
+                bytes: hex(&info["embedded"]["hex"])?,
+                version: ScriptVersion::SegwitV0,
+            });
+        }
+        if info["hex"].is_string() {
+            return Ok(ImportedScript {
+                bytes: hex(&info["hex"])?,
+                version: if info["iswitness"] == true {
+                    ScriptVersion::SegwitV0
+                } else {
+                    ScriptVersion::Legacy
+                },
+            });
+        }
+
+        revealed_script(&hex(&info["scriptPubKey"])?, None)
+    }
+}
@@ -0,0 +1,61 @@
+/* C interface of bitcoin-script-analyzer, link with libbsa. */
+
+#ifndef BSA_H
+#define BSA_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum {
+    BSA_OK = 0,
+    /* script is null and len is not 0 */
+    BSA_NULL_POINTER = 1,
+    BSA_INVALID_VERSION = 2,
+    BSA_INVALID_RULES = 3,
+    /* the script could not be parsed */
+    BSA_PARSE = 4,
+    /* the script violates a limit that applies to every path, like its size or op count */
+    BSA_SCRIPT_LIMIT = 5,
+    BSA_UNSPENDABLE = 6,
+    /* a limit of the analysis was exceeded */
+    BSA_LIMIT_EXCEEDED = 7,
+    /* the analysis needs threads, which this build does not support */
+    BSA_THREADING_UNAVAILABLE = 8,
+    /* the analyzer panicked, this is a bug */
+    BSA_PANIC = 9,
+} BsaError;
+
+enum {
+    BSA_VERSION_LEGACY = 0,
+    BSA_VERSION_SEGWIT_V0 = 1,
+    BSA_VERSION_TAPSCRIPT = 2,
+};
+
+enum {
+    BSA_RULES_CONSENSUS = 0,
+    BSA_RULES_ALL = 1,
+};
+
+/*
+ * Analyzes the script of len bytes at script.
+ *
+ * If out_json is not null, it is set to a JSON object, also on error:
+ * {"template": ..., "paths": [...], "report": ...} or {"error": ...}.
+ * The string has to be freed with bsa_free_string. If out_json is null, only
+ * the result is returned.
+ */
+BsaError bsa_analyze(const uint8_t *script, size_t len, uint32_t version, uint32_t rules,
+                     char **out_json);
+
+/* Frees a string returned by bsa_analyze, does nothing if s is null. */
+void bsa_free_string(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,263 @@
+//! C interface of the analyzer, see `include/bsa.h`.
+
+use bitcoin_script_analyzer::{
+    analyze_script, AnalyzeError, ScriptContext, ScriptRules, ScriptVersion,
+};
+use std::{
+    ffi::{c_char, CString},
+    fmt::Write,
+    panic::{self, AssertUnwindSafe},
+    slice,
+};
+
+/// Result of [`bsa_analyze`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BsaError {
+    Ok = 0,
+    /// `script` is null and `len` is not 0.
+    NullPointer = 1,
+    InvalidVersion = 2,
+    InvalidRules = 3,
+    /// The script could not be parsed.
+    Parse = 4,
+    /// The script violates a limit that applies to every path, like its size or op count.
+    ScriptLimit = 5,
+    Unspendable = 6,
+    /// A limit of the analysis was exceeded.
+    LimitExceeded = 7,
+    /// The analysis needs threads, which this build does not support.
+    ThreadingUnavailable = 8,
+    /// The analyzer panicked, this is a bug.
+    Panic = 9,
+}
+
+/// Analyzes the script of `len` bytes at `script`. `version` is 0 for legacy, 1 for segwit v0
+/// and 2 for tapscript, `rules` is 0 for consensus rules only and 1 for all (standardness)
+/// rules.
+///
+/// If `out_json` is not null, it is set to a JSON object, also on error:
+/// `{"template": ..., "paths": [...], "report": ...}` or `{"error": ...}`. The string has to be
+/// freed with [`bsa_free_string`]. If `out_json` is null, only the result is returned.
+///
+/// # Safety
+///
+/// `script` must point to `len` readable bytes, `out_json` must be null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn bsa_analyze(
+    script: *const u8,
+    len: usize,
+    version: u32,
+    rules: u32,
+    out_json: *mut *mut c_char,
+) -> BsaError {
+    let (code, json) = if script.is_null() && len != 0 {
+        (BsaError::NullPointer, error_json("script is null"))
+    } else {
+        let bytes = if len == 0 {
+            &[]
+        } else {
+            // SAFETY: checked for null, the caller guarantees `len` bytes can be read
+            unsafe { slice::from_raw_parts(script, len) }
+        };
+        // unwinding into C is undefined behavior
+        match panic::catch_unwind(AssertUnwindSafe(|| analyze(bytes, version, rules))) {
+            Ok(Ok(json)) => (BsaError::Ok, json),
+            Ok(Err((code, message))) => (code, error_json(&message)),
+            Err(_) => (BsaError::Panic, error_json("the analyzer panicked")),
+        }
+    };
+
+    if out_json.is_null() {
+        return code;
+    }
+    // json strings escape NUL, this can not fail
+    let json = CString::new(json).unwrap();
+    // SAFETY: checked for null, the caller guarantees it is writable
+    unsafe { *out_json = json.into_raw() };
+
+    code
+}
+
+/// Frees a string returned by [`bsa_analyze`]. Does nothing if `s` is null.
+///
+/// # Safety
+///
+/// `s` must be null or a string returned by [`bsa_analyze`] that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn bsa_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        // SAFETY: the caller guarantees `s` was created by `CString::into_raw`
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+fn analyze(bytes: &[u8], version: u32, rules: u32) -> Result<String, (BsaError, String)> {
+    let version = ScriptVersion::from_u32(version).ok_or_else(|| {
+        (
+            BsaError::InvalidVersion,
+            format!("invalid version {version}"),
+        )
+    })?;
+    let rules = ScriptRules::from_u32(rules)
+        .ok_or_else(|| (BsaError::InvalidRules, format!("invalid rules {rules}")))?;
+    let ctx = ScriptContext::new(version, rules);
+
+    let script = ctx
+        .parse_script(bytes)
+        .map_err(|err| (BsaError::Parse, err.to_string()))?;
+    let analysis = analyze_script(&script, ctx, Default::default()).map_err(|err| {
+        let code = match err {
+            AnalyzeError::Unspendable { .. } => BsaError::Unspendable,
+            AnalyzeError::LimitExceeded(_) => BsaError::LimitExceeded,
+            AnalyzeError::ThreadingUnavailable => BsaError::ThreadingUnavailable,
+            _ => BsaError::ScriptLimit,
+        };
+        (code, err.to_string())
+    })?;
+
+    let mut json = String::from("{\"template\":");
+    match &analysis.template {
+        Some(template) => write_str(&mut json, &template.to_string()),
+        None => json.push_str("null"),
+    }
+    json.push_str(",\"paths\":[");
+    for (i, path) in analysis.paths.iter().enumerate() {
+        if i != 0 {
+            json.push(',');
+        }
+        write!(
+            json,
+            "{{\"stack_size\":{},\"conditions\":[",
+            path.stack_size
+        )
+        .unwrap();
+        for (j, condition) in path.spending_conditions.iter().enumerate() {
+            if j != 0 {
+                json.push(',');
+            }
+            write_str(&mut json, &condition.to_string());
+        }
+        json.push_str("],\"locktime\":");
+        write_opt_num(&mut json, path.locktime_req.req);
+        json.push_str(",\"sequence\":");
+        write_opt_num(&mut json, path.sequence_req.req);
+        json.push('}');
+    }
+    json.push_str("],\"report\":");
+    write_str(&mut json, &analysis.to_string());
+    json.push('}');
+
+    Ok(json)
+}
+
+fn error_json(message: &str) -> String {
+    let mut json = String::from("{\"error\":");
+    write_str(&mut json, message);
+    json.push('}');
+    json
+}
+
+fn write_opt_num(json: &mut String, n: Option<u32>) {
+    match n {
+        Some(n) => write!(json, "{n}").unwrap(),
+        None => json.push_str("null"),
+    }
+}
+
+/// Writes `s` as a JSON string.
+fn write_str(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if c < ' ' => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bsa_analyze, bsa_free_string, BsaError};
+    use std::{ffi::CStr, ptr};
+
+    fn analyze(script: &[u8], version: u32) -> (BsaError, String) {
+        let mut out = ptr::null_mut();
+        let code = unsafe { bsa_analyze(script.as_ptr(), script.len(), version, 1, &mut out) };
+        let json = unsafe { CStr::from_ptr(out) }.to_str().unwrap().to_owned();
+        unsafe { bsa_free_string(out) };
+        (code, json)
+    }
+
+    #[test]
+    fn test_analyze() {
+        // OP_1 OP_CHECKSEQUENCEVERIFY
+        let (code, json) = analyze(&[0x51, 0xb2], 1);
+        assert_eq!(code, BsaError::Ok);
+        assert!(json.starts_with("{\"template\":null,\"paths\":[{\"stack_size\":0,"));
+        assert!(json.contains("\"sequence\":1}"));
+
+        assert_eq!(analyze(&[0x6a], 1).0, BsaError::Unspendable);
+        assert_eq!(
+            analyze(&[0x51], 3),
+            (
+                BsaError::InvalidVersion,
+                "{\"error\":\"invalid version 3\"}".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let mut out = ptr::null_mut();
+        assert_eq!(
+            unsafe { bsa_analyze([0x51].as_ptr(), 1, 1, 2, &mut out) },
+            BsaError::InvalidRules
+        );
+        unsafe { bsa_free_string(out) };
+        // push of 1 byte that is not cut off
+        assert_eq!(analyze(&[0x01], 1).0, BsaError::Parse);
+        // non-minimal push of 1, these tests use the standardness rules
+        assert_eq!(analyze(&[0x01, 0x01], 1).0, BsaError::Parse);
+        // OP_CAT is disabled
+        assert_eq!(analyze(&[0x51, 0x51, 0x7e], 1).0, BsaError::ScriptLimit);
+    }
+
+    #[test]
+    fn test_null_pointers() {
+        let mut out = ptr::null_mut();
+        assert_eq!(
+            unsafe { bsa_analyze(ptr::null(), 1, 1, 1, &mut out) },
+            BsaError::NullPointer
+        );
+        let json = unsafe { CStr::from_ptr(out) }.to_str().unwrap().to_owned();
+        unsafe { bsa_free_string(out) };
+        assert_eq!(json, "{\"error\":\"script is null\"}");
+
+        // an empty script may be null
+        assert_eq!(
+            unsafe { bsa_analyze(ptr::null(), 0, 1, 1, ptr::null_mut()) },
+            BsaError::Ok
+        );
+        // without out_json, the result is still returned
+        assert_eq!(
+            unsafe { bsa_analyze(ptr::null(), 1, 1, 1, ptr::null_mut()) },
+            BsaError::NullPointer
+        );
+        let script = [0x51, 0xb2];
+        assert_eq!(
+            unsafe { bsa_analyze(script.as_ptr(), 2, 1, 1, ptr::null_mut()) },
+            BsaError::Ok
+        );
+        assert_eq!(
+            unsafe { bsa_analyze(script.as_ptr(), 2, 3, 1, ptr::null_mut()) },
+            BsaError::InvalidVersion
+        );
+
+        unsafe { bsa_free_string(ptr::null_mut()) };
+    }
+}
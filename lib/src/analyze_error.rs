@@ -0,0 +1,104 @@
+use crate::{opcode::Opcode, script_error::ScriptError};
+use core::fmt;
+
+/// Error returned by [`analyze_script`](crate::analyze_script).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnalyzeError {
+    /// The script is larger than 10000 bytes.
+    ScriptSize,
+    /// The script contains a disabled opcode at byte `offset`.
+    DisabledOpcode { opcode: Opcode, offset: usize },
+    /// The script contains a byte at `offset` that is not an opcode, see
+    /// [`OwnedScript::parse_from_bytes_lenient`](crate::OwnedScript::parse_from_bytes_lenient).
+    UnknownOpcode { opcode: Opcode, offset: usize },
+    /// The data push at byte `offset` is larger than 520 bytes.
+    PushSize { offset: usize },
+    /// The script contains more than 201 non-push opcodes.
+    OpCount,
+    /// The scripts do not match the output they spend, like a witness script with a different
+    /// hash than the witness program, see [`output_script`](crate::output_script).
+    Output(ScriptError),
+    /// All spending paths fail, with the error of every path.
+    Unspendable { per_path_errors: Vec<ScriptError> },
+    /// `worker_threads` is not 0, but the `threads` feature is disabled.
+    ThreadingUnavailable,
+    /// A limit of [`AnalysisOptions`](crate::AnalysisOptions) was exceeded.
+    LimitExceeded(AnalysisLimit),
+}
+
+/// A limit of [`AnalysisOptions`](crate::AnalysisOptions).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnalysisLimit {
+    Paths,
+    Forks,
+    ExprNodes,
+    Timeout,
+}
+
+impl fmt::Display for AnalysisLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Paths => "too many paths",
+                Self::Forks => "too many forks",
+                Self::ExprNodes => "expression too large",
+                Self::Timeout => "timeout",
+            }
+        )
+    }
+}
+
+impl AnalyzeError {
+    /// The closest [`ScriptError`] for this error.
+    pub fn script_error(&self) -> ScriptError {
+        match self {
+            Self::ScriptSize => ScriptError::SCRIPT_ERR_SCRIPT_SIZE,
+            Self::DisabledOpcode { .. } => ScriptError::SCRIPT_ERR_DISABLED_OPCODE,
+            Self::UnknownOpcode { .. } => ScriptError::SCRIPT_ERR_BAD_OPCODE,
+            Self::PushSize { .. } => ScriptError::SCRIPT_ERR_PUSH_SIZE,
+            Self::OpCount => ScriptError::SCRIPT_ERR_OP_COUNT,
+            Self::Output(err) => *err,
+            Self::Unspendable { per_path_errors } => per_path_errors
+                .first()
+                .copied()
+                .unwrap_or(ScriptError::SCRIPT_ERR_UNKNOWN_ERROR),
+            Self::ThreadingUnavailable | Self::LimitExceeded(_) => {
+                ScriptError::SCRIPT_ERR_UNKNOWN_ERROR
+            }
+        }
+    }
+}
+
+impl fmt::Display for AnalyzeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ScriptSize | Self::OpCount | Self::Output(_) => {
+                write!(f, "Script error: {}", self.script_error())
+            }
+            Self::DisabledOpcode { opcode, offset } | Self::UnknownOpcode { opcode, offset } => {
+                write!(
+                    f,
+                    "Script error: {} ({opcode} at byte {offset})",
+                    self.script_error()
+                )
+            }
+            Self::PushSize { offset } => {
+                write!(
+                    f,
+                    "Script error: {} (at byte {offset})",
+                    self.script_error()
+                )
+            }
+            Self::Unspendable { .. } => write!(f, "Script is unspendable"),
+            Self::ThreadingUnavailable => write!(
+                f,
+                "Feature \"threads\" disabled, set `worker_threads` to 0 or enable the feature"
+            ),
+            Self::LimitExceeded(limit) => write!(f, "Analysis aborted: {limit}"),
+        }
+    }
+}
+
+impl std::error::Error for AnalyzeError {}
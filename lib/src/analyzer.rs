@@ -1,35 +1,94 @@
+#[cfg(feature = "threads")]
+use crate::threadpool::PoolScope;
+use crate::threadpool::ThreadPool;
 use crate::{
+    analyze_error::{AnalysisLimit, AnalyzeError},
+    classify::{classify, ScriptTemplate},
     condition_stack::ConditionStack,
-    context::{ScriptContext, ScriptRules, ScriptVersion},
-    expr::{Expr, MultisigArgs, OpExprArgs, Opcode1, Opcode2, Opcode3},
+    context::{Network, ScriptContext, ScriptVersion, VerifyFlags},
+    expr::{
+        EqualityClasses, Expr, ExprId, ExprInterner, MultisigArgs, OpExprArgs, Opcode1, Opcode2,
+        Opcode3, Origin,
+    },
+    known::known_constant,
+    multisig::{multisig_key_sets, MultisigKeySets},
     opcode::opcodes,
+    range::{expr_range, item_ranges, item_sizes, numeric_contradiction, ItemRange, NumRange},
+    report::ReportOptions,
+    roles::ItemUsage,
     script::{
         convert::{decode_bool, decode_int, encode_bool_expr, encode_int_expr},
+        encoded_push_len,
         stack::Stack,
-        Script, ScriptElem,
+        Instruction, Script, ScriptElem, ScriptRef, MAX_OPS_PER_SCRIPT, MAX_PUBKEYS_PER_MULTISIG,
+        MAX_SCRIPT_ELEMENT_SIZE, MAX_SCRIPT_SIZE, MAX_STACK_SIZE, MAX_STANDARD_P2WSH_STACK_ITEMS,
     },
+    script_code::ScriptCode,
     script_error::ScriptError,
+    sighash::{sighash_requirements, SighashRequirement},
+    stats::AnalysisStats,
+    summary::Summary,
     util::locktime::{
-        locktime_to_string, locktime_type_equals, LocktimeType, SEQUENCE_LOCKTIME_MASK,
-        SEQUENCE_LOCKTIME_TYPE_FLAG,
+        locktime_to_string_with_format, locktime_type_equals, LocktimeFormat, LocktimeType,
+        SEQUENCE_LOCKTIME_DISABLE_FLAG, SEQUENCE_LOCKTIME_MASK, SEQUENCE_LOCKTIME_TYPE_FLAG,
     },
+    weight::{SigOps, SizeEstimate},
 };
-use core::fmt::{self, Write};
-
-struct LocktimeRequirement {
-    exprs: Vec<Expr>,
-    req: Option<u32>,
+use core::{cmp::Ordering, fmt, ops::ControlFlow, time::Duration};
+
+/// Locktime (`OP_CHECKLOCKTIMEVERIFY`) or sequence (`OP_CHECKSEQUENCEVERIFY`) requirement of a
+/// spending path.
+#[derive(Debug, Clone)]
+pub struct LocktimeRequirement {
+    /// Arguments that could not be evaluated.
+    pub exprs: Vec<Expr>,
+    /// The range of every argument in `exprs`, if other spending conditions constrain it.
+    pub expr_ranges: Vec<Option<NumRange>>,
+    /// Highest known minimum value, includes the lower bounds of `expr_ranges` that determine
+    /// the type of the locktime.
+    pub req: Option<u32>,
 }
 
 impl LocktimeRequirement {
     fn new() -> Self {
         Self {
             exprs: Vec::new(),
+            expr_ranges: Vec::new(),
             req: None,
         }
     }
 
-    fn locktime_requirement_to_string(&self, relative: bool) -> Option<String> {
+    /// Returns true if there is a requirement.
+    pub fn is_required(&self) -> bool {
+        self.req.is_some() || !self.exprs.is_empty()
+    }
+
+    /// Describes the requirement, `relative` for sequence requirements. Returns `None` if there is
+    /// no requirement.
+    pub fn locktime_requirement_to_string(&self, relative: bool) -> Option<String> {
+        self.locktime_requirement_to_string_with_network(relative, None)
+    }
+
+    /// Like [`locktime_requirement_to_string`](Self::locktime_requirement_to_string), with the
+    /// estimated date of block heights on `network`.
+    pub fn locktime_requirement_to_string_with_network(
+        &self,
+        relative: bool,
+        network: Option<Network>,
+    ) -> Option<String> {
+        self.locktime_requirement_to_string_with_format(relative, network, Default::default())
+    }
+
+    /// Like [`locktime_requirement_to_string_with_network`], with the minimum value in `format`.
+    ///
+    /// [`locktime_requirement_to_string_with_network`]:
+    ///     Self::locktime_requirement_to_string_with_network
+    pub fn locktime_requirement_to_string_with_format(
+        &self,
+        relative: bool,
+        network: Option<Network>,
+        format: LocktimeFormat,
+    ) -> Option<String> {
         if self.exprs.is_empty() && self.req.is_none() {
             return None;
         }
@@ -43,7 +102,7 @@ impl LocktimeRequirement {
         let tmp;
         let min_value = match self.req {
             Some(req) => {
-                tmp = locktime_to_string(req, relative);
+                tmp = locktime_to_string_with_format(req, relative, network, format);
                 &tmp
             }
             None => "unknown",
@@ -59,7 +118,11 @@ impl LocktimeRequirement {
                     ", stack elements: {}",
                     self.exprs
                         .iter()
-                        .map(|s| s.to_string())
+                        .zip(&self.expr_ranges)
+                        .map(|(s, range)| match range {
+                            Some(range) => format!("{s} in {range}"),
+                            None => s.to_string(),
+                        })
                         .collect::<Vec<_>>()
                         .join(", ")
                 );
@@ -71,802 +134,2406 @@ impl LocktimeRequirement {
     }
 }
 
-struct AnalyzerResult {
-    stack_size: u32,
-    spending_conditions: Vec<Expr>,
-    locktime_req: LocktimeRequirement,
-    sequence_req: LocktimeRequirement,
+/// A way to spend the script, found by [`analyze_script`].
+#[derive(Debug, Clone)]
+pub struct SpendingPath {
+    /// Amount of initial stack items used.
+    pub stack_size: u32,
+    /// The initial stack must have exactly `stack_size` items. Without
+    /// [`VerifyFlags::CLEANSTACK`], legacy scripts can be spent with more.
+    pub exact_stack_size: bool,
+    /// Standardness limit of `stack_size`, only P2WSH inputs have one.
+    pub stack_size_limit: Option<u32>,
+    pub spending_conditions: Vec<Expr>,
+    pub locktime_req: LocktimeRequirement,
+    pub sequence_req: LocktimeRequirement,
+    pub size: SizeEstimate,
+    pub sigops: SigOps,
+    /// Numeric ranges of stack items, derived from the spending conditions.
+    pub ranges: Vec<ItemRange>,
+    /// Inferred role and size bounds of every initial stack item.
+    pub items: Vec<ItemUsage>,
+    /// Branches taken to reach this path, in execution order.
+    pub branches: Vec<BranchDecision>,
+    /// The sets of keys that can sign the `OP_CHECKMULTISIG`s of this path that need fewer
+    /// signatures than they have keys, with [`AnalysisOptions::max_key_sets`].
+    pub key_sets: Vec<MultisigKeySets>,
+    /// The sighash types the signatures of this path can use.
+    pub sighash: Vec<SighashRequirement>,
+    /// The scriptCode of every executed signature check, in execution order.
+    pub script_codes: Vec<ScriptCode>,
 }
 
-impl fmt::Display for AnalyzerResult {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let stack_size = self.stack_size;
-
-        let mut tmp;
-        let stack_items_str = if !self.spending_conditions.is_empty() {
-            tmp = String::new();
-            for s in &self.spending_conditions {
-                write!(tmp, "\n{s}").unwrap();
-            }
-            &tmp
+impl SpendingPath {
+    /// Minimum version of the spending transaction. Sequence requirements (BIP 68) only apply to
+    /// transactions with version 2 or higher.
+    pub fn min_tx_version(&self) -> u32 {
+        if self.sequence_req.is_required() {
+            2
         } else {
-            " none"
-        };
-
-        let locktime = self.locktime_req.locktime_requirement_to_string(false);
-        let sequence = self.sequence_req.locktime_requirement_to_string(true);
-
-        let locktime_str = match &locktime {
-            Some(s) => s,
-            None => "none",
-        };
-        let sequence_str = match (&sequence, &locktime) {
-            (Some(s), _) => s,
-            (None, Some(_)) => "non-final (not 0xffffffff)",
-            (None, None) => "none",
-        };
-
-        write!(
-            f,
-            "Stack size: {stack_size}\n\
-            Stack item requirements:\
-            {stack_items_str}\n\
-            Locktime requirement: {locktime_str}\n\
-            Sequence requirement: {sequence_str}"
-        )
+            1
+        }
     }
-}
 
-type Results<'a> = Vec<ScriptAnalyzer<'a>>;
+    /// Returns true if this path requires no signature, hash preimage or timelock, so anyone who
+    /// sees the script can spend it.
+    pub fn is_anyone_can_spend(&self) -> bool {
+        // A known key that anyone can sign for, or a known hash that anyone can provide the
+        // preimage of.
+        fn is_public(expr: &Expr) -> bool {
+            matches!(expr, Expr::Bytes(b) if known_constant(b.as_ref()).is_some_and(|c| c.anyone_can_use))
+        }
 
-#[cfg(feature = "threads")]
-type ResultsMut<'a, 'b, 'f> = &'b std::sync::Mutex<Results<'a>>;
+        fn is_hash(expr: &Expr) -> bool {
+            matches!(
+                expr,
+                Expr::Op(op) if matches!(
+                    op.args,
+                    OpExprArgs::Args1(
+                        Opcode1::OP_RIPEMD160 | Opcode1::OP_SHA1 | Opcode1::OP_SHA256,
+                        _
+                    )
+                )
+            )
+        }
 
-#[cfg(not(feature = "threads"))]
-type ResultsMut<'a, 'b, 'f> = &'f mut Results<'a>;
+        // The highest value a sum of `OP_CHECKSIGADD`s (or `OP_CHECKSIG`s added together) can
+        // reach when only the checks anyone can satisfy succeed, `None` if `expr` is not such a
+        // sum.
+        fn unsecured_sum(expr: &Expr) -> Option<i64> {
+            match expr {
+                Expr::Bytes(b) => decode_int(b.as_ref(), 4).ok(),
+                Expr::Op(op) => match &op.args {
+                    OpExprArgs::Args2(Opcode2::OP_CHECKSIG, args) => {
+                        Some(is_public(&args[1]).into())
+                    }
+                    OpExprArgs::Args2(Opcode2::OP_ADD, args) => {
+                        Some(unsecured_sum(&args[0])? + unsecured_sum(&args[1])?)
+                    }
+                    _ => None,
+                },
+                Expr::Stack(_) => None,
+            }
+        }
 
-#[cfg(feature = "threads")]
-type ThreadPool<'a, 'f> = &'f crate::threadpool::ThreadPool<'a>;
+        // Returns true if `expr >= threshold` needs a check that not everyone can satisfy.
+        fn is_secured_threshold(expr: &Expr, threshold: &Expr, offset: i64) -> bool {
+            let Expr::Bytes(threshold) = threshold else {
+                return false;
+            };
+            let (Ok(threshold), Some(max)) =
+                (decode_int(threshold.as_ref(), 4), unsecured_sum(expr))
+            else {
+                return false;
+            };
+            threshold + offset > max
+        }
 
-#[cfg(not(feature = "threads"))]
-type ThreadPool<'a, 'f> = ();
+        fn is_zero(expr: &Expr) -> bool {
+            matches!(expr, Expr::Bytes(b) if decode_int(b.as_ref(), 4) == Ok(0))
+        }
 
-pub fn analyze_script(
-    script: &Script<'_>,
-    ctx: ScriptContext,
-    worker_threads: usize,
-) -> Result<String, String> {
-    #[cfg(not(feature = "threads"))]
-    assert_eq!(
-        worker_threads, 0,
-        "Feature \"threads\" disabled, set `worker_threads` to 0 or enable the feature"
-    );
+        // Returns true if `expr == constant` needs a check that not everyone can satisfy.
+        fn is_secured_equal(expr: &Expr, constant: &Expr) -> bool {
+            let Expr::Bytes(c) = constant else {
+                return false;
+            };
+            (is_hash(expr) && !is_public(constant)) || (c.is_true() && is_secured(expr))
+        }
 
-    for &op in &**script {
-        if let ScriptElem::Op(op) = op {
-            if op.is_disabled() {
-                return Err(format!(
-                    "Script error: {}",
-                    ScriptError::SCRIPT_ERR_DISABLED_OPCODE
-                ));
+        // Returns true if `expr` can only be true when the spender provides a signature or hash
+        // preimage that not everyone can provide. Checks whose success is not required, like
+        // one under `OP_NOT`, do not count.
+        fn is_secured(expr: &Expr) -> bool {
+            let Expr::Op(op) = expr else {
+                return false;
+            };
+            match &op.args {
+                OpExprArgs::Args2(Opcode2::OP_CHECKSIG, args) => !is_public(&args[1]),
+                OpExprArgs::Multisig(m) => {
+                    m.sigs().len() > m.keys().iter().filter(|k| is_public(k)).count()
+                }
+                OpExprArgs::Args1(Opcode1::OP_0NOTEQUAL, args) => is_secured(&args[0]),
+                OpExprArgs::Args2(Opcode2::OP_BOOLAND, args) => args.iter().any(is_secured),
+                OpExprArgs::Args2(Opcode2::OP_BOOLOR, args) => args.iter().all(is_secured),
+                OpExprArgs::Args2(opcode, args) => {
+                    let [a, b] = &**args;
+                    match opcode {
+                        Opcode2::OP_EQUAL => is_secured_equal(a, b) || is_secured_equal(b, a),
+                        Opcode2::OP_NUMEQUAL => {
+                            is_secured_equal(a, b)
+                                || is_secured_equal(b, a)
+                                || is_secured_threshold(a, b, 0)
+                                || is_secured_threshold(b, a, 0)
+                        }
+                        Opcode2::OP_NUMNOTEQUAL => {
+                            (is_zero(a) && is_secured(b)) || (is_zero(b) && is_secured(a))
+                        }
+                        Opcode2::OP_LESSTHANOREQUAL => is_secured_threshold(b, a, 0),
+                        Opcode2::OP_LESSTHAN => is_secured_threshold(b, a, 1),
+                        _ => false,
+                    }
+                }
+                _ => false,
             }
         }
+
+        !self.locktime_req.is_required()
+            && !self.sequence_req.is_required()
+            && !self.spending_conditions.iter().any(is_secured)
     }
+}
 
-    let analyzer = ScriptAnalyzer::from_script(script);
+impl fmt::Display for SpendingPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.report(ReportOptions::default()))
+    }
+}
 
-    #[cfg(feature = "threads")]
-    let results = {
-        let results = std::sync::Mutex::new(Vec::new());
+/// A branch taken on a forked path: the value the argument of a conditional opcode had. A path
+/// split on an `OP_BOOLOR` condition requires its first argument if `condition` is true, and its
+/// second otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BranchDecision {
+    pub origin: Origin,
+    pub condition: bool,
+    /// For an `OP_PICK` or `OP_ROLL` with an unknown index: the index this branch decides on.
+    /// The index is `pick_index` if `condition` is true, and a larger one otherwise.
+    pub pick_index: Option<usize>,
+}
 
-        std::thread::scope(|scope| {
-            let pool = crate::threadpool::ThreadPool::new(scope, worker_threads);
-            analyzer.analyze(&results, ctx, &pool);
-        });
+impl fmt::Display for BranchDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.pick_index {
+            Some(index) if self.condition => write!(f, "{} with index {index}", self.origin),
+            Some(index) => write!(f, "{} with an index other than {index}", self.origin),
+            None => write!(f, "{} with a {} argument", self.origin, self.condition),
+        }
+    }
+}
 
-        results.into_inner().unwrap()
-    };
+/// Orders paths in script order: the branch with a true argument comes first.
+pub(crate) fn cmp_branches(a: &[BranchDecision], b: &[BranchDecision]) -> Ordering {
+    a.iter()
+        .map(|b| !b.condition)
+        .cmp(b.iter().map(|b| !b.condition))
+}
 
-    #[cfg(not(feature = "threads"))]
-    let results = {
-        let mut results = Vec::new();
+/// Order of [`Analysis::paths`], see [`Analysis::sort_paths`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PathOrder {
+    /// Script order, by the branches taken. This is the order paths are returned in.
+    #[default]
+    Branches,
+    /// Smallest estimated input size first.
+    Size,
+    /// Fewest spending conditions first.
+    Conditions,
+}
 
-        analyzer.analyze(&mut results, ctx, ());
+/// A path that fails for every possible input, found by [`diagnose_script`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedPath {
+    /// Branches taken to reach the failure, in execution order.
+    pub branches: Vec<BranchDecision>,
+    /// [`SCRIPT_ERR_UNKNOWN_ERROR`](ScriptError::SCRIPT_ERR_UNKNOWN_ERROR) if no opcode fails,
+    /// but the conditions of the path contradict each other, as found by the analyzer or proven
+    /// by [`Analysis::solve`](crate::Analysis::solve).
+    pub error: ScriptError,
+}
 
-        results
-    };
+impl fmt::Display for FailedPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Branches:")?;
+        if self.branches.is_empty() {
+            write!(f, " none")?;
+        }
+        for branch in &self.branches {
+            write!(f, "\n{branch}")?;
+        }
+        write!(f, "\nError: {}", self.error)
+    }
+}
 
-    // TODO does not run on multiple threads yet
-    let mut results = results
-        .into_iter()
-        .filter_map(|mut a| {
-            a.calculate_locktime_requirements()
-                .ok()
-                .map(|(locktime_req, sequence_req)| AnalyzerResult {
-                    locktime_req,
-                    sequence_req,
-                    stack_size: a.stack.items_used(),
-                    spending_conditions: a.spending_conditions,
-                })
-        })
-        .peekable();
+/// The result of [`analyze_script`], displays as a human readable report.
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    /// The recognized script template, if any.
+    pub template: Option<ScriptTemplate>,
+    pub paths: Vec<SpendingPath>,
+    /// Paths that can never be spent, only collected by [`diagnose_script`].
+    pub failed_paths: Vec<FailedPath>,
+    pub stats: AnalysisStats,
+}
 
-    if results.peek().is_none() {
-        return Err("Script is unspendable".to_string());
+impl Analysis {
+    /// Sorts the spending paths, ties are kept in script order.
+    pub fn sort_paths(&mut self, order: PathOrder) {
+        self.paths
+            .sort_by(|a, b| cmp_branches(&a.branches, &b.branches));
+        match order {
+            PathOrder::Branches => {}
+            PathOrder::Size => self.paths.sort_by_key(|p| p.size.vsize),
+            PathOrder::Conditions => self.paths.sort_by_key(|p| p.spending_conditions.len()),
+        }
     }
 
-    let mut s = String::from("Spending paths:");
-    for res in results {
-        write!(s, "\n\n{res}").unwrap();
+    /// Aggregate over all spending paths.
+    pub fn summary(&self) -> Summary {
+        Summary::new(&self.paths)
     }
-    Ok(s)
 }
 
-#[derive(Clone)]
-pub struct ScriptAnalyzer<'a> {
-    stack: Stack,
-    altstack: Vec<Expr>,
-    spending_conditions: Vec<Expr>,
-    script: &'a Script<'a>,
-    script_offset: usize,
-    cs: ConditionStack,
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.report(ReportOptions::default()))
+    }
 }
 
-impl<'a> ScriptAnalyzer<'a> {
-    fn from_script(script: &'a Script<'a>) -> Self {
-        Self {
-            stack: Stack::new(),
-            altstack: Vec::new(),
-            spending_conditions: Vec::new(),
-            script,
-            script_offset: 0,
-            cs: ConditionStack::new(),
-        }
-    }
+type AnalyzerResult<'a> = Result<ScriptAnalyzer<'a>, FailedPath>;
 
-    fn calculate_locktime_requirements(
-        &mut self,
-    ) -> Result<(LocktimeRequirement, LocktimeRequirement), ScriptError> {
-        let mut locktime_requirement = LocktimeRequirement::new();
-        let mut sequence_requirement = LocktimeRequirement::new();
+/// Amount of spending conditions from which they are simplified in parallel.
+const PARALLEL_CONDITIONS: usize = 32;
 
-        let mut i = 0;
-        while i < self.spending_conditions.len() {
-            let expr = &self.spending_conditions[i];
-            if let Expr::Op(expr) = expr {
-                if let OpExprArgs::Args1(op, arg) = &expr.args {
-                    let arg = &arg[0];
+/// Simplification of a spending condition using another one, see [`rewrite_with`].
+enum Rewrite {
+    Remove,
+    Contradiction,
+    Replace(Expr),
+}
 
-                    if matches!(
-                        op,
-                        Opcode1::OP_CHECKLOCKTIMEVERIFY | Opcode1::OP_CHECKSEQUENCEVERIFY
-                    ) {
-                        let relative = expr.opcode() == opcodes::OP_CHECKSEQUENCEVERIFY;
-                        let r = if relative {
-                            &mut sequence_requirement
-                        } else {
-                            &mut locktime_requirement
-                        };
-                        if let Expr::Bytes(arg) = arg {
-                            let min_value = decode_int(arg, 5)?;
-                            if min_value < 0 {
-                                return Err(ScriptError::SCRIPT_ERR_NEGATIVE_LOCKTIME);
-                            } else if !relative && min_value > u32::MAX as i64 {
-                                return Err(ScriptError::SCRIPT_ERR_UNSATISFIED_LOCKTIME);
-                            }
-                            let mut min_value = min_value as u32;
-                            if relative {
-                                min_value &= SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK;
-                            }
-                            if let Some(ref mut req) = r.req {
-                                if !locktime_type_equals(*req, min_value, relative) {
-                                    return Err(ScriptError::SCRIPT_ERR_UNSATISFIED_LOCKTIME);
-                                }
-                                if *req < min_value {
-                                    *req = min_value;
-                                }
-                            } else {
-                                r.req = Some(min_value);
-                            }
-                        } else {
-                            r.exprs.push(arg.clone());
-                        }
+/// Simplifies `expr2` using the knowledge that `expr1` is true. `negated` is the id of `a` if
+/// `expr1` is `!a`.
+fn rewrite_with(
+    expr1: &Expr,
+    expr2: &Expr,
+    id1: ExprId,
+    id2: ExprId,
+    negated: Option<ExprId>,
+) -> Option<Rewrite> {
+    if id1 == id2 {
+        // (a && a) == a
+        return Some(Rewrite::Remove);
+    }
+    let Expr::Op(op) = expr1 else {
+        return None;
+    };
+    // have to write multiple nested if blocks for now https://github.com/rust-lang/rust/issues/53667
+    if let OpExprArgs::Args1(op, args) = &op.args {
+        if *op == Opcode1::OP_NOT || *op == Opcode1::OP_INTERNAL_NOT {
+            if negated == Some(id2) {
+                // (a && !a) == 0
+                return Some(Rewrite::Contradiction);
+            }
 
-                        self.spending_conditions.remove(i);
-                        continue;
+            if let Expr::Op(expr_args_0) = &args[0] {
+                if expr_args_0.opcode().returns_boolean() {
+                    // (!a && f(a)) -> f(false)
+
+                    let mut res = expr2.clone();
+                    if res.replace_all(&args[0], &encode_bool_expr(false)) {
+                        return Some(Rewrite::Replace(res));
                     }
                 }
             }
-
-            i += 1;
         }
+    }
+    if op.opcode().returns_boolean() {
+        // (a && f(a)) -> f(true)
 
-        Ok((locktime_requirement, sequence_requirement))
+        let mut res = expr2.clone();
+        if res.replace_all(expr1, &encode_bool_expr(true)) {
+            return Some(Rewrite::Replace(res));
+        }
     }
+    None
+}
 
-    fn eval_conditions(&mut self, ctx: ScriptContext) -> Result<(), ScriptError> {
-        let exprs = &mut self.spending_conditions;
-        'i: loop {
-            Expr::sort_recursive(exprs);
-            let mut j = 0;
-            'j: while j < exprs.len() {
-                let expr1 = &exprs[j];
-                if let Expr::Bytes(bytes) = expr1 {
-                    if decode_bool(bytes) {
-                        // TODO swap_remove is O(1) but then exprs is not sorted anymore
-                        exprs.remove(j);
-                        continue 'j;
-                    } else {
-                        // TODO expr1.error
-                        return Err(ScriptError::SCRIPT_ERR_UNKNOWN_ERROR);
-                    }
-                } else if let Expr::Op(op) = expr1 {
-                    if let OpExprArgs::Args2(Opcode2::OP_BOOLAND, args) = &op.args {
-                        // TODO no clone needed here
-                        let args = args.clone();
-                        exprs.remove(j);
-                        exprs.extend(args.into_iter());
-                        continue 'i;
-                    }
+/// Rewrites the spending conditions using the equalities among them: with `a == b` and
+/// `b == c`, every `a` and `b` is replaced with `c` and the equalities become `a == c` and
+/// `b == c`. Returns true if anything changed.
+fn propagate_equalities(
+    exprs: &mut Vec<Expr>,
+    interner: &mut ExprInterner,
+) -> Result<bool, ScriptError> {
+    let mut classes = EqualityClasses::new();
+    let mut members = Vec::new();
+    for expr in exprs.iter() {
+        if let Some([a, b]) = equality(expr) {
+            let (a, b) = (interner.intern(a), interner.intern(b));
+            if !classes.union(interner, a, b) {
+                // different constants
+                return Err(expr
+                    .error()
+                    .unwrap_or(ScriptError::SCRIPT_ERR_UNKNOWN_ERROR));
+            }
+            // the new equalities keep the origin and error of the one a member was first seen in
+            members.extend([
+                (a, expr.origin(), expr.error()),
+                (b, expr.origin(), expr.error()),
+            ]);
+        }
+    }
+    if members.is_empty() {
+        return Ok(false);
+    }
+    members.sort_by_key(|&(id, ..)| id);
+    members.dedup_by_key(|&mut (id, ..)| id);
+
+    let mut res: Vec<_> = exprs
+        .iter()
+        .filter(|expr| equality(expr).is_none())
+        .map(|expr| substitute(expr, interner, &mut classes))
+        .collect();
+    for (id, origin, error) in members {
+        let root = classes.find(id);
+        if root != id {
+            // the arguments can contain members of other classes
+            let mut member = interner.get(id).clone();
+            if let Expr::Op(op) = &mut member {
+                for arg in op.args_mut() {
+                    *arg = substitute(arg, interner, &mut classes);
                 }
-                let mut k = 0;
-                'k: while k < exprs.len() {
-                    if j == k {
-                        k += 1;
-                        continue 'k;
-                    }
-                    let expr2 = &exprs[k];
-                    if expr1 == expr2 {
-                        // (a && a) == a
-                        exprs.remove(k);
-                        continue 'i;
-                    }
-                    if let Expr::Op(op) = expr1 {
-                        // have to write multiple nested if blocks for now https://github.com/rust-lang/rust/issues/53667
-                        if let OpExprArgs::Args1(op, args) = &op.args {
-                            if *op == Opcode1::OP_NOT || *op == Opcode1::OP_INTERNAL_NOT {
-                                if &args[0] == expr2 {
-                                    // (a && !a) == 0
+            }
+            let args = [member, interner.get(root).clone()];
+            let mut equality = match error {
+                Some(error) => Opcode2::OP_EQUAL.expr_with_error(args, error),
+                None => Opcode2::OP_EQUAL.expr(args),
+            };
+            if let Some(origin) = origin {
+                equality.set_origin(origin);
+            }
+            res.push(equality);
+        }
+    }
+    Expr::sort_recursive(&mut res);
 
-                                    // TODO expr{1,2}.error
-                                    return Err(ScriptError::SCRIPT_ERR_UNKNOWN_ERROR);
-                                }
+    if res == *exprs {
+        return Ok(false);
+    }
+    *exprs = res;
+    Ok(true)
+}
 
-                                if let Expr::Op(expr_args_0) = &args[0] {
-                                    if expr_args_0.opcode().returns_boolean() {
-                                        // (!a && f(a)) -> f(false)
+/// The arguments of `expr` if it is `a == b`.
+fn equality(expr: &Expr) -> Option<&[Expr; 2]> {
+    match expr {
+        Expr::Op(op) => match &op.args {
+            OpExprArgs::Args2(Opcode2::OP_EQUAL, args) => Some(args),
+            _ => None,
+        },
+        _ => None,
+    }
+}
 
-                                        let mut res = expr2.clone();
-                                        if res.replace_all(&args[0], &encode_bool_expr(false)) {
-                                            exprs[k] = res;
-                                            continue 'i;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        if let OpExprArgs::Args2(Opcode2::OP_EQUAL, args) = &op.args {
-                            // (a == b && f(a)) -> f(b)
+/// Replaces every subexpression of `expr` with the representative of its class.
+fn substitute(expr: &Expr, interner: &mut ExprInterner, classes: &mut EqualityClasses) -> Expr {
+    let id = interner.intern(expr);
+    let root = classes.find(id);
+    if root != id {
+        let mut res = interner.get(root).clone();
+        if let Some(origin) = expr.origin() {
+            res.set_origin(origin);
+        }
+        return res;
+    }
+    let mut res = expr.clone();
+    if let Expr::Op(op) = &mut res {
+        for arg in op.args_mut() {
+            *arg = substitute(arg, interner, classes);
+        }
+    }
+    res
+}
 
-                            let mut res = expr2.clone();
-                            if res.replace_all(&args[0], &args[1]) {
-                                exprs[k] = res;
-                                continue 'i;
-                            }
-                        }
-                        if op.opcode().returns_boolean() {
-                            // (a && f(a)) -> f(true)
+/// Checks that the sizes of stack items that are used as signatures and public keys in
+/// signature checks that must succeed allow them to be valid.
+fn check_key_sizes(
+    conditions: &[Expr],
+    sizes: &[ItemRange],
+    ctx: ScriptContext,
+) -> Result<(), ScriptError> {
+    let size = |expr: &Expr| match expr {
+        Expr::Stack(s) => sizes
+            .iter()
+            .find(|r| r.stack_item == s.pos())
+            .map(|r| r.range),
+        _ => None,
+    };
+    let allows =
+        |range: NumRange, min: i64, max: i64| !range.intersect(NumRange { min, max }).is_empty();
 
-                            let mut res = expr2.clone();
-                            if res.replace_all(expr1, &encode_bool_expr(true)) {
-                                exprs[k] = res;
-                                continue 'i;
-                            }
-                        }
-                    }
+    for condition in conditions {
+        let Expr::Op(op) = condition else {
+            continue;
+        };
+        let OpExprArgs::Args2(Opcode2::OP_CHECKSIG, args) = &op.args else {
+            continue;
+        };
+        let [sig, pubkey] = &**args;
+        if ctx.version == ScriptVersion::SegwitV1 {
+            if size(pubkey).is_some_and(|r| r.max == 0) {
+                return Err(ScriptError::SCRIPT_ERR_PUBKEYTYPE);
+            }
+            if size(sig).is_some_and(|r| !allows(r, 64, 65)) {
+                return Err(ScriptError::SCRIPT_ERR_SCHNORR_SIG_SIZE);
+            }
+        } else {
+            // compressed or uncompressed
+            if size(pubkey).is_some_and(|r| !allows(r, 33, 33) && !allows(r, 65, 65)) {
+                return Err(if ctx.has(VerifyFlags::STRICTENC) {
+                    ScriptError::SCRIPT_ERR_PUBKEYTYPE
+                } else {
+                    ScriptError::SCRIPT_ERR_EVAL_FALSE
+                });
+            }
+            // DER signature with sighash byte, without DERSIG other encodings can be valid
+            if ctx.has(VerifyFlags::DERSIG) && size(sig).is_some_and(|r| !allows(r, 9, 73)) {
+                return Err(ScriptError::SCRIPT_ERR_SIG_DER);
+            }
+        }
+    }
 
-                    k += 1;
-                }
+    Ok(())
+}
 
-                if exprs[j].eval(ctx)? {
-                    continue 'i; // 'j
-                }
+/// Limits that apply while executing paths, see [`AnalysisOptions`].
+#[derive(Clone, Copy)]
+struct Limits {
+    max_paths: Option<usize>,
+    max_forks: Option<usize>,
+    max_expr_nodes: Option<usize>,
+    /// Time of [`Self::clock`] after which the analysis is aborted.
+    deadline: Option<Duration>,
+    clock: fn() -> Duration,
+}
 
-                j += 1;
-            }
+/// State shared by all threads analyzing a script.
+#[cfg(feature = "threads")]
+struct Shared {
+    limits: Limits,
+    stopped: std::sync::atomic::AtomicBool,
+    paths: std::sync::atomic::AtomicUsize,
+    forks: std::sync::atomic::AtomicUsize,
+    simplifications: std::sync::atomic::AtomicUsize,
+    peak_stack_depth: std::sync::atomic::AtomicUsize,
+    exceeded: std::sync::Mutex<Option<AnalysisLimit>>,
+}
 
-            break Ok(());
-        }
-    }
+/// Receives the analyzers of finished paths.
+#[cfg(feature = "threads")]
+#[derive(Clone)]
+struct Sink<'a, 'b> {
+    sender: std::sync::mpsc::Sender<AnalyzerResult<'a>>,
+    shared: &'b Shared,
+    pool: &'b ThreadPool,
+}
 
-    fn analyze<'b>(
-        mut self,
-        results: ResultsMut<'a, 'b, '_>,
-        ctx: ScriptContext,
-        pool: ThreadPool<'b, '_>,
-    ) {
-        if self.analyze_path(results, ctx, pool).is_err() {
+#[cfg(feature = "threads")]
+impl<'a> Sink<'a, '_> {
+    fn push(&mut self, res: AnalyzerResult<'a>) {
+        let paths = self
+            .shared
+            .paths
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        if paths >= self.limits().max_paths.unwrap_or(usize::MAX) {
+            self.exceed(AnalysisLimit::Paths);
             return;
         }
+        // the receiver is gone if the analysis was stopped
+        let _ = self.sender.send(res);
+    }
+
+    fn stopped(&self) -> bool {
+        self.shared
+            .stopped
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    fn limits(&self) -> &Limits {
+        &self.shared.limits
+    }
+
+    fn pool(&self) -> Option<&ThreadPool> {
+        Some(self.pool)
+    }
+
+    /// Counts a fork, returns false if there are too many.
+    fn count_fork(&mut self) -> bool {
+        let forks = self
+            .shared
+            .forks
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        forks < self.limits().max_forks.unwrap_or(usize::MAX)
+    }
 
-        if self.eval_conditions(ctx).is_err() {
+    /// Adds the counters of a finished path to the statistics.
+    fn record_path(&mut self, analyzer: &ScriptAnalyzer) {
+        self.shared.simplifications.fetch_add(
+            analyzer.simplifications,
+            std::sync::atomic::Ordering::Relaxed,
+        );
+        self.shared.peak_stack_depth.fetch_max(
+            analyzer.peak_stack_depth,
+            std::sync::atomic::Ordering::Relaxed,
+        );
+    }
+
+    fn exceed(&mut self, limit: AnalysisLimit) {
+        self.shared.exceeded.lock().unwrap().get_or_insert(limit);
+        self.shared
+            .stopped
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+/// Receives the analyzers of finished paths.
+#[cfg(not(feature = "threads"))]
+struct Sink<'a, 'b> {
+    callback: &'b mut dyn FnMut(AnalyzerResult<'a>) -> ControlFlow<()>,
+    limits: Limits,
+    stopped: bool,
+    paths: usize,
+    forks: usize,
+    simplifications: usize,
+    peak_stack_depth: usize,
+    exceeded: Option<AnalysisLimit>,
+}
+
+#[cfg(not(feature = "threads"))]
+impl<'a> Sink<'a, '_> {
+    fn push(&mut self, res: AnalyzerResult<'a>) {
+        self.paths += 1;
+        if self.paths > self.limits.max_paths.unwrap_or(usize::MAX) {
+            self.exceed(AnalysisLimit::Paths);
             return;
         }
+        if (self.callback)(res).is_break() {
+            self.stopped = true;
+        }
+    }
 
-        #[cfg(feature = "threads")]
-        let mut results = results.lock().unwrap();
+    fn stopped(&self) -> bool {
+        self.stopped
+    }
 
-        results.push(self);
+    fn limits(&self) -> &Limits {
+        &self.limits
     }
 
-    fn analyze_path<'b>(
-        &mut self,
-        results: ResultsMut<'a, 'b, '_>,
-        ctx: ScriptContext,
-        pool: ThreadPool<'b, '_>,
-    ) -> Result<(), ScriptError> {
-        while self.script_offset < self.script.len() {
-            let f_exec = self.cs.all_true();
-            let op = self.script[self.script_offset];
-            self.script_offset += 1;
-
-            if !f_exec {
-                match op {
-                    ScriptElem::Bytes(_) => {
-                        continue;
+    fn pool(&self) -> Option<&ThreadPool> {
+        None
+    }
+
+    /// Counts a fork, returns false if there are too many.
+    fn count_fork(&mut self) -> bool {
+        self.forks += 1;
+        self.forks <= self.limits.max_forks.unwrap_or(usize::MAX)
+    }
+
+    /// Adds the counters of a finished path to the statistics.
+    fn record_path(&mut self, analyzer: &ScriptAnalyzer) {
+        self.simplifications += analyzer.simplifications;
+        self.peak_stack_depth = self.peak_stack_depth.max(analyzer.peak_stack_depth);
+    }
+
+    fn exceed(&mut self, limit: AnalysisLimit) {
+        self.exceeded.get_or_insert(limit);
+        self.stopped = true;
+    }
+}
+
+/// Analyzes `analyzer` and its forks, forks are shared with idle worker threads.
+#[cfg(feature = "threads")]
+fn run_worklist<'a, 'b>(
+    analyzer: ScriptAnalyzer<'a>,
+    mut sink: Sink<'a, 'b>,
+    ctx: ScriptContext,
+    scope: &PoolScope<'b>,
+) where
+    'a: 'b,
+{
+    let mut work = vec![analyzer];
+    while let Some(analyzer) = work.pop() {
+        if sink.stopped() {
+            break;
+        }
+        analyzer.run_path(&mut sink, ctx, &mut work);
+
+        if scope.has_workers() {
+            // continue with the last fork on this thread
+            let keep = work.pop();
+            for fork in work.drain(..) {
+                let sink = sink.clone();
+                let scope_ = scope.clone();
+                scope.spawn(move || run_worklist(fork, sink, ctx, &scope_));
+            }
+            work.extend(keep);
+        }
+    }
+}
+
+/// Checks that apply to the whole script, regardless of which path is executed.
+pub(crate) fn check_script(script: ScriptRef<'_>, ctx: ScriptContext) -> Result<(), AnalyzeError> {
+    if ctx.version != ScriptVersion::SegwitV1 && script.serialized_len() > MAX_SCRIPT_SIZE {
+        return Err(AnalyzeError::ScriptSize);
+    }
+
+    let mut op_count = 0;
+    for Instruction { offset, elem, .. } in script.instructions() {
+        match elem {
+            ScriptElem::Op(op) => {
+                if op.name().is_none() {
+                    return Err(AnalyzeError::UnknownOpcode { opcode: op, offset });
+                }
+                if op.is_disabled() {
+                    return Err(AnalyzeError::DisabledOpcode { opcode: op, offset });
+                }
+                if op > opcodes::OP_16 {
+                    op_count += 1;
+                }
+            }
+            ScriptElem::Bytes(b) => {
+                if b.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                    return Err(AnalyzeError::PushSize { offset });
+                }
+            }
+        }
+    }
+
+    if ctx.version != ScriptVersion::SegwitV1 && op_count > MAX_OPS_PER_SCRIPT {
+        return Err(AnalyzeError::OpCount);
+    }
+
+    Ok(())
+}
+
+/// Runs the analyzer and all its forks, `callback` is called for every finished path until it
+/// returns [`ControlFlow::Break`]. Only the counters of the engine are set in the returned
+/// statistics, paths are counted by the caller.
+fn run_analyzer<'a>(
+    analyzer: ScriptAnalyzer<'a>,
+    ctx: ScriptContext,
+    opts: &AnalysisOptions,
+    mut callback: impl FnMut(AnalyzerResult<'a>) -> ControlFlow<()>,
+) -> Result<AnalysisStats, AnalysisLimit> {
+    let limits = Limits {
+        max_paths: opts.max_paths,
+        max_forks: opts.max_forks,
+        max_expr_nodes: opts.max_expr_nodes,
+        deadline: opts.timeout.map(|timeout| (opts.clock)() + timeout),
+        clock: opts.clock,
+    };
+
+    #[cfg(not(feature = "threads"))]
+    assert_eq!(
+        opts.worker_threads, 0,
+        "Feature \"threads\" disabled, set `worker_threads` to 0 or enable the feature"
+    );
+
+    #[cfg(feature = "threads")]
+    let (exceeded, stats) = {
+        let shared = Shared {
+            limits,
+            stopped: std::sync::atomic::AtomicBool::new(false),
+            paths: std::sync::atomic::AtomicUsize::new(0),
+            forks: std::sync::atomic::AtomicUsize::new(0),
+            simplifications: std::sync::atomic::AtomicUsize::new(0),
+            peak_stack_depth: std::sync::atomic::AtomicUsize::new(0),
+            exceeded: std::sync::Mutex::new(None),
+        };
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        let owned_pool;
+        let pool = match opts.pool {
+            Some(pool) => pool,
+            None => {
+                owned_pool = ThreadPool::new(opts.worker_threads);
+                &owned_pool
+            }
+        };
+
+        let mut deliver = |res| {
+            if shared.stopped.load(std::sync::atomic::Ordering::Relaxed) {
+                return ControlFlow::Break(());
+            }
+            let flow = callback(res);
+            if flow.is_break() {
+                shared
+                    .stopped
+                    .store(true, std::sync::atomic::Ordering::Relaxed);
+            }
+            flow
+        };
+
+        pool.scope(|scope| {
+            let mut sink = Sink {
+                sender,
+                shared: &shared,
+                pool,
+            };
+
+            if !scope.has_workers() {
+                // jobs would run to completion before the first result is received, analyze on
+                // this thread and pass on the results of every path as soon as it finishes
+                let mut work = vec![analyzer];
+                while let Some(analyzer) = work.pop() {
+                    if sink.stopped() {
+                        break;
+                    }
+                    analyzer.run_path(&mut sink, ctx, &mut work);
+                    if receiver.try_iter().try_for_each(&mut deliver).is_break() {
+                        break;
+                    }
+                }
+                return;
+            }
+
+            let scope_ = scope.clone();
+            // the receiver is done when all jobs, which hold a sender, are done
+            scope.spawn(move || run_worklist(analyzer, sink, ctx, &scope_));
+
+            let _ = receiver.into_iter().try_for_each(deliver);
+        });
+
+        let stats = AnalysisStats {
+            forks: shared.forks.into_inner(),
+            simplifications: shared.simplifications.into_inner(),
+            peak_stack_depth: shared.peak_stack_depth.into_inner(),
+            ..Default::default()
+        };
+        (shared.exceeded.into_inner().unwrap(), stats)
+    };
+
+    #[cfg(not(feature = "threads"))]
+    let (exceeded, stats) = {
+        let mut sink = Sink {
+            callback: &mut callback,
+            limits,
+            stopped: false,
+            paths: 0,
+            forks: 0,
+            simplifications: 0,
+            peak_stack_depth: 0,
+            exceeded: None,
+        };
+
+        let mut work = vec![analyzer];
+        while let Some(analyzer) = work.pop() {
+            if sink.stopped() {
+                break;
+            }
+            analyzer.run_path(&mut sink, ctx, &mut work);
+        }
+
+        let stats = AnalysisStats {
+            forks: sink.forks,
+            simplifications: sink.simplifications,
+            peak_stack_depth: sink.peak_stack_depth,
+            ..Default::default()
+        };
+        (sink.exceeded, stats)
+    };
+
+    match exceeded {
+        Some(limit) => Err(limit),
+        None => Ok(stats),
+    }
+}
+
+/// Runs the analyzer and collects all paths in script order.
+fn collect_results<'a>(
+    analyzer: ScriptAnalyzer<'a>,
+    ctx: ScriptContext,
+    worker_threads: usize,
+) -> Vec<AnalyzerResult<'a>> {
+    let opts = AnalysisOptions {
+        worker_threads,
+        ..Default::default()
+    };
+    let mut results = Vec::new();
+    // there are no limits, this can not fail
+    let _ = run_analyzer(analyzer, ctx, &opts, |res| {
+        results.push(res);
+        ControlFlow::Continue(())
+    });
+
+    // the order paths finish in depends on scheduling
+    fn branches<'r>(res: &'r AnalyzerResult) -> &'r [BranchDecision] {
+        match res {
+            Ok(a) => &a.branches,
+            Err(p) => &p.branches,
+        }
+    }
+    results.sort_by(|a, b| cmp_branches(branches(a), branches(b)));
+
+    results
+}
+
+/// Options for [`analyze_script`] and related functions. The limits protect against scripts
+/// that take too long to analyze, exceeding one aborts the analysis with
+/// [`AnalyzeError::LimitExceeded`].
+#[derive(Debug, Clone, Copy)]
+pub struct AnalysisOptions {
+    /// Amount of threads to analyze paths on, 0 analyzes on the calling thread. Only supported
+    /// with the `threads` feature.
+    pub worker_threads: usize,
+    /// Pool to analyze paths on instead of starting `worker_threads` new threads, for example
+    /// [`ThreadPool::global`].
+    #[cfg(feature = "threads")]
+    pub pool: Option<&'static ThreadPool>,
+    /// Maximum amount of paths, including failed ones.
+    pub max_paths: Option<usize>,
+    /// Maximum amount of times execution forks on an unknown value.
+    pub max_forks: Option<usize>,
+    /// Maximum amount of nodes of an expression on the stack.
+    pub max_expr_nodes: Option<usize>,
+    /// Lists the sets of keys that can sign every `OP_CHECKMULTISIG` with fewer signatures than
+    /// keys, see [`SpendingPath::key_sets`]. Multisigs with more possible sets than this are not
+    /// expanded, `None` expands none.
+    pub max_key_sets: Option<usize>,
+    pub timeout: Option<Duration>,
+    /// Monotonic clock used for `timeout` and [`AnalysisStats::wall_time`]. The default uses
+    /// [`std::time::Instant`]. That is not available on `wasm32-unknown-unknown`, where the
+    /// default clock stands still: timeouts never expire and the wall time is zero.
+    pub clock: fn() -> Duration,
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        Self {
+            worker_threads: 0,
+            #[cfg(feature = "threads")]
+            pool: None,
+            max_paths: None,
+            max_forks: None,
+            max_expr_nodes: None,
+            max_key_sets: None,
+            timeout: None,
+            clock: default_clock,
+        }
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+fn default_clock() -> Duration {
+    Duration::ZERO
+}
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+fn default_clock() -> Duration {
+    static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    START.get_or_init(std::time::Instant::now).elapsed()
+}
+
+/// A finished path, reported by [`analyze_script_with`].
+pub type PathResult = Result<SpendingPath, FailedPath>;
+
+pub fn analyze_script<'a>(
+    script: impl Into<ScriptRef<'a>>,
+    ctx: ScriptContext,
+    opts: AnalysisOptions,
+) -> Result<Analysis, AnalyzeError> {
+    ScriptAnalyzer::from_script(script.into()).analyze(ctx, opts)
+}
+
+/// Returns [`AnalyzeError::Unspendable`] if there are no spending paths, failed paths are
+/// dropped.
+fn require_spendable(analysis: Analysis) -> Result<Analysis, AnalyzeError> {
+    if analysis.paths.is_empty() {
+        return Err(AnalyzeError::Unspendable {
+            per_path_errors: analysis.failed_paths.iter().map(|p| p.error).collect(),
+        });
+    }
+
+    Ok(Analysis {
+        failed_paths: Vec::new(),
+        ..analysis
+    })
+}
+
+/// Like [`analyze_script`], but also reports the paths that fail, with the branches that lead to
+/// them. An unspendable script is not an error, the analysis will have no spending paths.
+pub fn diagnose_script<'a>(
+    script: impl Into<ScriptRef<'a>>,
+    ctx: ScriptContext,
+    opts: AnalysisOptions,
+) -> Result<Analysis, AnalyzeError> {
+    ScriptAnalyzer::from_script(script.into()).diagnose(ctx, opts)
+}
+
+/// Calls `callback` for every path as soon as it is analyzed, in no particular order. Returning
+/// [`ControlFlow::Break`] stops the analysis, paths that are still being analyzed are discarded.
+pub fn analyze_script_with<'a>(
+    script: impl Into<ScriptRef<'a>>,
+    ctx: ScriptContext,
+    opts: AnalysisOptions,
+    callback: impl FnMut(PathResult) -> ControlFlow<()>,
+) -> Result<AnalysisStats, AnalyzeError> {
+    run_with(
+        ScriptAnalyzer::from_script(script.into()),
+        ctx,
+        opts,
+        callback,
+    )
+}
+
+/// [`analyze_script_with`] starting from the state of `analyzer`.
+fn run_with(
+    analyzer: ScriptAnalyzer<'_>,
+    ctx: ScriptContext,
+    opts: AnalysisOptions,
+    mut callback: impl FnMut(PathResult) -> ControlFlow<()>,
+) -> Result<AnalysisStats, AnalyzeError> {
+    if cfg!(not(feature = "threads")) && opts.worker_threads != 0 {
+        return Err(AnalyzeError::ThreadingUnavailable);
+    }
+
+    let start = (opts.clock)();
+    let script = analyzer.script;
+    check_script(script, ctx)?;
+
+    let mut paths = 0;
+    let mut pruned = AnalysisStats::default();
+    // TODO paths are converted on the calling thread
+    let mut stats = run_analyzer(analyzer, ctx, &opts, |res| {
+        paths += 1;
+        let res = res.and_then(|mut a| {
+            a.take_spending_path(script, ctx)
+                .map(|mut path| {
+                    if let Some(max) = opts.max_key_sets {
+                        path.key_sets = multisig_key_sets(&path.spending_conditions, ctx, max);
                     }
-                    ScriptElem::Op(opcode) => {
-                        if opcode < opcodes::OP_IF || opcode > opcodes::OP_ENDIF {
-                            continue;
+                    path
+                })
+                .map_err(|error| FailedPath {
+                    branches: a.branches,
+                    error,
+                })
+        });
+        if let Err(path) = &res {
+            pruned.count_pruned(path.error);
+        }
+        callback(res)
+    })
+    .map_err(AnalyzeError::LimitExceeded)?;
+
+    stats.paths_explored = paths;
+    stats.paths_pruned = pruned.paths_pruned;
+    stats.sort_pruned();
+    stats.wall_time = (opts.clock)().saturating_sub(start);
+
+    Ok(stats)
+}
+
+fn collect_analysis(
+    analyzer: ScriptAnalyzer<'_>,
+    ctx: ScriptContext,
+    opts: AnalysisOptions,
+) -> Result<Analysis, AnalyzeError> {
+    let script = analyzer.script();
+    let mut paths = Vec::new();
+    let mut failed_paths = Vec::new();
+    let stats = run_with(analyzer, ctx, opts, |res| {
+        match res {
+            Ok(path) => paths.push(path),
+            Err(path) => failed_paths.push(path),
+        }
+        ControlFlow::Continue(())
+    })?;
+
+    failed_paths.sort_by(|a: &FailedPath, b| cmp_branches(&a.branches, &b.branches));
+    let mut analysis = Analysis {
+        template: classify(script),
+        paths,
+        failed_paths,
+        stats,
+    };
+    analysis.sort_paths(PathOrder::Branches);
+
+    Ok(analysis)
+}
+
+/// The outcome of a successful [`execute_script`] call.
+#[derive(Debug, Clone)]
+pub struct ExecutionResult {
+    /// The stack after execution, bottom item first.
+    pub final_stack: Vec<Expr>,
+    /// Conditions that can not be checked without the spending transaction, like signature
+    /// checks and locktimes.
+    pub unverified_conditions: Vec<Expr>,
+}
+
+/// Executes `script` with the concrete initial stack `witness` (first item at the bottom, in the
+/// order witness items appear in a transaction).
+///
+/// When the control flow depends on a signature check that can not be verified, every possible
+/// path is executed and the first successful one is returned. Otherwise
+/// [`AnalyzeError::Unspendable`] is returned with the error of every path, in script order.
+pub fn execute_script<'a>(
+    script: impl Into<ScriptRef<'a>>,
+    ctx: ScriptContext,
+    witness: &[&[u8]],
+    worker_threads: usize,
+) -> Result<ExecutionResult, AnalyzeError> {
+    if cfg!(not(feature = "threads")) && worker_threads != 0 {
+        return Err(AnalyzeError::ThreadingUnavailable);
+    }
+
+    let script = script.into();
+    check_script(script, ctx)?;
+
+    let stack = Stack::from_elements(witness.iter().map(|item| Expr::bytes(item)).collect());
+    let analyzer = ScriptAnalyzer::from_script_and_stack(script, stack);
+
+    let mut per_path_errors = Vec::new();
+    for res in collect_results(analyzer, ctx, worker_threads) {
+        match res
+            .map_err(|p| p.error)
+            .and_then(|a| a.into_execution_result(ctx))
+        {
+            Ok(res) => return Ok(res),
+            Err(err) => per_path_errors.push(err),
+        }
+    }
+
+    Err(AnalyzeError::Unspendable { per_path_errors })
+}
+
+/// Stand-in for the amount of initial stack items, pushed by `OP_DEPTH`. It is replaced when a
+/// path finishes, see [`ScriptAnalyzer::resolve_depth`].
+const INITIAL_DEPTH: u32 = u32::MAX;
+
+/// Symbolic execution state of a single path through a script.
+///
+/// [`analyze_script`] starts with an empty stack where every item is unknown. Use
+/// [`ScriptAnalyzer::builder`] to start with known stack items or spending conditions, then
+/// either analyze all paths with [`analyze`](Self::analyze) or execute one element at a time with
+/// [`step`](Self::step).
+#[derive(Clone)]
+pub struct ScriptAnalyzer<'a> {
+    stack: Stack,
+    altstack: Vec<Expr>,
+    spending_conditions: Vec<Expr>,
+    script: ScriptRef<'a>,
+    script_offset: usize,
+    /// Byte offset of `script[script_offset]`.
+    byte_offset: usize,
+    /// Index of the next push in [`ScriptRef::push_opcodes`].
+    push_index: usize,
+    cs: ConditionStack,
+    op_count: usize,
+    sigops: u32,
+    /// Branches taken where execution forked.
+    branches: Vec<BranchDecision>,
+    peak_stack_depth: usize,
+    /// Spending conditions rewritten or removed by [`Self::eval_conditions`].
+    simplifications: usize,
+    /// The last executed `OP_CODESEPARATOR`.
+    code_separator: Option<Origin>,
+    script_codes: Vec<ScriptCode>,
+    /// The index the next `OP_PICK` or `OP_ROLL` with an unknown index takes.
+    pick_index: usize,
+    /// Largest size of the stack and altstack after an opcode, minus the initial stack items
+    /// used at that point. The initial stack items that were not used yet are added when the
+    /// path finishes.
+    peak_stack_size: i64,
+}
+
+/// Builder for a [`ScriptAnalyzer`], returned by [`ScriptAnalyzer::builder`].
+#[derive(Clone)]
+pub struct ScriptAnalyzerBuilder<'a> {
+    script: ScriptRef<'a>,
+    /// Known initial stack items, top of the stack first.
+    known_items: Vec<Option<Expr>>,
+    spending_conditions: Vec<Expr>,
+}
+
+impl<'a> ScriptAnalyzerBuilder<'a> {
+    /// Sets the initial stack item at `pos` (0 is the top of the stack, the last witness item)
+    /// to `value`. Items that are not set remain unknown.
+    pub fn stack_item(mut self, pos: u32, value: Expr) -> Self {
+        let pos = pos as usize;
+        if self.known_items.len() <= pos {
+            self.known_items.resize(pos + 1, None);
+        }
+        self.known_items[pos] = Some(value);
+        self
+    }
+
+    /// Adds a condition that is assumed to hold on every path, for example a restriction on a
+    /// stack item that is enforced elsewhere. It is reported and simplified like the conditions
+    /// found by the analysis.
+    pub fn spending_condition(mut self, condition: Expr) -> Self {
+        self.spending_conditions.push(condition);
+        self
+    }
+
+    pub fn build(self) -> ScriptAnalyzer<'a> {
+        let known_items = self
+            .known_items
+            .into_iter()
+            .enumerate()
+            .map(|(pos, item)| item.unwrap_or_else(|| Expr::stack(pos as u32)))
+            .collect();
+        let mut analyzer = ScriptAnalyzer::from_script_and_stack(
+            self.script,
+            Stack::with_known_items(known_items),
+        );
+        analyzer.spending_conditions = self.spending_conditions;
+        analyzer
+    }
+}
+
+impl<'a> ScriptAnalyzer<'a> {
+    pub fn builder(script: impl Into<ScriptRef<'a>>) -> ScriptAnalyzerBuilder<'a> {
+        ScriptAnalyzerBuilder {
+            script: script.into(),
+            known_items: Vec::new(),
+            spending_conditions: Vec::new(),
+        }
+    }
+
+    pub(crate) fn from_script(script: ScriptRef<'a>) -> Self {
+        Self::from_script_and_stack(script, Stack::new())
+    }
+
+    fn from_script_and_stack(script: ScriptRef<'a>, stack: Stack) -> Self {
+        Self {
+            stack,
+            altstack: Vec::new(),
+            spending_conditions: Vec::new(),
+            script,
+            script_offset: 0,
+            byte_offset: 0,
+            push_index: 0,
+            cs: ConditionStack::new(),
+            op_count: 0,
+            sigops: 0,
+            branches: Vec::new(),
+            peak_stack_depth: 0,
+            simplifications: 0,
+            code_separator: None,
+            script_codes: Vec::new(),
+            pick_index: 0,
+            peak_stack_size: i64::MIN,
+        }
+    }
+
+    /// The stack, top item last. Only items that have been touched are included, items below
+    /// are initial stack items.
+    pub fn stack(&self) -> &[Expr] {
+        self.stack.elements()
+    }
+
+    pub fn altstack(&self) -> &[Expr] {
+        &self.altstack
+    }
+
+    /// The (unsimplified) conditions collected so far on this path.
+    pub fn spending_conditions(&self) -> &[Expr] {
+        &self.spending_conditions
+    }
+
+    pub fn script(&self) -> &'a Script<'a> {
+        self.script.script
+    }
+
+    pub(crate) fn script_ref(&self) -> ScriptRef<'a> {
+        self.script
+    }
+
+    /// Index of the next element to execute.
+    pub fn script_offset(&self) -> usize {
+        self.script_offset
+    }
+
+    /// Byte offset of the next element to execute.
+    pub(crate) fn byte_offset(&self) -> usize {
+        self.byte_offset
+    }
+
+    /// Branches taken where execution forked.
+    pub fn branches(&self) -> &[BranchDecision] {
+        &self.branches
+    }
+
+    /// Returns false if the analyzer is in a branch that is not executed.
+    pub fn executing(&self) -> bool {
+        self.cs.all_true()
+    }
+
+    /// Analyzes this path and all paths forking from it, [`analyze_script`] does this for a path
+    /// starting at the beginning of the script with an unknown stack.
+    pub fn analyze(
+        self,
+        ctx: ScriptContext,
+        opts: AnalysisOptions,
+    ) -> Result<Analysis, AnalyzeError> {
+        collect_analysis(self, ctx, opts).and_then(require_spendable)
+    }
+
+    /// Like [`analyze`](Self::analyze), but also reports the failed paths, see
+    /// [`diagnose_script`].
+    pub fn diagnose(
+        self,
+        ctx: ScriptContext,
+        opts: AnalysisOptions,
+    ) -> Result<Analysis, AnalyzeError> {
+        collect_analysis(self, ctx, opts)
+    }
+
+    fn take_spending_path(
+        &mut self,
+        script: ScriptRef<'_>,
+        ctx: ScriptContext,
+    ) -> Result<SpendingPath, ScriptError> {
+        let (locktime_req, sequence_req) = self.calculate_locktime_requirements()?;
+        let stack_size = self.stack.items_used();
+        let size = SizeEstimate::new(
+            script,
+            ctx,
+            stack_size,
+            &self.spending_conditions,
+            locktime_req.exprs.iter().chain(&sequence_req.exprs),
+        );
+        let sigops = SigOps::new(ctx, self.sigops)?;
+        let ranges = item_ranges(&self.spending_conditions);
+        let items = ItemUsage::infer(
+            ctx,
+            stack_size,
+            &self.spending_conditions,
+            &ranges,
+            locktime_req.exprs.iter().chain(&sequence_req.exprs),
+        );
+        let sighash = sighash_requirements(ctx, &self.spending_conditions, &items);
+        let stack_size_limit = (ctx.version == ScriptVersion::SegwitV0
+            && ctx.has(VerifyFlags::STANDARD))
+        .then_some(MAX_STANDARD_P2WSH_STACK_ITEMS);
+        Ok(SpendingPath {
+            exact_stack_size: ctx.version != ScriptVersion::Legacy
+                || ctx.has(VerifyFlags::CLEANSTACK),
+            stack_size_limit,
+            ranges,
+            items,
+            locktime_req,
+            sequence_req,
+            stack_size,
+            spending_conditions: core::mem::take(&mut self.spending_conditions),
+            size,
+            sigops,
+            branches: self.branches.clone(),
+            key_sets: Vec::new(),
+            sighash,
+            script_codes: core::mem::take(&mut self.script_codes),
+        })
+    }
+
+    fn into_execution_result(mut self, ctx: ScriptContext) -> Result<ExecutionResult, ScriptError> {
+        // the script accessed more items than there were on the initial stack
+        if self.stack.items_used() != 0 {
+            return Err(ScriptError::SCRIPT_ERR_INVALID_STACK_OPERATION);
+        }
+
+        let unverified_conditions = self.spending_conditions.clone();
+        self.calculate_locktime_requirements()?;
+
+        let mut final_stack = self.stack.elements().to_vec();
+        for elem in &mut final_stack {
+            elem.eval_value(ctx)?;
+        }
+
+        Ok(ExecutionResult {
+            final_stack,
+            unverified_conditions,
+        })
+    }
+
+    fn calculate_locktime_requirements(
+        &mut self,
+    ) -> Result<(LocktimeRequirement, LocktimeRequirement), ScriptError> {
+        let mut locktime_requirement = LocktimeRequirement::new();
+        let mut sequence_requirement = LocktimeRequirement::new();
+
+        let mut i = 0;
+        while i < self.spending_conditions.len() {
+            let expr = &self.spending_conditions[i];
+            if let Expr::Op(expr) = expr {
+                if let OpExprArgs::Args1(op, arg) = &expr.args {
+                    let arg = &arg[0];
+
+                    if matches!(
+                        op,
+                        Opcode1::OP_CHECKLOCKTIMEVERIFY | Opcode1::OP_CHECKSEQUENCEVERIFY
+                    ) {
+                        let relative = expr.opcode() == opcodes::OP_CHECKSEQUENCEVERIFY;
+                        let r = if relative {
+                            &mut sequence_requirement
+                        } else {
+                            &mut locktime_requirement
+                        };
+                        if let Expr::Bytes(arg) = arg {
+                            let min_value = decode_int(arg, 5)?;
+                            if min_value < 0 {
+                                return Err(ScriptError::SCRIPT_ERR_NEGATIVE_LOCKTIME);
+                            } else if !relative && min_value > u32::MAX as i64 {
+                                return Err(ScriptError::SCRIPT_ERR_UNSATISFIED_LOCKTIME);
+                            }
+                            if relative && min_value & SEQUENCE_LOCKTIME_DISABLE_FLAG as i64 != 0 {
+                                // not a requirement, OP_CHECKSEQUENCEVERIFY is a NOP
+                                self.spending_conditions.remove(i);
+                                continue;
+                            }
+                            let mut min_value = min_value as u32;
+                            if relative {
+                                min_value &= SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK;
+                            }
+                            if let Some(ref mut req) = r.req {
+                                if !locktime_type_equals(*req, min_value, relative) {
+                                    return Err(ScriptError::SCRIPT_ERR_UNSATISFIED_LOCKTIME);
+                                }
+                                if *req < min_value {
+                                    *req = min_value;
+                                }
+                            } else {
+                                r.req = Some(min_value);
+                            }
+                        } else {
+                            r.exprs.push(arg.clone());
+                        }
+
+                        self.spending_conditions.remove(i);
+                        continue;
+                    }
+                }
+            }
+
+            i += 1;
+        }
+
+        // symbolic arguments can have a type and a minimum value too if their range is known,
+        // like a stack item that must be less than 500000000
+        for (r, relative) in [
+            (&mut locktime_requirement, false),
+            (&mut sequence_requirement, true),
+        ] {
+            let mut type_ = r.req.map(|req| LocktimeType::new(req, relative));
+            for expr in &r.exprs {
+                let range = expr_range(expr, &self.spending_conditions);
+                if range.is_some_and(|range| range.max < 0) {
+                    return Err(ScriptError::SCRIPT_ERR_NEGATIVE_LOCKTIME);
+                }
+                // negative arguments fail
+                let range = range.map(|range| NumRange {
+                    min: range.min.max(0),
+                    max: range.max,
+                });
+                r.expr_ranges.push(range);
+                let Some(range) = range else {
+                    continue;
+                };
+                let Some(t) = LocktimeType::of_range(range.min, range.max, relative) else {
+                    continue;
+                };
+                if type_.is_some_and(|known| known != t) {
+                    return Err(ScriptError::SCRIPT_ERR_UNSATISFIED_LOCKTIME);
+                }
+                type_ = Some(t);
+
+                let min = range.min as u32;
+                let min = if !relative {
+                    min
+                } else if (range.min ^ range.max) >> 16 == 0 {
+                    min & (SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK)
+                } else {
+                    // the lower bits can be anything
+                    min & SEQUENCE_LOCKTIME_TYPE_FLAG
+                };
+                r.req = Some(r.req.map_or(min, |req| req.max(min)));
+            }
+        }
+
+        Ok((locktime_requirement, sequence_requirement))
+    }
+
+    /// Simplifies the spending conditions. With many conditions, work is split over the workers
+    /// of `pool`.
+    pub(crate) fn eval_conditions(
+        &mut self,
+        ctx: ScriptContext,
+        pool: Option<&ThreadPool>,
+    ) -> Result<(), ScriptError> {
+        let pool = pool.filter(|pool| pool.worker_threads() != 0);
+        let exprs = &mut self.spending_conditions;
+        let simplifications = &mut self.simplifications;
+        // conditions are compared pairwise, ids make that O(1)
+        let mut interner = ExprInterner::new();
+        'i: loop {
+            Expr::sort_recursive(exprs);
+
+            let parallel = pool.filter(|_| exprs.len() >= PARALLEL_CONDITIONS);
+            if let Some(pool) = parallel {
+                let mut evaluated: Vec<_> = exprs.drain(..).map(|e| (e, Ok(false))).collect();
+                pool.for_each_mut(&mut evaluated, |(expr, res)| *res = expr.eval(ctx));
+                let mut changed = 0;
+                for (expr, res) in evaluated {
+                    changed += res? as usize;
+                    exprs.push(expr);
+                }
+                *simplifications += changed;
+                if changed != 0 {
+                    continue 'i;
+                }
+            }
+
+            if propagate_equalities(exprs, &mut interner)? {
+                *simplifications += 1;
+                continue 'i;
+            }
+
+            let mut ids: Vec<_> = exprs.iter().map(|e| interner.intern(e)).collect();
+            let mut j = 0;
+            'j: while j < exprs.len() {
+                let expr1 = &exprs[j];
+                if let Expr::Bytes(bytes) = expr1 {
+                    if decode_bool(bytes) {
+                        // TODO swap_remove is O(1) but then exprs is not sorted anymore
+                        exprs.remove(j);
+                        ids.remove(j);
+                        *simplifications += 1;
+                        continue 'j;
+                    } else {
+                        // TODO expr1.error
+                        return Err(ScriptError::SCRIPT_ERR_UNKNOWN_ERROR);
+                    }
+                } else if let Expr::Op(op) = expr1 {
+                    if let OpExprArgs::Args2(Opcode2::OP_BOOLAND, args) = &op.args {
+                        // cheap, the arguments are shared
+                        let args = args.clone();
+                        exprs.remove(j);
+                        exprs.extend(args.iter().cloned());
+                        *simplifications += 1;
+                        continue 'i;
+                    }
+                }
+                // id of `a` if expr1 is `!a`
+                let negated = match expr1 {
+                    Expr::Op(op) => match &op.args {
+                        OpExprArgs::Args1(Opcode1::OP_NOT | Opcode1::OP_INTERNAL_NOT, args) => {
+                            Some(interner.intern(&args[0]))
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                };
+
+                // the first rewrite of another condition using expr1
+                let rewrite = match parallel {
+                    Some(pool) => {
+                        let mut rewrites: Vec<_> = (0..exprs.len()).map(|k| (k, None)).collect();
+                        pool.for_each_mut(&mut rewrites, |(k, rewrite)| {
+                            if *k != j {
+                                *rewrite =
+                                    rewrite_with(expr1, &exprs[*k], ids[j], ids[*k], negated);
+                            }
+                        });
+                        rewrites
+                            .into_iter()
+                            .find_map(|(k, rewrite)| Some((k, rewrite?)))
+                    }
+                    None => (0..exprs.len()).filter(|&k| k != j).find_map(|k| {
+                        Some((k, rewrite_with(expr1, &exprs[k], ids[j], ids[k], negated)?))
+                    }),
+                };
+                match rewrite {
+                    Some((k, Rewrite::Remove)) => {
+                        exprs.remove(k);
+                        *simplifications += 1;
+                        continue 'i;
+                    }
+                    Some((_, Rewrite::Contradiction)) => {
+                        // TODO expr{1,2}.error
+                        return Err(ScriptError::SCRIPT_ERR_UNKNOWN_ERROR);
+                    }
+                    Some((k, Rewrite::Replace(res))) => {
+                        exprs[k] = res;
+                        *simplifications += 1;
+                        continue 'i;
+                    }
+                    None => {}
+                }
+
+                if parallel.is_none() && exprs[j].eval(ctx)? {
+                    *simplifications += 1;
+                    continue 'i; // 'j
+                }
+
+                j += 1;
+            }
+
+            if item_ranges(exprs).iter().any(|r| r.range.is_empty()) || numeric_contradiction(exprs)
+            {
+                // TODO error of the conflicting exprs
+                return Err(ScriptError::SCRIPT_ERR_UNKNOWN_ERROR);
+            }
+            let sizes = item_sizes(exprs);
+            if sizes.iter().any(|r| r.range.is_empty()) {
+                return Err(ScriptError::SCRIPT_ERR_UNKNOWN_ERROR);
+            }
+            check_key_sizes(exprs, &sizes, ctx)?;
+
+            break Ok(());
+        }
+    }
+
+    /// Analyzes this path, new forks are added to `forks`.
+    fn run_path(mut self, sink: &mut Sink<'a, '_>, ctx: ScriptContext, forks: &mut Vec<Self>) {
+        let res = self.analyze_path(sink, ctx, forks);
+        self.conclude_path(res, sink, ctx);
+    }
+
+    /// Simplifies the spending conditions of an executed path and sends the result. A path with
+    /// an `OP_BOOLOR` condition is split first, like `OP_IF` forks.
+    fn conclude_path(
+        mut self,
+        res: Result<(), ScriptError>,
+        sink: &mut Sink<'a, '_>,
+        ctx: ScriptContext,
+    ) {
+        let res = res.and_then(|()| self.eval_conditions(ctx, sink.pool()));
+        if res.is_ok() && !sink.stopped() {
+            if let Some(other) = self.split_disjunction() {
+                if !sink.count_fork() {
+                    sink.exceed(AnalysisLimit::Forks);
+                    return;
+                }
+                self.conclude_path(Ok(()), sink, ctx);
+                other.conclude_path(Ok(()), sink, ctx);
+                return;
+            }
+        }
+        sink.record_path(&self);
+
+        if sink.stopped() {
+            return;
+        }
+
+        sink.push(match res {
+            Ok(()) => Ok(self),
+            Err(error) => Err(FailedPath {
+                branches: self.branches,
+                error,
+            }),
+        });
+    }
+
+    /// Splits off the path of the second argument of the first `OP_BOOLOR` condition, this path
+    /// continues with the first argument.
+    fn split_disjunction(&mut self) -> Option<Self> {
+        let (i, origin, args) =
+            self.spending_conditions
+                .iter()
+                .enumerate()
+                .find_map(|(i, expr)| {
+                    let Expr::Op(op) = expr else {
+                        return None;
+                    };
+                    match &op.args {
+                        OpExprArgs::Args2(Opcode2::OP_BOOLOR, args) => {
+                            Some((i, expr.origin()?, args.clone()))
                         }
+                        _ => None,
                     }
+                })?;
+        let [first, second] = (*args).clone();
+
+        let mut other = self.clone();
+        // counted by this path
+        other.simplifications = 0;
+        self.spending_conditions[i] = first;
+        other.spending_conditions[i] = second;
+        self.branches.push(BranchDecision {
+            origin,
+            condition: true,
+            pick_index: None,
+        });
+        other.branches.push(BranchDecision {
+            origin,
+            condition: false,
+            pick_index: None,
+        });
+        Some(other)
+    }
+
+    fn analyze_path(
+        &mut self,
+        sink: &mut Sink<'a, '_>,
+        ctx: ScriptContext,
+        forks: &mut Vec<Self>,
+    ) -> Result<(), ScriptError> {
+        while !self.done() && !sink.stopped() {
+            let fork = self.step(ctx)?;
+            self.peak_stack_depth = self.peak_stack_depth.max(self.stack.len());
+
+            if let Err(limit) = self.check_limits(sink.limits()) {
+                sink.exceed(limit);
+                break;
+            }
+
+            if let Some(fork) = fork {
+                if !sink.count_fork() {
+                    sink.exceed(AnalysisLimit::Forks);
+                    break;
                 }
+
+                forks.push(fork);
+            }
+        }
+
+        self.finish(ctx)
+    }
+
+    fn check_limits(&self, limits: &Limits) -> Result<(), AnalysisLimit> {
+        if limits
+            .deadline
+            .is_some_and(|deadline| (limits.clock)() > deadline)
+        {
+            return Err(AnalysisLimit::Timeout);
+        }
+
+        if let Some(max) = limits.max_expr_nodes {
+            // only the last opcode can have created a larger expression
+            let new_exprs = self
+                .stack
+                .elements()
+                .last()
+                .into_iter()
+                .chain(self.spending_conditions.last());
+            if new_exprs.map(Expr::node_count).any(|n| n > max) {
+                return Err(AnalysisLimit::ExprNodes);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if all script elements have been executed.
+    pub fn done(&self) -> bool {
+        self.script_offset >= self.script.script.len()
+    }
+
+    /// Executes the next script element. If execution depends on an unknown value, the analyzer for
+    /// the alternative path is returned.
+    ///
+    /// Unlike [`analyze`](Self::analyze), checks that apply to the whole script, like the maximum script
+    /// size, are not done. [`ScriptDebugger`](crate::ScriptDebugger) does these.
+    pub fn step(&mut self, ctx: ScriptContext) -> Result<Option<Self>, ScriptError> {
+        let mut fork = None;
+        // the index taken by this path if an `OP_PICK` or `OP_ROLL` forked
+        let mut picked = None;
+
+        let f_exec = self.cs.all_true();
+        let op = self.script.script[self.script_offset];
+        let index = self.script_offset;
+        let offset = self.byte_offset;
+        self.script_offset += 1;
+        self.byte_offset += match op {
+            ScriptElem::Op(_) => 1,
+            ScriptElem::Bytes(b) => {
+                let opcode = self.script.push_opcodes().get(self.push_index).copied();
+                self.push_index += 1;
+                encoded_push_len(b, opcode)
             }
+        };
+        let conditions_len = self.spending_conditions.len();
+
+        if let ScriptElem::Op(opcode) = op {
+            if opcode > opcodes::OP_16 {
+                self.count_ops(ctx, 1)?;
+            }
+        }
 
+        if !f_exec {
             match op {
-                ScriptElem::Bytes(b) => self.stack.push(Expr::bytes(b)),
-                ScriptElem::Op(op) => match op {
-                    opcodes::OP_0 => self.stack.push(Expr::bytes(&[])),
-
-                    opcodes::OP_1NEGATE => self.stack.push(Expr::bytes(&[0x81])),
-
-                    opcodes::OP_1
-                    | opcodes::OP_2
-                    | opcodes::OP_3
-                    | opcodes::OP_4
-                    | opcodes::OP_5
-                    | opcodes::OP_6
-                    | opcodes::OP_7
-                    | opcodes::OP_8
-                    | opcodes::OP_9
-                    | opcodes::OP_10
-                    | opcodes::OP_11
-                    | opcodes::OP_12
-                    | opcodes::OP_13
-                    | opcodes::OP_14
-                    | opcodes::OP_15
-                    | opcodes::OP_16 => self.stack.push(Expr::bytes(&[op.opcode - 0x50])),
-
-                    opcodes::OP_NOP => {}
-
-                    opcodes::OP_IF | opcodes::OP_NOTIF => {
-                        if f_exec {
-                            let minimal_if = ctx.version == ScriptVersion::SegwitV1
-                                || (ctx.version == ScriptVersion::SegwitV0
-                                    && ctx.rules == ScriptRules::All);
-                            let [elem] = self.stack.pop();
-                            let mut fork = self.clone();
+                // OP_VERIF and OP_VERNOTIF fail even in a branch that is not executed
+                ScriptElem::Op(
+                    opcodes::OP_IF
+                    | opcodes::OP_NOTIF
+                    | opcodes::OP_VERIF
+                    | opcodes::OP_VERNOTIF
+                    | opcodes::OP_ELSE
+                    | opcodes::OP_ENDIF,
+                ) => {}
+                // including OP_RETURN
+                _ => return Ok(None),
+            }
+        }
+
+        match op {
+            ScriptElem::Bytes(b) => self.stack.push(Expr::bytes(b)),
+            ScriptElem::Op(op) => match op {
+                opcodes::OP_0 => self.stack.push(Expr::bytes(&[])),
+
+                opcodes::OP_1NEGATE => self.stack.push(Expr::bytes(&[0x81])),
+
+                opcodes::OP_1
+                | opcodes::OP_2
+                | opcodes::OP_3
+                | opcodes::OP_4
+                | opcodes::OP_5
+                | opcodes::OP_6
+                | opcodes::OP_7
+                | opcodes::OP_8
+                | opcodes::OP_9
+                | opcodes::OP_10
+                | opcodes::OP_11
+                | opcodes::OP_12
+                | opcodes::OP_13
+                | opcodes::OP_14
+                | opcodes::OP_15
+                | opcodes::OP_16 => self.stack.push(Expr::bytes(&[op.opcode - 0x50])),
+
+                opcodes::OP_NOP => {}
+
+                opcodes::OP_IF | opcodes::OP_NOTIF => {
+                    if f_exec {
+                        let minimal_if = ctx.version == ScriptVersion::SegwitV1
+                            || (ctx.version == ScriptVersion::SegwitV0
+                                && ctx.has(VerifyFlags::MINIMALIF));
+                        let error = if ctx.version == ScriptVersion::SegwitV1 {
+                            ScriptError::SCRIPT_ERR_TAPSCRIPT_MINIMALIF
+                        } else {
+                            ScriptError::SCRIPT_ERR_MINIMALIF
+                        };
+                        let [mut elem] = self.stack.pop();
+                        elem.eval_value(ctx)?;
+                        if let Expr::Bytes(b) = &elem {
+                            if minimal_if && !b.is_true() && !b.is_false() {
+                                return Err(error);
+                            }
+                            self.cs.push_back(decode_bool(b) == (op == opcodes::OP_IF));
+                        } else {
+                            let mut other = self.clone();
                             self.cs.push_back(op == opcodes::OP_IF);
-                            fork.cs.push_back(op != opcodes::OP_IF);
+                            other.cs.push_back(op != opcodes::OP_IF);
                             if minimal_if {
-                                let error = if ctx.version == ScriptVersion::SegwitV1 {
-                                    ScriptError::SCRIPT_ERR_TAPSCRIPT_MINIMALIF
-                                } else {
-                                    ScriptError::SCRIPT_ERR_MINIMALIF
-                                };
                                 self.spending_conditions
                                     .push(Opcode2::OP_EQUAL.expr_with_error(
-                                        Box::new([elem.clone(), encode_bool_expr(true)]),
-                                        error,
-                                    ));
-                                fork.spending_conditions
-                                    .push(Opcode2::OP_EQUAL.expr_with_error(
-                                        Box::new([elem, encode_bool_expr(false)]),
+                                        [elem.clone(), encode_bool_expr(true)],
                                         error,
                                     ));
+                                other.spending_conditions.push(
+                                    Opcode2::OP_EQUAL
+                                        .expr_with_error([elem, encode_bool_expr(false)], error),
+                                );
                             } else {
                                 self.spending_conditions.push(elem.clone());
-                                fork.spending_conditions
-                                    .push(Opcode1::OP_INTERNAL_NOT.expr(Box::new([elem])));
+                                other
+                                    .spending_conditions
+                                    .push(Opcode1::OP_INTERNAL_NOT.expr([elem]));
                             }
 
-                            #[cfg(feature = "threads")]
-                            {
-                                let pool_ = pool.clone();
-                                pool.submit_job(move || {
-                                    fork.analyze(results, ctx, &pool_);
-                                });
-                            }
-
-                            #[cfg(not(feature = "threads"))]
-                            fork.analyze(results, ctx, pool);
-                        } else {
-                            self.cs.push_back(false);
-                        }
-                    }
-
-                    opcodes::OP_ELSE => {
-                        if self.cs.empty() {
-                            return Err(ScriptError::SCRIPT_ERR_UNBALANCED_CONDITIONAL);
+                            fork = Some(other);
                         }
-                        self.cs.toggle_top();
+                    } else {
+                        self.cs.push_back(false);
                     }
+                }
 
-                    opcodes::OP_ENDIF => {
-                        if self.cs.empty() {
-                            return Err(ScriptError::SCRIPT_ERR_UNBALANCED_CONDITIONAL);
-                        }
-                        self.cs.pop_back();
+                opcodes::OP_ELSE => {
+                    if self.cs.empty() {
+                        return Err(ScriptError::SCRIPT_ERR_UNBALANCED_CONDITIONAL);
                     }
+                    self.cs.toggle_top();
+                }
 
-                    opcodes::OP_VERIFY => {
-                        self.verify(ScriptError::SCRIPT_ERR_VERIFY)?;
+                opcodes::OP_ENDIF => {
+                    if self.cs.empty() {
+                        return Err(ScriptError::SCRIPT_ERR_UNBALANCED_CONDITIONAL);
                     }
+                    self.cs.pop_back();
+                }
 
-                    opcodes::OP_RETURN => {
-                        return Err(ScriptError::SCRIPT_ERR_OP_RETURN);
-                    }
+                opcodes::OP_VERIFY => {
+                    self.verify(ctx, ScriptError::SCRIPT_ERR_VERIFY)?;
+                }
 
-                    opcodes::OP_TOALTSTACK => {
-                        let [elem] = self.stack.pop();
-                        self.altstack.push(elem);
-                    }
+                opcodes::OP_RETURN => {
+                    return Err(ScriptError::SCRIPT_ERR_OP_RETURN);
+                }
 
-                    opcodes::OP_FROMALTSTACK => {
-                        self.stack.push(
-                            self.altstack
-                                .pop()
-                                .ok_or(ScriptError::SCRIPT_ERR_INVALID_ALTSTACK_OPERATION)?,
-                        );
-                    }
+                opcodes::OP_TOALTSTACK => {
+                    let [elem] = self.stack.pop();
+                    self.altstack.push(elem);
+                }
 
-                    opcodes::OP_2DROP => {
-                        self.stack.pop::<2>();
-                    }
+                opcodes::OP_FROMALTSTACK => {
+                    self.stack.push(
+                        self.altstack
+                            .pop()
+                            .ok_or(ScriptError::SCRIPT_ERR_INVALID_ALTSTACK_OPERATION)?,
+                    );
+                }
 
-                    opcodes::OP_2DUP => {
-                        self.stack.extend_from_within_back(2, 0);
-                    }
+                opcodes::OP_2DROP => {
+                    self.stack.pop::<2>();
+                }
 
-                    opcodes::OP_3DUP => {
-                        self.stack.extend_from_within_back(3, 0);
-                    }
+                opcodes::OP_2DUP => {
+                    self.stack.extend_from_within_back(2, 0);
+                }
 
-                    opcodes::OP_2OVER => {
-                        self.stack.extend_from_within_back(2, 2);
-                    }
+                opcodes::OP_3DUP => {
+                    self.stack.extend_from_within_back(3, 0);
+                }
 
-                    opcodes::OP_2ROT => {
-                        self.stack.swap_back(0, 2);
-                        self.stack.swap_back(1, 3);
-                        self.stack.swap_back(2, 4);
-                        self.stack.swap_back(3, 5);
-                    }
+                opcodes::OP_2OVER => {
+                    self.stack.extend_from_within_back(2, 2);
+                }
 
-                    opcodes::OP_2SWAP => {
-                        self.stack.swap_back(0, 2);
-                        self.stack.swap_back(1, 3);
-                    }
+                opcodes::OP_2ROT => {
+                    self.stack.swap_back(0, 2);
+                    self.stack.swap_back(1, 3);
+                    self.stack.swap_back(2, 4);
+                    self.stack.swap_back(3, 5);
+                }
 
-                    opcodes::OP_IFDUP => {
-                        let elem = self.stack.get_back(0).clone();
+                opcodes::OP_2SWAP => {
+                    self.stack.swap_back(0, 2);
+                    self.stack.swap_back(1, 3);
+                }
 
-                        let mut fork = self.clone();
-                        fork.spending_conditions
-                            .push(Opcode1::OP_INTERNAL_NOT.expr(Box::new([elem.clone()])));
+                opcodes::OP_IFDUP => {
+                    let mut elem = self.stack.get_back(0).clone();
+                    elem.eval_value(ctx)?;
 
-                        #[cfg(feature = "threads")]
-                        {
-                            let pool_ = pool.clone();
-                            pool.submit_job(move || {
-                                fork.analyze(results, ctx, &pool_);
-                            });
+                    if let Expr::Bytes(b) = &elem {
+                        if decode_bool(b) {
+                            self.stack.push(elem);
                         }
+                    } else {
+                        let mut other = self.clone();
+                        other
+                            .spending_conditions
+                            .push(Opcode1::OP_INTERNAL_NOT.expr([elem.clone()]));
 
-                        #[cfg(not(feature = "threads"))]
-                        fork.analyze(results, ctx, pool);
+                        fork = Some(other);
 
                         self.spending_conditions.push(elem.clone());
                         self.stack.push(elem);
                     }
+                }
 
-                    opcodes::OP_DEPTH => {
-                        self.stack.push(encode_int_expr(self.stack.len() as i64));
-                    }
+                opcodes::OP_DEPTH => {
+                    let depth = match self.stack.depth() {
+                        Some(depth) => encode_int_expr(depth as i64),
+                        None => {
+                            // the initial stack has an unknown amount of items, `items_used` of
+                            // them have been taken off the stack so far
+                            let offset = self.stack.len() as i64 - self.stack.items_used() as i64;
+                            let depth = Expr::stack(INITIAL_DEPTH);
+                            if offset == 0 {
+                                depth
+                            } else {
+                                Opcode2::OP_ADD.expr([depth, encode_int_expr(offset)])
+                            }
+                        }
+                    };
+                    self.stack.push(depth);
+                }
 
-                    opcodes::OP_DROP => {
-                        self.stack.pop::<1>();
-                    }
+                opcodes::OP_DROP => {
+                    self.stack.pop::<1>();
+                }
 
-                    opcodes::OP_DUP => {
-                        self.stack.extend_from_within_back(1, 0);
-                    }
+                opcodes::OP_DUP => {
+                    self.stack.extend_from_within_back(1, 0);
+                }
 
-                    opcodes::OP_NIP => {
-                        self.stack.remove_back(1);
-                    }
+                opcodes::OP_NIP => {
+                    self.stack.remove_back(1);
+                }
 
-                    opcodes::OP_OVER => {
-                        self.stack.extend_from_within_back(1, 1);
-                    }
+                opcodes::OP_OVER => {
+                    self.stack.extend_from_within_back(1, 1);
+                }
 
-                    opcodes::OP_PICK | opcodes::OP_ROLL => {
-                        let index = self.num_from_stack()?;
-                        if index < 0 {
-                            return Err(ScriptError::SCRIPT_ERR_INVALID_STACK_OPERATION);
-                        }
-                        let index = index as usize;
-                        let elem = match op {
-                            opcodes::OP_PICK => self.stack.get_back(index).clone(),
-                            opcodes::OP_ROLL => self.stack.remove_back(index),
-                            _ => unreachable!(),
-                        };
-                        self.stack.push(elem);
+                opcodes::OP_PICK | opcodes::OP_ROLL => {
+                    let n = match self.stack.pop() {
+                        [Expr::Bytes(n)] => decode_int(&n, 4)?,
+                        [n] => {
+                            // the index is not known, this path takes `pick_index` and a fork
+                            // executes this opcode again with the next one
+                            let k = core::mem::take(&mut self.pick_index);
+                            if k < self.max_pick_index(&n) {
+                                let mut other = self.clone();
+                                other.stack.push(n.clone());
+                                other.pick_index = k + 1;
+                                other.script_offset = index;
+                                other.byte_offset = offset;
+                                other.op_count -= 1;
+                                fork = Some(other);
+                                picked = Some(k);
+                            }
+                            self.spending_conditions
+                                .push(Opcode2::OP_NUMEQUAL.expr([n, encode_int_expr(k as i64)]));
+                            k as i64
+                        }
+                    };
+                    if n < 0 {
+                        return Err(ScriptError::SCRIPT_ERR_INVALID_STACK_OPERATION);
                     }
+                    let n = n as usize;
+                    let elem = match op {
+                        opcodes::OP_PICK => self.stack.get_back(n).clone(),
+                        opcodes::OP_ROLL => self.stack.remove_back(n),
+                        _ => unreachable!(),
+                    };
+                    self.stack.push(elem);
+                }
 
-                    opcodes::OP_ROT => {
-                        self.stack.swap_back(2, 1);
-                        self.stack.swap_back(1, 0);
-                    }
+                opcodes::OP_ROT => {
+                    self.stack.swap_back(2, 1);
+                    self.stack.swap_back(1, 0);
+                }
 
-                    opcodes::OP_SWAP => {
-                        self.stack.swap_back(0, 1);
-                    }
+                opcodes::OP_SWAP => {
+                    self.stack.swap_back(0, 1);
+                }
 
-                    opcodes::OP_TUCK => {
-                        self.stack.swap_back(0, 1);
-                        self.stack.extend_from_within_back(1, 1);
-                    }
+                opcodes::OP_TUCK => {
+                    self.stack.swap_back(0, 1);
+                    self.stack.extend_from_within_back(1, 1);
+                }
 
-                    opcodes::OP_SIZE => {
-                        let size = match self.stack.get_back(0) {
-                            Expr::Bytes(b) => encode_int_expr(b.len() as i64),
-                            elem => Opcode1::OP_SIZE.expr(Box::new([elem.clone()])),
-                        };
+                opcodes::OP_SIZE => {
+                    let size = match self.stack.get_back(0) {
+                        Expr::Bytes(b) => encode_int_expr(b.len() as i64),
+                        elem => Opcode1::OP_SIZE.expr([elem.clone()]),
+                    };
+
+                    self.stack.push(size);
+                }
 
-                        self.stack.push(size);
+                opcodes::OP_EQUAL | opcodes::OP_EQUALVERIFY => {
+                    let elems = self.stack.pop::<2>();
+                    self.stack.push(Opcode2::OP_EQUAL.expr(elems));
+                    if op == opcodes::OP_EQUALVERIFY {
+                        self.verify(ctx, ScriptError::SCRIPT_ERR_EQUALVERIFY)?;
                     }
+                }
 
-                    opcodes::OP_EQUAL | opcodes::OP_EQUALVERIFY => {
-                        let elems = self.stack.pop::<2>();
-                        self.stack.push(Opcode2::OP_EQUAL.expr(Box::new(elems)));
-                        if op == opcodes::OP_EQUALVERIFY {
-                            self.verify(ScriptError::SCRIPT_ERR_EQUALVERIFY)?;
+                opcodes::OP_1ADD | opcodes::OP_1SUB => {
+                    let [elem] = self.stack.pop();
+                    self.stack.push(
+                        match op {
+                            opcodes::OP_1ADD => Opcode2::OP_ADD,
+                            opcodes::OP_1SUB => Opcode2::OP_SUB,
+                            _ => unreachable!(),
                         }
-                    }
+                        .expr([elem, Expr::bytes(&[1])]),
+                    );
+                }
 
-                    opcodes::OP_1ADD | opcodes::OP_1SUB => {
-                        let [elem] = self.stack.pop();
-                        self.stack.push(
-                            match op {
-                                opcodes::OP_1ADD => Opcode2::OP_ADD,
-                                opcodes::OP_1SUB => Opcode2::OP_SUB,
-                                _ => unreachable!(),
-                            }
-                            .expr(Box::new([elem, Expr::bytes(&[1])])),
-                        );
-                    }
+                opcodes::OP_NEGATE => {
+                    let [elem] = self.stack.pop();
+                    self.stack
+                        .push(Opcode2::OP_SUB.expr([Expr::bytes(&[]), elem]));
+                }
 
-                    opcodes::OP_NEGATE => {
-                        let [elem] = self.stack.pop();
-                        self.stack
-                            .push(Opcode2::OP_SUB.expr(Box::new([Expr::bytes(&[]), elem])));
-                    }
+                opcodes::OP_ABS | opcodes::OP_NOT | opcodes::OP_0NOTEQUAL => {
+                    let [elem] = self.stack.pop();
+                    self.stack.push(
+                        match op {
+                            opcodes::OP_ABS => Opcode1::OP_ABS,
+                            opcodes::OP_NOT => Opcode1::OP_NOT,
+                            opcodes::OP_0NOTEQUAL => Opcode1::OP_0NOTEQUAL,
+                            _ => unreachable!(),
+                        }
+                        .expr([elem]),
+                    );
+                }
 
-                    opcodes::OP_ABS | opcodes::OP_NOT | opcodes::OP_0NOTEQUAL => {
-                        let [elem] = self.stack.pop();
-                        self.stack.push(
-                            match op {
-                                opcodes::OP_ABS => Opcode1::OP_ABS,
-                                opcodes::OP_NOT => Opcode1::OP_NOT,
-                                opcodes::OP_0NOTEQUAL => Opcode1::OP_0NOTEQUAL,
-                                _ => unreachable!(),
+                opcodes::OP_ADD
+                | opcodes::OP_SUB
+                | opcodes::OP_BOOLAND
+                | opcodes::OP_BOOLOR
+                | opcodes::OP_NUMEQUAL
+                | opcodes::OP_NUMEQUALVERIFY
+                | opcodes::OP_NUMNOTEQUAL
+                | opcodes::OP_LESSTHAN
+                | opcodes::OP_GREATERTHAN
+                | opcodes::OP_LESSTHANOREQUAL
+                | opcodes::OP_GREATERTHANOREQUAL
+                | opcodes::OP_MIN
+                | opcodes::OP_MAX => {
+                    let mut elems = self.stack.pop::<2>();
+                    self.stack.push(
+                        match op {
+                            opcodes::OP_ADD => Opcode2::OP_ADD,
+                            opcodes::OP_SUB => Opcode2::OP_SUB,
+                            opcodes::OP_BOOLAND => Opcode2::OP_BOOLAND,
+                            opcodes::OP_BOOLOR => Opcode2::OP_BOOLOR,
+                            opcodes::OP_NUMEQUAL | opcodes::OP_NUMEQUALVERIFY => {
+                                Opcode2::OP_NUMEQUAL
                             }
-                            .expr(Box::new([elem])),
-                        );
-                    }
-
-                    opcodes::OP_ADD
-                    | opcodes::OP_SUB
-                    | opcodes::OP_BOOLAND
-                    | opcodes::OP_BOOLOR
-                    | opcodes::OP_NUMEQUAL
-                    | opcodes::OP_NUMEQUALVERIFY
-                    | opcodes::OP_NUMNOTEQUAL
-                    | opcodes::OP_LESSTHAN
-                    | opcodes::OP_GREATERTHAN
-                    | opcodes::OP_LESSTHANOREQUAL
-                    | opcodes::OP_GREATERTHANOREQUAL
-                    | opcodes::OP_MIN
-                    | opcodes::OP_MAX => {
-                        let mut elems = self.stack.pop::<2>();
-                        self.stack.push(
-                            match op {
-                                opcodes::OP_ADD => Opcode2::OP_ADD,
-                                opcodes::OP_SUB => Opcode2::OP_SUB,
-                                opcodes::OP_BOOLAND => Opcode2::OP_BOOLAND,
-                                opcodes::OP_BOOLOR => Opcode2::OP_BOOLOR,
-                                opcodes::OP_NUMEQUAL | opcodes::OP_NUMEQUALVERIFY => {
-                                    Opcode2::OP_NUMEQUAL
-                                }
-                                opcodes::OP_NUMNOTEQUAL => Opcode2::OP_NUMNOTEQUAL,
-                                opcodes::OP_LESSTHAN => Opcode2::OP_LESSTHAN,
-                                opcodes::OP_GREATERTHAN => {
-                                    elems.swap(0, 1);
-                                    Opcode2::OP_LESSTHAN
-                                }
-                                opcodes::OP_LESSTHANOREQUAL => Opcode2::OP_LESSTHANOREQUAL,
-                                opcodes::OP_GREATERTHANOREQUAL => {
-                                    elems.swap(0, 1);
-                                    Opcode2::OP_LESSTHANOREQUAL
-                                }
-                                opcodes::OP_MIN => Opcode2::OP_MIN,
-                                opcodes::OP_MAX => Opcode2::OP_MAX,
-                                _ => unreachable!(),
+                            opcodes::OP_NUMNOTEQUAL => Opcode2::OP_NUMNOTEQUAL,
+                            opcodes::OP_LESSTHAN => Opcode2::OP_LESSTHAN,
+                            opcodes::OP_GREATERTHAN => {
+                                elems.swap(0, 1);
+                                Opcode2::OP_LESSTHAN
+                            }
+                            opcodes::OP_LESSTHANOREQUAL => Opcode2::OP_LESSTHANOREQUAL,
+                            opcodes::OP_GREATERTHANOREQUAL => {
+                                elems.swap(0, 1);
+                                Opcode2::OP_LESSTHANOREQUAL
                             }
-                            .expr(Box::new(elems)),
-                        );
-                        if op == opcodes::OP_NUMEQUALVERIFY {
-                            self.verify(ScriptError::SCRIPT_ERR_NUMEQUALVERIFY)?;
+                            opcodes::OP_MIN => Opcode2::OP_MIN,
+                            opcodes::OP_MAX => Opcode2::OP_MAX,
+                            _ => unreachable!(),
                         }
+                        .expr(elems),
+                    );
+                    if op == opcodes::OP_NUMEQUALVERIFY {
+                        self.verify(ctx, ScriptError::SCRIPT_ERR_NUMEQUALVERIFY)?;
                     }
+                }
 
-                    opcodes::OP_WITHIN => {
-                        let elems = self.stack.pop::<3>();
-                        self.stack.push(Opcode3::OP_WITHIN.expr(Box::new(elems)));
-                    }
+                opcodes::OP_WITHIN => {
+                    let elems = self.stack.pop::<3>();
+                    self.stack.push(Opcode3::OP_WITHIN.expr(elems));
+                }
 
-                    opcodes::OP_RIPEMD160 | opcodes::OP_SHA1 | opcodes::OP_SHA256 => {
-                        let [elem] = self.stack.pop();
-                        self.stack.push(
-                            match op {
-                                opcodes::OP_RIPEMD160 => Opcode1::OP_RIPEMD160,
-                                opcodes::OP_SHA1 => Opcode1::OP_SHA1,
-                                opcodes::OP_SHA256 => Opcode1::OP_SHA256,
-                                _ => unreachable!(),
-                            }
-                            .expr(Box::new([elem])),
-                        );
-                    }
+                opcodes::OP_RIPEMD160 | opcodes::OP_SHA1 | opcodes::OP_SHA256 => {
+                    let [elem] = self.stack.pop();
+                    self.stack.push(
+                        match op {
+                            opcodes::OP_RIPEMD160 => Opcode1::OP_RIPEMD160,
+                            opcodes::OP_SHA1 => Opcode1::OP_SHA1,
+                            opcodes::OP_SHA256 => Opcode1::OP_SHA256,
+                            _ => unreachable!(),
+                        }
+                        .expr([elem]),
+                    );
+                }
 
-                    opcodes::OP_HASH160 | opcodes::OP_HASH256 => {
-                        let [elem] = self.stack.pop();
-                        self.stack.push(
-                            match op {
-                                opcodes::OP_HASH160 => Opcode1::OP_RIPEMD160,
-                                opcodes::OP_HASH256 => Opcode1::OP_SHA256,
-                                _ => unreachable!(),
-                            }
-                            .expr(Box::new([Opcode1::OP_SHA256.expr(Box::new([elem]))])),
-                        );
-                    }
+                opcodes::OP_HASH160 | opcodes::OP_HASH256 => {
+                    let [elem] = self.stack.pop();
+                    self.stack.push(
+                        match op {
+                            opcodes::OP_HASH160 => Opcode1::OP_RIPEMD160,
+                            opcodes::OP_HASH256 => Opcode1::OP_SHA256,
+                            _ => unreachable!(),
+                        }
+                        .expr([Opcode1::OP_SHA256.expr([elem])]),
+                    );
+                }
 
-                    opcodes::OP_CODESEPARATOR => {}
+                opcodes::OP_CODESEPARATOR => {
+                    if ctx.version == ScriptVersion::Legacy
+                        && ctx.has(VerifyFlags::CONST_SCRIPTCODE)
+                    {
+                        return Err(ScriptError::SCRIPT_ERR_OP_CODESEPARATOR);
+                    }
+                }
 
-                    opcodes::OP_CHECKSIG | opcodes::OP_CHECKSIGVERIFY => {
-                        let elems = self.stack.pop::<2>();
-                        self.stack.push(Opcode2::OP_CHECKSIG.expr(Box::new(elems)));
-                        if op == opcodes::OP_CHECKSIGVERIFY {
-                            self.verify(ScriptError::SCRIPT_ERR_CHECKSIGVERIFY)?;
-                        }
+                opcodes::OP_CHECKSIG | opcodes::OP_CHECKSIGVERIFY => {
+                    let elems = self.stack.pop::<2>();
+                    self.count_sigop(ctx, &elems[0]);
+                    self.find_and_delete(ctx, &elems[..1])?;
+                    self.stack.push(Opcode2::OP_CHECKSIG.expr(elems));
+                    if op == opcodes::OP_CHECKSIGVERIFY {
+                        self.verify(ctx, ScriptError::SCRIPT_ERR_CHECKSIGVERIFY)?;
                     }
+                }
 
-                    opcodes::OP_CHECKMULTISIG | opcodes::OP_CHECKMULTISIGVERIFY => {
-                        if ctx.version == ScriptVersion::SegwitV1 {
-                            return Err(ScriptError::SCRIPT_ERR_TAPSCRIPT_CHECKMULTISIG);
-                        }
+                opcodes::OP_CHECKMULTISIG | opcodes::OP_CHECKMULTISIGVERIFY => {
+                    if ctx.version == ScriptVersion::SegwitV1 {
+                        return Err(ScriptError::SCRIPT_ERR_TAPSCRIPT_CHECKMULTISIG);
+                    }
 
-                        let kcount = self.num_from_stack()?;
-                        if !(0..=20).contains(&kcount) {
-                            return Err(ScriptError::SCRIPT_ERR_PUBKEY_COUNT);
-                        }
+                    let kcount = self.num_from_stack()?;
+                    if !(0..=MAX_PUBKEYS_PER_MULTISIG as i64).contains(&kcount) {
+                        return Err(ScriptError::SCRIPT_ERR_PUBKEY_COUNT);
+                    }
+                    self.count_ops(ctx, kcount as usize)?;
+                    // legacy sigop counting does not look at the amount of keys
+                    self.sigops += if ctx.version == ScriptVersion::Legacy {
+                        MAX_PUBKEYS_PER_MULTISIG as u32
+                    } else {
+                        kcount as u32
+                    };
 
-                        // TODO save some allocations
+                    // TODO save some allocations
 
-                        let pks = self.stack.pop_to_box(kcount as usize);
+                    let pks = self.stack.pop_to_box(kcount as usize);
 
-                        let scount = self.num_from_stack()?;
-                        if !(0..=kcount).contains(&scount) {
-                            return Err(ScriptError::SCRIPT_ERR_SIG_COUNT);
-                        }
+                    let scount = self.num_from_stack()?;
+                    if !(0..=kcount).contains(&scount) {
+                        return Err(ScriptError::SCRIPT_ERR_SIG_COUNT);
+                    }
 
-                        let kcount = kcount as usize;
-                        let scount = scount as usize;
+                    let kcount = kcount as usize;
+                    let scount = scount as usize;
 
-                        let sigs = self.stack.pop_to_box(scount);
+                    let sigs = self.stack.pop_to_box(scount);
+                    self.find_and_delete(ctx, &sigs)?;
 
-                        let [dummy] = self.stack.pop();
+                    let [dummy] = self.stack.pop();
 
-                        if ctx.rules == ScriptRules::All {
-                            self.spending_conditions
-                                .push(Opcode2::OP_EQUAL.expr_with_error(
-                                    Box::new([dummy, Expr::bytes_owned(Box::new([]))]),
-                                    ScriptError::SCRIPT_ERR_SIG_NULLDUMMY,
-                                ));
-                        }
+                    if ctx.has(VerifyFlags::NULLDUMMY) {
+                        self.spending_conditions
+                            .push(Opcode2::OP_EQUAL.expr_with_error(
+                                [dummy, Expr::bytes_owned(Box::new([]))],
+                                ScriptError::SCRIPT_ERR_SIG_NULLDUMMY,
+                            ));
+                    }
 
-                        let mut args = Vec::with_capacity(scount + kcount);
-                        args.extend(sigs.into_vec());
-                        args.extend(pks.into_vec());
+                    let mut args = Vec::with_capacity(scount + kcount);
+                    args.extend(sigs.into_vec());
+                    args.extend(pks.into_vec());
 
-                        self.stack
-                            .push(MultisigArgs::expr(args.into_boxed_slice(), scount));
+                    self.stack
+                        .push(MultisigArgs::expr(args.into_boxed_slice(), scount));
 
-                        if op == opcodes::OP_CHECKMULTISIGVERIFY {
-                            self.verify(ScriptError::SCRIPT_ERR_CHECKMULTISIGVERIFY)?;
-                        }
+                    if op == opcodes::OP_CHECKMULTISIGVERIFY {
+                        self.verify(ctx, ScriptError::SCRIPT_ERR_CHECKMULTISIGVERIFY)?;
                     }
+                }
 
-                    opcodes::OP_CHECKLOCKTIMEVERIFY | opcodes::OP_CHECKSEQUENCEVERIFY => {
+                opcodes::OP_CHECKLOCKTIMEVERIFY | opcodes::OP_CHECKSEQUENCEVERIFY => {
+                    let (opcode, flag) = match op {
+                        opcodes::OP_CHECKLOCKTIMEVERIFY => (
+                            Opcode1::OP_CHECKLOCKTIMEVERIFY,
+                            VerifyFlags::CHECKLOCKTIMEVERIFY,
+                        ),
+                        opcodes::OP_CHECKSEQUENCEVERIFY => (
+                            Opcode1::OP_CHECKSEQUENCEVERIFY,
+                            VerifyFlags::CHECKSEQUENCEVERIFY,
+                        ),
+                        _ => unreachable!(),
+                    };
+                    // without the flag, this is still OP_NOP2 or OP_NOP3
+                    if ctx.has(flag) {
                         let elem = self.stack.get_back(0).clone();
-                        self.spending_conditions.push(
-                            match op {
-                                opcodes::OP_CHECKLOCKTIMEVERIFY => Opcode1::OP_CHECKLOCKTIMEVERIFY,
-                                opcodes::OP_CHECKSEQUENCEVERIFY => Opcode1::OP_CHECKSEQUENCEVERIFY,
-                                _ => unreachable!(),
-                            }
-                            .expr(Box::new([elem])),
-                        );
-                    }
-
-                    opcodes::OP_NOP1
-                    | opcodes::OP_NOP4
-                    | opcodes::OP_NOP5
-                    | opcodes::OP_NOP6
-                    | opcodes::OP_NOP7
-                    | opcodes::OP_NOP8
-                    | opcodes::OP_NOP9
-                    | opcodes::OP_NOP10 => {}
-
-                    opcodes::OP_CHECKSIGADD => {
-                        if ctx.version != ScriptVersion::SegwitV1 {
-                            return Err(ScriptError::SCRIPT_ERR_BAD_OPCODE);
-                        }
-                        let [sig, n, pk] = self.stack.pop();
-                        self.stack.push(Opcode2::OP_ADD.expr(Box::new([
-                            n,
-                            Opcode2::OP_CHECKSIG.expr(Box::new([sig, pk])),
-                        ])));
+                        self.spending_conditions.push(opcode.expr([elem]));
+                    } else if ctx.has(VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS) {
+                        return Err(ScriptError::SCRIPT_ERR_DISCOURAGE_UPGRADABLE_NOPS);
+                    }
+                }
+
+                opcodes::OP_NOP1
+                | opcodes::OP_NOP4
+                | opcodes::OP_NOP5
+                | opcodes::OP_NOP6
+                | opcodes::OP_NOP7
+                | opcodes::OP_NOP8
+                | opcodes::OP_NOP9
+                | opcodes::OP_NOP10 => {
+                    if ctx.has(VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS) {
+                        return Err(ScriptError::SCRIPT_ERR_DISCOURAGE_UPGRADABLE_NOPS);
                     }
+                }
 
-                    _ => {
+                opcodes::OP_CHECKSIGADD => {
+                    if ctx.version != ScriptVersion::SegwitV1 {
                         return Err(ScriptError::SCRIPT_ERR_BAD_OPCODE);
                     }
-                },
+                    let [sig, n, pk] = self.stack.pop();
+                    self.count_sigop(ctx, &sig);
+                    self.stack
+                        .push(Opcode2::OP_ADD.expr([n, Opcode2::OP_CHECKSIG.expr([sig, pk])]));
+                }
+
+                _ => {
+                    return Err(ScriptError::SCRIPT_ERR_BAD_OPCODE);
+                }
+            },
+        }
+
+        if let ScriptElem::Op(opcode) = op {
+            let origin = Origin {
+                opcode,
+                index,
+                offset,
+            };
+            self.set_origin(origin, conditions_len);
+            match opcode {
+                opcodes::OP_CODESEPARATOR => self.code_separator = Some(origin),
+                opcodes::OP_CHECKSIG
+                | opcodes::OP_CHECKSIGVERIFY
+                | opcodes::OP_CHECKSIGADD
+                | opcodes::OP_CHECKMULTISIG
+                | opcodes::OP_CHECKMULTISIGVERIFY => self.script_codes.push(ScriptCode {
+                    checksig: origin,
+                    code_separator: self.code_separator,
+                }),
+                _ => (),
             }
+            if let Some(fork) = &mut fork {
+                fork.set_origin(origin, conditions_len);
+            }
+            if let (
+                Some(fork),
+                opcodes::OP_IF
+                | opcodes::OP_NOTIF
+                | opcodes::OP_IFDUP
+                | opcodes::OP_PICK
+                | opcodes::OP_ROLL,
+            ) = (&mut fork, opcode)
+            {
+                // the forked path continues with a false argument, or with a larger index
+                self.branches.push(BranchDecision {
+                    origin,
+                    condition: true,
+                    pick_index: picked,
+                });
+                fork.branches.push(BranchDecision {
+                    origin,
+                    condition: false,
+                    pick_index: picked,
+                });
+            }
+        }
+
+        if self
+            .stack
+            .elements()
+            .last()
+            .is_some_and(|top| top.size_bounds().0 > MAX_SCRIPT_ELEMENT_SIZE)
+        {
+            return Err(ScriptError::SCRIPT_ERR_PUSH_SIZE);
+        }
+
+        let size = self.stack.len() + self.altstack.len();
+        if size > MAX_STACK_SIZE {
+            return Err(ScriptError::SCRIPT_ERR_STACK_SIZE);
+        }
+        self.peak_stack_size = self
+            .peak_stack_size
+            .max(size as i64 - self.stack.items_used() as i64);
 
-            if self.stack.len() + self.altstack.len() > 1000 {
-                return Err(ScriptError::SCRIPT_ERR_STACK_SIZE);
+        Ok(fork)
+    }
+
+    /// Fails with [`ScriptError::SCRIPT_ERR_SIG_FINDANDDELETE`] if one of `sigs` is a constant
+    /// that is pushed in the scriptCode of a legacy script, with
+    /// [`VerifyFlags::CONST_SCRIPTCODE`]. Such pushes are removed from the scriptCode before it is
+    /// signed. `OP_CODESEPARATOR` fails with the same flag, so the scriptCode is the whole script.
+    /// Pushes are assumed to be minimal.
+    fn find_and_delete(&self, ctx: ScriptContext, sigs: &[Expr]) -> Result<(), ScriptError> {
+        if ctx.version != ScriptVersion::Legacy || !ctx.has(VerifyFlags::CONST_SCRIPTCODE) {
+            return Ok(());
+        }
+        for sig in sigs {
+            let Expr::Bytes(sig) = sig else {
+                continue;
+            };
+            let sig: &[u8] = sig.as_ref();
+            let found = self.script.script.iter().any(|elem| match elem {
+                // an empty signature is serialized as `OP_0`
+                ScriptElem::Op(op) => sig.is_empty() && *op == opcodes::OP_0,
+                ScriptElem::Bytes(b) => !sig.is_empty() && *b == sig,
+            });
+            if found {
+                return Err(ScriptError::SCRIPT_ERR_SIG_FINDANDDELETE);
             }
         }
+        Ok(())
+    }
+
+    /// Sets the origin of the expressions created by the last executed opcode.
+    fn set_origin(&mut self, origin: Origin, conditions_len: usize) {
+        if let Some(top) = self.stack.last_mut() {
+            top.set_origin(origin);
+        }
+        for condition in &mut self.spending_conditions[conditions_len..] {
+            condition.set_origin(origin);
+        }
+    }
 
+    /// Checks the state after executing the whole script.
+    pub(crate) fn finish(&mut self, ctx: ScriptContext) -> Result<(), ScriptError> {
         if !self.cs.empty() {
             return Err(ScriptError::SCRIPT_ERR_UNBALANCED_CONDITIONAL);
         }
 
         if self.stack.len() > 1
-            && !(ctx.version == ScriptVersion::Legacy && ctx.rules == ScriptRules::ConsensusOnly)
+            && (ctx.version != ScriptVersion::Legacy || ctx.has(VerifyFlags::CLEANSTACK))
         {
             return Err(ScriptError::SCRIPT_ERR_CLEANSTACK);
         }
 
-        self.verify(ScriptError::SCRIPT_ERR_EVAL_FALSE)?;
+        // the top element stays on the stack, it is part of the final stack
+        self.stack.get_back(0);
+        self.resolve_depth();
+
+        // the stack limit applies to the initial stack too, in tapscript before any opcode runs
+        let initial = if ctx.version == ScriptVersion::SegwitV1 {
+            0
+        } else {
+            i64::MIN
+        };
+        let peak = self.peak_stack_size.max(initial);
+        if peak.saturating_add(self.stack.items_used() as i64) > MAX_STACK_SIZE as i64 {
+            return Err(ScriptError::SCRIPT_ERR_STACK_SIZE);
+        }
+        let top = self.stack.get_back(0).clone();
+        self.verify_expr(top, ctx, ScriptError::SCRIPT_ERR_EVAL_FALSE)?;
+
+        Ok(())
+    }
+
+    /// Replaces the initial stack depth pushed by `OP_DEPTH` with the amount of initial stack
+    /// items the path used. Unused items would stay on the stack, which fails with
+    /// [`ScriptError::SCRIPT_ERR_CLEANSTACK`]. Without that rule, the initial stack is assumed to
+    /// have no unused items, like [`SpendingPath::stack_size`].
+    fn resolve_depth(&mut self) {
+        let depth = Expr::stack(INITIAL_DEPTH);
+        let items = encode_int_expr(self.stack.items_used() as i64);
+        for expr in self
+            .stack
+            .elements_mut()
+            .iter_mut()
+            .chain(&mut self.altstack)
+            .chain(&mut self.spending_conditions)
+        {
+            expr.replace_all(&depth, &items);
+        }
+    }
 
+    /// Counts non-push opcodes against the limit for non-tapscript scripts.
+    fn count_ops(&mut self, ctx: ScriptContext, n: usize) -> Result<(), ScriptError> {
+        if ctx.version != ScriptVersion::SegwitV1 {
+            self.op_count += n;
+            if self.op_count > MAX_OPS_PER_SCRIPT {
+                return Err(ScriptError::SCRIPT_ERR_OP_COUNT);
+            }
+        }
         Ok(())
     }
 
-    fn verify(&mut self, error: ScriptError) -> Result<(), ScriptError> {
+    fn count_sigop(&mut self, ctx: ScriptContext, sig: &Expr) {
+        // signature checks with an empty signature don't use validation weight in tapscript
+        if ctx.version != ScriptVersion::SegwitV1
+            || !matches!(sig, Expr::Bytes(sig) if sig.is_false())
+        {
+            self.sigops += 1;
+        }
+    }
+
+    fn verify(&mut self, ctx: ScriptContext, error: ScriptError) -> Result<(), ScriptError> {
         let [elem] = self.stack.pop();
+        self.verify_expr(elem, ctx, error)
+    }
+
+    fn verify_expr(
+        &mut self,
+        mut elem: Expr,
+        ctx: ScriptContext,
+        error: ScriptError,
+    ) -> Result<(), ScriptError> {
+        elem.eval(ctx)?;
         if let Expr::Bytes(elem) = elem {
             if !decode_bool(&elem) {
                 return Err(error);
@@ -878,6 +2545,23 @@ impl<'a> ScriptAnalyzer<'a> {
         Ok(())
     }
 
+    /// The largest index an `OP_PICK` or `OP_ROLL` with the unknown index `n` is tried with. For
+    /// a stack item with a known range this is the end of the range, otherwise every element that
+    /// is on the stack and the first initial stack item below them are reachable.
+    fn max_pick_index(&self, n: &Expr) -> usize {
+        let max = match n {
+            Expr::Stack(item) => item_ranges(&self.spending_conditions)
+                .into_iter()
+                .find(|r| r.stack_item == item.pos())
+                .map(|r| r.range.max),
+            _ => None,
+        };
+        match max {
+            Some(max) => max.clamp(0, MAX_STACK_SIZE as i64) as usize,
+            None => self.stack.len(),
+        }
+    }
+
     fn num_from_stack(&mut self) -> Result<i64, ScriptError> {
         if let [Expr::Bytes(top)] = self.stack.pop() {
             decode_int(&top, 4)
@@ -886,3 +2570,1089 @@ impl<'a> ScriptAnalyzer<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{analyze_script, execute_script, BranchDecision, ScriptAnalyzer, SigOps};
+    use crate::{
+        expr::{Expr, Opcode1, Opcode2},
+        opcodes,
+        script_error::ScriptError,
+        AnalysisLimit, AnalysisOptions, AnalyzeError, NumRange, OwnedScript, PathOrder,
+        ScriptContext, ScriptRules, ScriptVersion, VerifyFlags,
+    };
+    use core::ops::ControlFlow;
+
+    const CTX: ScriptContext = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All);
+
+    fn execute(asm: &str, witness: &[&[u8]]) -> Result<super::ExecutionResult, ScriptError> {
+        let mut buf = asm.as_bytes().to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        execute_script(&script, CTX, witness, 0).map_err(|err| err.script_error())
+    }
+
+    fn analyze(asm: &str, ctx: ScriptContext) -> Result<super::Analysis, AnalyzeError> {
+        let mut buf = asm.as_bytes().to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        analyze_script(&script, ctx, Default::default())
+    }
+
+    fn analyze_script_with_opts(
+        asm: &str,
+        opts: AnalysisOptions,
+    ) -> Result<super::Analysis, AnalyzeError> {
+        let mut buf = asm.as_bytes().to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        analyze_script(&script, CTX, opts)
+    }
+
+    #[test]
+    fn test_op_count_limit() {
+        assert!(analyze(&format!("{}1", "OP_NOP ".repeat(201)), CTX).is_ok());
+        assert_eq!(
+            analyze(&format!("{}1", "OP_NOP ".repeat(202)), CTX).unwrap_err(),
+            AnalyzeError::OpCount
+        );
+
+        let tapscript = ScriptContext::new(ScriptVersion::SegwitV1, ScriptRules::All);
+        assert!(analyze(&format!("{}1", "OP_NOP ".repeat(202)), tapscript).is_ok());
+    }
+
+    #[test]
+    fn test_script_limits() {
+        let push = format!("<{}> OP_DROP ", "00".repeat(520));
+        assert!(analyze(&format!("{}1", push.repeat(19)), CTX).is_ok());
+        assert_eq!(
+            analyze(&format!("{}1", push.repeat(20)), CTX).unwrap_err(),
+            AnalyzeError::ScriptSize
+        );
+
+        let mut bytes = vec![0x51, 0x4d, 0x09, 0x02];
+        bytes.extend([0; 521]);
+        let script = OwnedScript::parse_from_bytes(&bytes).unwrap();
+        assert_eq!(
+            analyze_script(&script, CTX, Default::default()).unwrap_err(),
+            AnalyzeError::PushSize { offset: 1 }
+        );
+
+        let err = analyze("1 <01> OP_CAT", CTX).unwrap_err();
+        assert_eq!(
+            err,
+            AnalyzeError::DisabledOpcode {
+                opcode: opcodes::OP_CAT,
+                offset: 3
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Script error: Attempted to use a disabled opcode (OP_CAT at byte 3)"
+        );
+
+        // offsets are in the parsed bytes, here with a non-minimal push of 0x01
+        let script = OwnedScript::parse_from_bytes(&[0x51, 0x4c, 0x01, 0x01, 0x7e]).unwrap();
+        assert_eq!(
+            analyze_script(&script, CTX, Default::default()).unwrap_err(),
+            AnalyzeError::DisabledOpcode {
+                opcode: opcodes::OP_CAT,
+                offset: 4
+            }
+        );
+
+        let (script, _) = OwnedScript::parse_from_bytes_lenient(&[0x51, 0xbb]).unwrap();
+        let err = analyze_script(&script, CTX, Default::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Script error: Opcode missing or not understood (UNKNOWN(0xbb) at byte 1)"
+        );
+    }
+
+    #[test]
+    fn test_unspendable() {
+        assert_eq!(
+            analyze("OP_DUP OP_NOT OP_VERIFY OP_VERIFY", CTX).unwrap_err(),
+            AnalyzeError::Unspendable {
+                per_path_errors: vec![ScriptError::SCRIPT_ERR_UNKNOWN_ERROR]
+            }
+        );
+    }
+
+    #[test]
+    fn test_failed_paths() {
+        let mut buf = b"OP_IF OP_RETURN OP_ELSE 1 OP_ENDIF".to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let analysis = super::diagnose_script(&script, CTX, Default::default()).unwrap();
+        assert_eq!(analysis.paths.len(), 1);
+        assert_eq!(analysis.failed_paths.len(), 1);
+        let failed = &analysis.failed_paths[0];
+        assert_eq!(failed.error, ScriptError::SCRIPT_ERR_OP_RETURN);
+        assert_eq!(failed.branches.len(), 1);
+        assert!(failed.branches[0].condition);
+        assert_eq!(
+            failed.branches[0].to_string(),
+            "OP_IF at byte 0 with a true argument"
+        );
+
+        assert!(analyze("OP_IF OP_RETURN OP_ELSE 1 OP_ENDIF", CTX)
+            .unwrap()
+            .failed_paths
+            .is_empty());
+    }
+
+    #[test]
+    fn test_unexecuted_opcodes() {
+        for op in ["OP_VERIF", "OP_VERNOTIF"] {
+            assert_eq!(
+                analyze(&format!("OP_IF {op} OP_ELSE 1 OP_ENDIF"), CTX).unwrap_err(),
+                AnalyzeError::Unspendable {
+                    per_path_errors: vec![ScriptError::SCRIPT_ERR_BAD_OPCODE; 2],
+                }
+            );
+        }
+        // other invalid opcodes only fail when executed
+        for op in ["OP_RETURN", "OP_VER", "OP_RESERVED"] {
+            let analysis = analyze(&format!("OP_IF {op} OP_ELSE 1 OP_ENDIF"), CTX).unwrap();
+            assert_eq!(analysis.paths.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_origin_offset() {
+        // <01> OP_DROP OP_IF 1 OP_ELSE 1 OP_ENDIF, with a non-minimal push
+        let bytes = [0x4c, 0x01, 0x01, 0x75, 0x63, 0x51, 0x67, 0x51, 0x68];
+        let script = OwnedScript::parse_from_bytes(&bytes).unwrap();
+        let ctx = ScriptContext::new(ScriptVersion::Legacy, ScriptRules::ConsensusOnly);
+        let analysis = analyze_script(&script, ctx, Default::default()).unwrap();
+        assert_eq!(analysis.paths[0].branches[0].origin.offset, 4);
+
+        // without the original encoding, the push is assumed to be minimal
+        let analysis = analyze_script(&*script, ctx, Default::default()).unwrap();
+        assert_eq!(analysis.paths[0].branches[0].origin.offset, 3);
+    }
+
+    #[test]
+    fn test_unknown_pick_index() {
+        // every index allowed by the range of stack item #0 gets its own path
+        let script =
+            "OP_DUP 2 OP_LESSTHAN OP_VERIFY OP_PICK OP_TOALTSTACK OP_2DROP OP_FROMALTSTACK";
+        let mut buf = script.as_bytes().to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let analysis = super::diagnose_script(&script, CTX, Default::default()).unwrap();
+        assert!(analysis.failed_paths.is_empty());
+        let requirements: Vec<_> = analysis
+            .paths
+            .iter()
+            .map(|p| p.requirements(Default::default()))
+            .collect();
+        assert_eq!(
+            requirements,
+            [
+                ["stack item #0 must be the number 0", "<stack item #1>"],
+                ["stack item #0 must be the number 1", "<stack item #2>"]
+            ]
+        );
+        assert_eq!(analysis.stats.forks, 1);
+
+        let branches: Vec<_> = analysis.paths.iter().map(|p| &p.branches[..]).collect();
+        assert_eq!(branches[0].len(), 1);
+        assert_eq!(branches[0][0].origin.opcode, opcodes::OP_PICK);
+        assert_eq!(branches[0][0].pick_index, Some(0));
+        assert!(branches[0][0].condition);
+        assert_eq!(
+            branches[1],
+            [BranchDecision {
+                condition: false,
+                ..branches[0][0]
+            }]
+        );
+        assert!(branches[1][0]
+            .to_string()
+            .ends_with("with an index other than 0"));
+    }
+
+    #[test]
+    fn test_depth() {
+        // the branch depends on the amount of witness items
+        let script = "OP_DEPTH 2 OP_EQUAL OP_IF OP_ADD 5 OP_EQUAL OP_ELSE 7 OP_EQUAL OP_ENDIF";
+        let analysis = analyze(script, CTX).unwrap();
+        let stack_sizes: Vec<_> = analysis.paths.iter().map(|p| p.stack_size).collect();
+        assert_eq!(stack_sizes, [2, 1]);
+        let script = "OP_DEPTH 3 OP_EQUAL OP_IF OP_ADD 5 OP_EQUAL OP_ELSE 7 OP_EQUAL OP_ENDIF";
+        assert_eq!(analyze(script, CTX).unwrap().paths.len(), 1);
+
+        // items taken off the stack before OP_DEPTH are not counted
+        let analysis = analyze("OP_DROP OP_DEPTH 1 OP_EQUALVERIFY", CTX).unwrap();
+        assert_eq!(analysis.paths[0].stack_size, 2);
+
+        let res = execute("OP_DEPTH 2 OP_EQUALVERIFY OP_DROP", &[b"\x01", b"\x02"]).unwrap();
+        assert_eq!(res.final_stack, [Expr::bytes(b"\x01")]);
+    }
+
+    #[test]
+    fn test_stack_size_limit() {
+        let script = format!("{}1", "OP_2DROP ".repeat(51));
+        let path = &analyze(&script, CTX).unwrap().paths[0];
+        assert_eq!((path.stack_size, path.exact_stack_size), (102, true));
+        assert!(path.to_string().starts_with(
+            "Stack size: 102 (warning: P2WSH inputs with more than 100 witness items are not \
+             standard)\n"
+        ));
+        let tapscript = ScriptContext::new(ScriptVersion::SegwitV1, ScriptRules::All);
+        assert_eq!(
+            analyze(&script, tapscript).unwrap().paths[0].stack_size_limit,
+            None
+        );
+
+        // unused items below the ones the script uses are allowed
+        let legacy = ScriptContext::new(ScriptVersion::Legacy, ScriptRules::ConsensusOnly);
+        let path = &analyze("OP_DROP 1", legacy).unwrap().paths[0];
+        assert!(!path.exact_stack_size);
+        assert!(path.to_string().starts_with("Stack size: at least 1\n"));
+    }
+
+    #[test]
+    fn test_size_bounds() {
+        let hash = Opcode1::OP_SHA256.expr([Expr::stack(0)]);
+        assert_eq!(hash.size_bounds(), (32, 32));
+        assert_eq!(Expr::stack(0).size_bounds(), (0, 520));
+
+        // a SHA256 digest is never 20 bytes
+        let script = format!("OP_SHA256 <{}> OP_EQUAL", "ab".repeat(20));
+        assert_eq!(
+            analyze(&script, CTX).unwrap_err(),
+            AnalyzeError::Unspendable {
+                per_path_errors: vec![ScriptError::SCRIPT_ERR_EVAL_FALSE]
+            }
+        );
+        let script = format!("OP_SIZE <{}> OP_EQUAL", "ab".repeat(6));
+        assert!(analyze(&script, CTX).is_err());
+    }
+
+    #[test]
+    fn test_initial_stack_size() {
+        let tapscript = ScriptContext::new(ScriptVersion::SegwitV1, ScriptRules::All);
+        let stack_size_err = AnalyzeError::Unspendable {
+            per_path_errors: vec![ScriptError::SCRIPT_ERR_STACK_SIZE],
+        };
+
+        // tapscript checks the initial stack, 1001 items here
+        assert_eq!(
+            analyze(&"OP_2DROP ".repeat(500), tapscript).unwrap_err(),
+            stack_size_err
+        );
+        assert!(analyze(&"OP_2DROP ".repeat(499), tapscript).is_ok());
+        // otherwise only the stack after an opcode is checked
+        let legacy = ScriptContext::new(ScriptVersion::Legacy, ScriptRules::ConsensusOnly);
+        let path = &analyze("OP_2DROP 998 OP_PICK", legacy).unwrap().paths[0];
+        assert_eq!(path.stack_size, 1001);
+
+        // the items that are not used yet count towards the limit
+        let script = "OP_DUP OP_TOALTSTACK ".repeat(400);
+        let script = |drops| format!("{script}{}", "OP_2DROP ".repeat(drops));
+        assert!(analyze(&script(299), tapscript).is_ok());
+        assert_eq!(
+            analyze(&script(300), tapscript).unwrap_err(),
+            stack_size_err
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        let analysis = analyze(
+            "OP_IF OP_RETURN OP_ELSE OP_IF OP_RETURN OP_ELSE OP_DUP OP_DUP OP_2DROP OP_ENDIF OP_ENDIF",
+            CTX,
+        )
+        .unwrap();
+        let stats = &analysis.stats;
+        assert_eq!(stats.paths_explored, 3);
+        assert_eq!(stats.paths_pruned, [(ScriptError::SCRIPT_ERR_OP_RETURN, 2)]);
+        assert_eq!(stats.pruned(), 2);
+        assert_eq!(stats.forks, 2);
+        assert_eq!(stats.peak_stack_depth, 3);
+        assert!(stats.to_string().contains("Paths pruned: 2\n  "));
+    }
+
+    #[test]
+    fn test_anyone_can_spend() {
+        let analysis = analyze("OP_IF OP_CHECKSIG OP_ELSE OP_DROP 1 OP_ENDIF", CTX).unwrap();
+        let anyone_can_spend: Vec<_> = analysis
+            .paths
+            .iter()
+            .map(|p| p.is_anyone_can_spend())
+            .collect();
+        assert_eq!(anyone_can_spend.iter().filter(|&&b| b).count(), 1);
+        assert!(analysis.to_string().starts_with("WARNING: "));
+
+        let analysis = analyze(&format!("OP_SHA256 <{}> OP_EQUAL", "00".repeat(32)), CTX).unwrap();
+        assert!(!analysis.paths[0].is_anyone_can_spend());
+        let analysis = analyze("OP_CHECKLOCKTIMEVERIFY", CTX).unwrap();
+        assert!(!analysis.paths[0].is_anyone_can_spend());
+
+        let g = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        let h = "0250929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
+        let consensus = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::ConsensusOnly);
+        let tapscript = ScriptContext::new(ScriptVersion::SegwitV1, ScriptRules::All);
+        for (script, ctx, anyone_can_spend) in [
+            // an empty signature makes the check fail
+            (format!("<{h}> OP_CHECKSIG OP_NOT"), consensus, true),
+            (format!("<{h}> OP_CHECKSIG 0 OP_NUMNOTEQUAL"), CTX, false),
+            (
+                format!("<{h}> OP_CHECKSIG 1 OP_NUMNOTEQUAL"),
+                consensus,
+                true,
+            ),
+            (format!("<{h}> OP_CHECKSIG"), CTX, false),
+            (format!("<{g}> OP_CHECKSIG"), CTX, true),
+            (format!("2 <{g}> <{h}> 2 OP_CHECKMULTISIG"), CTX, false),
+            (format!("1 <{g}> <{h}> 2 OP_CHECKMULTISIG"), CTX, true),
+            (
+                format!(
+                    "<{}> OP_CHECKSIG <{}> OP_CHECKSIGADD 2 OP_NUMEQUAL",
+                    &g[2..],
+                    &h[2..]
+                ),
+                tapscript,
+                false,
+            ),
+            (
+                format!(
+                    "<{}> OP_CHECKSIG <{}> OP_CHECKSIGADD 1 OP_NUMEQUAL",
+                    &g[2..],
+                    &h[2..]
+                ),
+                tapscript,
+                true,
+            ),
+            // two equal items satisfy it
+            (
+                "OP_SHA256 OP_SWAP OP_SHA256 OP_EQUAL".to_string(),
+                CTX,
+                true,
+            ),
+            (
+                "OP_SHA256 <e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855> \
+                 OP_EQUAL"
+                    .to_string(),
+                CTX,
+                true,
+            ),
+        ] {
+            let analysis = analyze(&script, ctx).unwrap();
+            assert!(
+                analysis
+                    .paths
+                    .iter()
+                    .all(|p| p.is_anyone_can_spend() == anyone_can_spend),
+                "{script}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_sequence_disable_flag() {
+        let path = &analyze("10 OP_CHECKSEQUENCEVERIFY", CTX).unwrap().paths[0];
+        assert_eq!(path.sequence_req.req, Some(10));
+        assert_eq!(path.min_tx_version(), 2);
+
+        // 1 << 31, OP_CHECKSEQUENCEVERIFY does nothing
+        let path = &analyze("<0000008000> OP_CHECKSEQUENCEVERIFY", CTX)
+            .unwrap()
+            .paths[0];
+        assert!(!path.sequence_req.is_required());
+        assert_eq!(path.min_tx_version(), 1);
+    }
+
+    #[test]
+    fn test_symbolic_locktime_type() {
+        // stack item #0 is a block height
+        let height = "OP_DUP 500000000 OP_LESSTHAN OP_VERIFY OP_CHECKLOCKTIMEVERIFY OP_DROP";
+        assert!(analyze(height, CTX).is_ok());
+        assert_eq!(
+            analyze(&format!("{height} 600000000 OP_CHECKLOCKTIMEVERIFY"), CTX)
+                .unwrap_err()
+                .script_error(),
+            ScriptError::SCRIPT_ERR_UNSATISFIED_LOCKTIME
+        );
+        assert_eq!(
+            analyze(
+                &format!(
+                    "{height} OP_DUP 500000000 OP_GREATERTHANOREQUAL OP_VERIFY \
+                     OP_CHECKLOCKTIMEVERIFY"
+                ),
+                CTX
+            )
+            .unwrap_err()
+            .script_error(),
+            ScriptError::SCRIPT_ERR_UNSATISFIED_LOCKTIME
+        );
+        assert!(analyze(&format!("{height} 700000 OP_CHECKLOCKTIMEVERIFY"), CTX).is_ok());
+
+        // the size of a stack item is a relative block height, not a time
+        assert_eq!(
+            analyze(
+                "OP_SIZE OP_CHECKSEQUENCEVERIFY OP_2DROP <00004000> OP_CHECKSEQUENCEVERIFY",
+                CTX
+            )
+            .unwrap_err()
+            .script_error(),
+            ScriptError::SCRIPT_ERR_UNSATISFIED_LOCKTIME
+        );
+    }
+
+    #[test]
+    fn test_locktime_bounds() {
+        let path = &analyze(
+            "OP_DUP 500000000 OP_LESSTHAN OP_VERIFY OP_CHECKLOCKTIMEVERIFY",
+            CTX,
+        )
+        .unwrap()
+        .paths[0];
+        assert_eq!(path.locktime_req.req, Some(0));
+        assert_eq!(
+            path.locktime_req
+                .locktime_requirement_to_string(false)
+                .unwrap(),
+            "type: height, minValue: at block 0, stack elements: <stack item #0> in [0, 499999999]"
+        );
+
+        let path = &analyze(
+            "OP_DUP 600000000 OP_GREATERTHAN OP_VERIFY OP_CHECKLOCKTIMEVERIFY",
+            CTX,
+        )
+        .unwrap()
+        .paths[0];
+        assert_eq!(path.locktime_req.req, Some(600000001));
+
+        // relative block heights of at least 10
+        let path = &analyze(
+            "OP_DUP 10 65536 OP_WITHIN OP_VERIFY OP_CHECKSEQUENCEVERIFY",
+            CTX,
+        )
+        .unwrap()
+        .paths[0];
+        assert_eq!(path.sequence_req.req, Some(10));
+
+        // no type, no minimum
+        let path = &analyze(
+            "OP_DUP 1 OP_GREATERTHAN OP_VERIFY OP_CHECKLOCKTIMEVERIFY",
+            CTX,
+        )
+        .unwrap()
+        .paths[0];
+        assert_eq!(path.locktime_req.req, None);
+        assert_eq!(
+            path.locktime_req.expr_ranges,
+            [Some(NumRange {
+                min: 2,
+                max: 2147483647
+            })]
+        );
+    }
+
+    #[test]
+    fn test_path_order() {
+        let mut analysis = analyze("OP_IF OP_CHECKSIG OP_ELSE OP_DROP 1 OP_ENDIF", CTX).unwrap();
+        let conditions = |a: &super::Analysis| {
+            a.paths
+                .iter()
+                .map(|p| p.spending_conditions.len())
+                .collect::<Vec<_>>()
+        };
+        assert!(analysis.paths[0].branches[0].condition);
+        assert!(!analysis.paths[1].branches[0].condition);
+        assert_eq!(conditions(&analysis), [2, 1]);
+
+        analysis.sort_paths(PathOrder::Conditions);
+        assert_eq!(conditions(&analysis), [1, 2]);
+        analysis.sort_paths(PathOrder::Branches);
+        assert_eq!(conditions(&analysis), [2, 1]);
+
+        // the path that duplicates a true item comes first
+        let analysis = analyze("OP_IFDUP OP_NOTIF 1 OP_ENDIF", CTX).unwrap();
+        let branches: Vec<_> = analysis.paths.iter().map(|p| p.branches[0]).collect();
+        assert_eq!(branches.len(), 2);
+        assert_eq!(branches[0].origin.opcode, opcodes::OP_IFDUP);
+        assert!(branches[0].condition);
+        assert!(!branches[1].condition);
+    }
+
+    #[test]
+    fn test_analyze_script_with() {
+        let mut buf = b"OP_IF OP_IF 1 OP_ELSE 2 OP_ENDIF OP_ELSE 3 OP_ENDIF".to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+
+        let mut paths = 0;
+        super::analyze_script_with(&script, CTX, Default::default(), |res| {
+            assert!(res.is_ok());
+            paths += 1;
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        assert_eq!(paths, 3);
+
+        let mut paths = 0;
+        super::analyze_script_with(&script, CTX, Default::default(), |_| {
+            paths += 1;
+            ControlFlow::Break(())
+        })
+        .unwrap();
+        assert_eq!(paths, 1);
+
+        // stopping at the first path leaves the other 4095 unexplored
+        let mut buf = format!("{}1", "OP_IF OP_ENDIF ".repeat(12)).into_bytes();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let stats =
+            super::analyze_script_with(
+                &script,
+                CTX,
+                Default::default(),
+                |_| ControlFlow::Break(()),
+            )
+            .unwrap();
+        assert!(stats.forks <= 12, "{} forks", stats.forks);
+    }
+
+    #[test]
+    fn test_analysis_limits() {
+        let mut buf = b"OP_IF OP_IF 1 OP_ELSE 2 OP_ENDIF OP_ELSE 3 OP_ENDIF".to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let limit = |opts| analyze_script(&script, CTX, opts).map(|a| a.paths.len());
+
+        assert_eq!(
+            limit(AnalysisOptions {
+                max_paths: Some(3),
+                max_forks: Some(2),
+                ..Default::default()
+            }),
+            Ok(3)
+        );
+        assert_eq!(
+            limit(AnalysisOptions {
+                max_paths: Some(2),
+                ..Default::default()
+            }),
+            Err(AnalyzeError::LimitExceeded(AnalysisLimit::Paths))
+        );
+
+        // paths past the limit are not analyzed further, or passed to the callback
+        let mut buf = format!("{}1", "OP_IF OP_ENDIF ".repeat(12)).into_bytes();
+        let (_, many_paths) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let opts = AnalysisOptions {
+            max_paths: Some(10),
+            #[cfg(feature = "threads")]
+            worker_threads: 2,
+            ..Default::default()
+        };
+        let mut paths = 0;
+        let res = super::analyze_script_with(&many_paths, CTX, opts, |_| {
+            paths += 1;
+            ControlFlow::Continue(())
+        });
+        assert_eq!(res, Err(AnalyzeError::LimitExceeded(AnalysisLimit::Paths)));
+        assert!(paths <= 10);
+        assert_eq!(
+            limit(AnalysisOptions {
+                max_forks: Some(1),
+                ..Default::default()
+            }),
+            Err(AnalyzeError::LimitExceeded(AnalysisLimit::Forks))
+        );
+
+        let asm = format!("1 {}", "OP_DUP OP_ADD ".repeat(3));
+        let opts = AnalysisOptions {
+            max_expr_nodes: Some(100),
+            ..Default::default()
+        };
+        analyze_script_with_opts(&asm, opts).unwrap();
+        let asm = format!("1 {}", "OP_DUP OP_ADD ".repeat(10));
+        assert_eq!(
+            analyze_script_with_opts(&asm, opts).unwrap_err(),
+            AnalyzeError::LimitExceeded(AnalysisLimit::ExprNodes)
+        );
+    }
+
+    #[cfg(feature = "threads")]
+    #[test]
+    fn test_shared_pool() {
+        let asm = "OP_IF OP_IF 1 OP_ELSE 2 OP_ENDIF OP_ELSE OP_IF 3 OP_ELSE 4 OP_ENDIF OP_ENDIF";
+        let expected = analyze(asm, CTX).unwrap().to_string();
+        for _ in 0..4 {
+            let analysis = analyze_script_with_opts(
+                asm,
+                AnalysisOptions {
+                    pool: Some(crate::ThreadPool::global()),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            assert_eq!(analysis.to_string(), expected);
+        }
+    }
+
+    #[cfg(feature = "threads")]
+    #[test]
+    fn test_parallel_conditions() {
+        // more conditions than `PARALLEL_CONDITIONS`
+        // the generator point, a valid public key also when signatures are checked with
+        // secp256k1. the conditions differ by the signature size
+        let asm = (0..40)
+            .map(|i| {
+                format!(
+                    "OP_SIZE {} OP_EQUALVERIFY \
+                     <0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798> \
+                     OP_CHECKSIGVERIFY ",
+                    30 + i
+                )
+            })
+            .collect::<String>()
+            + "1";
+        let expected = analyze(&asm, CTX).unwrap().to_string();
+        let analysis = analyze_script_with_opts(
+            &asm,
+            AnalysisOptions {
+                worker_threads: 4,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(analysis.to_string(), expected);
+    }
+
+    #[test]
+    fn test_verify_flags() {
+        let legacy = |flags| ScriptContext::with_flags(ScriptVersion::Legacy, flags);
+
+        assert!(analyze("1 1", legacy(VerifyFlags::CONSENSUS)).is_ok());
+        assert!(analyze(
+            "1 1",
+            legacy(VerifyFlags::CONSENSUS | VerifyFlags::CLEANSTACK)
+        )
+        .is_err());
+
+        let nop = "1 OP_IF OP_NOP4 OP_ENDIF 1";
+        assert!(analyze(nop, CTX).is_err());
+        assert!(analyze(
+            nop,
+            ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::ConsensusOnly)
+        )
+        .is_ok());
+        assert!(analyze("0 OP_IF OP_NOP4 OP_ENDIF 1", CTX).is_ok());
+
+        let cltv = "<e803> OP_CHECKLOCKTIMEVERIFY";
+        let without_cltv = VerifyFlags::CONSENSUS - VerifyFlags::CHECKLOCKTIMEVERIFY;
+        assert!(analyze(cltv, CTX).unwrap().paths[0]
+            .locktime_req
+            .req
+            .is_some());
+        assert!(analyze(cltv, legacy(without_cltv)).unwrap().paths[0]
+            .locktime_req
+            .req
+            .is_none());
+    }
+
+    #[test]
+    fn test_nullfail() {
+        let key = format!("<02{}>", "ab".repeat(32));
+        let empty_sig = |analysis: &super::Analysis, item: u32| {
+            analysis.paths[0]
+                .spending_conditions
+                .iter()
+                .any(|c| c.to_string() == format!("OP_EQUAL(<stack item #{item}>, <>)"))
+        };
+
+        let analysis = analyze("OP_CHECKSIG OP_NOT", CTX).unwrap();
+        assert!(empty_sig(&analysis, 1));
+        let consensus = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::ConsensusOnly);
+        assert!(!empty_sig(
+            &analyze("OP_CHECKSIG OP_NOT", consensus).unwrap(),
+            1
+        ));
+
+        // all signatures of a failing multisig are empty, not only the first
+        let multisig = format!("2 {key} {key} 2 OP_CHECKMULTISIG OP_NOT");
+        let analysis = analyze(&multisig, CTX).unwrap();
+        assert!(empty_sig(&analysis, 0) && empty_sig(&analysis, 1));
+
+        let sig = "<300602010102010101>";
+        assert_eq!(
+            analyze(&format!("{sig} {key} OP_CHECKSIG OP_NOT"), CTX).unwrap_err(),
+            AnalyzeError::Unspendable {
+                per_path_errors: vec![ScriptError::SCRIPT_ERR_SIG_NULLFAIL]
+            }
+        );
+    }
+
+    #[test]
+    fn test_multisig_encoding() {
+        let key = format!("<02{}>", "ab".repeat(32));
+        let uncompressed = format!("<04{}>", "ab".repeat(64));
+
+        // the last key is always compared with a signature
+        assert_eq!(
+            analyze(&format!("1 {key} {uncompressed} 2 OP_CHECKMULTISIG"), CTX).unwrap_err(),
+            AnalyzeError::Unspendable {
+                per_path_errors: vec![ScriptError::SCRIPT_ERR_WITNESS_PUBKEYTYPE]
+            }
+        );
+        // the first one is not reached if the signature is for the last key
+        let analysis = analyze(&format!("1 {uncompressed} {key} 2 OP_CHECKMULTISIG"), CTX);
+        assert!(analysis.is_ok());
+
+        // without STRICTENC, no signature is valid for an invalid key
+        let consensus = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::ConsensusOnly);
+        let analysis = analyze(&format!("1 {key} <05> 2 OP_CHECKMULTISIG"), consensus).unwrap();
+        assert_eq!(
+            analysis.paths[0].spending_conditions[1].to_string(),
+            format!("OP_CHECKSIG(<stack item #0>, {key})")
+        );
+
+        let analysis = analyze(&format!("2 {key} {key} {key} 3 OP_CHECKMULTISIG"), CTX).unwrap();
+        let items = &analysis.paths[0].items;
+        assert_eq!(
+            (items[0].multisig, items[1].multisig),
+            (Some((1, 2)), Some((0, 2)))
+        );
+        assert!(items[0]
+            .to_string()
+            .ends_with("multisig signature 2 of 2, in the order of the public keys"));
+    }
+
+    #[test]
+    fn test_multisig_sigops() {
+        let script = "OP_TOALTSTACK 1 OP_FROMALTSTACK 1 OP_CHECKMULTISIG";
+        let legacy = ScriptContext::new(ScriptVersion::Legacy, ScriptRules::All);
+        assert_eq!(analyze(script, legacy).unwrap().paths[0].sigops.count(), 20);
+        assert_eq!(analyze(script, CTX).unwrap().paths[0].sigops.count(), 1);
+    }
+
+    #[test]
+    fn test_validation_weight() {
+        let tapscript = ScriptContext::new(ScriptVersion::SegwitV1, ScriptRules::All);
+        let check = |n| {
+            analyze(
+                &format!(
+                    "<{}> <{}> {}OP_CHECKSIG",
+                    "01".repeat(64),
+                    "02".repeat(32),
+                    "OP_2DUP OP_CHECKSIGVERIFY ".repeat(n)
+                ),
+                tapscript,
+            )
+        };
+
+        let analysis = check(1).unwrap();
+        assert_eq!(analysis.paths[0].sigops.count(), 2);
+        // the spender can make the witness larger to increase the budget
+        let analysis = check(10).unwrap();
+        assert_eq!(
+            analysis.paths[0].sigops,
+            SigOps::ValidationWeight {
+                count: 11,
+                weight: 550,
+                min_witness_size: 500,
+            }
+        );
+    }
+
+    #[test]
+    fn test_execute_hashlock() {
+        const SCRIPT: &str =
+            "OP_SHA256 <ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad> OP_EQUAL";
+
+        let res = execute(SCRIPT, &[b"abc"]).unwrap();
+        assert_eq!(res.final_stack, [Expr::bytes(&[1])]);
+        assert!(res.unverified_conditions.is_empty());
+
+        let err = execute(SCRIPT, &[b"abd"]).unwrap_err();
+        assert_eq!(err, ScriptError::SCRIPT_ERR_EVAL_FALSE);
+    }
+
+    #[cfg(not(feature = "threads"))]
+    #[test]
+    fn test_threading_unavailable() {
+        let mut buf = b"OP_DROP 1".to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let opts = AnalysisOptions {
+            worker_threads: 1,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            analyze_script(&script, CTX, opts).unwrap_err(),
+            AnalyzeError::ThreadingUnavailable
+        );
+        assert_eq!(
+            execute_script(&script, CTX, &[b"a"], 1).unwrap_err(),
+            AnalyzeError::ThreadingUnavailable
+        );
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        let stack = |asm| execute(asm, &[]).map(|res| res.final_stack);
+
+        assert_eq!(stack("3 5 OP_MIN 7 OP_MAX").unwrap(), [Expr::bytes(&[7])]);
+        assert!(stack("2 1 OP_LESSTHAN").is_err());
+        assert!(stack("5 1 10 OP_WITHIN 1 OP_BOOLAND 0 OP_BOOLOR").is_ok());
+        assert!(stack("-1 -1 OP_NUMNOTEQUAL").is_err());
+        assert_eq!(stack("-5 OP_ABS").unwrap(), [Expr::bytes(&[5])]);
+        assert_eq!(stack("-5 OP_0NOTEQUAL").unwrap(), [Expr::bytes(&[1])]);
+        assert_eq!(
+            stack("1 OP_IF 0 OP_ELSE 2 OP_ENDIF OP_0NOTEQUAL OP_ABS OP_NOT").unwrap(),
+            [Expr::bytes(&[1])]
+        );
+
+        let analysis = analyze("OP_SIZE 32 OP_EQUAL OP_NIP OP_0NOTEQUAL OP_ABS", CTX).unwrap();
+        assert_eq!(
+            analysis.paths[0].spending_conditions[0].to_string(),
+            "OP_EQUAL(OP_SIZE(<stack item #0>), <20>)"
+        );
+        assert_eq!(
+            stack("<0000000001> 1 OP_LESSTHANOREQUAL").unwrap_err(),
+            ScriptError::SCRIPT_ERR_NUM_OVERFLOW
+        );
+    }
+
+    #[test]
+    fn test_origin() {
+        let analysis = analyze(
+            &format!(
+                "OP_DUP OP_HASH160 <{}> OP_EQUALVERIFY OP_CHECKSIG",
+                "ab".repeat(20)
+            ),
+            CTX,
+        )
+        .unwrap();
+        let origins: Vec<_> = analysis.paths[0]
+            .spending_conditions
+            .iter()
+            .map(|c| {
+                let origin = c.origin().unwrap();
+                (origin.opcode.to_string(), origin.index, origin.offset)
+            })
+            .collect();
+        assert_eq!(
+            origins,
+            [
+                ("OP_EQUALVERIFY".to_string(), 3, 23),
+                ("OP_CHECKSIG".to_string(), 4, 24)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_code_separator() {
+        let script = "OP_2DUP OP_CHECKSIGVERIFY OP_CODESEPARATOR OP_CHECKSIG";
+        let analysis = analyze(script, CTX).unwrap();
+        let script_codes = &analysis.paths[0].script_codes;
+        assert_eq!(
+            script_codes
+                .iter()
+                .map(|c| (c.checksig.index, c.start(), c.codesep_pos()))
+                .collect::<Vec<_>>(),
+            [(1, 0, u32::MAX), (3, 3, 2)]
+        );
+        assert_eq!(
+            script_codes[1].to_string(),
+            "OP_CHECKSIG at byte 3 signs the script from byte 3, after OP_CODESEPARATOR at byte 2 \
+             (opcode position 2)"
+        );
+
+        let legacy = ScriptContext::new(ScriptVersion::Legacy, ScriptRules::All);
+        assert_eq!(
+            analyze(script, legacy).unwrap_err(),
+            AnalyzeError::Unspendable {
+                per_path_errors: vec![ScriptError::SCRIPT_ERR_OP_CODESEPARATOR]
+            }
+        );
+        let consensus = ScriptContext::new(ScriptVersion::Legacy, ScriptRules::ConsensusOnly);
+        assert!(analyze(script, consensus).is_ok());
+    }
+
+    #[test]
+    fn test_find_and_delete() {
+        let legacy = ScriptContext::new(ScriptVersion::Legacy, ScriptRules::All);
+        let key = "<0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798>";
+        let sig = "<300602010102010101>";
+        let err = AnalyzeError::Unspendable {
+            per_path_errors: vec![ScriptError::SCRIPT_ERR_SIG_FINDANDDELETE],
+        };
+
+        let script = format!("{sig} {key} OP_CHECKSIG");
+        assert_eq!(analyze(&script, legacy).unwrap_err(), err);
+        assert!(analyze(&script, CTX).is_ok());
+        let consensus = ScriptContext::new(ScriptVersion::Legacy, ScriptRules::ConsensusOnly);
+        assert!(analyze(&script, consensus).is_ok());
+
+        // an empty signature is the same as OP_0
+        let script = format!("0 {key} OP_CHECKSIG OP_NOT");
+        assert_eq!(analyze(&script, legacy).unwrap_err(), err);
+        let script = format!("0 0 1 {key} 1 OP_CHECKMULTISIG OP_NOT");
+        assert_eq!(analyze(&script, legacy).unwrap_err(), err);
+    }
+
+    #[test]
+    fn test_execute_branches() {
+        const SCRIPT: &str = "OP_IF 2 OP_ELSE 3 OP_ENDIF OP_ADD 5 OP_EQUAL";
+
+        let res = execute(SCRIPT, &[&[3], &[1]]).unwrap();
+        assert_eq!(res.final_stack, [Expr::bytes(&[1])]);
+
+        let err = execute(SCRIPT, &[&[3], &[]]).unwrap_err();
+        assert_eq!(err, ScriptError::SCRIPT_ERR_EVAL_FALSE);
+
+        let err = execute(SCRIPT, &[&[3], &[2]]).unwrap_err();
+        assert_eq!(err, ScriptError::SCRIPT_ERR_MINIMALIF);
+
+        let err = execute(SCRIPT, &[&[1]]).unwrap_err();
+        assert_eq!(err, ScriptError::SCRIPT_ERR_INVALID_STACK_OPERATION);
+    }
+
+    #[test]
+    fn test_builder() {
+        let mut buf = b"OP_IF OP_SHA256 <ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad> OP_EQUAL OP_ELSE OP_DROP OP_CHECKSIG OP_ENDIF".to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+
+        // the preimage is known, the selector is not
+        let analysis = ScriptAnalyzer::builder(&script)
+            .stack_item(1, Expr::bytes(b"abc"))
+            .build()
+            .analyze(CTX, Default::default())
+            .unwrap();
+        assert_eq!(analysis.paths.len(), 2);
+        assert_eq!(analysis.paths[0].spending_conditions.len(), 1);
+        assert_eq!(analysis.paths[0].stack_size, 2);
+
+        // the assumed condition rules out the other branch
+        let analysis = ScriptAnalyzer::builder(&script)
+            .stack_item(1, Expr::bytes(b"abc"))
+            .spending_condition(Expr::stack(0))
+            .build()
+            .diagnose(CTX, Default::default())
+            .unwrap();
+        assert_eq!(analysis.paths.len(), 1);
+        assert_eq!(analysis.failed_paths.len(), 1);
+
+        let err = ScriptAnalyzer::builder(&script)
+            .stack_item(0, Expr::bytes(&[1]))
+            .stack_item(1, Expr::bytes(b"abd"))
+            .build()
+            .analyze(CTX, Default::default())
+            .unwrap_err();
+        assert!(matches!(err, AnalyzeError::Unspendable { .. }));
+
+        let mut analyzer = ScriptAnalyzer::builder(&script)
+            .stack_item(0, Expr::bytes(&[]))
+            .build();
+        assert!(analyzer.step(CTX).unwrap().is_none());
+        assert_eq!(analyzer.script_offset(), 1);
+        assert!(!analyzer.executing());
+    }
+
+    #[test]
+    fn test_equality_propagation() {
+        let mut buf = b"1".to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let analyze = |max| {
+            ScriptAnalyzer::builder(&script)
+                .spending_condition(Opcode2::OP_EQUAL.expr([Expr::stack(0), Expr::stack(1)]))
+                .spending_condition(Opcode2::OP_EQUAL.expr([Expr::stack(1), Expr::stack(2)]))
+                .spending_condition(Opcode2::OP_EQUAL.expr([Expr::stack(2), Expr::bytes(&[5])]))
+                .spending_condition(
+                    Opcode2::OP_LESSTHAN.expr([Expr::stack(0), Expr::bytes(&[max])]),
+                )
+                .build()
+                .analyze(CTX, Default::default())
+        };
+
+        let conditions: Vec<_> = analyze(10).unwrap().paths[0]
+            .spending_conditions
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(
+            conditions,
+            [
+                "OP_EQUAL(<stack item #0>, <05>)",
+                "OP_EQUAL(<stack item #1>, <05>)",
+                "OP_EQUAL(<stack item #2>, <05>)"
+            ]
+        );
+        assert!(analyze(3).is_err());
+    }
+
+    #[test]
+    fn test_split_disjunction() {
+        let analysis = analyze("OP_DUP 1 OP_EQUAL OP_SWAP 2 OP_EQUAL OP_BOOLOR", CTX).unwrap();
+        let conditions: Vec<_> = analysis
+            .paths
+            .iter()
+            .map(|path| path.spending_conditions[0].to_string())
+            .collect();
+        assert_eq!(
+            conditions,
+            [
+                "OP_EQUAL(<stack item #0>, <01>)",
+                "OP_EQUAL(<stack item #0>, <02>)"
+            ]
+        );
+        assert!(analysis.paths[0].branches[0].condition);
+        assert_eq!(analysis.stats.forks, 1);
+
+        // only the second argument can be true
+        let mut buf = b"OP_DUP 1 OP_EQUAL OP_SWAP 2 OP_EQUAL OP_BOOLOR".to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let analysis = ScriptAnalyzer::builder(&script)
+            .spending_condition(Opcode2::OP_LESSTHAN.expr([Expr::stack(0), Expr::bytes(&[2])]))
+            .build()
+            .diagnose(CTX, Default::default())
+            .unwrap();
+        assert_eq!(analysis.paths.len(), 1);
+        assert_eq!(analysis.failed_paths.len(), 1);
+    }
+
+    #[test]
+    fn test_boolean_simplification() {
+        let conditions = |asm| -> Vec<String> {
+            analyze(asm, CTX).unwrap().paths[0]
+                .spending_conditions
+                .iter()
+                .map(|c| c.to_string())
+                .collect()
+        };
+
+        // De Morgan, the conditions of `!(a || b)` are `!a` and `!b`
+        assert_eq!(
+            conditions("1 OP_EQUAL OP_SWAP 2 OP_EQUAL OP_BOOLOR OP_NOT"),
+            [
+                "OP_NOT(OP_EQUAL(<stack item #0>, <01>))",
+                "OP_NOT(OP_EQUAL(<stack item #1>, <02>))"
+            ]
+        );
+        // identity and absorption
+        assert_eq!(
+            conditions("1 OP_EQUAL OP_DUP OP_ROT 2 OP_EQUAL OP_BOOLOR OP_BOOLAND 1 OP_BOOLAND"),
+            ["OP_EQUAL(<stack item #0>, <01>)"]
+        );
+        // annihilator
+        assert!(analyze("1 OP_EQUAL 0 OP_BOOLAND", CTX).is_err());
+        // a stack item that is not a number still fails
+        assert_eq!(
+            conditions("1 OP_BOOLAND"),
+            ["OP_0NOTEQUAL(<stack item #0>)"]
+        );
+    }
+
+    #[test]
+    fn test_execute_unverified_signature() {
+        let pubkey = [2; 33];
+        let res = execute(
+            "OP_CHECKSIG",
+            &[
+                &[0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x01, 0x01],
+                &pubkey,
+            ],
+        )
+        .unwrap();
+        assert_eq!(res.unverified_conditions.len(), 1);
+    }
+}
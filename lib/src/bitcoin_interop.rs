@@ -0,0 +1,530 @@
+//! Conversions from and to [rust-bitcoin](bitcoin) types, enabled by the `bitcoin` feature.
+
+use crate::{
+    analyzer::{cmp_branches, Analysis, FailedPath},
+    expr::{null_signature_error, BytesExprBox, Expr, OpExprArgs, Opcode2},
+    output::{revealed_script, OutputKind, RevealedScript},
+    script::convert::{decode_bool, encode_bool_expr},
+    script_error::ScriptError,
+    OwnedScript, ParseScriptError, ScriptContext, ScriptVersion,
+};
+use bitcoin::{
+    hashes::{hash160, ripemd160, sha1, sha256, sha256d, Hash},
+    script::Instruction,
+    secp256k1::{ecdsa, schnorr, Message, Secp256k1},
+    sighash::{Annex, EcdsaSighashType, Prevouts, SighashCache, TapSighashType},
+    taproot::{LeafVersion, TapLeafHash},
+    Amount, Transaction, TxOut, XOnlyPublicKey,
+};
+use core::fmt;
+
+impl<'a> TryFrom<&'a bitcoin::Script> for OwnedScript<'a> {
+    type Error = ParseScriptError;
+
+    fn try_from(script: &'a bitcoin::Script) -> Result<Self, Self::Error> {
+        Self::parse_from_bytes(script.as_bytes())
+    }
+}
+
+/// Error returned by [`input_script`].
+#[derive(Debug, Clone)]
+pub enum InputScriptError {
+    /// The transaction has no input with this index.
+    NoInput(usize),
+    /// The output spent by the input is not known, see [`SpendingTx::prevouts`].
+    NoPrevout,
+    /// The input does not reveal a script, like key path spends and non-P2SH legacy inputs.
+    NoScript,
+    Parse(ParseScriptError),
+}
+
+impl fmt::Display for InputScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoInput(index) => write!(f, "transaction has no input #{index}"),
+            Self::NoPrevout => write!(f, "the output spent by the input is not known"),
+            Self::NoScript => write!(f, "input does not reveal a script"),
+            Self::Parse(err) => write!(f, "invalid script: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for InputScriptError {}
+
+/// Returns the script that is executed when input `index` of `tx` spends an output with
+/// `script_pubkey`, with its version: the tapscript or witness script of a segwit input, or the
+/// redeem script of a P2SH input. See [`revealed_script`] to also get the script of bare outputs.
+pub fn input_script<'a>(
+    tx: &'a Transaction,
+    index: usize,
+    script_pubkey: &'a bitcoin::Script,
+) -> Result<(OwnedScript<'a>, ScriptVersion), InputScriptError> {
+    let revealed = executed_script(tx, index, script_pubkey)?;
+    OwnedScript::parse_from_bytes(revealed.script)
+        .map(|script| (script, revealed.version))
+        .map_err(InputScriptError::Parse)
+}
+
+/// Like [`input_script`], but the script is not parsed.
+fn executed_script<'a>(
+    tx: &'a Transaction,
+    index: usize,
+    script_pubkey: &'a bitcoin::Script,
+) -> Result<RevealedScript<'a>, InputScriptError> {
+    let input = tx
+        .input
+        .get(index)
+        .ok_or(InputScriptError::NoInput(index))?;
+    let witness: Vec<_> = input.witness.iter().collect();
+
+    match revealed_script(
+        script_pubkey.as_bytes(),
+        input.script_sig.as_bytes(),
+        &witness,
+    ) {
+        Ok(revealed) if revealed.kind != OutputKind::Bare => Ok(revealed),
+        _ => Err(InputScriptError::NoScript),
+    }
+}
+
+/// The transaction spending a script and the outputs it spends, used to verify signatures with
+/// [`Analysis::verify_signatures`].
+#[derive(Debug, Clone, Copy)]
+pub struct SpendingTx<'a> {
+    pub tx: &'a Transaction,
+    pub input_index: usize,
+    /// The outputs spent by every input of `tx`, or only the one spent by `input_index`. The
+    /// amount is committed to by segwit signatures, taproot signatures commit to all outputs,
+    /// unless they use `SIGHASH_ANYONECANPAY`.
+    pub prevouts: &'a [TxOut],
+}
+
+impl SpendingTx<'_> {
+    /// The initial stack of the script: the witness items before the script, or the pushes of
+    /// the script sig before the redeem script. The last item is the top of the stack, stack
+    /// item #0.
+    pub fn initial_stack(&self) -> Result<Vec<Vec<u8>>, InputScriptError> {
+        let revealed = self.executed_script()?;
+        let input = &self.tx.input[self.input_index];
+        if revealed.kind != OutputKind::P2sh {
+            return Ok(input
+                .witness
+                .iter()
+                .take(revealed.stack_items)
+                .map(<[u8]>::to_vec)
+                .collect());
+        }
+
+        let mut pushes = Vec::new();
+        for instruction in input.script_sig.instructions().take(revealed.stack_items) {
+            match instruction {
+                Ok(Instruction::PushBytes(bytes)) => pushes.push(bytes.as_bytes().to_vec()),
+                _ => return Err(InputScriptError::NoScript),
+            }
+        }
+        Ok(pushes)
+    }
+
+    fn executed_script(&self) -> Result<RevealedScript<'_>, InputScriptError> {
+        let prevout = self.prevout().ok_or(InputScriptError::NoPrevout)?;
+        executed_script(self.tx, self.input_index, &prevout.script_pubkey)
+    }
+
+    /// The output spent by the input.
+    fn prevout(&self) -> Option<&TxOut> {
+        match self.prevouts {
+            [prevout] => Some(prevout),
+            prevouts => prevouts.get(self.input_index),
+        }
+    }
+
+    fn prevouts(&self) -> Option<Prevouts<'_, TxOut>> {
+        if self.prevouts.len() == self.tx.input.len() {
+            Some(Prevouts::All(self.prevouts))
+        } else if let [prevout] = self.prevouts {
+            Some(Prevouts::One(self.input_index, prevout.clone()))
+        } else {
+            None
+        }
+    }
+
+    fn amount(&self) -> Option<Amount> {
+        self.prevout().map(|p| p.value)
+    }
+
+    fn annex(&self) -> Option<Annex<'_>> {
+        let witness = &self.tx.input.get(self.input_index)?.witness;
+        let last = witness.last().filter(|_| witness.len() >= 2)?;
+        Annex::new(last).ok()
+    }
+
+    /// Verifies `sig` for `key`, with the sighash of the script `script`. Returns `None` if the
+    /// sighash can not be computed, or if the signature or key can not be parsed.
+    fn verify(
+        &self,
+        script: &bitcoin::Script,
+        version: ScriptVersion,
+        sig: &[u8],
+        key: &[u8],
+    ) -> Option<bool> {
+        let secp = Secp256k1::verification_only();
+        let mut cache = SighashCache::new(self.tx);
+        if version == ScriptVersion::SegwitV1 {
+            let (sig, sighash_type) = match *sig {
+                [ref sig @ .., sighash_type] if sig.len() == 64 => {
+                    (sig, TapSighashType::from_consensus_u8(sighash_type).ok()?)
+                }
+                _ => (sig, TapSighashType::Default),
+            };
+            let sig = schnorr::Signature::from_slice(sig).ok()?;
+            let key = XOnlyPublicKey::from_slice(key).ok()?;
+            let leaf_hash = TapLeafHash::from_script(script, LeafVersion::TapScript);
+            let sighash = cache
+                .taproot_signature_hash(
+                    self.input_index,
+                    &self.prevouts()?,
+                    self.annex(),
+                    Some((leaf_hash, u32::MAX)),
+                    sighash_type,
+                )
+                .ok()?;
+            let msg = Message::from_digest(sighash.to_byte_array());
+            return Some(secp.verify_schnorr(&sig, &msg, &key).is_ok());
+        }
+
+        let (&sighash_type, der) = sig.split_last()?;
+        let mut sig = ecdsa::Signature::from_der_lax(der).ok()?;
+        // like Bitcoin Core, high S values are accepted here, LOW_S is checked separately
+        sig.normalize_s();
+        let key = bitcoin::secp256k1::PublicKey::from_slice(key).ok()?;
+        let sighash = if version == ScriptVersion::SegwitV0 {
+            cache
+                .p2wsh_signature_hash(
+                    self.input_index,
+                    script,
+                    self.amount()?,
+                    EcdsaSighashType::from_consensus(sighash_type.into()),
+                )
+                .ok()?
+                .to_byte_array()
+        } else {
+            cache
+                .legacy_signature_hash(self.input_index, script, sighash_type.into())
+                .ok()?
+                .to_byte_array()
+        };
+        let msg = Message::from_digest(sighash);
+        Some(secp.verify_ecdsa(&msg, &sig, &key).is_ok())
+    }
+}
+
+impl Analysis {
+    /// Verifies the signatures of `OP_CHECKSIG`s where both the signature and the public key
+    /// are constants, for example because the initial stack was set to
+    /// [`SpendingTx::initial_stack`] with [`ScriptAnalyzer::builder`]. Verified signature checks
+    /// are replaced by their result and conditions that become true are removed, paths with a
+    /// condition that becomes false are moved to [`failed_paths`](Self::failed_paths). The
+    /// analysis must be of the script spent by the input, with the same `ctx`. Returns the
+    /// amount of verified signatures.
+    ///
+    /// The whole script is signed, `OP_CODESEPARATOR` is not taken into account.
+    ///
+    /// [`ScriptAnalyzer::builder`]: crate::ScriptAnalyzer::builder
+    pub fn verify_signatures(
+        &mut self,
+        ctx: ScriptContext,
+        spending: &SpendingTx<'_>,
+    ) -> Result<usize, InputScriptError> {
+        let script = bitcoin::Script::from_bytes(spending.executed_script()?.script);
+
+        let mut verified = 0;
+        let mut paths = Vec::new();
+        for mut path in self.paths.drain(..) {
+            let res = path
+                .spending_conditions
+                .iter_mut()
+                .try_for_each(|condition| {
+                    let error = condition.error();
+                    verified += verify_checksigs(condition, ctx, &mut |sig, key| {
+                        spending.verify(script, ctx.version, sig, key)
+                    })?;
+                    condition.eval(ctx)?;
+                    match condition {
+                        Expr::Bytes(b) if !decode_bool(&*b) => {
+                            Err(error.unwrap_or(ScriptError::SCRIPT_ERR_UNKNOWN_ERROR))
+                        }
+                        _ => Ok(()),
+                    }
+                });
+            match res {
+                Ok(()) => {
+                    path.spending_conditions
+                        .retain(|c| !matches!(c, Expr::Bytes(_)));
+                    paths.push(path);
+                }
+                Err(error) => self.failed_paths.push(FailedPath {
+                    branches: path.branches,
+                    error,
+                }),
+            }
+        }
+        self.paths = paths;
+        self.failed_paths
+            .sort_by(|a, b| cmp_branches(&a.branches, &b.branches));
+        Ok(verified)
+    }
+}
+
+/// Replaces the `OP_CHECKSIG`s in `expr` with a constant signature and public key by the result
+/// of `verify`, if it has one. Returns the amount of replaced signature checks.
+fn verify_checksigs(
+    expr: &mut Expr,
+    ctx: ScriptContext,
+    verify: &mut impl FnMut(&[u8], &[u8]) -> Option<bool>,
+) -> Result<usize, ScriptError> {
+    let Expr::Op(op) = expr else {
+        return Ok(0);
+    };
+    let mut verified = 0;
+    for arg in op.args_mut() {
+        verified += verify_checksigs(arg, ctx, verify)?;
+    }
+    let OpExprArgs::Args2(Opcode2::OP_CHECKSIG, args) = &op.args else {
+        return Ok(verified);
+    };
+    let [Expr::Bytes(sig), Expr::Bytes(key)] = &**args else {
+        return Ok(verified);
+    };
+    if sig.len() == 0 {
+        return Ok(verified);
+    }
+    let Some(valid) = verify(&sig[..], &key[..]) else {
+        return Ok(verified);
+    };
+    if !valid {
+        // a signature that is not empty must be valid
+        if let Some(error) = null_signature_error(ctx) {
+            return Err(error);
+        }
+    }
+    *expr = encode_bool_expr(valid);
+    Ok(verified + 1)
+}
+
+impl TryFrom<&BytesExprBox> for bitcoin::PublicKey {
+    type Error = bitcoin::key::FromSliceError;
+
+    fn try_from(bytes: &BytesExprBox) -> Result<Self, Self::Error> {
+        Self::from_slice(&bytes[..])
+    }
+}
+
+impl TryFrom<&BytesExprBox> for XOnlyPublicKey {
+    type Error = bitcoin::secp256k1::Error;
+
+    fn try_from(bytes: &BytesExprBox) -> Result<Self, Self::Error> {
+        Self::from_slice(&bytes[..])
+    }
+}
+
+macro_rules! impl_try_from_hash {
+    ($($hash:ident),*) => {
+        $(
+            impl TryFrom<&BytesExprBox> for $hash::Hash {
+                type Error = bitcoin::hashes::FromSliceError;
+
+                fn try_from(bytes: &BytesExprBox) -> Result<Self, Self::Error> {
+                    Self::from_slice(&bytes[..])
+                }
+            }
+        )*
+    };
+}
+
+impl_try_from_hash!(hash160, ripemd160, sha1, sha256, sha256d);
+
+#[cfg(test)]
+mod tests {
+    use super::{input_script, InputScriptError, SpendingTx};
+    use crate::{
+        analyze_script, expr::Expr, script_error::ScriptError, PushEncoding, ScriptAnalyzer,
+        ScriptContext, ScriptRules, ScriptVersion,
+    };
+    use bitcoin::{
+        absolute::LockTime,
+        hashes::{hash160, Hash},
+        secp256k1::{Message, Secp256k1, SecretKey},
+        sighash::{EcdsaSighashType, SighashCache},
+        transaction::Version,
+        Amount, ScriptBuf, Transaction, TxIn, TxOut, Witness,
+    };
+    use core::str::FromStr;
+
+    #[test]
+    fn test_input_script() {
+        // the generator point
+        let key = bitcoin::PublicKey::from_str(
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        )
+        .unwrap();
+        let digest = [0xab; 20];
+        let script = bitcoin::script::Builder::new()
+            .push_opcode(bitcoin::opcodes::all::OP_HASH160)
+            .push_slice(digest)
+            .push_opcode(bitcoin::opcodes::all::OP_EQUALVERIFY)
+            .push_key(&key)
+            .push_opcode(bitcoin::opcodes::all::OP_CHECKSIG)
+            .into_script();
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                witness: Witness::from_slice(&[&[][..], &[], script.as_bytes()]),
+                ..Default::default()
+            }],
+            output: Vec::new(),
+        };
+
+        let p2wsh = ScriptBuf::new_p2wsh(&script.wscript_hash());
+        let (owned, version) = input_script(&tx, 0, &p2wsh).unwrap();
+        assert_eq!(version, ScriptVersion::SegwitV0);
+        assert_eq!(owned.len(), 5);
+        assert!(input_script(&tx, 1, &p2wsh).is_err());
+
+        let ctx = ScriptContext::new(version, ScriptRules::All);
+        let analysis = analyze_script(&owned, ctx, Default::default()).unwrap();
+        let mut bytes = Vec::new();
+        for c in &analysis.paths[0].spending_conditions {
+            let Expr::Op(op) = c else { continue };
+            bytes.extend(op.args().iter().filter_map(|arg| match arg {
+                Expr::Bytes(b) => Some(b.clone()),
+                _ => None,
+            }));
+        }
+        assert!(bytes
+            .iter()
+            .any(|b| bitcoin::PublicKey::try_from(b) == Ok(key)));
+        assert!(bytes
+            .iter()
+            .any(|b| hash160::Hash::try_from(b) == Ok(hash160::Hash::from_byte_array(digest))));
+
+        let redeem_script = ScriptBuf::from_bytes(vec![0x51]);
+        let p2sh = ScriptBuf::new_p2sh(&redeem_script.script_hash());
+        let tx = Transaction {
+            input: vec![TxIn {
+                script_sig: ScriptBuf::builder().push_slice([0x51]).into_script(),
+                ..Default::default()
+            }],
+            ..tx
+        };
+        let (owned, version) = input_script(&tx, 0, &p2sh).unwrap();
+        assert_eq!(version, ScriptVersion::Legacy);
+        assert_eq!(owned.serialize(PushEncoding::Original), [0x51]);
+    }
+
+    #[test]
+    fn test_input_without_script() {
+        let key = bitcoin::PublicKey::from_str(
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        )
+        .unwrap();
+        let sig = [0x30; 72];
+        let input = |script_sig, witness: &[&[u8]]| Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                script_sig,
+                witness: Witness::from_slice(witness),
+                ..Default::default()
+            }],
+            output: Vec::new(),
+        };
+        let no_script = |tx: &Transaction, script_pubkey: &ScriptBuf| {
+            matches!(
+                input_script(tx, 0, script_pubkey),
+                Err(InputScriptError::NoScript)
+            )
+        };
+
+        // the public key is not a redeem script
+        let p2pkh = ScriptBuf::new_p2pkh(&key.pubkey_hash());
+        let script_sig = ScriptBuf::builder()
+            .push_slice(sig)
+            .push_key(&key)
+            .into_script();
+        assert!(no_script(&input(script_sig, &[]), &p2pkh));
+
+        // or a witness script
+        let p2wpkh = ScriptBuf::new_p2wpkh(&key.wpubkey_hash().unwrap());
+        let tx = input(ScriptBuf::new(), &[&sig, &key.to_bytes()]);
+        assert!(no_script(&tx, &p2wpkh));
+
+        // a key path spend with an annex
+        let p2tr = ScriptBuf::from_bytes([&[0x51, 0x20][..], &[0x11; 32]].concat());
+        let tx = input(ScriptBuf::new(), &[&[0x01; 64], &[0x50]]);
+        assert!(no_script(&tx, &p2tr));
+    }
+
+    #[test]
+    fn test_verify_signatures() {
+        let secp = Secp256k1::new();
+        let secret = SecretKey::from_slice(&[1; 32]).unwrap();
+        let key = bitcoin::PublicKey::new(secret.public_key(&secp));
+        let script = bitcoin::script::Builder::new()
+            .push_key(&key)
+            .push_opcode(bitcoin::opcodes::all::OP_CHECKSIG)
+            .into_script();
+        let prevout = TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: ScriptBuf::new_p2wsh(&script.wscript_hash()),
+        };
+        let mut tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![TxOut {
+                value: Amount::from_sat(9_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let sighash = SighashCache::new(&tx)
+            .p2wsh_signature_hash(0, &script, prevout.value, EcdsaSighashType::All)
+            .unwrap();
+        let msg = Message::from_digest(sighash.to_byte_array());
+        let mut sig = secp.sign_ecdsa(&msg, &secret).serialize_der().to_vec();
+        sig.push(EcdsaSighashType::All as u8);
+
+        let ctx = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All);
+        tx.input[0].witness = Witness::from_slice(&[&sig[..], script.as_bytes()]);
+        let prevouts = [prevout];
+        let analyze = |tx: &Transaction| {
+            let spending = SpendingTx {
+                tx,
+                input_index: 0,
+                prevouts: &prevouts,
+            };
+            let (script, _) = input_script(tx, 0, &prevouts[0].script_pubkey).unwrap();
+            let mut builder = ScriptAnalyzer::builder(&script);
+            for (pos, item) in spending.initial_stack().unwrap().iter().rev().enumerate() {
+                builder = builder.stack_item(pos as u32, Expr::bytes(item));
+            }
+            let mut analysis = builder.build().diagnose(ctx, Default::default()).unwrap();
+            let verified = analysis.verify_signatures(ctx, &spending).unwrap();
+            (analysis, verified)
+        };
+
+        let (analysis, verified) = analyze(&tx);
+        assert_eq!(verified, 1);
+        assert!(analysis.paths[0].spending_conditions.is_empty());
+
+        // signs another transaction
+        let mut other = tx.clone();
+        other.lock_time = LockTime::from_height(1).unwrap();
+        let (analysis, _) = analyze(&other);
+        assert!(analysis.paths.is_empty());
+        assert_eq!(
+            analysis.failed_paths[0].error,
+            ScriptError::SCRIPT_ERR_SIG_NULLFAIL
+        );
+    }
+}
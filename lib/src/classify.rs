@@ -0,0 +1,398 @@
+use crate::{
+    opcode::{opcodes, Opcode},
+    script::{Script, ScriptElem},
+};
+use core::fmt;
+
+/// Standard output scripts and common contract patterns, see [`classify`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptTemplate {
+    /// `<pubkey> OP_CHECKSIG`, also used as tapscript leaf
+    P2pk,
+    P2pkh,
+    P2sh,
+    P2wpkh,
+    P2wsh,
+    P2tr,
+    /// Bare `m`-of-`n` OP_CHECKMULTISIG
+    Multisig {
+        required: u8,
+        keys: u8,
+    },
+    /// OP_RETURN followed by data pushes
+    NullData,
+    /// Hashlock spendable by one key, timelock spendable by another key
+    Htlc {
+        relative: bool,
+    },
+    /// `<n> OP_CHECKLOCKTIMEVERIFY OP_DROP <pubkey> OP_CHECKSIG`, or with OP_CHECKSEQUENCEVERIFY
+    TimelockedPubkey {
+        relative: bool,
+    },
+    /// Multisig, or a single key after a timelock
+    TimelockedEscrow {
+        required: u8,
+        keys: u8,
+        relative: bool,
+    },
+    /// BOLT 3 `to_local` output
+    LightningToLocal,
+    /// BOLT 3 offered HTLC output, with or without `option_anchors`
+    LightningOfferedHtlc {
+        anchors: bool,
+    },
+    /// BOLT 3 anchor output
+    LightningAnchor,
+}
+
+impl fmt::Display for ScriptTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lock = |relative| if relative { "relative" } else { "absolute" };
+        match *self {
+            Self::P2pk => write!(f, "P2PK"),
+            Self::P2pkh => write!(f, "P2PKH"),
+            Self::P2sh => write!(f, "P2SH"),
+            Self::P2wpkh => write!(f, "P2WPKH"),
+            Self::P2wsh => write!(f, "P2WSH"),
+            Self::P2tr => write!(f, "P2TR"),
+            Self::Multisig { required, keys } => write!(f, "{required}-of-{keys} multisig"),
+            Self::NullData => write!(f, "OP_RETURN data"),
+            Self::Htlc { relative } => write!(f, "HTLC ({} timelock)", lock(relative)),
+            Self::TimelockedPubkey { relative } => {
+                write!(f, "public key with {} timelock", lock(relative))
+            }
+            Self::TimelockedEscrow {
+                required,
+                keys,
+                relative,
+            } => write!(
+                f,
+                "{required}-of-{keys} escrow with {} timelocked fallback",
+                lock(relative)
+            ),
+            Self::LightningToLocal => write!(f, "Lightning to_local"),
+            Self::LightningOfferedHtlc { anchors } => write!(
+                f,
+                "Lightning offered HTLC{}",
+                if anchors { " (anchors)" } else { "" }
+            ),
+            Self::LightningAnchor => write!(f, "Lightning anchor"),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Pattern {
+    Op(Opcode),
+    /// Data push of exactly this size
+    Push(usize),
+    /// Public key push (x-only, compressed or uncompressed)
+    Key,
+    /// 20 or 32 byte push
+    Digest,
+    /// Number push
+    Num,
+    /// OP_RIPEMD160, OP_SHA256, OP_HASH160 or OP_HASH256
+    Hash,
+    /// OP_CHECKLOCKTIMEVERIFY or OP_CHECKSEQUENCEVERIFY
+    Timelock,
+}
+
+impl Pattern {
+    fn matches(self, elem: ScriptElem) -> bool {
+        match (self, elem) {
+            (Self::Op(a), ScriptElem::Op(b)) => a == b,
+            (Self::Push(len), ScriptElem::Bytes(b)) => b.len() == len,
+            (Self::Key, ScriptElem::Bytes(b)) => matches!(b.len(), 32 | 33 | 65),
+            (Self::Digest, ScriptElem::Bytes(b)) => matches!(b.len(), 20 | 32),
+            (Self::Num, ScriptElem::Bytes(b)) => b.len() <= 5,
+            (Self::Num, ScriptElem::Op(op)) => small_int(op).is_some(),
+            (Self::Hash, ScriptElem::Op(op)) => matches!(
+                op,
+                opcodes::OP_RIPEMD160
+                    | opcodes::OP_SHA256
+                    | opcodes::OP_HASH160
+                    | opcodes::OP_HASH256
+            ),
+            (Self::Timelock, ScriptElem::Op(op)) => {
+                op == opcodes::OP_CHECKLOCKTIMEVERIFY || op == opcodes::OP_CHECKSEQUENCEVERIFY
+            }
+            _ => false,
+        }
+    }
+}
+
+fn matches(script: &[ScriptElem], pattern: &[Pattern]) -> bool {
+    script.len() == pattern.len() && script.iter().zip(pattern).all(|(&e, p)| p.matches(e))
+}
+
+/// Value of OP_0, OP_1NEGATE and OP_1..OP_16
+fn small_int(op: Opcode) -> Option<i8> {
+    match op {
+        opcodes::OP_0 => Some(0),
+        opcodes::OP_1NEGATE => Some(-1),
+        op if op >= opcodes::OP_1 && op <= opcodes::OP_16 => Some((op.opcode - 0x50) as i8),
+        _ => None,
+    }
+}
+
+/// For scripts that matched [`Pattern::Timelock`]: whether the timelock is relative.
+fn is_relative(script: &[ScriptElem]) -> bool {
+    !script
+        .iter()
+        .any(|e| matches!(e, ScriptElem::Op(opcodes::OP_CHECKLOCKTIMEVERIFY)))
+}
+
+/// Returns `(required, keys)` for `OP_m <pubkey>... OP_n OP_CHECKMULTISIG`.
+fn multisig(script: &[ScriptElem]) -> Option<(u8, u8)> {
+    let [ScriptElem::Op(m), keys @ .., ScriptElem::Op(n), ScriptElem::Op(opcodes::OP_CHECKMULTISIG)] =
+        script
+    else {
+        return None;
+    };
+    let m = small_int(*m)?;
+    let n = small_int(*n)?;
+    (m >= 1 && m <= n && keys.len() == n as usize && keys.iter().all(|&k| Pattern::Key.matches(k)))
+        .then_some((m as u8, n as u8))
+}
+
+/// Recognizes standard output script templates and some common contract patterns.
+pub fn classify(script: &Script) -> Option<ScriptTemplate> {
+    use self::Pattern::*;
+    use crate::opcode::opcodes::*;
+
+    let s = &**script;
+
+    let template = if matches(s, &[Key, Op(OP_CHECKSIG)]) {
+        ScriptTemplate::P2pk
+    } else if matches(
+        s,
+        &[
+            Op(OP_DUP),
+            Op(OP_HASH160),
+            Push(20),
+            Op(OP_EQUALVERIFY),
+            Op(OP_CHECKSIG),
+        ],
+    ) {
+        ScriptTemplate::P2pkh
+    } else if matches(s, &[Op(OP_HASH160), Push(20), Op(OP_EQUAL)]) {
+        ScriptTemplate::P2sh
+    } else if matches(s, &[Op(OP_0), Push(20)]) {
+        ScriptTemplate::P2wpkh
+    } else if matches(s, &[Op(OP_0), Push(32)]) {
+        ScriptTemplate::P2wsh
+    } else if matches(s, &[Op(OP_1), Push(32)]) {
+        ScriptTemplate::P2tr
+    } else if let Some((required, keys)) = multisig(s) {
+        ScriptTemplate::Multisig { required, keys }
+    } else if let [ScriptElem::Op(OP_RETURN), data @ ..] = s {
+        if data.iter().all(|&e| match e {
+            ScriptElem::Op(op) => small_int(op).is_some(),
+            ScriptElem::Bytes(_) => true,
+        }) {
+            ScriptTemplate::NullData
+        } else {
+            return None;
+        }
+    } else if matches(
+        s,
+        &[
+            Op(OP_IF),
+            Hash,
+            Digest,
+            Op(OP_EQUALVERIFY),
+            Key,
+            Op(OP_ELSE),
+            Num,
+            Timelock,
+            Op(OP_DROP),
+            Key,
+            Op(OP_ENDIF),
+            Op(OP_CHECKSIG),
+        ],
+    ) {
+        ScriptTemplate::Htlc {
+            relative: is_relative(s),
+        }
+    } else if matches(
+        s,
+        &[
+            Op(OP_IF),
+            Key,
+            Op(OP_ELSE),
+            Num,
+            Op(OP_CHECKSEQUENCEVERIFY),
+            Op(OP_DROP),
+            Key,
+            Op(OP_ENDIF),
+            Op(OP_CHECKSIG),
+        ],
+    ) {
+        ScriptTemplate::LightningToLocal
+    } else if matches(
+        s,
+        &[
+            Key,
+            Op(OP_CHECKSIG),
+            Op(OP_IFDUP),
+            Op(OP_NOTIF),
+            Op(OP_16),
+            Op(OP_CHECKSEQUENCEVERIFY),
+            Op(OP_ENDIF),
+        ],
+    ) {
+        ScriptTemplate::LightningAnchor
+    } else if let Some(anchors) = offered_htlc(s) {
+        ScriptTemplate::LightningOfferedHtlc { anchors }
+    } else if matches(s, &[Num, Timelock, Op(OP_DROP), Key, Op(OP_CHECKSIG)]) {
+        ScriptTemplate::TimelockedPubkey {
+            relative: is_relative(s),
+        }
+    } else if let [ScriptElem::Op(OP_IF), inner @ .., ScriptElem::Op(OP_ENDIF)] = s {
+        let (ms, fallback) = inner.split_at(inner.len().checked_sub(6)?);
+        let (required, keys) = multisig(ms)?;
+        if !matches(
+            fallback,
+            &[
+                Op(OP_ELSE),
+                Num,
+                Timelock,
+                Op(OP_DROP),
+                Key,
+                Op(OP_CHECKSIG),
+            ],
+        ) {
+            return None;
+        }
+        ScriptTemplate::TimelockedEscrow {
+            required,
+            keys,
+            relative: is_relative(s),
+        }
+    } else {
+        return None;
+    };
+
+    Some(template)
+}
+
+/// Matches a BOLT 3 offered HTLC, returns whether it is the `option_anchors` variant.
+fn offered_htlc(s: &[ScriptElem]) -> Option<bool> {
+    use self::Pattern::*;
+    use crate::opcode::opcodes::*;
+
+    let head = [
+        Op(OP_DUP),
+        Op(OP_HASH160),
+        Push(20),
+        Op(OP_EQUAL),
+        Op(OP_IF),
+        Op(OP_CHECKSIG),
+        Op(OP_ELSE),
+        Key,
+        Op(OP_SWAP),
+        Op(OP_SIZE),
+        Push(1),
+        Op(OP_EQUAL),
+        Op(OP_NOTIF),
+        Op(OP_DROP),
+        Op(OP_2),
+        Op(OP_SWAP),
+        Key,
+        Op(OP_2),
+        Op(OP_CHECKMULTISIG),
+        Op(OP_ELSE),
+        Op(OP_HASH160),
+        Push(20),
+        Op(OP_EQUALVERIFY),
+        Op(OP_CHECKSIG),
+        Op(OP_ENDIF),
+    ];
+    let anchors = [Op(OP_1), Op(OP_CHECKSEQUENCEVERIFY), Op(OP_DROP)];
+
+    if s.len() < head.len() || !matches(&s[..head.len()], &head) {
+        return None;
+    }
+    // the payment preimage is 32 bytes
+    if !matches!(s[10], ScriptElem::Bytes([32])) {
+        return None;
+    }
+    match &s[head.len()..] {
+        [ScriptElem::Op(OP_ENDIF)] => Some(false),
+        [rest @ .., ScriptElem::Op(OP_ENDIF)] if matches(rest, &anchors) => Some(true),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{classify, ScriptTemplate};
+    use crate::OwnedScript;
+
+    fn classify_asm(asm: &str) -> Option<ScriptTemplate> {
+        let mut buf = asm.as_bytes().to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        classify(&script)
+    }
+
+    #[test]
+    fn test_classify() {
+        let key = format!("<{}>", "02".repeat(33));
+        let hash20 = format!("<{}>", "aa".repeat(20));
+        let hash32 = format!("<{}>", "bb".repeat(32));
+
+        assert_eq!(
+            classify_asm(&format!(
+                "OP_DUP OP_HASH160 {hash20} OP_EQUALVERIFY OP_CHECKSIG"
+            )),
+            Some(ScriptTemplate::P2pkh)
+        );
+        assert_eq!(
+            classify_asm(&format!("1 {hash32}")),
+            Some(ScriptTemplate::P2tr)
+        );
+        assert_eq!(
+            classify_asm(&format!("2 {key} {key} {key} 3 OP_CHECKMULTISIG")),
+            Some(ScriptTemplate::Multisig {
+                required: 2,
+                keys: 3
+            })
+        );
+        assert_eq!(
+            classify_asm("OP_RETURN <deadbeef>"),
+            Some(ScriptTemplate::NullData)
+        );
+        assert_eq!(
+            classify_asm(&format!(
+                "OP_IF OP_SHA256 {hash32} OP_EQUALVERIFY {key} OP_ELSE 144 OP_CSV OP_DROP {key} OP_ENDIF OP_CHECKSIG"
+            )),
+            Some(ScriptTemplate::Htlc { relative: true })
+        );
+        assert_eq!(
+            classify_asm(&format!(
+                "OP_IF 2 {key} {key} 2 OP_CHECKMULTISIG OP_ELSE 800000 OP_CLTV OP_DROP {key} OP_CHECKSIG OP_ENDIF"
+            )),
+            Some(ScriptTemplate::TimelockedEscrow {
+                required: 2,
+                keys: 2,
+                relative: false
+            })
+        );
+        assert_eq!(
+            classify_asm(&format!(
+                "OP_DUP OP_HASH160 {hash20} OP_EQUAL OP_IF OP_CHECKSIG OP_ELSE {key} OP_SWAP \
+                 OP_SIZE 32 OP_EQUAL OP_NOTIF OP_DROP 2 OP_SWAP {key} 2 OP_CHECKMULTISIG OP_ELSE \
+                 OP_HASH160 {hash20} OP_EQUALVERIFY OP_CHECKSIG OP_ENDIF 1 OP_CSV OP_DROP OP_ENDIF"
+            )),
+            Some(ScriptTemplate::LightningOfferedHtlc { anchors: true })
+        );
+        assert_eq!(
+            classify_asm(&format!(
+                "{key} OP_CHECKSIG OP_IFDUP OP_NOTIF 16 OP_CSV OP_ENDIF"
+            )),
+            Some(ScriptTemplate::LightningAnchor)
+        );
+        assert_eq!(classify_asm(&format!("{key} OP_CHECKSIGVERIFY")), None);
+    }
+}
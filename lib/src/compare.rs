@@ -0,0 +1,266 @@
+use crate::{
+    analyze_error::AnalyzeError,
+    analyzer::{analyze_script, Analysis, AnalysisOptions, LocktimeRequirement, SpendingPath},
+    context::ScriptContext,
+    expr::Expr,
+    range::{constraint, ItemRange},
+    script::ScriptRef,
+};
+use core::fmt;
+
+/// A path of the old script matched with a path of the new script, by index.
+#[derive(Debug, Clone)]
+pub struct ChangedPath {
+    pub old: usize,
+    pub new: usize,
+    pub removed_conditions: Vec<Expr>,
+    pub added_conditions: Vec<Expr>,
+    /// Numeric ranges of stack items that are only in the old path, or differ.
+    pub removed_ranges: Vec<ItemRange>,
+    /// Numeric ranges of stack items that are only in the new path, or differ.
+    pub added_ranges: Vec<ItemRange>,
+    /// Old and new locktime requirement, if it changed.
+    pub locktime: Option<(String, String)>,
+    /// Old and new sequence requirement, if it changed.
+    pub sequence: Option<(String, String)>,
+    /// Old and new stack size, if it changed.
+    pub stack_size: Option<(u32, u32)>,
+}
+
+/// The spending paths of two scripts matched by their requirements, see [`compare_scripts`].
+/// Displays as a human readable report.
+#[derive(Debug, Clone)]
+pub struct ScriptComparison {
+    pub old: Analysis,
+    pub new: Analysis,
+    /// Paths with the same conditions and timelocks, as pairs of old and new index.
+    pub unchanged: Vec<(usize, usize)>,
+    pub changed: Vec<ChangedPath>,
+    /// Paths of the old script without a counterpart.
+    pub removed: Vec<usize>,
+    /// Paths of the new script without a counterpart.
+    pub added: Vec<usize>,
+}
+
+fn same_locktime(a: &LocktimeRequirement, b: &LocktimeRequirement) -> bool {
+    a.req == b.req && a.exprs == b.exprs && a.expr_ranges == b.expr_ranges
+}
+
+/// Conditions of `a` not in `b`. Conditions are compared structurally, where in the script they
+/// come from does not matter. Comparisons of stack items with constants are compared as
+/// [`ranges`](SpendingPath::ranges), so `x < 5` and `x <= 4` are the same.
+fn conditions_not_in(a: &SpendingPath, b: &SpendingPath) -> Vec<Expr> {
+    a.spending_conditions
+        .iter()
+        .filter(|c| constraint(c).is_none() && !b.spending_conditions.contains(c))
+        .cloned()
+        .collect()
+}
+
+fn ranges_not_in(a: &SpendingPath, b: &SpendingPath) -> Vec<ItemRange> {
+    a.ranges
+        .iter()
+        .filter(|r| !b.ranges.contains(r))
+        .copied()
+        .collect()
+}
+
+fn is_unchanged(a: &SpendingPath, b: &SpendingPath) -> bool {
+    a.stack_size == b.stack_size
+        && same_locktime(&a.locktime_req, &b.locktime_req)
+        && same_locktime(&a.sequence_req, &b.sequence_req)
+        && a.ranges == b.ranges
+        && conditions_not_in(a, b).is_empty()
+        && conditions_not_in(b, a).is_empty()
+}
+
+impl ScriptComparison {
+    /// Matches the paths of `old` and `new`. Identical paths are matched first, the remaining
+    /// paths are matched with the path sharing the most requirements with it, if any.
+    pub fn new(old: Analysis, new: Analysis) -> Self {
+        let mut old_left: Vec<usize> = (0..old.paths.len()).collect();
+        let mut new_left: Vec<usize> = (0..new.paths.len()).collect();
+
+        let mut unchanged = Vec::new();
+        old_left.retain(|&i| {
+            let matching = new_left
+                .iter()
+                .position(|&j| is_unchanged(&old.paths[i], &new.paths[j]));
+            match matching {
+                Some(pos) => {
+                    unchanged.push((i, new_left.remove(pos)));
+                    false
+                }
+                None => true,
+            }
+        });
+
+        let mut changed = Vec::new();
+        old_left.retain(|&i| {
+            let a = &old.paths[i];
+            let requirements = |p: &SpendingPath| {
+                p.ranges.len()
+                    + p.spending_conditions
+                        .iter()
+                        .filter(|c| constraint(c).is_none())
+                        .count()
+            };
+            let best = new_left
+                .iter()
+                .enumerate()
+                .map(|(pos, &j)| {
+                    let b = &new.paths[j];
+                    let shared =
+                        requirements(a) - conditions_not_in(a, b).len() - ranges_not_in(a, b).len();
+                    (pos, shared)
+                })
+                .filter(|&(_, shared)| shared != 0)
+                .max_by_key(|&(pos, shared)| (shared, core::cmp::Reverse(pos)));
+            let Some((pos, _)) = best else {
+                return true;
+            };
+
+            let j = new_left.remove(pos);
+            let b = &new.paths[j];
+            let locktime_change = |a: &LocktimeRequirement, b: &LocktimeRequirement, relative| {
+                let to_string = |req: &LocktimeRequirement| {
+                    req.locktime_requirement_to_string(relative)
+                        .unwrap_or_else(|| "none".to_owned())
+                };
+                (!same_locktime(a, b)).then(|| (to_string(a), to_string(b)))
+            };
+            changed.push(ChangedPath {
+                old: i,
+                new: j,
+                removed_conditions: conditions_not_in(a, b),
+                added_conditions: conditions_not_in(b, a),
+                removed_ranges: ranges_not_in(a, b),
+                added_ranges: ranges_not_in(b, a),
+                locktime: locktime_change(&a.locktime_req, &b.locktime_req, false),
+                sequence: locktime_change(&a.sequence_req, &b.sequence_req, true),
+                stack_size: (a.stack_size != b.stack_size).then_some((a.stack_size, b.stack_size)),
+            });
+            false
+        });
+
+        Self {
+            old,
+            new,
+            unchanged,
+            changed,
+            removed: old_left,
+            added: new_left,
+        }
+    }
+
+    /// Returns true if every path of one script has an identical path in the other.
+    pub fn is_equivalent(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty() && self.added.is_empty()
+    }
+}
+
+/// Analyzes both scripts and matches their spending paths, for example to check that an
+/// optimized script can be spent in the same ways as the original. Paths are compared by their
+/// simplified conditions, so scripts that compute the same conditions differently are
+/// equivalent. An error analyzing `old` is returned before one of `new`.
+pub fn compare_scripts<'a, 'b>(
+    old: impl Into<ScriptRef<'a>>,
+    new: impl Into<ScriptRef<'b>>,
+    ctx: ScriptContext,
+    opts: AnalysisOptions,
+) -> Result<ScriptComparison, AnalyzeError> {
+    let old = analyze_script(old, ctx, opts)?;
+    let new = analyze_script(new, ctx, opts)?;
+    Ok(ScriptComparison::new(old, new))
+}
+
+/// Paths are numbered from 1 like in the order of the analysis.
+impl fmt::Display for ScriptComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} unchanged, {} changed, {} removed, {} added",
+            self.unchanged.len(),
+            self.changed.len(),
+            self.removed.len(),
+            self.added.len()
+        )?;
+
+        for c in &self.changed {
+            write!(
+                f,
+                "\n\nChanged path (old #{}, new #{}):",
+                c.old + 1,
+                c.new + 1
+            )?;
+            if let Some((a, b)) = c.stack_size {
+                write!(f, "\nStack size: {a} -> {b}")?;
+            }
+            for range in &c.removed_ranges {
+                write!(f, "\n- {range}")?;
+            }
+            for cond in &c.removed_conditions {
+                write!(f, "\n- {cond}")?;
+            }
+            for range in &c.added_ranges {
+                write!(f, "\n+ {range}")?;
+            }
+            for cond in &c.added_conditions {
+                write!(f, "\n+ {cond}")?;
+            }
+            if let Some((a, b)) = &c.locktime {
+                write!(f, "\nLocktime requirement: {a} -> {b}")?;
+            }
+            if let Some((a, b)) = &c.sequence {
+                write!(f, "\nSequence requirement: {a} -> {b}")?;
+            }
+        }
+        for &i in &self.removed {
+            write!(
+                f,
+                "\n\nRemoved path (old #{}):\n{}",
+                i + 1,
+                self.old.paths[i]
+            )?;
+        }
+        for &j in &self.added {
+            write!(f, "\n\nAdded path (new #{}):\n{}", j + 1, self.new.paths[j])?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compare_scripts;
+    use crate::{OwnedScript, ScriptContext, ScriptRules, ScriptVersion};
+
+    fn compare(old: &str, new: &str) -> super::ScriptComparison {
+        let ctx = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All);
+        let mut old = old.as_bytes().to_vec();
+        let mut new = new.as_bytes().to_vec();
+        let (_, old) = OwnedScript::parse_from_asm_in_place(&mut old).unwrap();
+        let (_, new) = OwnedScript::parse_from_asm_in_place(&mut new).unwrap();
+        compare_scripts(&old, &new, ctx, Default::default()).unwrap()
+    }
+
+    #[test]
+    fn test_compare_scripts() {
+        // same comparison, written differently
+        let cmp = compare("5 OP_LESSTHAN", "4 OP_LESSTHANOREQUAL");
+        assert!(cmp.is_equivalent());
+        let cmp = compare("OP_DUP OP_DROP 1 OP_EQUAL", "1 OP_EQUAL");
+        assert!(cmp.is_equivalent());
+
+        let cmp = compare(
+            "OP_IF 5 OP_LESSTHAN OP_ELSE 1 OP_EQUAL OP_ENDIF",
+            "OP_IF 6 OP_LESSTHAN OP_ELSE 1 OP_EQUAL OP_ENDIF",
+        );
+        assert!(!cmp.is_equivalent());
+        assert_eq!(cmp.unchanged, [(1, 1)]);
+        assert_eq!(cmp.changed.len(), 1);
+        assert_eq!(cmp.changed[0].removed_ranges[0].range.max, 4);
+        assert_eq!(cmp.changed[0].added_ranges[0].range.max, 5);
+    }
+}
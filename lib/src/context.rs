@@ -1,3 +1,9 @@
+use crate::{
+    script::{OwnedScript, ParseScriptError, Script},
+    util::address::{p2sh_address, p2wsh_address},
+};
+use core::{fmt, ops};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ScriptVersion {
     Legacy,
@@ -5,20 +11,342 @@ pub enum ScriptVersion {
     SegwitV1,
 }
 
+impl ScriptVersion {
+    /// The version numbered `n` in the C and JavaScript interfaces: 0 for legacy, 1 for segwit v0
+    /// and 2 for tapscript.
+    pub const fn from_u32(n: u32) -> Option<Self> {
+        Some(match n {
+            0 => Self::Legacy,
+            1 => Self::SegwitV0,
+            2 => Self::SegwitV1,
+            _ => return None,
+        })
+    }
+}
+
+/// The network a script is used on, see [`ScriptContext::network`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Network {
+    #[default]
+    Mainnet,
+    Testnet,
+    Signet,
+    Regtest,
+}
+
+impl Network {
+    pub const ALL: [Self; 4] = [Self::Mainnet, Self::Testnet, Self::Signet, Self::Regtest];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Mainnet => "mainnet",
+            Self::Testnet => "testnet",
+            Self::Signet => "signet",
+            Self::Regtest => "regtest",
+        }
+    }
+
+    /// Looks up a network by its name, `bitcoin` is an alias of `mainnet`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("bitcoin") {
+            return Some(Self::Mainnet);
+        }
+        Self::ALL
+            .into_iter()
+            .find(|n| n.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Presets of [`VerifyFlags`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ScriptRules {
+    /// [`VerifyFlags::CONSENSUS`]
     ConsensusOnly,
+    /// [`VerifyFlags::STANDARD`]
     All,
 }
 
+impl ScriptRules {
+    /// The rules numbered `n` in the C and JavaScript interfaces: 0 for consensus rules only and 1
+    /// for all (standardness) rules.
+    pub const fn from_u32(n: u32) -> Option<Self> {
+        Some(match n {
+            0 => Self::ConsensusOnly,
+            1 => Self::All,
+            _ => return None,
+        })
+    }
+
+    pub const fn flags(self) -> VerifyFlags {
+        match self {
+            Self::ConsensusOnly => VerifyFlags::CONSENSUS,
+            Self::All => VerifyFlags::STANDARD,
+        }
+    }
+}
+
+/// Script verification flags, mirroring `SCRIPT_VERIFY_*` from Bitcoin Core (same bit values).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VerifyFlags(u32);
+
+macro_rules! verify_flags {
+    ($($(#[$attr:meta])* $k:ident = $v:expr),* $(,)?) => {
+        impl VerifyFlags {
+            $(
+                $(#[$attr])*
+                pub const $k: Self = Self($v);
+            )*
+
+            const NAMES: &'static [(&'static str, Self)] = &[$((stringify!($k), Self::$k)),*];
+        }
+    };
+}
+
+verify_flags! {
+    P2SH = 1 << 0,
+    /// Strict encoding of public keys and signature hash types.
+    STRICTENC = 1 << 1,
+    /// Strict DER encoding of signatures (BIP 66).
+    DERSIG = 1 << 2,
+    /// Signatures must have a low S value.
+    LOW_S = 1 << 3,
+    /// The dummy element of OP_CHECKMULTISIG must be empty (BIP 147).
+    NULLDUMMY = 1 << 4,
+    SIGPUSHONLY = 1 << 5,
+    /// Pushes must use the smallest possible encoding.
+    MINIMALDATA = 1 << 6,
+    /// OP_NOP1 and OP_NOP4-OP_NOP10 fail execution.
+    DISCOURAGE_UPGRADABLE_NOPS = 1 << 7,
+    /// Exactly one element must be left on the stack. Always enforced for segwit scripts.
+    CLEANSTACK = 1 << 8,
+    /// OP_CHECKLOCKTIMEVERIFY (BIP 65), OP_NOP2 when not set.
+    CHECKLOCKTIMEVERIFY = 1 << 9,
+    /// OP_CHECKSEQUENCEVERIFY (BIP 112), OP_NOP3 when not set.
+    CHECKSEQUENCEVERIFY = 1 << 10,
+    WITNESS = 1 << 11,
+    DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM = 1 << 12,
+    /// The argument of OP_IF and OP_NOTIF must be empty or `0x01` in segwit v0 scripts. Always
+    /// enforced for tapscript.
+    MINIMALIF = 1 << 13,
+    /// Failing signature checks must have an empty signature.
+    NULLFAIL = 1 << 14,
+    /// Public keys in segwit v0 scripts must be compressed.
+    WITNESS_PUBKEYTYPE = 1 << 15,
+    CONST_SCRIPTCODE = 1 << 16,
+    TAPROOT = 1 << 17,
+    DISCOURAGE_UPGRADABLE_TAPROOT_VERSION = 1 << 18,
+    DISCOURAGE_OP_SUCCESS = 1 << 19,
+    /// Public keys with an unknown type in tapscript fail execution.
+    DISCOURAGE_UPGRADABLE_PUBKEYTYPE = 1 << 20,
+}
+
+impl VerifyFlags {
+    pub const NONE: Self = Self(0);
+
+    /// Flags enforced by consensus for all blocks after the taproot activation.
+    pub const CONSENSUS: Self = Self(
+        Self::P2SH.0
+            | Self::DERSIG.0
+            | Self::NULLDUMMY.0
+            | Self::CHECKLOCKTIMEVERIFY.0
+            | Self::CHECKSEQUENCEVERIFY.0
+            | Self::WITNESS.0
+            | Self::TAPROOT.0,
+    );
+
+    /// Flags enforced by Bitcoin Core's standardness (relay) policy, includes
+    /// [`CONSENSUS`](Self::CONSENSUS).
+    pub const STANDARD: Self = Self(
+        Self::CONSENSUS.0
+            | Self::STRICTENC.0
+            | Self::MINIMALDATA.0
+            | Self::DISCOURAGE_UPGRADABLE_NOPS.0
+            | Self::CLEANSTACK.0
+            | Self::MINIMALIF.0
+            | Self::NULLFAIL.0
+            | Self::LOW_S.0
+            | Self::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM.0
+            | Self::WITNESS_PUBKEYTYPE.0
+            | Self::CONST_SCRIPTCODE.0
+            | Self::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION.0
+            | Self::DISCOURAGE_OP_SUCCESS.0
+            | Self::DISCOURAGE_UPGRADABLE_PUBKEYTYPE.0,
+    );
+
+    pub const ALL: Self = Self((1 << 21) - 1);
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Unknown bits are dropped.
+    pub const fn from_bits_truncate(bits: u32) -> Self {
+        Self(bits & Self::ALL.0)
+    }
+
+    /// Looks up a flag by its name, with or without the `SCRIPT_VERIFY_` prefix.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.strip_prefix("SCRIPT_VERIFY_").unwrap_or(name);
+        Self::NAMES
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|&(_, flag)| flag)
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        *self = self.union(other);
+    }
+
+    pub fn remove(&mut self, other: Self) {
+        *self = self.difference(other);
+    }
+
+    pub fn set(&mut self, other: Self, value: bool) {
+        if value {
+            self.insert(other);
+        } else {
+            self.remove(other);
+        }
+    }
+
+    /// Iterates over the names of the flags that are set.
+    pub fn names(self) -> impl Iterator<Item = &'static str> {
+        Self::NAMES
+            .iter()
+            .filter(move |&&(_, flag)| self.contains(flag))
+            .map(|&(name, _)| name)
+    }
+}
+
+impl Default for VerifyFlags {
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
+impl From<ScriptRules> for VerifyFlags {
+    fn from(rules: ScriptRules) -> Self {
+        rules.flags()
+    }
+}
+
+impl ops::BitOr for VerifyFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        self.union(rhs)
+    }
+}
+
+impl ops::BitOrAssign for VerifyFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.insert(rhs);
+    }
+}
+
+impl ops::BitAnd for VerifyFlags {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl ops::Sub for VerifyFlags {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        self.difference(rhs)
+    }
+}
+
+impl fmt::Display for VerifyFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "NONE");
+        }
+        for (i, name) in self.names().enumerate() {
+            if i != 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{name}")?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ScriptContext {
     pub version: ScriptVersion,
-    pub rules: ScriptRules,
+    pub flags: VerifyFlags,
+    /// Used for addresses, [`Network::Mainnet`] unless set with
+    /// [`with_network`](Self::with_network).
+    pub network: Network,
 }
 
 impl ScriptContext {
-    pub fn new(version: ScriptVersion, rules: ScriptRules) -> Self {
-        Self { version, rules }
+    pub const fn new(version: ScriptVersion, rules: ScriptRules) -> Self {
+        Self::with_flags(version, rules.flags())
+    }
+
+    pub const fn with_flags(version: ScriptVersion, flags: VerifyFlags) -> Self {
+        Self {
+            version,
+            flags,
+            network: Network::Mainnet,
+        }
+    }
+
+    /// Sets the network, used for addresses. The rules are the same on every network.
+    pub const fn with_network(self, network: Network) -> Self {
+        Self { network, ..self }
+    }
+
+    pub const fn has(&self, flags: VerifyFlags) -> bool {
+        self.flags.contains(flags)
+    }
+
+    /// Parses a script that is used in this context, like
+    /// [`OwnedScript::parse_from_bytes_checked`] with these flags, so non-minimal pushes are an
+    /// error if this context requires minimal pushes. Other warnings are dropped.
+    pub fn parse_script<'a>(&self, bytes: &'a [u8]) -> Result<OwnedScript<'a>, ParseScriptError> {
+        OwnedScript::parse_from_bytes_checked(bytes, self.flags).map(|(script, _)| script)
+    }
+
+    /// The address that pays to `script` in this context: P2SH for legacy scripts and P2WSH for
+    /// segwit v0 scripts, assuming minimal pushes. `None` for tapscript, its address depends on
+    /// the internal key and the other leaves, see [`p2tr_address`](crate::util::address).
+    pub fn address(&self, script: &Script<'_>) -> Option<String> {
+        match self.version {
+            ScriptVersion::Legacy => Some(p2sh_address(script, self.network)),
+            ScriptVersion::SegwitV0 => Some(p2wsh_address(script, self.network)),
+            ScriptVersion::SegwitV1 => None,
+        }
     }
 }
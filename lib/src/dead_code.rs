@@ -0,0 +1,232 @@
+use crate::{
+    analyze_error::{AnalysisLimit, AnalyzeError},
+    analyzer::{check_script, AnalysisOptions, ScriptAnalyzer},
+    context::ScriptContext,
+    expr::Origin,
+    opcode::opcodes,
+    script::{Script, ScriptElem, ScriptRef},
+};
+use core::{fmt, ops::Range};
+
+/// Why a part of a script is never executed, see [`DeadCode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeadCodeReason {
+    /// The argument of the `OP_IF` or `OP_NOTIF` is the same on every path that reaches it, so
+    /// the branch after `branch` (the `OP_IF`, `OP_NOTIF` or `OP_ELSE`) is never taken.
+    ConstantCondition { branch: Origin },
+    /// The branch after `branch` is only taken on paths with spending conditions that contradict
+    /// each other.
+    Contradiction { branch: Origin },
+    /// Every path that gets here has failed before.
+    AfterFailure,
+}
+
+impl fmt::Display for DeadCodeReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ConstantCondition { branch } => write!(
+                f,
+                "the branch after {branch} is never taken, its condition is constant"
+            ),
+            Self::Contradiction { branch } => write!(
+                f,
+                "the branch after {branch} is only taken on paths with contradicting conditions"
+            ),
+            Self::AfterFailure => write!(f, "every path fails before it"),
+        }
+    }
+}
+
+/// A range of script elements that no path executes, see [`find_dead_code`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadCode {
+    /// Indices of the script elements.
+    pub elements: Range<usize>,
+    /// Offsets in the serialized script, assuming minimal pushes.
+    pub bytes: Range<usize>,
+    pub reason: DeadCodeReason,
+}
+
+impl fmt::Display for DeadCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "bytes {}..{} are never executed, {}",
+            self.bytes.start, self.bytes.end, self.reason
+        )
+    }
+}
+
+/// Returns true if the spending conditions collected so far can all be true.
+fn is_possible(analyzer: &ScriptAnalyzer<'_>, ctx: ScriptContext) -> bool {
+    analyzer.clone().eval_conditions(ctx, None).is_ok()
+}
+
+/// Index of the `OP_IF` or `OP_NOTIF` that the `OP_ELSE` at `index` belongs to.
+fn matching_if(script: &Script<'_>, index: usize) -> Option<usize> {
+    let mut depth = 0;
+    for i in (0..index).rev() {
+        match script[i] {
+            ScriptElem::Op(opcodes::OP_ENDIF) => depth += 1,
+            ScriptElem::Op(opcodes::OP_IF | opcodes::OP_NOTIF) if depth == 0 => return Some(i),
+            ScriptElem::Op(opcodes::OP_IF | opcodes::OP_NOTIF) => depth -= 1,
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Finds the parts of `script` that are never executed, branches of an `OP_IF` with a constant
+/// argument or with conditions that contradict earlier ones, and code after an opcode that always
+/// fails. Paths are followed until their conditions contradict, so code after an impossible
+/// `OP_VERIFY` is dead too. Unlike [`analyze_script`](crate::analyze_script), this also works on
+/// unspendable scripts. Of the limits in `opts`, only `max_forks` is used.
+pub fn find_dead_code<'a>(
+    script: impl Into<ScriptRef<'a>>,
+    ctx: ScriptContext,
+    opts: AnalysisOptions,
+) -> Result<Vec<DeadCode>, AnalyzeError> {
+    let script_ref = script.into();
+    let script = script_ref.script;
+    check_script(script_ref, ctx)?;
+
+    let mut live = vec![false; script.len()];
+    // the `OP_IF`s and `OP_NOTIF`s where execution forked on some path
+    let mut forked = vec![false; script.len()];
+    let mut forks = 0;
+    let mut paths = vec![ScriptAnalyzer::from_script(script_ref)];
+    while let Some(mut path) = paths.pop() {
+        while !path.done() {
+            let index = path.script_offset();
+            let executing = path.executing();
+            let conditions = path.spending_conditions().len();
+            let res = path.step(ctx);
+            // the end of a branch that is not executed belongs to the code around it
+            let resumes = matches!(
+                script[index],
+                ScriptElem::Op(opcodes::OP_ELSE | opcodes::OP_ENDIF)
+            ) && path.executing();
+            live[index] |= executing || resumes;
+
+            let Ok(fork) = res else {
+                break;
+            };
+            if let Some(fork) = fork {
+                forks += 1;
+                if opts.max_forks.is_some_and(|max| forks > max) {
+                    return Err(AnalyzeError::LimitExceeded(AnalysisLimit::Forks));
+                }
+                forked[index] = true;
+                if is_possible(&fork, ctx) {
+                    paths.push(fork);
+                }
+            }
+            if path.spending_conditions().len() != conditions && !is_possible(&path, ctx) {
+                break;
+            }
+        }
+    }
+
+    let mut offsets: Vec<_> = script_ref.instructions().map(|i| i.offset).collect();
+    offsets.push(script_ref.serialized_len());
+    let origin = |index: usize| {
+        let ScriptElem::Op(opcode) = script[index] else {
+            unreachable!("branches start at an opcode");
+        };
+        Origin {
+            opcode,
+            index,
+            offset: offsets[index],
+        }
+    };
+
+    let mut dead_code = Vec::new();
+    let mut index = 0;
+    while index < script.len() {
+        if live[index] {
+            index += 1;
+            continue;
+        }
+        let start = index;
+        while index < script.len() && !live[index] {
+            index += 1;
+        }
+
+        let branch = start.checked_sub(1).filter(|&i| {
+            matches!(
+                script[i],
+                ScriptElem::Op(opcodes::OP_IF | opcodes::OP_NOTIF | opcodes::OP_ELSE)
+            )
+        });
+        let reason = match branch {
+            Some(branch) => {
+                let condition = match script[branch] {
+                    ScriptElem::Op(opcodes::OP_ELSE) => matching_if(script, branch),
+                    _ => Some(branch),
+                };
+                let branch = origin(branch);
+                if condition.is_some_and(|i| forked[i]) {
+                    DeadCodeReason::Contradiction { branch }
+                } else {
+                    DeadCodeReason::ConstantCondition { branch }
+                }
+            }
+            None => DeadCodeReason::AfterFailure,
+        };
+        dead_code.push(DeadCode {
+            elements: start..index,
+            bytes: offsets[start]..offsets[index],
+            reason,
+        });
+    }
+
+    Ok(dead_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_dead_code, DeadCodeReason};
+    use crate::{opcodes, OwnedScript, ScriptContext, ScriptRules, ScriptVersion};
+
+    fn dead_code(script: &str) -> Vec<super::DeadCode> {
+        let ctx = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All);
+        let mut buf = script.as_bytes().to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        find_dead_code(&script, ctx, Default::default()).unwrap()
+    }
+
+    #[test]
+    fn test_find_dead_code() {
+        let dead = dead_code("1 OP_IF OP_DUP OP_ELSE OP_SHA256 OP_DROP OP_ENDIF OP_CHECKSIG");
+        assert_eq!(dead.len(), 1);
+        assert_eq!(
+            (dead[0].elements.clone(), dead[0].bytes.clone()),
+            (4..6, 4..6)
+        );
+        assert!(matches!(
+            dead[0].reason,
+            DeadCodeReason::ConstantCondition { branch } if branch.opcode == opcodes::OP_ELSE
+        ));
+
+        // nested branches are part of the dead range
+        let dead = dead_code("0 OP_IF OP_IF OP_DUP OP_ENDIF OP_ENDIF 1");
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].elements, 2..5);
+
+        let dead = dead_code(
+            "OP_DUP 5 OP_LESSTHAN OP_VERIFY 10 OP_GREATERTHAN OP_IF OP_RETURN OP_ENDIF 1",
+        );
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].elements, 7..8);
+        assert!(matches!(
+            dead[0].reason,
+            DeadCodeReason::Contradiction { .. }
+        ));
+
+        let dead = dead_code("OP_RETURN OP_DUP <abcd>");
+        assert_eq!(dead[0].bytes, 1..5);
+        assert_eq!(dead[0].reason, DeadCodeReason::AfterFailure);
+
+        assert!(dead_code("OP_IF OP_DUP OP_ELSE OP_SHA256 OP_ENDIF OP_DROP 1").is_empty());
+    }
+}
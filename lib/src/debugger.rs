@@ -0,0 +1,171 @@
+use crate::{
+    analyzer::{check_script, ScriptAnalyzer},
+    context::ScriptContext,
+    expr::Expr,
+    script::{ScriptElem, ScriptRef},
+    script_error::ScriptError,
+};
+
+/// The state after executing a single script element, returned by [`ScriptDebugger::step`].
+pub struct DebugStep<'a> {
+    /// Index of the element in the script.
+    pub index: usize,
+    /// Byte offset of the element in the serialized script.
+    pub offset: usize,
+    pub elem: ScriptElem<'a>,
+    /// False if the element was skipped because it is in a branch that is not executed.
+    pub executed: bool,
+    /// Stack after executing the element, top item last. Only items that have been touched are
+    /// included, items below are initial stack items.
+    pub stack: Vec<Expr>,
+    pub altstack: Vec<Expr>,
+    /// When the executed element depends on an unknown value, for example an `OP_IF` on a stack
+    /// item, this debugger continues the alternative path.
+    pub fork: Option<ScriptDebugger<'a>>,
+}
+
+/// Executes a script symbolically, one element at a time, for btcdeb-style stepping.
+///
+/// Only a single path is followed. Every time execution forks, the [`DebugStep`] contains a new
+/// debugger for the other path.
+#[derive(Clone)]
+pub struct ScriptDebugger<'a> {
+    analyzer: ScriptAnalyzer<'a>,
+    ctx: ScriptContext,
+    error: Option<ScriptError>,
+}
+
+impl<'a> ScriptDebugger<'a> {
+    pub fn new(script: impl Into<ScriptRef<'a>>, ctx: ScriptContext) -> Self {
+        Self::from_analyzer(ScriptAnalyzer::from_script(script.into()), ctx)
+    }
+
+    /// Continues execution from the state of `analyzer`, for example one with known stack items
+    /// created with [`ScriptAnalyzer::builder`].
+    pub fn from_analyzer(analyzer: ScriptAnalyzer<'a>, ctx: ScriptContext) -> Self {
+        let error = check_script(analyzer.script_ref(), ctx)
+            .err()
+            .map(|err| err.script_error());
+        Self {
+            analyzer,
+            ctx,
+            error,
+        }
+    }
+
+    /// Index of the next element to execute.
+    pub fn index(&self) -> usize {
+        self.analyzer.script_offset()
+    }
+
+    pub fn stack(&self) -> &[Expr] {
+        self.analyzer.stack()
+    }
+
+    pub fn altstack(&self) -> &[Expr] {
+        self.analyzer.altstack()
+    }
+
+    /// The (unsimplified) conditions collected so far on this path.
+    pub fn spending_conditions(&self) -> &[Expr] {
+        self.analyzer.spending_conditions()
+    }
+
+    /// Executes the next script element. Returns `None` when the whole script was executed or an
+    /// error occurred previously (this includes errors found when checking the whole script before
+    /// execution, [`finish`] returns these).
+    ///
+    /// [`finish`]: Self::finish
+    pub fn step(&mut self) -> Option<Result<DebugStep<'a>, ScriptError>> {
+        if self.error.is_some() || self.analyzer.done() {
+            return None;
+        }
+
+        let index = self.analyzer.script_offset();
+        let offset = self.analyzer.byte_offset();
+        let elem = self.analyzer.script()[index];
+        let executed = self.analyzer.executing();
+
+        Some(match self.analyzer.step(self.ctx) {
+            Ok(fork) => Ok(DebugStep {
+                index,
+                offset,
+                elem,
+                executed,
+                stack: self.stack().to_vec(),
+                altstack: self.altstack().to_vec(),
+                fork: fork.map(|analyzer| Self {
+                    analyzer,
+                    ctx: self.ctx,
+                    error: None,
+                }),
+            }),
+            Err(err) => {
+                self.error = Some(err);
+                Err(err)
+            }
+        })
+    }
+
+    /// Executes the remaining elements on the current path (ignoring forks), checks the final
+    /// state and returns the simplified spending conditions of this path.
+    pub fn finish(mut self) -> Result<Vec<Expr>, ScriptError> {
+        while let Some(res) = self.step() {
+            res?;
+        }
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        self.analyzer.finish(self.ctx)?;
+        self.analyzer.eval_conditions(self.ctx, None)?;
+
+        Ok(self.analyzer.spending_conditions().to_vec())
+    }
+}
+
+impl<'a> Iterator for ScriptDebugger<'a> {
+    type Item = Result<DebugStep<'a>, ScriptError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.step()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScriptDebugger;
+    use crate::{expr::Expr, OwnedScript, ScriptContext, ScriptRules, ScriptVersion};
+
+    #[test]
+    fn test_debugger_steps() {
+        let mut buf = b"OP_IF OP_DUP OP_ELSE 2 OP_ENDIF OP_TOALTSTACK".to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let ctx = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All);
+
+        let mut debugger = ScriptDebugger::new(&script, ctx);
+
+        let step = debugger.step().unwrap().unwrap();
+        assert_eq!((step.index, step.offset), (0, 0));
+        assert!(step.stack.is_empty());
+        let mut fork = step.fork.unwrap();
+
+        let step = debugger.step().unwrap().unwrap();
+        assert!(step.executed);
+        assert_eq!(step.stack, [Expr::stack(1), Expr::stack(1)]);
+
+        let step = fork.nth(1).unwrap().unwrap();
+        assert!(!step.executed);
+        let step = fork.nth(1).unwrap().unwrap();
+        assert_eq!(step.stack, [Expr::bytes(&[2])]);
+        let step = fork.next().unwrap().unwrap();
+        assert_eq!(step.altstack, [Expr::bytes(&[2])]);
+        assert!(fork.next().is_none());
+
+        assert_eq!(debugger.count(), 4);
+
+        let mut buf = b"<0102> OP_DROP".to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let step = ScriptDebugger::new(&script, ctx).nth(1).unwrap().unwrap();
+        assert_eq!((step.index, step.offset), (1, 3));
+    }
+}
@@ -0,0 +1,139 @@
+use crate::{
+    analyzer::{Analysis, BranchDecision},
+    report::ReportOptions,
+    util::escape_with,
+};
+use std::fmt::Write;
+
+/// Escapes a string for a quoted Graphviz label, lines are left aligned.
+fn escape(s: &str) -> String {
+    escape_with(s, |c| match c {
+        '"' => Some("\\\""),
+        '\\' => Some("\\\\"),
+        '\n' => Some("\\l"),
+        _ => None,
+    })
+}
+
+struct Graph<'a> {
+    out: String,
+    /// Decision nodes, identified by the branches taken before reaching them.
+    decisions: Vec<&'a [BranchDecision]>,
+    leaves: usize,
+}
+
+impl<'a> Graph<'a> {
+    /// Adds the decisions of a path to the tree and returns the node the leaf of the path
+    /// attaches to, with the label of the edge.
+    fn add_branches(&mut self, branches: &'a [BranchDecision]) -> (String, Option<bool>) {
+        let mut parent = "start".to_owned();
+        let mut edge_label = None;
+        for (i, branch) in branches.iter().enumerate() {
+            let prefix = &branches[..i];
+            let id = match self.decisions.iter().position(|&d| d == prefix) {
+                Some(id) => id,
+                None => {
+                    let id = self.decisions.len();
+                    self.decisions.push(prefix);
+                    let mut label = branch.origin.to_string();
+                    if let Some(index) = branch.pick_index {
+                        write!(label, " index {index}").unwrap();
+                    }
+                    writeln!(
+                        self.out,
+                        "  d{id} [label=\"{}\", shape=diamond];",
+                        escape(&label)
+                    )
+                    .unwrap();
+                    self.edge(&parent, &format!("d{id}"), edge_label);
+                    id
+                }
+            };
+            parent = format!("d{id}");
+            edge_label = Some(branch.condition);
+        }
+        (parent, edge_label)
+    }
+
+    fn edge(&mut self, from: &str, to: &str, label: Option<bool>) {
+        match label {
+            Some(condition) => {
+                writeln!(self.out, "  {from} -> {to} [label=\"{condition}\"];").unwrap()
+            }
+            None => writeln!(self.out, "  {from} -> {to};").unwrap(),
+        }
+    }
+
+    fn leaf(&mut self, branches: &'a [BranchDecision], label: &str, attrs: &str) {
+        let (parent, edge_label) = self.add_branches(branches);
+        let id = format!("p{}", self.leaves);
+        self.leaves += 1;
+        writeln!(self.out, "  {id} [label=\"{}\\l\"{attrs}];", escape(label)).unwrap();
+        self.edge(&parent, &id, edge_label);
+    }
+}
+
+impl Analysis {
+    /// Renders the tree of branches taken by the spending paths in the Graphviz DOT language.
+    /// Decisions of conditional opcodes are diamonds, the leaves list the requirements of a
+    /// spending path, or the error of a failed path (only collected by
+    /// [`diagnose_script`](crate::diagnose_script)).
+    pub fn to_dot(&self) -> String {
+        let mut graph = Graph {
+            out: "digraph script {\n  node [shape=box, fontname=\"monospace\"];\n  start \
+                  [shape=circle];\n"
+                .to_owned(),
+            decisions: Vec::new(),
+            leaves: 0,
+        };
+
+        for (i, path) in self.paths.iter().enumerate() {
+            let mut label = format!("Spending path #{}", i + 1);
+            for req in path.requirements(ReportOptions::default()) {
+                write!(label, "\n{req}").unwrap();
+            }
+            if let Some(locktime) = path.locktime_req.locktime_requirement_to_string(false) {
+                write!(label, "\nLocktime: {locktime}").unwrap();
+            }
+            if let Some(sequence) = path.sequence_req.locktime_requirement_to_string(true) {
+                write!(label, "\nSequence: {sequence}").unwrap();
+            }
+            graph.leaf(&path.branches, &label, "");
+        }
+        for path in &self.failed_paths {
+            graph.leaf(
+                &path.branches,
+                &format!("Fails: {}", path.error),
+                ", color=red, fontcolor=red",
+            );
+        }
+
+        graph.out.push_str("}\n");
+        graph.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        analyze_script, AnalysisOptions, OwnedScript, ScriptContext, ScriptRules, ScriptVersion,
+    };
+
+    #[test]
+    fn test_dot_branch_tree() {
+        let mut buf = b"OP_IF OP_IF 1 OP_ELSE 2 OP_ENDIF OP_ELSE 3 OP_ENDIF".to_vec();
+        let (bytes, _) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let script = OwnedScript::parse_from_bytes(bytes).unwrap();
+        let ctx = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All);
+        let dot = analyze_script(&script, ctx, AnalysisOptions::default())
+            .unwrap()
+            .to_dot();
+
+        // two decisions, the nested one is only reached when the first is true
+        assert_eq!(dot.matches("shape=diamond").count(), 2);
+        assert_eq!(dot.matches("Spending path #").count(), 3);
+        assert!(dot.contains("start -> d0;"));
+        assert!(dot.contains("d0 -> d1 [label=\"true\"];"));
+        assert!(dot.ends_with("}\n"));
+    }
+}
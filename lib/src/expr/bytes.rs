@@ -3,13 +3,14 @@ use core::{
     ops::{Deref, Index},
     slice::SliceIndex,
 };
+use std::sync::Arc;
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
-pub struct BytesExprBox(Box<[u8]>);
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BytesExprBox(Arc<[u8]>);
 
 impl BytesExprBox {
     pub fn new(bytes: Box<[u8]>) -> Self {
-        Self(bytes)
+        Self(bytes.into())
     }
 }
 
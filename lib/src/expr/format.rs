@@ -0,0 +1,364 @@
+use super::{Expr, OpExprArgs, Opcode1, Opcode2, Opcode3};
+use crate::script::convert::decode_int_unchecked;
+use core::fmt;
+use std::collections::BTreeMap;
+
+/// Human readable names for byte constants, like public keys and hashes, see
+/// [`ExprFormat::labels`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Labels {
+    labels: BTreeMap<Box<[u8]>, String>,
+}
+
+impl Labels {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names `bytes` `label`, replacing a previous label.
+    pub fn insert(&mut self, bytes: &[u8], label: impl Into<String>) {
+        self.labels.insert(bytes.into(), label.into());
+    }
+
+    pub fn get(&self, bytes: &[u8]) -> Option<&str> {
+        self.labels.get(bytes).map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+}
+
+/// Options for rendering an [`Expr`] with [`Expr::display`]. The default renders like the
+/// [`Display`](fmt::Display) impl of [`Expr`]: `OP_ADD(<stack item #0>, <01>)`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExprFormat<'a> {
+    /// Operators in infix notation and other opcodes as lowercase functions:
+    /// `(a + b) < c`, `sha256(a) == b`.
+    pub infix: bool,
+    /// Names stack items `witness[0]` instead of `<stack item #0>`. Stack item 0 is the top of
+    /// the initial stack, which is the last item of the witness.
+    pub witness_labels: bool,
+    /// Shows arguments of numeric opcodes in decimal.
+    pub numbers: bool,
+    /// Byte strings longer than this are truncated, ending with `…`.
+    pub max_bytes: Option<usize>,
+    /// Byte strings of the size of a hash or public key (20, 32 or 33 bytes) are shortened to
+    /// their first and last 4 bytes.
+    pub abbreviate_hashes: bool,
+    /// Byte strings with a label are written as their label: `OP_CHECKSIG(<stack item #0>,
+    /// Alice)`.
+    pub labels: Option<&'a Labels>,
+}
+
+impl ExprFormat<'_> {
+    /// Infix notation with labels and numbers, hashes and keys are truncated to 4 bytes.
+    pub fn human() -> Self {
+        Self {
+            infix: true,
+            witness_labels: true,
+            numbers: true,
+            max_bytes: Some(4),
+            abbreviate_hashes: false,
+            labels: None,
+        }
+    }
+}
+
+/// Displays an [`Expr`] according to an [`ExprFormat`], returned by [`Expr::display`].
+pub struct ExprDisplay<'a> {
+    expr: &'a Expr,
+    format: ExprFormat<'a>,
+}
+
+impl Expr {
+    pub fn display<'a>(&'a self, format: ExprFormat<'a>) -> ExprDisplay<'a> {
+        ExprDisplay { expr: self, format }
+    }
+}
+
+impl fmt::Display for ExprDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_expr(f, self.expr, self.format, false, 0)
+    }
+}
+
+// operator precedences, higher binds tighter. top level expressions are written with 0, so they
+// never need parentheses
+const PREC_BOOLOR: u8 = 1;
+const PREC_BOOLAND: u8 = 2;
+const PREC_EQUALITY: u8 = 3;
+const PREC_COMPARISON: u8 = 4;
+const PREC_ADDITIVE: u8 = 5;
+const PREC_UNARY: u8 = 6;
+
+/// Writes `expr`, in parentheses if its precedence is lower than `min_prec`. `numeric` is true
+/// for arguments of opcodes that interpret their arguments as numbers.
+fn write_expr(
+    f: &mut fmt::Formatter<'_>,
+    expr: &Expr,
+    format: ExprFormat<'_>,
+    numeric: bool,
+    min_prec: u8,
+) -> fmt::Result {
+    match expr {
+        Expr::Stack(item) if format.witness_labels => write!(f, "witness[{}]", item.pos()),
+        Expr::Stack(item) => write!(f, "{item}"),
+        Expr::Bytes(bytes) => {
+            if numeric && format.numbers && bytes.len() <= 4 {
+                return write!(f, "{}", decode_int_unchecked(bytes));
+            }
+            write_bytes(f, bytes.as_ref(), format)
+        }
+        Expr::Op(op) if format.infix => {
+            let (prec, args_numeric) = infix_prec(&op.args);
+            let parens = prec < min_prec;
+            if parens {
+                write!(f, "(")?;
+            }
+            write_infix(f, &op.args, format, prec, args_numeric)?;
+            if parens {
+                write!(f, ")")?;
+            }
+            Ok(())
+        }
+        Expr::Op(op) => {
+            write!(f, "{}(", op.opcode())?;
+            if let OpExprArgs::Multisig(args) = &op.args {
+                write!(f, "sigs=[")?;
+                write_list(f, args.sigs(), format, false)?;
+                write!(f, "], pubkeys=[")?;
+                write_list(f, args.keys(), format, false)?;
+                write!(f, "]")?;
+            } else {
+                write_list(f, op.args(), format, takes_numbers(&op.args))?;
+            }
+            write!(f, ")")
+        }
+    }
+}
+
+/// Writes a byte string in hex, between angle brackets if not in infix notation.
+pub(crate) fn write_bytes(
+    f: &mut fmt::Formatter<'_>,
+    bytes: &[u8],
+    format: ExprFormat<'_>,
+) -> fmt::Result {
+    if let Some(label) = format.labels.and_then(|labels| labels.get(bytes)) {
+        return write!(f, "{label}");
+    }
+    if format.infix && bytes.is_empty() {
+        return write!(f, "\"\"");
+    }
+    if !format.infix {
+        write!(f, "<")?;
+    }
+    let hex = |f: &mut fmt::Formatter<'_>, bytes: &[u8]| {
+        bytes.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+    };
+    match format.max_bytes {
+        _ if format.abbreviate_hashes && matches!(bytes.len(), 20 | 32 | 33) => {
+            hex(f, &bytes[..4])?;
+            write!(f, "…")?;
+            hex(f, &bytes[bytes.len() - 4..])?;
+        }
+        Some(max) if bytes.len() > max => {
+            hex(f, &bytes[..max])?;
+            write!(f, "…")?;
+        }
+        _ => hex(f, bytes)?,
+    }
+    if !format.infix {
+        write!(f, ">")?;
+    }
+    Ok(())
+}
+
+fn write_list(
+    f: &mut fmt::Formatter<'_>,
+    args: &[Expr],
+    format: ExprFormat<'_>,
+    numeric: bool,
+) -> fmt::Result {
+    for (i, arg) in args.iter().enumerate() {
+        if i != 0 {
+            write!(f, ", ")?;
+        }
+        write_expr(f, arg, format, numeric, 0)?;
+    }
+    Ok(())
+}
+
+/// True if the opcode interprets its arguments as numbers.
+fn takes_numbers(args: &OpExprArgs) -> bool {
+    match args {
+        OpExprArgs::Args1(op, _) => !matches!(
+            op,
+            Opcode1::OP_SIZE | Opcode1::OP_RIPEMD160 | Opcode1::OP_SHA1 | Opcode1::OP_SHA256
+        ),
+        OpExprArgs::Args2(op, _) => !matches!(op, Opcode2::OP_EQUAL | Opcode2::OP_CHECKSIG),
+        OpExprArgs::Args3(Opcode3::OP_WITHIN, _) => true,
+        OpExprArgs::Multisig(_) => false,
+    }
+}
+
+/// Precedence of the infix rendering of an opcode, and whether its arguments are numbers.
+fn infix_prec(args: &OpExprArgs) -> (u8, bool) {
+    let prec = match args {
+        OpExprArgs::Args1(Opcode1::OP_NOT | Opcode1::OP_INTERNAL_NOT, _) => PREC_UNARY,
+        OpExprArgs::Args1(Opcode1::OP_0NOTEQUAL, _) => PREC_EQUALITY,
+        OpExprArgs::Args2(op, _) => match op {
+            Opcode2::OP_BOOLOR => PREC_BOOLOR,
+            Opcode2::OP_BOOLAND => PREC_BOOLAND,
+            Opcode2::OP_EQUAL | Opcode2::OP_NUMEQUAL | Opcode2::OP_NUMNOTEQUAL => PREC_EQUALITY,
+            Opcode2::OP_LESSTHAN | Opcode2::OP_LESSTHANOREQUAL => PREC_COMPARISON,
+            Opcode2::OP_ADD | Opcode2::OP_SUB => PREC_ADDITIVE,
+            Opcode2::OP_MIN | Opcode2::OP_MAX | Opcode2::OP_CHECKSIG => u8::MAX,
+        },
+        OpExprArgs::Args3(Opcode3::OP_WITHIN, _) => PREC_COMPARISON,
+        // functions
+        OpExprArgs::Args1(..) | OpExprArgs::Multisig(_) => u8::MAX,
+    };
+    (prec, takes_numbers(args))
+}
+
+fn write_infix(
+    f: &mut fmt::Formatter<'_>,
+    args: &OpExprArgs,
+    format: ExprFormat<'_>,
+    prec: u8,
+    numeric: bool,
+) -> fmt::Result {
+    let binary = |f: &mut fmt::Formatter<'_>, [a, b]: &[Expr; 2], op: &str| {
+        // left associative
+        write_expr(f, a, format, numeric, prec)?;
+        write!(f, " {op} ")?;
+        write_expr(f, b, format, numeric, prec + 1)
+    };
+
+    match args {
+        OpExprArgs::Args1(op, arg) => match op {
+            Opcode1::OP_NOT | Opcode1::OP_INTERNAL_NOT => {
+                write!(f, "!")?;
+                write_expr(f, &arg[0], format, numeric, prec)
+            }
+            Opcode1::OP_0NOTEQUAL => {
+                write_expr(f, &arg[0], format, numeric, prec + 1)?;
+                write!(f, " != 0")
+            }
+            _ => {
+                write!(f, "{}(", function_name(*op as u8))?;
+                write_expr(f, &arg[0], format, numeric, 0)?;
+                write!(f, ")")
+            }
+        },
+        OpExprArgs::Args2(op, args) => match op {
+            Opcode2::OP_BOOLOR => binary(f, args, "||"),
+            Opcode2::OP_BOOLAND => binary(f, args, "&&"),
+            Opcode2::OP_EQUAL | Opcode2::OP_NUMEQUAL => binary(f, args, "=="),
+            Opcode2::OP_NUMNOTEQUAL => binary(f, args, "!="),
+            Opcode2::OP_LESSTHAN => binary(f, args, "<"),
+            Opcode2::OP_LESSTHANOREQUAL => binary(f, args, "<="),
+            Opcode2::OP_ADD => binary(f, args, "+"),
+            Opcode2::OP_SUB => binary(f, args, "-"),
+            Opcode2::OP_MIN | Opcode2::OP_MAX | Opcode2::OP_CHECKSIG => {
+                write!(f, "{}(", function_name(*op as u8))?;
+                write_list(f, &args[..], format, numeric)?;
+                write!(f, ")")
+            }
+        },
+        OpExprArgs::Args3(Opcode3::OP_WITHIN, args) => {
+            let [x, min, max] = &**args;
+            write_expr(f, min, format, numeric, prec + 1)?;
+            write!(f, " <= ")?;
+            write_expr(f, x, format, numeric, prec + 1)?;
+            write!(f, " < ")?;
+            write_expr(f, max, format, numeric, prec + 1)
+        }
+        OpExprArgs::Multisig(args) => {
+            write!(f, "checkmultisig([")?;
+            write_list(f, args.sigs(), format, false)?;
+            write!(f, "], [")?;
+            write_list(f, args.keys(), format, false)?;
+            write!(f, "])")
+        }
+    }
+}
+
+/// `OP_SHA256` becomes `sha256`.
+fn function_name(opcode: u8) -> String {
+    let name = crate::opcode::Opcode { opcode }.to_string();
+    name.strip_prefix("OP_").unwrap_or(&name).to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExprFormat, Labels};
+    use crate::expr::{Expr, Opcode1, Opcode2, Opcode3};
+
+    #[test]
+    fn test_expr_format() {
+        let sum = Opcode2::OP_ADD.expr([Expr::stack(0), Expr::bytes(&[1])]);
+        let lt = Opcode2::OP_LESSTHAN.expr([sum, Expr::bytes(&[100])]);
+        assert_eq!(
+            lt.display(ExprFormat::default()).to_string(),
+            lt.to_string()
+        );
+        assert_eq!(
+            lt.display(ExprFormat::human()).to_string(),
+            "witness[0] + 1 < 100"
+        );
+
+        let sub = Opcode2::OP_SUB.expr([
+            Expr::stack(0),
+            Opcode2::OP_SUB.expr([Expr::stack(1), Expr::stack(2)]),
+        ]);
+        assert_eq!(
+            sub.display(ExprFormat::human()).to_string(),
+            "witness[0] - (witness[1] - witness[2])"
+        );
+
+        let hash = Opcode2::OP_EQUAL.expr([
+            Opcode1::OP_SHA256.expr([Expr::stack(1)]),
+            Expr::bytes(&[0x12, 0x34, 0x56, 0x78, 0x9a]),
+        ]);
+        assert_eq!(
+            hash.display(ExprFormat::human()).to_string(),
+            "sha256(witness[1]) == 12345678…"
+        );
+
+        let within =
+            Opcode3::OP_WITHIN.expr([Expr::stack(0), Expr::bytes(&[]), Expr::bytes(&[10])]);
+        let or = Opcode2::OP_BOOLOR.expr([within, Opcode1::OP_NOT.expr([Expr::stack(1)])]);
+        assert_eq!(
+            or.display(ExprFormat::human()).to_string(),
+            "0 <= witness[0] < 10 || !witness[1]"
+        );
+
+        let format = ExprFormat {
+            max_bytes: Some(2),
+            abbreviate_hashes: true,
+            ..ExprFormat::default()
+        };
+        assert_eq!(
+            Expr::bytes(&[0xab; 40]).display(format).to_string(),
+            "<abab…>"
+        );
+        assert_eq!(
+            Expr::bytes(&[0xcd; 32]).display(format).to_string(),
+            "<cdcdcdcd…cdcdcdcd>"
+        );
+
+        let mut labels = Labels::new();
+        labels.insert(&[2; 33], "Alice");
+        let checksig = Opcode2::OP_CHECKSIG.expr([Expr::stack(0), Expr::bytes(&[2; 33])]);
+        let format = ExprFormat {
+            labels: Some(&labels),
+            ..ExprFormat::human()
+        };
+        assert_eq!(
+            checksig.display(format).to_string(),
+            "checksig(witness[0], Alice)"
+        );
+    }
+}
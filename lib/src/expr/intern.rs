@@ -0,0 +1,130 @@
+use super::Expr;
+use std::collections::HashMap;
+
+/// Small integer id of an interned expression, equal ids mean equal expressions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ExprId(u32);
+
+/// Hash-consing arena: every distinct expression is stored once and gets an [`ExprId`], so
+/// expressions can be compared in O(1) after interning.
+#[derive(Debug, Default)]
+pub struct ExprInterner {
+    ids: HashMap<Expr, ExprId>,
+    exprs: Vec<Expr>,
+}
+
+impl ExprInterner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn intern(&mut self, expr: &Expr) -> ExprId {
+        if let Some(&id) = self.ids.get(expr) {
+            return id;
+        }
+        let id = ExprId(self.exprs.len() as u32);
+        // cheap, the arguments are shared
+        self.exprs.push(expr.clone());
+        self.ids.insert(expr.clone(), id);
+        id
+    }
+
+    pub fn get(&self, id: ExprId) -> &Expr {
+        &self.exprs[id.0 as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.exprs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exprs.is_empty()
+    }
+}
+
+/// Union-find over interned expressions, built from facts `a == b`. The representative of a
+/// class is its greatest member in the order of [`Expr`], so a constant if the class has one,
+/// otherwise a stack item if it has one.
+#[derive(Debug, Default)]
+pub struct EqualityClasses {
+    parent: Vec<ExprId>,
+}
+
+impl EqualityClasses {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The representative of the class of `id`.
+    pub fn find(&mut self, id: ExprId) -> ExprId {
+        let Some(&parent) = self.parent.get(id.0 as usize) else {
+            return id;
+        };
+        if parent == id {
+            return id;
+        }
+        let root = self.find(parent);
+        // path compression
+        self.parent[id.0 as usize] = root;
+        root
+    }
+
+    /// Merges the classes of `a` and `b`. Returns false if both contain a different constant,
+    /// then `a == b` can not be true.
+    pub fn union(&mut self, interner: &ExprInterner, a: ExprId, b: ExprId) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return true;
+        }
+        let (expr_a, expr_b) = (interner.get(a), interner.get(b));
+        if matches!((expr_a, expr_b), (Expr::Bytes(_), Expr::Bytes(_))) {
+            return false;
+        }
+        let (child, root) = if expr_a < expr_b { (a, b) } else { (b, a) };
+        // ids without an entry are their own representative
+        while self.parent.len() <= child.0 as usize {
+            self.parent.push(ExprId(self.parent.len() as u32));
+        }
+        self.parent[child.0 as usize] = root;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EqualityClasses, ExprInterner};
+    use crate::expr::{Expr, Opcode2};
+
+    #[test]
+    fn test_intern() {
+        let mut interner = ExprInterner::new();
+        let a = Opcode2::OP_CHECKSIG.expr([Expr::stack(0), Expr::bytes(&[2; 33])]);
+        let b = Opcode2::OP_CHECKSIG.expr([Expr::stack(0), Expr::bytes(&[2; 33])]);
+        let c = Opcode2::OP_CHECKSIG.expr([Expr::stack(1), Expr::bytes(&[2; 33])]);
+
+        let id = interner.intern(&a);
+        assert_eq!(interner.intern(&b), id);
+        assert_ne!(interner.intern(&c), id);
+        assert_eq!(interner.get(id), &a);
+        assert_eq!(interner.len(), 2);
+    }
+
+    #[test]
+    fn test_equality_classes() {
+        let mut interner = ExprInterner::new();
+        let [a, b, c, d] = [
+            Expr::stack(0),
+            Expr::stack(1),
+            Expr::bytes(&[1]),
+            Expr::bytes(&[2]),
+        ]
+        .map(|e| interner.intern(&e));
+
+        let mut classes = EqualityClasses::new();
+        assert!(classes.union(&interner, a, b));
+        assert!(classes.union(&interner, b, c));
+        // the constant represents the class
+        assert_eq!(classes.find(a), c);
+        assert!(!classes.union(&interner, a, d));
+    }
+}
@@ -1,28 +1,37 @@
 mod bytes;
+mod format;
+mod intern;
 mod op;
 mod opcode;
 mod stack;
 
+pub(crate) use self::format::write_bytes;
 pub use self::{
     bytes::BytesExprBox,
-    op::{MultisigArgs, OpExpr, OpExprArgs},
+    format::{ExprDisplay, ExprFormat, Labels},
+    intern::{EqualityClasses, ExprId, ExprInterner},
+    op::{MultisigArgs, OpExpr, OpExprArgs, Origin},
     opcode::{Opcode1, Opcode2, Opcode3},
     stack::StackExpr,
 };
 use crate::{
-    context::{ScriptContext, ScriptRules, ScriptVersion},
-    script::convert::{
-        check_int, decode_bool, decode_int_unchecked, encode_bool_expr, encode_int_expr,
+    context::{ScriptContext, ScriptVersion, VerifyFlags},
+    script::{
+        convert::{
+            check_int, decode_bool, decode_int_unchecked, encode_bool_expr, encode_int_expr,
+        },
+        MAX_SCRIPT_ELEMENT_SIZE,
     },
     script_error::ScriptError,
     util::checksig::{
-        check_pub_key, is_valid_signature_encoding, PubKeyCheckResult, SIG_HASH_TYPES,
+        check_pub_key_encoding, check_signature_encoding, is_on_curve, SIG_HASH_TYPES,
     },
 };
 use bitcoin_hashes::{ripemd160, sha1, sha256, Hash};
 use core::{cmp::Ordering, fmt, mem::replace};
+use std::sync::Arc;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Expr {
     Op(OpExpr),
     Stack(StackExpr),
@@ -79,15 +88,135 @@ impl Expr {
         self.eval_(ctx, 0)
     }
 
+    /// Like [`eval`], but for expressions that are used as a value instead of a spending
+    /// condition. Simplifications that only preserve truthiness are not applied.
+    ///
+    /// [`eval`]: Self::eval
+    pub fn eval_value(&mut self, ctx: ScriptContext) -> Result<bool, ScriptError> {
+        self.eval_(ctx, 1)
+    }
+
+    /// Origin of the expression, `None` for stack items and constants.
+    pub fn origin(&self) -> Option<Origin> {
+        match self {
+            Self::Op(op) => op.origin(),
+            _ => None,
+        }
+    }
+
+    /// Error of the script when this expression is a spending condition that is false.
+    pub(crate) fn error(&self) -> Option<ScriptError> {
+        match self {
+            Self::Op(op) => op.error(),
+            _ => None,
+        }
+    }
+
+    /// Number of nodes in the expression tree.
+    pub fn node_count(&self) -> usize {
+        match self {
+            Self::Op(op) => 1 + op.args().iter().map(Self::node_count).sum::<usize>(),
+            _ => 1,
+        }
+    }
+
+    /// Inclusive bounds of the size of the value of this expression in bytes.
+    pub fn size_bounds(&self) -> (usize, usize) {
+        match self {
+            Self::Bytes(b) => (b.len(), b.len()),
+            // initial stack items larger than this fail the script before it runs
+            Self::Stack(_) => (0, MAX_SCRIPT_ELEMENT_SIZE),
+            Self::Op(op) => match &op.args {
+                OpExprArgs::Args1(Opcode1::OP_RIPEMD160 | Opcode1::OP_SHA1, _) => (20, 20),
+                OpExprArgs::Args1(Opcode1::OP_SHA256, _) => (32, 32),
+                OpExprArgs::Args1(
+                    Opcode1::OP_NOT | Opcode1::OP_0NOTEQUAL | Opcode1::OP_INTERNAL_NOT,
+                    _,
+                )
+                | OpExprArgs::Args2(
+                    Opcode2::OP_EQUAL
+                    | Opcode2::OP_BOOLAND
+                    | Opcode2::OP_BOOLOR
+                    | Opcode2::OP_NUMEQUAL
+                    | Opcode2::OP_NUMNOTEQUAL
+                    | Opcode2::OP_LESSTHAN
+                    | Opcode2::OP_LESSTHANOREQUAL
+                    | Opcode2::OP_CHECKSIG,
+                    _,
+                )
+                | OpExprArgs::Args3(Opcode3::OP_WITHIN, _)
+                | OpExprArgs::Multisig(_) => (0, 1),
+                // numbers, the sum of two 4 byte numbers can be 5 bytes
+                _ => (0, 5),
+            },
+        }
+    }
+
+    pub(crate) fn set_origin(&mut self, origin: Origin) {
+        if let Self::Op(op) = self {
+            op.set_origin(origin);
+        }
+    }
+
     fn eval_(&mut self, ctx: ScriptContext, depth: usize) -> Result<bool, ScriptError> {
+        let origin = self.origin();
+        let error = self.error();
+        let changed = self.eval_inner(ctx, depth)?;
+        // simplified expressions keep the origin of the expression they replace
+        if let Some(origin) = origin {
+            self.set_origin(origin);
+        }
+        // a spending condition that is false fails the script with its own error
+        if let (0, Some(error), Self::Bytes(b)) = (depth, error, &*self) {
+            if !decode_bool(b) {
+                return Err(error);
+            }
+        }
+        Ok(changed)
+    }
+
+    /// With NULLFAIL, a failing `OP_CHECKMULTISIG` requires all signatures to be empty. This
+    /// has to be done before the multisig itself is evaluated, with as many signatures as keys
+    /// it becomes `OP_BOOLAND`s of `OP_CHECKSIG`s, which each only require their own signature to
+    /// be empty.
+    fn failed_multisig(&self, error: ScriptError) -> Option<Self> {
+        let Self::Op(op) = self else {
+            return None;
+        };
+        let OpExprArgs::Args1(Opcode1::OP_NOT | Opcode1::OP_INTERNAL_NOT, args) = &op.args else {
+            return None;
+        };
+        let Self::Op(arg) = &args[0] else {
+            return None;
+        };
+        let OpExprArgs::Multisig(m) = &arg.args else {
+            return None;
+        };
+        Some(
+            m.sigs()
+                .iter()
+                .map(|sig| empty_signature(sig.clone(), error))
+                .reduce(|a, b| Opcode2::OP_BOOLAND.expr_with_error([a, b], error))
+                // without signatures, OP_CHECKMULTISIG is true
+                .unwrap_or_else(|| encode_bool_expr(false)),
+        )
+    }
+
+    fn eval_inner(&mut self, ctx: ScriptContext, depth: usize) -> Result<bool, ScriptError> {
         let mut changed = false;
+        if let (0, Some(error)) = (depth, null_signature_error(ctx)) {
+            if let Some(expr) = self.failed_multisig(error) {
+                *self = expr;
+                changed = true;
+            }
+        }
         if let Expr::Op(ref mut op) = self {
             for arg in op.args_mut() {
                 changed |= arg.eval_(ctx, depth + 1)?;
             }
             match &mut op.args {
                 OpExprArgs::Args1(op, args) => {
-                    let arg = &mut args[0];
+                    let arg = &mut Arc::make_mut(args)[0];
                     match op {
                         Opcode1::OP_SIZE => {
                             match arg {
@@ -125,6 +254,24 @@ impl Expr {
                             }
                         }
 
+                        Opcode1::OP_ABS | Opcode1::OP_0NOTEQUAL => match arg {
+                            Expr::Bytes(b) => {
+                                check_int(b.as_ref(), 4)?;
+                                let n = decode_int_unchecked(b.as_ref());
+                                *self = match op {
+                                    Opcode1::OP_ABS => encode_int_expr(n.abs()),
+                                    _ => encode_bool_expr(n != 0),
+                                };
+                                return Ok(true);
+                            }
+                            // booleans are 0 or 1, both are unchanged by these opcodes
+                            Expr::Op(op) if op.opcode().returns_boolean() => {
+                                *self = replace(arg, Self::valid_garbage());
+                                return Ok(true);
+                            }
+                            _ => {}
+                        },
+
                         Opcode1::OP_INTERNAL_NOT | Opcode1::OP_NOT => {
                             if let Expr::Bytes(arg) = arg {
                                 return if *op == Opcode1::OP_NOT && arg.len() > 4 {
@@ -135,6 +282,24 @@ impl Expr {
                                 };
                             }
                             if let Expr::Op(arg) = arg {
+                                // De Morgan, BOOLAND and BOOLOR return 0 or 1 like NOT does
+                                if let OpExprArgs::Args2(
+                                    op @ (Opcode2::OP_BOOLAND | Opcode2::OP_BOOLOR),
+                                    args,
+                                ) = &arg.args
+                                {
+                                    let dual = if *op == Opcode2::OP_BOOLAND {
+                                        Opcode2::OP_BOOLOR
+                                    } else {
+                                        Opcode2::OP_BOOLAND
+                                    };
+                                    let [a, b] = (**args).clone();
+                                    *self = dual.expr([
+                                        Opcode1::OP_NOT.expr([a]),
+                                        Opcode1::OP_NOT.expr([b]),
+                                    ]);
+                                    return Ok(true);
+                                }
                                 if let OpExprArgs::Args1(op, arg) = &arg.args {
                                     if (*op == Opcode1::OP_NOT || *op == Opcode1::OP_INTERNAL_NOT)
                                         && match &arg[0] {
@@ -148,17 +313,13 @@ impl Expr {
                                     }
                                 }
                             }
-                            if let Expr::Op(arg) = arg {
-                                if depth == 0 && ctx.rules == ScriptRules::All {
-                                    if let OpExprArgs::Args2(Opcode2::OP_CHECKSIG, args) = &arg.args
-                                    {
-                                        // assumes valid pubkey TODO fix
-                                        *self = Opcode2::OP_EQUAL.expr(Box::new([
-                                            args[0].clone(),
-                                            encode_bool_expr(false),
-                                        ]));
-                                        return Ok(true);
-                                    }
+                            if let (Expr::Op(arg), 0, Some(error)) =
+                                (arg, depth, null_signature_error(ctx))
+                            {
+                                if let OpExprArgs::Args2(Opcode2::OP_CHECKSIG, args) = &arg.args {
+                                    // the public key was checked when OP_CHECKSIG was evaluated
+                                    *self = empty_signature(args[0].clone(), error);
+                                    return Ok(true);
                                 }
                             }
                         }
@@ -169,7 +330,16 @@ impl Expr {
 
                 OpExprArgs::Args2(op, args) => {
                     match op {
-                        Opcode2::OP_ADD | Opcode2::OP_SUB => {
+                        Opcode2::OP_ADD
+                        | Opcode2::OP_SUB
+                        | Opcode2::OP_BOOLAND
+                        | Opcode2::OP_BOOLOR
+                        | Opcode2::OP_NUMEQUAL
+                        | Opcode2::OP_NUMNOTEQUAL
+                        | Opcode2::OP_LESSTHAN
+                        | Opcode2::OP_LESSTHANOREQUAL
+                        | Opcode2::OP_MIN
+                        | Opcode2::OP_MAX => {
                             let [ref a1, ref a2] = **args;
                             if let Expr::Bytes(a1) = a1 {
                                 check_int(a1, 4)?;
@@ -180,10 +350,23 @@ impl Expr {
                             if let (Expr::Bytes(a1), Expr::Bytes(a2)) = (a1, a2) {
                                 let a = decode_int_unchecked(a1);
                                 let b = decode_int_unchecked(a2);
-                                *self = encode_int_expr(match op {
-                                    Opcode2::OP_ADD => a + b,
-                                    _ => a - b,
-                                });
+                                *self = match op {
+                                    Opcode2::OP_ADD => encode_int_expr(a + b),
+                                    Opcode2::OP_SUB => encode_int_expr(a - b),
+                                    Opcode2::OP_BOOLAND => encode_bool_expr(a != 0 && b != 0),
+                                    Opcode2::OP_BOOLOR => encode_bool_expr(a != 0 || b != 0),
+                                    Opcode2::OP_NUMEQUAL => encode_bool_expr(a == b),
+                                    Opcode2::OP_NUMNOTEQUAL => encode_bool_expr(a != b),
+                                    Opcode2::OP_LESSTHAN => encode_bool_expr(a < b),
+                                    Opcode2::OP_LESSTHANOREQUAL => encode_bool_expr(a <= b),
+                                    Opcode2::OP_MIN => encode_int_expr(a.min(b)),
+                                    Opcode2::OP_MAX => encode_int_expr(a.max(b)),
+                                    _ => unreachable!(),
+                                };
+                                return Ok(true);
+                            }
+                            if let Some(res) = simplify_logic(*op, args) {
+                                *self = res;
                                 return Ok(true);
                             }
                         }
@@ -195,19 +378,26 @@ impl Expr {
                                     *self = encode_bool_expr(a1 == a2);
                                     return Ok(true);
                                 }
-                                (Expr::Op(a1), Expr::Bytes(a2)) => {
-                                    if a1.opcode().returns_boolean() {
-                                        if a2.is_true() {
-                                            *self = a1_.clone()
-                                        } else if a2.is_false() {
-                                            *self = Opcode1::OP_NOT.expr(Box::new([a1_.clone()]))
-                                        } else {
-                                            *self = encode_bool_expr(false)
-                                        }
+                                (Expr::Op(a1), Expr::Bytes(a2))
+                                    if a1.opcode().returns_boolean() =>
+                                {
+                                    if a2.is_true() {
+                                        *self = a1_.clone()
+                                    } else if a2.is_false() {
+                                        *self = Opcode1::OP_NOT.expr([a1_.clone()])
+                                    } else {
+                                        *self = encode_bool_expr(false)
+                                    }
+                                    return Ok(true);
+                                }
+                                _ => {
+                                    let (min1, max1) = a1_.size_bounds();
+                                    let (min2, max2) = a2.size_bounds();
+                                    if max1 < min2 || max2 < min1 {
+                                        *self = encode_bool_expr(false);
                                         return Ok(true);
                                     }
                                 }
-                                _ => {}
                             }
                         }
 
@@ -218,7 +408,9 @@ impl Expr {
                                     if pubkey.len() == 0 {
                                         return Err(ScriptError::SCRIPT_ERR_PUBKEYTYPE);
                                     } else if pubkey.len() != 32 {
-                                        return if ctx.rules == ScriptRules::All {
+                                        return if ctx
+                                            .has(VerifyFlags::DISCOURAGE_UPGRADABLE_PUBKEYTYPE)
+                                        {
                                             Err(ScriptError::SCRIPT_ERR_DISCOURAGE_UPGRADABLE_PUBKEYTYPE)
                                         } else {
                                             *self = encode_bool_expr(true);
@@ -239,60 +431,69 @@ impl Expr {
                                             );
                                         }
                                     }
-                                }
-                            } else if let Expr::Bytes(pubkey) = pubkey {
-                                // TODO CheckPubKeyEncoding without SCRIPT_VERIFY_STRICTENC?
-                                match check_pub_key(pubkey) {
-                                    PubKeyCheckResult::Invalid => {
-                                        return Err(ScriptError::SCRIPT_ERR_PUBKEYTYPE);
-                                    }
-                                    PubKeyCheckResult::Valid { compressed } => {
-                                        if !compressed
-                                            && ctx.version == ScriptVersion::SegwitV0
-                                            && ctx.rules == ScriptRules::All
-                                        {
-                                            return Err(ScriptError::SCRIPT_ERR_WITNESS_PUBKEYTYPE);
+                                    if !is_on_curve(pubkey) {
+                                        // no signature is valid for this key, one that is not
+                                        // empty fails the script
+                                        if let Expr::Bytes(_) = sig {
+                                            return Err(ScriptError::SCRIPT_ERR_SCHNORR_SIG);
                                         }
+                                        *self = encode_bool_expr(false);
+                                        return Ok(true);
                                     }
                                 }
+                            } else {
+                                // like Bitcoin Core, the signature is checked before the public key
                                 if let Expr::Bytes(sig) = sig {
-                                    if sig.len() == 0 {
+                                    check_signature_encoding(sig, ctx.flags)?;
+                                }
+                                if let Expr::Bytes(pubkey) = pubkey {
+                                    if !check_pub_key_encoding(pubkey, ctx)?
+                                        || matches!(sig, Expr::Bytes(sig) if sig.len() == 0)
+                                    {
                                         *self = encode_bool_expr(false);
                                         return Ok(true);
                                     }
-                                    if ctx.rules == ScriptRules::All {
-                                        // TODO low s
-                                        if !is_valid_signature_encoding(sig) {
-                                            return Err(ScriptError::SCRIPT_ERR_SIG_DER);
-                                        } else if !SIG_HASH_TYPES.contains(&sig[sig.len() - 1]) {
-                                            return Err(ScriptError::SCRIPT_ERR_SIG_HASHTYPE);
-                                        }
-                                    }
                                 }
                             }
                         }
-
-                        _ => {}
                     }
                 }
 
-                OpExprArgs::Args3(_, _) => {}
+                OpExprArgs::Args3(Opcode3::OP_WITHIN, args) => {
+                    for arg in args.iter() {
+                        if let Expr::Bytes(arg) = arg {
+                            check_int(arg, 4)?;
+                        }
+                    }
+                    if let [Expr::Bytes(x), Expr::Bytes(min), Expr::Bytes(max)] = &**args {
+                        let x = decode_int_unchecked(x);
+                        let min = decode_int_unchecked(min);
+                        let max = decode_int_unchecked(max);
+                        *self = encode_bool_expr(min <= x && x < max);
+                        return Ok(true);
+                    }
+                }
 
                 OpExprArgs::Multisig(m) => {
+                    if m.needs_check() {
+                        check_multisig(m, ctx)?;
+                    }
+                    if m.keys().len() < m.sigs().len() {
+                        *self = encode_bool_expr(false);
+                        return Ok(true);
+                    }
                     if m.keys().len() == m.sigs().len() {
                         let (sigs, pks) = replace(m, MultisigArgs::valid_garbage()).into_vecs();
 
                         *self = sigs
                             .into_iter()
                             .zip(pks)
-                            .map(|(sig, pk)| Opcode2::OP_CHECKSIG.expr(Box::new([sig, pk])))
-                            .reduce(|a, b| Opcode2::OP_BOOLAND.expr(Box::new([a, b])))
+                            .map(|(sig, pk)| Opcode2::OP_CHECKSIG.expr([sig, pk]))
+                            .reduce(|a, b| Opcode2::OP_BOOLAND.expr([a, b]))
                             .unwrap_or_else(|| encode_bool_expr(true));
 
                         return Ok(true);
                     }
-                    // TODO check pubkeys, sigs like with checksig, maybe cache check results to
-                    // not repeat them multiple times
                 }
             }
         }
@@ -316,6 +517,101 @@ impl Expr {
     }
 }
 
+/// Checks the encoding of the constant signatures and public keys of an `OP_CHECKMULTISIG` like
+/// `OP_CHECKSIG` does. Bitcoin Core compares signatures and keys from the top of the stack, the
+/// last key first, and stop at the first error, so only the last key for every signature is
+/// reached for sure. Invalid keys that are allowed are removed, no signature is valid for them.
+fn check_multisig(m: &mut MultisigArgs, ctx: ScriptContext) -> Result<(), ScriptError> {
+    for sig in m.sigs() {
+        if let Expr::Bytes(sig) = sig {
+            check_signature_encoding(sig, ctx.flags)?;
+        }
+    }
+    let checked = m.keys().len().saturating_sub(m.sigs().len());
+    let mut invalid = Vec::new();
+    for (i, pubkey) in m.keys().iter().enumerate() {
+        if let Expr::Bytes(pubkey) = pubkey {
+            match check_pub_key_encoding(pubkey, ctx) {
+                Ok(true) => {}
+                Ok(false) => invalid.push(i),
+                Err(err) if i >= checked => return Err(err),
+                // only fails the script if this key is reached
+                Err(_) => {}
+            }
+        }
+    }
+    m.remove_keys(&invalid);
+    m.set_checked();
+    Ok(())
+}
+
+/// Error when a signature check that has to fail has a signature that is not empty, with
+/// NULLFAIL. In tapscript, such a signature always fails the script.
+pub(crate) fn null_signature_error(ctx: ScriptContext) -> Option<ScriptError> {
+    if ctx.version == ScriptVersion::SegwitV1 {
+        Some(ScriptError::SCRIPT_ERR_SCHNORR_SIG)
+    } else if ctx.has(VerifyFlags::NULLFAIL) {
+        Some(ScriptError::SCRIPT_ERR_SIG_NULLFAIL)
+    } else {
+        None
+    }
+}
+
+/// `sig` is empty, or the script fails with `error`.
+fn empty_signature(sig: Expr, error: ScriptError) -> Expr {
+    Opcode2::OP_EQUAL.expr_with_error([sig, encode_bool_expr(false)], error)
+}
+
+fn is_boolean(expr: &Expr) -> bool {
+    matches!(expr, Expr::Op(op) if op.opcode().returns_boolean())
+}
+
+/// Identity, annihilator, idempotence and absorption rules of `OP_BOOLAND` and `OP_BOOLOR`,
+/// `None` for other opcodes. Arguments are only dropped if they can not fail as a number,
+/// others are kept in an `OP_0NOTEQUAL`.
+fn simplify_logic(op: Opcode2, args: &[Expr; 2]) -> Option<Expr> {
+    let and = match op {
+        Opcode2::OP_BOOLAND => true,
+        Opcode2::OP_BOOLOR => false,
+        _ => return None,
+    };
+    for [a, b] in [[&args[0], &args[1]], [&args[1], &args[0]]] {
+        if let Expr::Bytes(b) = b {
+            return Some(if decode_bool(b) != and {
+                // `a && false`, `a || true`
+                if !is_boolean(a) {
+                    return None;
+                }
+                encode_bool_expr(!and)
+            } else {
+                // `a && true`, `a || false`
+                Opcode1::OP_0NOTEQUAL.expr([a.clone()])
+            });
+        }
+        if a == b {
+            return Some(Opcode1::OP_0NOTEQUAL.expr([a.clone()]));
+        }
+        // `a && (a || c)`, `a || (a && c)`
+        if let Expr::Op(b) = b {
+            if let OpExprArgs::Args2(
+                inner @ (Opcode2::OP_BOOLAND | Opcode2::OP_BOOLOR),
+                inner_args,
+            ) = &b.args
+            {
+                if *inner != op
+                    && inner_args
+                        .iter()
+                        .zip(inner_args.iter().rev())
+                        .any(|(x, c)| x == a && is_boolean(c))
+                {
+                    return Some(Opcode1::OP_0NOTEQUAL.expr([a.clone()]));
+                }
+            }
+        }
+    }
+    None
+}
+
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
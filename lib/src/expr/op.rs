@@ -3,17 +3,66 @@ use crate::{
     opcode::{opcodes, Opcode},
     script_error::ScriptError,
 };
-use core::{cmp::Ordering, fmt};
+use core::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+};
+use std::sync::Arc;
+
+/// Where in the script an expression was created.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Origin {
+    /// The script opcode that created the expression.
+    pub opcode: Opcode,
+    /// Index of the opcode in the script.
+    pub index: usize,
+    /// Byte offset of the opcode in the script. Pushes are assumed to be minimal if the script
+    /// was not parsed from bytes, see [`ScriptRef`](crate::ScriptRef).
+    pub offset: usize,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.opcode, self.offset)
+    }
+}
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug)]
 pub struct OpExpr {
     pub args: OpExprArgs,
     error: Option<ScriptError>,
+    origin: Option<Origin>,
 }
 
 impl OpExpr {
     pub fn new(args: OpExprArgs, error: Option<ScriptError>) -> Self {
-        Self { args, error }
+        Self {
+            args,
+            error,
+            origin: None,
+        }
+    }
+
+    pub fn origin(&self) -> Option<Origin> {
+        self.origin
+    }
+
+    /// Error of the script when this expression is a spending condition that is false.
+    pub(crate) fn error(&self) -> Option<ScriptError> {
+        self.error
+    }
+
+    /// Sets the origin of this expression and its arguments, if they do not have one yet.
+    pub(crate) fn set_origin(&mut self, origin: Origin) {
+        if self.origin.is_some() {
+            // arguments are created before this expression, so they already have an origin
+            return;
+        }
+        self.origin = Some(origin);
+        for arg in self.args_mut() {
+            arg.set_origin(origin);
+        }
     }
 
     pub fn opcode(&self) -> Opcode {
@@ -38,14 +87,31 @@ impl OpExpr {
 
     pub fn args_mut(&mut self) -> &mut [Expr] {
         match &mut self.args {
-            OpExprArgs::Args1(_, args) => &mut **args,
-            OpExprArgs::Args2(_, args) => &mut **args,
-            OpExprArgs::Args3(_, args) => &mut **args,
-            OpExprArgs::Multisig(m) => &mut m.exprs,
+            // arguments are shared between forks, they are cloned when modified
+            OpExprArgs::Args1(_, args) => &mut Arc::make_mut(args)[..],
+            OpExprArgs::Args2(_, args) => &mut Arc::make_mut(args)[..],
+            OpExprArgs::Args3(_, args) => &mut Arc::make_mut(args)[..],
+            OpExprArgs::Multisig(m) => Arc::make_mut(&mut m.exprs),
         }
     }
 }
 
+// the origin is ignored, the same expression can be created at multiple places in a script
+impl PartialEq for OpExpr {
+    fn eq(&self, other: &Self) -> bool {
+        self.args == other.args && self.error == other.error
+    }
+}
+
+impl Eq for OpExpr {}
+
+impl Hash for OpExpr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.args.hash(state);
+        self.error.hash(state);
+    }
+}
+
 impl PartialOrd for OpExpr {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -108,24 +174,76 @@ impl fmt::Display for OpExpr {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, Eq)]
 pub enum OpExprArgs {
-    Args1(Opcode1, Box<[Expr; 1]>),
-    Args2(Opcode2, Box<[Expr; 2]>),
-    Args3(Opcode3, Box<[Expr; 3]>),
+    Args1(Opcode1, Arc<[Expr; 1]>),
+    Args2(Opcode2, Arc<[Expr; 2]>),
+    Args3(Opcode3, Arc<[Expr; 3]>),
     Multisig(MultisigArgs),
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+// arguments that are shared (cloned from the same expression) are equal without comparing them
+impl PartialEq for OpExprArgs {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Args1(op1, a), Self::Args1(op2, b)) => {
+                op1 == op2 && (Arc::ptr_eq(a, b) || a == b)
+            }
+            (Self::Args2(op1, a), Self::Args2(op2, b)) => {
+                op1 == op2 && (Arc::ptr_eq(a, b) || a == b)
+            }
+            (Self::Args3(op1, a), Self::Args3(op2, b)) => {
+                op1 == op2 && (Arc::ptr_eq(a, b) || a == b)
+            }
+            (Self::Multisig(a), Self::Multisig(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Hash for OpExprArgs {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        core::mem::discriminant(self).hash(state);
+        match self {
+            Self::Args1(op, args) => (op, args).hash(state),
+            Self::Args2(op, args) => (op, args).hash(state),
+            Self::Args3(op, args) => (op, args).hash(state),
+            Self::Multisig(m) => m.hash(state),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq)]
 pub struct MultisigArgs {
-    exprs: Box<[Expr]>,
+    exprs: Arc<[Expr]>,
     pk_offset: usize,
+    /// Amount of constant arguments when their encoding was last checked. Constants stay
+    /// constant, so the checks only have to be repeated when stack items are replaced by them.
+    checked: usize,
+}
+
+impl PartialEq for MultisigArgs {
+    fn eq(&self, other: &Self) -> bool {
+        self.pk_offset == other.pk_offset
+            && (Arc::ptr_eq(&self.exprs, &other.exprs) || self.exprs == other.exprs)
+    }
+}
+
+impl Hash for MultisigArgs {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.exprs.hash(state);
+        self.pk_offset.hash(state);
+    }
 }
 
 impl MultisigArgs {
     pub fn expr(exprs: Box<[Expr]>, pk_offset: usize) -> Expr {
         Expr::Op(OpExpr::new(
-            OpExprArgs::Multisig(Self { exprs, pk_offset }),
+            OpExprArgs::Multisig(Self {
+                exprs: exprs.into(),
+                pk_offset,
+                checked: 0,
+            }),
             None,
         ))
     }
@@ -137,8 +255,9 @@ impl MultisigArgs {
     /// [`take`]: core::mem::take
     pub fn valid_garbage() -> Self {
         Self {
-            exprs: Box::new([]),
+            exprs: Arc::new([]),
             pk_offset: 0,
+            checked: 0,
         }
     }
 
@@ -150,8 +269,39 @@ impl MultisigArgs {
         &self.exprs[self.pk_offset..]
     }
 
+    fn constants(&self) -> usize {
+        self.exprs
+            .iter()
+            .filter(|e| matches!(e, Expr::Bytes(_)))
+            .count()
+    }
+
+    /// Returns true if there are constant signatures or keys that were not checked yet.
+    pub fn needs_check(&self) -> bool {
+        self.constants() != self.checked
+    }
+
+    pub fn set_checked(&mut self) {
+        self.checked = self.constants();
+    }
+
+    /// Removes the keys at the given indices into [`keys`](Self::keys).
+    pub fn remove_keys(&mut self, indices: &[usize]) {
+        if indices.is_empty() {
+            return;
+        }
+        let pk_offset = self.pk_offset;
+        self.exprs = self
+            .exprs
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i < pk_offset || !indices.contains(&(i - pk_offset)))
+            .map(|(_, e)| e.clone())
+            .collect();
+    }
+
     pub fn into_vecs(self) -> (Vec<Expr>, Vec<Expr>) {
-        let mut sigs = self.exprs.into_vec();
+        let mut sigs = self.exprs.to_vec();
         let pks = sigs.split_off(self.pk_offset);
 
         (sigs, pks)
@@ -1,7 +1,8 @@
 use super::{Expr, OpExpr, OpExprArgs};
 use crate::script_error::ScriptError;
+use std::sync::Arc;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[allow(non_camel_case_types)]
 pub enum Opcode1 {
     OP_SIZE = 0x82,
@@ -21,12 +22,12 @@ pub enum Opcode1 {
 }
 
 impl Opcode1 {
-    pub fn expr(self, arg: Box<[Expr; 1]>) -> Expr {
-        Expr::Op(OpExpr::new(OpExprArgs::Args1(self, arg), None))
+    pub fn expr(self, arg: [Expr; 1]) -> Expr {
+        Expr::Op(OpExpr::new(OpExprArgs::Args1(self, Arc::new(arg)), None))
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[allow(non_camel_case_types)]
 pub enum Opcode2 {
     OP_EQUAL = 0x87,
@@ -47,23 +48,26 @@ pub enum Opcode2 {
 }
 
 impl Opcode2 {
-    pub fn expr(self, args: Box<[Expr; 2]>) -> Expr {
-        Expr::Op(OpExpr::new(OpExprArgs::Args2(self, args), None))
+    pub fn expr(self, args: [Expr; 2]) -> Expr {
+        Expr::Op(OpExpr::new(OpExprArgs::Args2(self, Arc::new(args)), None))
     }
 
-    pub fn expr_with_error(self, args: Box<[Expr; 2]>, error: ScriptError) -> Expr {
-        Expr::Op(OpExpr::new(OpExprArgs::Args2(self, args), Some(error)))
+    pub fn expr_with_error(self, args: [Expr; 2], error: ScriptError) -> Expr {
+        Expr::Op(OpExpr::new(
+            OpExprArgs::Args2(self, Arc::new(args)),
+            Some(error),
+        ))
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[allow(non_camel_case_types)]
 pub enum Opcode3 {
     OP_WITHIN = 0xa5,
 }
 
 impl Opcode3 {
-    pub fn expr(self, args: Box<[Expr; 3]>) -> Expr {
-        Expr::Op(OpExpr::new(OpExprArgs::Args3(self, args), None))
+    pub fn expr(self, args: [Expr; 3]) -> Expr {
+        Expr::Op(OpExpr::new(OpExprArgs::Args3(self, Arc::new(args)), None))
     }
 }
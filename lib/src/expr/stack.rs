@@ -1,12 +1,17 @@
 use core::fmt;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct StackExpr(u32);
 
 impl StackExpr {
     pub fn new(pos: u32) -> Self {
         Self(pos)
     }
+
+    /// Position of the item on the initial stack, 0 being the top.
+    pub fn pos(&self) -> u32 {
+        self.0
+    }
 }
 
 impl fmt::Display for StackExpr {
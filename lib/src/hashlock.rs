@@ -0,0 +1,186 @@
+use crate::{
+    expr::{write_bytes, BytesExprBox, Expr, ExprFormat, OpExprArgs, Opcode1, Opcode2},
+    script::{convert::decode_int, MAX_SCRIPT_ELEMENT_SIZE},
+};
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashFunction {
+    Ripemd160,
+    Sha1,
+    Sha256,
+    /// RIPEMD160(SHA256(x))
+    Hash160,
+    /// SHA256(SHA256(x))
+    Hash256,
+}
+
+impl HashFunction {
+    /// Matches a (nested) hash expression, returns the hash function and its argument.
+    fn from_expr(expr: &Expr) -> Option<(Self, &Expr)> {
+        fn single(expr: &Expr) -> Option<(Opcode1, &Expr)> {
+            match expr {
+                Expr::Op(op) => match &op.args {
+                    OpExprArgs::Args1(
+                        op @ (Opcode1::OP_RIPEMD160 | Opcode1::OP_SHA1 | Opcode1::OP_SHA256),
+                        args,
+                    ) => Some((*op, &args[0])),
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+
+        let (outer, arg) = single(expr)?;
+        Some(match (outer, single(arg)) {
+            (Opcode1::OP_RIPEMD160, Some((Opcode1::OP_SHA256, arg))) => (Self::Hash160, arg),
+            (Opcode1::OP_SHA256, Some((Opcode1::OP_SHA256, arg))) => (Self::Hash256, arg),
+            (Opcode1::OP_RIPEMD160, _) => (Self::Ripemd160, arg),
+            (Opcode1::OP_SHA1, _) => (Self::Sha1, arg),
+            _ => (Self::Sha256, arg),
+        })
+    }
+}
+
+impl fmt::Display for HashFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Ripemd160 => "RIPEMD160",
+                Self::Sha1 => "SHA1",
+                Self::Sha256 => "SHA256",
+                Self::Hash160 => "HASH160",
+                Self::Hash256 => "HASH256",
+            }
+        )
+    }
+}
+
+/// A hashlock: a spending condition of the form `OP_EQUAL(hash(<stack item>), <digest>)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreimageRequirement {
+    pub stack_item: u32,
+    pub hash: HashFunction,
+    pub digest: BytesExprBox,
+    /// Inclusive bounds of the preimage size in bytes.
+    pub size: (usize, usize),
+}
+
+impl PreimageRequirement {
+    /// Detects a hashlock in `condition`. The other spending conditions of the path are searched
+    /// for constraints on the preimage size.
+    pub fn from_condition(condition: &Expr, conditions: &[Expr]) -> Option<Self> {
+        let [a, b] = equal_args(condition)?;
+        let (hash, digest, stack_item) = [(a, b), (b, a)].into_iter().find_map(|(a, b)| {
+            let Expr::Bytes(digest) = b else {
+                return None;
+            };
+            let (hash, Expr::Stack(item)) = HashFunction::from_expr(a)? else {
+                return None;
+            };
+            Some((hash, digest, item.pos()))
+        })?;
+
+        let size = conditions
+            .iter()
+            .find_map(|c| {
+                let [a, b] = equal_args(c)?;
+                [(a, b), (b, a)].into_iter().find_map(|(a, b)| match (a, b) {
+                    (Expr::Op(a), Expr::Bytes(b)) => match &a.args {
+                        OpExprArgs::Args1(Opcode1::OP_SIZE, args)
+                            if matches!(&args[0], Expr::Stack(s) if s.pos() == stack_item) =>
+                        {
+                            let len = decode_int(b, 4).ok()? as usize;
+                            Some((len, len))
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                })
+            })
+            .unwrap_or((0, MAX_SCRIPT_ELEMENT_SIZE));
+
+        Some(Self {
+            stack_item,
+            hash,
+            digest: digest.clone(),
+            size,
+        })
+    }
+}
+
+fn equal_args(expr: &Expr) -> Option<[&Expr; 2]> {
+    match expr {
+        Expr::Op(op) => match &op.args {
+            OpExprArgs::Args2(Opcode2::OP_EQUAL, args) => Some([&args[0], &args[1]]),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+impl PreimageRequirement {
+    /// Displays the requirement, with the digest rendered according to `format`.
+    pub fn display<'a>(&'a self, format: ExprFormat<'a>) -> PreimageDisplay<'a> {
+        PreimageDisplay { req: self, format }
+    }
+}
+
+/// Returned by [`PreimageRequirement::display`].
+pub struct PreimageDisplay<'a> {
+    req: &'a PreimageRequirement,
+    format: ExprFormat<'a>,
+}
+
+impl fmt::Display for PreimageDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let req = self.req;
+        write!(
+            f,
+            "stack item #{} must be the {} preimage of ",
+            req.stack_item, req.hash
+        )?;
+        write_bytes(f, req.digest.as_ref(), self.format)?;
+        match req.size {
+            (min, max) if min == max => write!(f, " ({min} bytes)"),
+            (0, max) => write!(f, " (at most {max} bytes)"),
+            (min, max) => write!(f, " ({min} to {max} bytes)"),
+        }
+    }
+}
+
+impl fmt::Display for PreimageRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display(ExprFormat::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HashFunction, PreimageRequirement};
+    use crate::{OwnedScript, ScriptContext, ScriptRules, ScriptVersion};
+
+    #[test]
+    fn test_preimage_requirements() {
+        let ctx = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All);
+        let digest = "ab".repeat(20);
+        let asm = format!("OP_SIZE 32 OP_EQUALVERIFY OP_HASH160 <{digest}> OP_EQUAL");
+        let mut buf = asm.into_bytes();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let analysis = crate::analyze_script(&script, ctx, Default::default()).unwrap();
+        let conditions = &analysis.paths[0].spending_conditions;
+
+        let req: Vec<_> = conditions
+            .iter()
+            .filter_map(|c| PreimageRequirement::from_condition(c, conditions))
+            .collect();
+        assert_eq!(req.len(), 1);
+        assert_eq!(req[0].hash, HashFunction::Hash160);
+        assert_eq!(
+            req[0].to_string(),
+            format!("stack item #0 must be the HASH160 preimage of <{digest}> (32 bytes)")
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use crate::{
+    analyzer::Analysis,
+    opcode::OpcodeType,
+    script::{Script, ScriptElem},
+    util::escape_with,
+};
+use std::fmt::Write;
+
+const STYLE: &str = "\
+body { font-family: sans-serif; max-width: 60em; margin: 2em auto; padding: 0 1em; }
+pre { background: #f4f4f4; padding: 0.5em; overflow-x: auto; }
+summary { cursor: pointer; font-weight: bold; }
+details { margin: 0.5em 0; }
+.warning { color: #b35900; font-weight: bold; }
+.error { color: #c00; }
+.data { color: #777; }
+.number, .constant { color: #a67f00; }
+.flow { color: #a0a; }
+.stack { color: #00a; }
+.splice, .bitwise { color: #088; }
+.arithmetic { color: #080; }
+.crypto { color: #080; font-weight: bold; }
+.locktime { color: #a0a; font-weight: bold; }
+.disabled, .invalid { color: #c00; font-weight: bold; }
+";
+
+/// Escapes a string for HTML text and attribute values.
+fn escape(s: &str) -> String {
+    escape_with(s, |c| match c {
+        '<' => Some("&lt;"),
+        '>' => Some("&gt;"),
+        '&' => Some("&amp;"),
+        '"' => Some("&quot;"),
+        _ => None,
+    })
+}
+
+/// CSS class of an opcode category.
+fn class(opcode_type: OpcodeType) -> &'static str {
+    match opcode_type {
+        OpcodeType::Data => "data",
+        OpcodeType::Number => "number",
+        OpcodeType::Constant => "constant",
+        OpcodeType::Flow => "flow",
+        OpcodeType::Stack => "stack",
+        OpcodeType::Splice => "splice",
+        OpcodeType::Bitwise => "bitwise",
+        OpcodeType::Arithmetic => "arithmetic",
+        OpcodeType::Crypto => "crypto",
+        OpcodeType::Locktime => "locktime",
+        OpcodeType::Disabled => "disabled",
+        OpcodeType::Invalid => "invalid",
+    }
+}
+
+/// The indented script listing, every element has a tooltip with its category.
+fn script_listing(out: &mut String, script: &Script) {
+    let listing = script.to_string();
+    // the listing has one element per line
+    for (line, elem) in listing.lines().zip(script.iter()) {
+        let text = line.trim_start();
+        out.push_str(&line[..line.len() - text.len()]);
+        let (class, title) = match elem {
+            ScriptElem::Op(opcode) => {
+                let opcode_type = opcode.opcode_type();
+                (
+                    class(opcode_type),
+                    format!("0x{:02x}, {opcode_type}", opcode.opcode),
+                )
+            }
+            ScriptElem::Bytes(bytes) => ("data", format!("{} byte push", bytes.len())),
+        };
+        writeln!(
+            out,
+            "<span class=\"{class}\" title=\"{title}\">{}</span>",
+            escape(text)
+        )
+        .unwrap();
+    }
+}
+
+impl Analysis {
+    /// Renders the analysis of `script` as a standalone HTML document, with the script listing
+    /// and a collapsible section per spending path.
+    pub fn to_html(&self, script: &Script) -> String {
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+            <title>Script analysis</title>\n<style>\n{STYLE}</style>\n</head>\n<body>\n\
+            <h1>Script analysis</h1>\n"
+        );
+
+        if self.paths.iter().any(|path| path.is_anyone_can_spend()) {
+            out.push_str(
+                "<p class=\"warning\">This script is trivially spendable by anyone, a spending \
+                path requires no signature, hash preimage or timelock</p>\n",
+            );
+        }
+        if let Some(template) = self.template {
+            writeln!(out, "<p>Template: {}</p>", escape(&template.to_string())).unwrap();
+        }
+
+        out.push_str("<h2>Script</h2>\n<pre>");
+        script_listing(&mut out, script);
+        out.push_str("</pre>\n");
+
+        if !self.paths.is_empty() {
+            writeln!(
+                out,
+                "<h2>Summary</h2>\n<pre>{}</pre>",
+                escape(&self.summary().to_string())
+            )
+            .unwrap();
+        }
+
+        writeln!(out, "<h2>Spending paths ({})</h2>", self.paths.len()).unwrap();
+        for (i, path) in self.paths.iter().enumerate() {
+            writeln!(
+                out,
+                "<details open>\n<summary>Path #{}</summary>\n<pre>{}</pre>\n</details>",
+                i + 1,
+                escape(&path.to_string())
+            )
+            .unwrap();
+        }
+
+        if !self.failed_paths.is_empty() {
+            writeln!(out, "<h2>Failed paths ({})</h2>", self.failed_paths.len()).unwrap();
+            for (i, path) in self.failed_paths.iter().enumerate() {
+                writeln!(
+                    out,
+                    "<details>\n<summary class=\"error\">Failed path #{}</summary>\n\
+                    <pre>{}</pre>\n</details>",
+                    i + 1,
+                    escape(&path.to_string())
+                )
+                .unwrap();
+            }
+        }
+
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{expr::Expr, util::encode_hex_easy};
+use core::fmt;
+
+/// A well known byte string, like the hash of the empty string, see [`known_constants`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KnownConstant {
+    pub hex: &'static str,
+    pub description: &'static str,
+    /// True if anyone can provide the preimage of this hash or sign for this public key, so a
+    /// check against it does not secure anything.
+    pub anyone_can_use: bool,
+}
+
+impl fmt::Display for KnownConstant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{}> is {}", self.hex, self.description)
+    }
+}
+
+const fn known(hex: &'static str, description: &'static str) -> KnownConstant {
+    KnownConstant {
+        hex,
+        description,
+        anyone_can_use: false,
+    }
+}
+
+const fn known_public(hex: &'static str, description: &'static str) -> KnownConstant {
+    KnownConstant {
+        hex,
+        description,
+        anyone_can_use: true,
+    }
+}
+
+pub const KNOWN_CONSTANTS: &[KnownConstant] = &[
+    known_public(
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+        "the SHA256 of the empty string, anyone can provide the preimage",
+    ),
+    known_public(
+        "da39a3ee5e6b4b0d3255bfef95601890afd80709",
+        "the SHA1 of the empty string, anyone can provide the preimage",
+    ),
+    known_public(
+        "9c1185a5c5e9fc54612808977ee8f548b2258d31",
+        "the RIPEMD160 of the empty string, anyone can provide the preimage",
+    ),
+    known_public(
+        "b472a266d0bd89c13706a4132ccfb16f7c3b9fcb",
+        "the HASH160 of the empty string, anyone can provide the preimage",
+    ),
+    known_public(
+        "5df6e0e2761359d30a8275058e299fcc0381534545f55cf43e41983f5d4c9456",
+        "the HASH256 of the empty string, anyone can provide the preimage",
+    ),
+    known_public(
+        "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        "the generator point, its private key is 1 so anyone can sign for it",
+    ),
+    known_public(
+        "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        "the generator point, its private key is 1 so anyone can sign for it",
+    ),
+    known(
+        "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0",
+        "the NUMS point H from BIP341, no one knows its private key so it can not sign",
+    ),
+    known(
+        "0250929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0",
+        "the NUMS point H from BIP341, no one knows its private key so it can not sign",
+    ),
+];
+
+/// Looks up `bytes` in [`KNOWN_CONSTANTS`].
+pub fn known_constant(bytes: &[u8]) -> Option<&'static KnownConstant> {
+    let mut hex = None;
+    KNOWN_CONSTANTS
+        .iter()
+        .filter(|c| c.hex.len() == bytes.len() * 2)
+        .find(|c| c.hex == hex.get_or_insert_with(|| encode_hex_easy(bytes)))
+}
+
+/// Finds the constants in `expr` that are in [`KNOWN_CONSTANTS`].
+pub fn known_constants(expr: &Expr) -> Vec<&'static KnownConstant> {
+    fn visit(expr: &Expr, out: &mut Vec<&'static KnownConstant>) {
+        match expr {
+            Expr::Bytes(b) => {
+                if let Some(c) = known_constant(b.as_ref()) {
+                    if !out.contains(&c) {
+                        out.push(c);
+                    }
+                }
+            }
+            Expr::Op(op) => {
+                for arg in op.args() {
+                    visit(arg, out);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let mut out = Vec::new();
+    visit(expr, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::known_constant;
+    use crate::{
+        analyze_script, OwnedScript, ReportOptions, ScriptContext, ScriptRules, ScriptVersion,
+    };
+
+    #[test]
+    fn test_known_constants() {
+        assert!(known_constant(&[]).is_none());
+
+        let mut buf = b"OP_HASH160 <b472a266d0bd89c13706a4132ccfb16f7c3b9fcb> OP_EQUAL".to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let ctx = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All);
+        let analysis = analyze_script(&script, ctx, Default::default()).unwrap();
+        let requirements = analysis.paths[0].requirements(ReportOptions::default());
+        assert!(requirements[0].contains(
+            "(note: <b472a266d0bd89c13706a4132ccfb16f7c3b9fcb> is the HASH160 of the empty \
+             string, anyone can provide the preimage)"
+        ));
+    }
+}
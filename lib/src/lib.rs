@@ -2,18 +2,85 @@
 // #![cfg_attr(not(feature = "threads"), no_std)]
 // extern crate alloc;
 
+mod analyze_error;
 mod analyzer;
+#[cfg(feature = "bitcoin")]
+mod bitcoin_interop;
+mod classify;
+mod compare;
 pub mod condition_stack;
 mod context;
-mod expr;
+mod dead_code;
+mod debugger;
+mod dot;
+pub mod expr;
+mod hashlock;
+mod html;
+mod known;
+mod markdown;
+mod multisig;
 mod opcode;
+mod optimize;
+mod output;
+mod range;
+mod report;
+mod roles;
 mod script;
+mod script_code;
 pub mod script_error;
+mod sighash;
+mod smtlib;
+#[cfg(feature = "solver")]
+mod solver;
+mod stats;
+mod summary;
 mod threadpool;
+mod threshold;
+mod timelock;
 pub mod util;
+mod weight;
 
 pub use crate::{
-    analyzer::analyze_script,
-    context::{ScriptContext, ScriptRules, ScriptVersion},
-    script::{convert as script_convert, OwnedScript, ParseScriptError, Script, ScriptElem},
+    analyze_error::{AnalysisLimit, AnalyzeError},
+    analyzer::{
+        analyze_script, analyze_script_with, diagnose_script, execute_script, Analysis,
+        AnalysisOptions, BranchDecision, ExecutionResult, FailedPath, LocktimeRequirement,
+        PathOrder, PathResult, ScriptAnalyzer, ScriptAnalyzerBuilder, SpendingPath,
+    },
+    classify::{classify, ScriptTemplate},
+    compare::{compare_scripts, ChangedPath, ScriptComparison},
+    context::{Network, ScriptContext, ScriptRules, ScriptVersion, VerifyFlags},
+    dead_code::{find_dead_code, DeadCode, DeadCodeReason},
+    debugger::{DebugStep, ScriptDebugger},
+    hashlock::{HashFunction, PreimageDisplay, PreimageRequirement},
+    known::{known_constant, known_constants, KnownConstant, KNOWN_CONSTANTS},
+    multisig::{MultisigKeySets, MultisigKeySetsDisplay},
+    opcode::{opcodes, Opcode, OpcodeType},
+    optimize::{suggest_optimizations, Suggestion},
+    output::{
+        analyze_output, output_script, revealed_script, OutputKind, OutputScript, OutputScripts,
+        RevealedScript, RevealedScriptError, WitnessProgram,
+    },
+    range::{ItemRange, NumRange},
+    report::{AnalysisReport, PathReport, ReportOptions},
+    roles::{ItemRole, ItemUsage},
+    script::{
+        convert as script_convert, AsmDialect, AsmToken, Instruction, Instructions, OwnedScript,
+        ParseAsmScriptError, ParseScriptError, ParseWarning, PushEncoding, Script, ScriptBuf,
+        ScriptElem, ScriptRef,
+    },
+    script_code::ScriptCode,
+    sighash::{sighash_type_name, SighashDisplay, SighashRequirement, SighashTypes},
+    stats::AnalysisStats,
+    summary::{EarliestSpend, Summary},
+    threshold::{ThresholdDisplay, ThresholdMultisig},
+    timelock::{ChainTip, SpendableAt, TimelockMismatch, TxTimelocks},
+    weight::{SigOps, SizeEstimate},
 };
+
+#[cfg(feature = "bitcoin")]
+pub use crate::bitcoin_interop::{input_script, InputScriptError, SpendingTx};
+#[cfg(feature = "solver")]
+pub use crate::solver::{ItemValue, Model, Satisfiability, Solver};
+#[cfg(feature = "threads")]
+pub use crate::threadpool::ThreadPool;
@@ -0,0 +1,92 @@
+use crate::{analyzer::Analysis, report::ReportOptions};
+use std::fmt::Write;
+
+/// Formats `s` as inline code in a table cell.
+fn code(s: &str) -> String {
+    // the pipe would end the cell, even in inline code
+    format!("`{}`", s.replace('|', "\\|"))
+}
+
+impl Analysis {
+    /// Renders the analysis as Markdown, with a table of the spending paths and a list of the
+    /// requirements of each path.
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+
+        if self.paths.iter().any(|path| path.is_anyone_can_spend()) {
+            out.push_str(
+                "> **Warning:** this script is trivially spendable by anyone, a spending path \
+                requires no signature, hash preimage or timelock\n\n",
+            );
+        }
+        if let Some(template) = self.template {
+            writeln!(out, "**Template:** {template}\n").unwrap();
+        }
+        if !self.paths.is_empty() {
+            out.push_str("## Summary\n\n");
+            for line in self.summary().to_string().lines() {
+                writeln!(out, "- {line}").unwrap();
+            }
+            out.push('\n');
+        }
+
+        out.push_str("## Spending paths\n\n");
+        if self.paths.is_empty() {
+            out.push_str("None\n");
+        } else {
+            out.push_str(
+                "| Path | Stack size | Requirements | Locktime | Sequence | Input size | \
+                Sigops |\n|---|---|---|---|---|---|---|\n",
+            );
+            for (i, path) in self.paths.iter().enumerate() {
+                let requirements = path
+                    .requirements(ReportOptions::default())
+                    .iter()
+                    .map(|req| code(req))
+                    .collect::<Vec<_>>();
+                let timelock = |req: Option<String>| req.map_or("none".to_owned(), |s| code(&s));
+                writeln!(
+                    out,
+                    "| {} | {} | {} | {} | {} | {} vbytes | {} |",
+                    i + 1,
+                    path.stack_size,
+                    if requirements.is_empty() {
+                        "none".to_owned()
+                    } else {
+                        requirements.join("<br>")
+                    },
+                    timelock(path.locktime_req.locktime_requirement_to_string(false)),
+                    timelock(path.sequence_req.locktime_requirement_to_string(true)),
+                    path.size.vsize,
+                    path.sigops.count(),
+                )
+                .unwrap();
+            }
+        }
+
+        if !self.failed_paths.is_empty() {
+            out.push_str("\n## Failed paths\n\n| Path | Branches | Error |\n|---|---|---|\n");
+            for (i, path) in self.failed_paths.iter().enumerate() {
+                let branches = path
+                    .branches
+                    .iter()
+                    .map(|branch| branch.to_string())
+                    .collect::<Vec<_>>();
+                writeln!(
+                    out,
+                    "| {} | {} | {} |",
+                    i + 1,
+                    if branches.is_empty() {
+                        "none".to_owned()
+                    } else {
+                        branches.join("<br>")
+                    },
+                    code(&path.error.to_string()),
+                )
+                .unwrap();
+            }
+        }
+
+        out
+    }
+}
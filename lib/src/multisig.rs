@@ -0,0 +1,219 @@
+use crate::{
+    context::ScriptContext,
+    expr::{Expr, ExprFormat, OpExprArgs, Origin},
+    util::checksig::check_pub_key_encoding,
+};
+use core::fmt;
+
+/// The sets of public keys that can sign an `OP_CHECKMULTISIG` with fewer signatures than keys,
+/// see [`SpendingPath::key_sets`](crate::SpendingPath::key_sets). Every set is a way to spend
+/// the path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultisigKeySets {
+    pub origin: Option<Origin>,
+    pub sigs: Vec<Expr>,
+    /// Every set has a key for every signature, signatures and keys are in the same order.
+    /// Sets with a key that makes the script fail are left out.
+    pub key_sets: Vec<Vec<Expr>>,
+}
+
+impl MultisigKeySets {
+    pub fn display<'a>(&'a self, format: ExprFormat<'a>) -> MultisigKeySetsDisplay<'a> {
+        MultisigKeySetsDisplay { sets: self, format }
+    }
+}
+
+impl fmt::Display for MultisigKeySets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display(ExprFormat::default()))
+    }
+}
+
+/// Returned by [`MultisigKeySets::display`].
+pub struct MultisigKeySetsDisplay<'a> {
+    sets: &'a MultisigKeySets,
+    format: ExprFormat<'a>,
+}
+
+impl fmt::Display for MultisigKeySetsDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.sets.origin {
+            Some(origin) => write!(f, "{origin}")?,
+            None => write!(f, "OP_CHECKMULTISIG")?,
+        }
+        write!(f, " with signatures ")?;
+        write_list(f, &self.sets.sigs, self.format)?;
+        write!(f, ":")?;
+        for (i, keys) in self.sets.key_sets.iter().enumerate() {
+            write!(f, "\nkey set {}: ", i + 1)?;
+            write_list(f, keys, self.format)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter<'_>, exprs: &[Expr], format: ExprFormat<'_>) -> fmt::Result {
+    for (i, expr) in exprs.iter().enumerate() {
+        if i != 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", expr.display(format))?;
+    }
+    Ok(())
+}
+
+/// Binomial coefficient, `None` on overflow.
+fn combinations(n: usize, k: usize) -> Option<usize> {
+    (0..k).try_fold(1usize, |c, i| Some(c.checked_mul(n - i)? / (i + 1)))
+}
+
+/// Calls `f` with the indices of every `k` element subset of `0..n`, in lexicographic order.
+fn for_each_subset(n: usize, k: usize, mut f: impl FnMut(&[usize])) {
+    let mut indices: Vec<usize> = (0..k).collect();
+    loop {
+        f(&indices);
+        // the last index that can still move up
+        let Some(i) = (0..k).rev().find(|&i| indices[i] != i + n - k) else {
+            return;
+        };
+        indices[i] += 1;
+        for j in i + 1..k {
+            indices[j] = indices[j - 1] + 1;
+        }
+    }
+}
+
+/// Key sets of one `OP_CHECKMULTISIG`, `None` if it has more than `max`.
+fn key_sets(
+    sigs: &[Expr],
+    keys: &[Expr],
+    origin: Option<Origin>,
+    ctx: ScriptContext,
+    max: usize,
+) -> Option<MultisigKeySets> {
+    if combinations(keys.len(), sigs.len()).is_none_or(|c| c > max) {
+        return None;
+    }
+
+    let encoding: Vec<_> = keys
+        .iter()
+        .map(|key| match key {
+            Expr::Bytes(key) => check_pub_key_encoding(key, ctx),
+            _ => Ok(true),
+        })
+        .collect();
+    let mut key_sets: Vec<Vec<Expr>> = Vec::new();
+    for_each_subset(keys.len(), sigs.len(), |indices| {
+        // keys are compared from the last one, every key down to the first one of the set is
+        // checked
+        let reached = &encoding[indices[0]..];
+        if reached.iter().any(|e| e.is_err()) || indices.iter().any(|&i| encoding[i] != Ok(true)) {
+            return;
+        }
+        let set: Vec<_> = indices.iter().map(|&i| keys[i].clone()).collect();
+        if !key_sets.contains(&set) {
+            key_sets.push(set);
+        }
+    });
+
+    Some(MultisigKeySets {
+        origin,
+        sigs: sigs.to_vec(),
+        key_sets,
+    })
+}
+
+/// Finds every `OP_CHECKMULTISIG` in `conditions` that does not need a signature for every key,
+/// and lists the sets of keys that can sign it. Multisigs with more than `max` possible sets are
+/// skipped.
+pub(crate) fn multisig_key_sets(
+    conditions: &[Expr],
+    ctx: ScriptContext,
+    max: usize,
+) -> Vec<MultisigKeySets> {
+    fn visit(expr: &Expr, ctx: ScriptContext, max: usize, out: &mut Vec<MultisigKeySets>) {
+        let Expr::Op(op) = expr else {
+            return;
+        };
+        if let OpExprArgs::Multisig(m) = &op.args {
+            if !m.sigs().is_empty() && m.sigs().len() < m.keys().len() {
+                out.extend(key_sets(m.sigs(), m.keys(), op.origin(), ctx, max));
+            }
+        }
+        for arg in op.args() {
+            visit(arg, ctx, max, out);
+        }
+    }
+
+    let mut out = Vec::new();
+    for expr in conditions {
+        visit(expr, ctx, max, &mut out);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{combinations, for_each_subset};
+    use crate::{
+        analyze_script, AnalysisOptions, OwnedScript, ScriptContext, ScriptRules, ScriptVersion,
+    };
+
+    #[test]
+    fn test_subsets() {
+        let mut subsets = Vec::new();
+        for_each_subset(4, 2, |s| subsets.push(s.to_vec()));
+        assert_eq!(
+            subsets,
+            [[0, 1], [0, 2], [0, 3], [1, 2], [1, 3], [2, 3]].map(|s| s.to_vec())
+        );
+        assert_eq!(combinations(4, 2), Some(6));
+        assert_eq!(combinations(20, 10), Some(184756));
+    }
+
+    #[test]
+    fn test_multisig_key_sets() {
+        // x coordinates of points on the curve
+        let key = |x: &str| format!("<02{x}>");
+        let uncompressed = format!("<04{}>", "ee".repeat(64));
+        let asm = format!(
+            "2 {uncompressed} {} {} {} 4 OP_CHECKMULTISIG",
+            key("79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"),
+            key("c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5"),
+            key("f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9")
+        );
+        let mut buf = asm.into_bytes();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let ctx = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All);
+        let opts = AnalysisOptions {
+            max_key_sets: Some(10),
+            ..Default::default()
+        };
+        let analysis = analyze_script(&script, ctx, opts).unwrap();
+
+        let sets = &analysis.paths[0].key_sets;
+        assert_eq!(sets.len(), 1);
+        // the uncompressed key fails with WITNESS_PUBKEYTYPE if it is reached
+        assert_eq!(sets[0].key_sets.len(), 3);
+        let report = sets[0].to_string();
+        assert!(report.starts_with("OP_CHECKMULTISIG at byte 170 with signatures "));
+        assert_eq!(
+            report.lines().nth(1),
+            Some(
+                format!(
+                    "key set 1: {}, {}",
+                    key("79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"),
+                    key("c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5")
+                )
+                .as_str()
+            )
+        );
+
+        let opts = AnalysisOptions {
+            max_key_sets: Some(5),
+            ..Default::default()
+        };
+        let analysis = analyze_script(&script, ctx, opts).unwrap();
+        assert!(analysis.paths[0].key_sets.is_empty());
+    }
+}
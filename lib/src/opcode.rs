@@ -8,7 +8,10 @@ pub struct Opcode {
 
 impl fmt::Display for Opcode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.name().unwrap_or("UNKNOWN"))
+        match self.name() {
+            Some(name) => write!(f, "{name}"),
+            None => write!(f, "UNKNOWN(0x{:02x})", self.opcode),
+        }
     }
 }
 
@@ -315,6 +318,8 @@ impl Opcode {
     }
 }
 
+/// Category of an opcode, used for syntax highlighting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OpcodeType {
     Data,
     Number,
@@ -330,6 +335,25 @@ pub enum OpcodeType {
     Invalid,
 }
 
+impl fmt::Display for OpcodeType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Data => "data push",
+            Self::Number => "number",
+            Self::Constant => "constant",
+            Self::Flow => "flow control",
+            Self::Stack => "stack",
+            Self::Splice => "splice",
+            Self::Bitwise => "bitwise logic",
+            Self::Arithmetic => "arithmetic",
+            Self::Crypto => "crypto",
+            Self::Locktime => "locktime",
+            Self::Disabled => "disabled",
+            Self::Invalid => "invalid",
+        })
+    }
+}
+
 impl Opcode {
     pub fn opcode_type(&self) -> OpcodeType {
         let op = *self;
@@ -339,6 +363,8 @@ impl Opcode {
             OpcodeType::Invalid
         } else if op >= opcodes::OP_0 && op <= opcodes::OP_PUSHDATA4 {
             OpcodeType::Constant
+        } else if op == opcodes::OP_1NEGATE || (op >= opcodes::OP_1 && op <= opcodes::OP_16) {
+            OpcodeType::Number
         } else if op >= opcodes::OP_NOP && op <= opcodes::OP_RETURN {
             OpcodeType::Flow
         } else if op >= opcodes::OP_TOALTSTACK && op <= opcodes::OP_TUCK {
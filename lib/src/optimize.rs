@@ -0,0 +1,223 @@
+use crate::{
+    analyze_error::AnalyzeError,
+    analyzer::{analyze_script, Analysis, AnalysisOptions},
+    compare::ScriptComparison,
+    context::ScriptContext,
+    opcode::{opcodes, Opcode},
+    script::{Script, ScriptElem, ScriptRef},
+};
+use core::fmt;
+
+/// Sequences of opcodes and a shorter sequence to replace them with. Not all of them are
+/// equivalent everywhere, `OP_DUP OP_DROP` fails on an empty stack and `OP_1 OP_EQUAL OP_IF` is
+/// only the same as `OP_IF` when the argument is a boolean and minimal if applies, so every
+/// rewrite is checked by comparing the spending paths before it is suggested.
+const REWRITES: &[(&[Opcode], &[Opcode])] = &[
+    (&[opcodes::OP_DUP, opcodes::OP_DROP], &[]),
+    (&[opcodes::OP_TOALTSTACK, opcodes::OP_FROMALTSTACK], &[]),
+    (&[opcodes::OP_SWAP, opcodes::OP_SWAP], &[]),
+    (&[opcodes::OP_2SWAP, opcodes::OP_2SWAP], &[]),
+    (&[opcodes::OP_0, opcodes::OP_ROLL], &[]),
+    (&[opcodes::OP_NOP], &[]),
+    (&[opcodes::OP_0, opcodes::OP_PICK], &[opcodes::OP_DUP]),
+    (&[opcodes::OP_1, opcodes::OP_PICK], &[opcodes::OP_OVER]),
+    (&[opcodes::OP_1, opcodes::OP_ROLL], &[opcodes::OP_SWAP]),
+    (&[opcodes::OP_2, opcodes::OP_ROLL], &[opcodes::OP_ROT]),
+    (&[opcodes::OP_DROP, opcodes::OP_DROP], &[opcodes::OP_2DROP]),
+    (&[opcodes::OP_OVER, opcodes::OP_OVER], &[opcodes::OP_2DUP]),
+    (&[opcodes::OP_1, opcodes::OP_ADD], &[opcodes::OP_1ADD]),
+    (&[opcodes::OP_1, opcodes::OP_SUB], &[opcodes::OP_1SUB]),
+    (
+        &[opcodes::OP_EQUAL, opcodes::OP_VERIFY],
+        &[opcodes::OP_EQUALVERIFY],
+    ),
+    (
+        &[opcodes::OP_NUMEQUAL, opcodes::OP_VERIFY],
+        &[opcodes::OP_NUMEQUALVERIFY],
+    ),
+    (
+        &[opcodes::OP_CHECKSIG, opcodes::OP_VERIFY],
+        &[opcodes::OP_CHECKSIGVERIFY],
+    ),
+    (
+        &[opcodes::OP_CHECKMULTISIG, opcodes::OP_VERIFY],
+        &[opcodes::OP_CHECKMULTISIGVERIFY],
+    ),
+    (&[opcodes::OP_NOT, opcodes::OP_IF], &[opcodes::OP_NOTIF]),
+    (&[opcodes::OP_NOT, opcodes::OP_NOTIF], &[opcodes::OP_IF]),
+    (
+        &[opcodes::OP_1, opcodes::OP_EQUAL, opcodes::OP_IF],
+        &[opcodes::OP_IF],
+    ),
+    (
+        &[opcodes::OP_1, opcodes::OP_EQUAL, opcodes::OP_NOTIF],
+        &[opcodes::OP_NOTIF],
+    ),
+    (
+        &[opcodes::OP_0, opcodes::OP_EQUAL, opcodes::OP_IF],
+        &[opcodes::OP_NOTIF],
+    ),
+    (
+        &[opcodes::OP_0, opcodes::OP_EQUAL, opcodes::OP_NOTIF],
+        &[opcodes::OP_IF],
+    ),
+];
+
+/// A rewrite of a part of a script that makes it smaller without changing the ways it can be
+/// spent, see [`suggest_optimizations`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    /// Index of the first replaced script element.
+    pub index: usize,
+    /// Amount of replaced script elements.
+    pub len: usize,
+    /// Offset of the first replaced element in the serialized script.
+    pub offset: usize,
+    /// The replaced elements, as asm.
+    pub original: String,
+    /// Empty if the elements can be removed.
+    pub replacement: Vec<Opcode>,
+    /// Bytes saved by the rewrite.
+    pub savings: usize,
+}
+
+impl fmt::Display for Suggestion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` at byte {} ", self.original, self.offset)?;
+        if self.replacement.is_empty() {
+            write!(f, "is a no-op")?;
+        } else {
+            write!(f, "can be `{}`", asm(self.replacement.iter()))?;
+        }
+        let s = if self.savings == 1 { "" } else { "s" };
+        write!(f, ", saves {} byte{s}", self.savings)
+    }
+}
+
+/// Space separated, unlike the `Display` of [`Script`].
+fn asm<T: fmt::Display>(elems: impl Iterator<Item = T>) -> String {
+    elems.map(|e| e.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+/// Candidate rewrites of the elements starting at `index`, as the amount of replaced elements
+/// and the replacement.
+fn rewrites_at(script: &Script<'_>, index: usize) -> Vec<(usize, Vec<Opcode>)> {
+    let mut rewrites = Vec::new();
+    // pushes of numbers that have their own opcode
+    if let ScriptElem::Bytes(bytes) = script[index] {
+        match *bytes {
+            [n @ 1..=16] => rewrites.push((1, vec![Opcode { opcode: 0x50 + n }])),
+            [0x81] => rewrites.push((1, vec![opcodes::OP_1NEGATE])),
+            _ => (),
+        }
+    }
+    for (pattern, replacement) in REWRITES {
+        let matches = script[index..].len() >= pattern.len()
+            && pattern
+                .iter()
+                .zip(&script[index..])
+                .all(|(&op, elem)| matches!(*elem, ScriptElem::Op(o) if o == op));
+        if matches {
+            rewrites.push((pattern.len(), replacement.to_vec()));
+        }
+    }
+    rewrites
+}
+
+/// Returns true if the spending paths of `script` are the same as the paths of `analysis`.
+fn is_equivalent(
+    analysis: &Analysis,
+    script: &Script<'_>,
+    ctx: ScriptContext,
+    opts: AnalysisOptions,
+) -> bool {
+    analyze_script(script, ctx, opts)
+        .is_ok_and(|new| ScriptComparison::new(analysis.clone(), new).is_equivalent())
+}
+
+/// Finds parts of `script` that can be written shorter, like pushes of small numbers that could
+/// use `OP_1`..`OP_16` or opcodes that cancel each other out. A rewrite is only suggested if the
+/// rewritten script has the same spending paths, see [`compare_scripts`](crate::compare_scripts).
+/// Suggestions do not overlap and are ordered by their position in the script.
+pub fn suggest_optimizations<'a>(
+    script: impl Into<ScriptRef<'a>>,
+    ctx: ScriptContext,
+    opts: AnalysisOptions,
+) -> Result<Vec<Suggestion>, AnalyzeError> {
+    let script_ref = script.into();
+    let script = script_ref.script;
+    let analysis = analyze_script(script_ref, ctx, opts)?;
+    let instructions: Vec<_> = script_ref.instructions().collect();
+
+    let mut suggestions = Vec::new();
+    let mut index = 0;
+    while index < script.len() {
+        let suggestion = rewrites_at(script, index)
+            .into_iter()
+            .find(|(len, replacement)| {
+                let mut new = script[..index].to_vec();
+                new.extend(replacement.iter().map(|&op| ScriptElem::Op(op)));
+                new.extend_from_slice(&script[index + len..]);
+                is_equivalent(&analysis, Script::new(&new), ctx, opts)
+            })
+            .map(|(len, replacement)| {
+                let original = &instructions[index..index + len];
+                Suggestion {
+                    index,
+                    len,
+                    offset: original[0].offset,
+                    original: asm(original.iter().map(|i| i.elem)),
+                    savings: original.iter().map(|i| i.len).sum::<usize>() - replacement.len(),
+                    replacement,
+                }
+            });
+
+        index += suggestion.as_ref().map_or(1, |s| s.len);
+        suggestions.extend(suggestion);
+    }
+
+    Ok(suggestions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::suggest_optimizations;
+    use crate::{opcodes, OwnedScript, ScriptContext, ScriptRules, ScriptVersion};
+
+    fn suggest(script: &str, version: ScriptVersion) -> Vec<super::Suggestion> {
+        let ctx = ScriptContext::new(version, ScriptRules::All);
+        let mut buf = script.as_bytes().to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        suggest_optimizations(&script, ctx, Default::default()).unwrap()
+    }
+
+    #[test]
+    fn test_suggest_optimizations() {
+        let suggestions = suggest(
+            "OP_DUP OP_DROP <05> OP_EQUAL OP_VERIFY <07> OP_EQUAL",
+            ScriptVersion::SegwitV0,
+        );
+        assert_eq!(suggestions.len(), 4);
+        assert_eq!(
+            suggestions[0].to_string(),
+            "`OP_DUP OP_DROP` at byte 0 is a no-op, saves 2 bytes"
+        );
+        assert_eq!(
+            suggestions[1].to_string(),
+            "`<05>` at byte 2 can be `OP_5`, saves 1 byte"
+        );
+        assert_eq!(
+            suggestions[2].to_string(),
+            "`OP_EQUAL OP_VERIFY` at byte 4 can be `OP_EQUALVERIFY`, saves 1 byte"
+        );
+        assert_eq!((suggestions[3].index, suggestions[3].offset), (5, 6));
+
+        // only a boolean can be given to OP_IF directly
+        let script = "OP_CHECKSIG 1 OP_EQUAL OP_IF 1 OP_ELSE 2 OP_ENDIF";
+        let suggestions = suggest(script, ScriptVersion::SegwitV1);
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].replacement, [opcodes::OP_IF]);
+        let script = "1 OP_EQUAL OP_IF 1 OP_ELSE 2 OP_ENDIF";
+        assert!(suggest(script, ScriptVersion::SegwitV1).is_empty());
+    }
+}
@@ -0,0 +1,646 @@
+use crate::{
+    analyze_error::AnalyzeError,
+    analyzer::{analyze_script, Analysis, AnalysisOptions},
+    context::{ScriptContext, ScriptVersion, VerifyFlags},
+    opcode::opcodes,
+    script::{OwnedScript, PushEncoding, Script, ScriptElem},
+    script_error::ScriptError,
+};
+use bitcoin_hashes::{hash160, sha256, Hash};
+use core::fmt;
+
+/// A witness program (BIP 141): a scriptPubKey with a version push (`OP_0` or `OP_1`..`OP_16`)
+/// followed by a push of 2 to 40 bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WitnessProgram<'a> {
+    pub version: u8,
+    pub program: &'a [u8],
+}
+
+impl<'a> WitnessProgram<'a> {
+    pub fn from_script(script: &Script<'a>) -> Option<Self> {
+        let [ScriptElem::Op(version), ScriptElem::Bytes(program)] = **script else {
+            return None;
+        };
+        let version = match version {
+            opcodes::OP_0 => 0,
+            v if v >= opcodes::OP_1 && v <= opcodes::OP_16 => v.opcode - 0x50,
+            _ => return None,
+        };
+        (2..=40)
+            .contains(&program.len())
+            .then_some(Self { version, program })
+    }
+}
+
+/// The scripts revealed when spending an output, see [`output_script`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OutputScripts<'s, 'a> {
+    /// The script sig of the input. Its last push is the redeem script of a P2SH output, the
+    /// other pushes are not used.
+    pub script_sig: Option<&'s Script<'a>>,
+    /// The redeem script of a P2SH output.
+    pub redeem_script: Option<&'s Script<'a>>,
+    /// The witness script of a P2WSH output, or the tapscript of a P2TR script path spend.
+    pub witness_script: Option<&'s Script<'a>>,
+}
+
+/// How an output is spent, see [`OutputScript`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputKind {
+    /// The scriptPubKey itself is executed.
+    Bare,
+    P2sh,
+    /// `wrapped` if the witness program is the redeem script of a P2SH output.
+    P2wpkh {
+        wrapped: bool,
+    },
+    P2wsh {
+        wrapped: bool,
+    },
+    P2trKeyPath,
+    /// The tapscript of the leaf, its commitment to the output key is not checked.
+    P2trScriptPath,
+    /// A witness version or program length without consensus rules, anyone can spend it.
+    UnknownWitness {
+        version: u8,
+        wrapped: bool,
+    },
+}
+
+impl fmt::Display for OutputKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let wrapped = |wrapped| if wrapped { "P2SH-" } else { "" };
+        match *self {
+            Self::Bare => write!(f, "bare script"),
+            Self::P2sh => write!(f, "P2SH"),
+            Self::P2wpkh { wrapped: w } => write!(f, "{}P2WPKH", wrapped(w)),
+            Self::P2wsh { wrapped: w } => write!(f, "{}P2WSH", wrapped(w)),
+            Self::P2trKeyPath => write!(f, "P2TR key path"),
+            Self::P2trScriptPath => write!(f, "P2TR script path"),
+            Self::UnknownWitness {
+                version,
+                wrapped: w,
+            } => write!(f, "{}witness version {version}", wrapped(w)),
+        }
+    }
+}
+
+/// The script that is executed when spending an output, returned by [`output_script`].
+#[derive(Debug, Clone)]
+pub struct OutputScript<'a> {
+    pub kind: OutputKind,
+    pub script: OwnedScript<'a>,
+    pub ctx: ScriptContext,
+}
+
+impl OutputScript<'_> {
+    pub fn analyze(&self, opts: AnalysisOptions) -> Result<Analysis, AnalyzeError> {
+        analyze_script(&self.script, self.ctx, opts)
+    }
+}
+
+/// Finds the script that is executed when spending `script_pubkey` with `scripts` under `flags`,
+/// after checking the structure of witness programs and the hash of P2SH redeem scripts. A
+/// P2WPKH program is executed as its P2PKH script, a P2TR key path spend as `<key> OP_CHECKSIG`
+/// and an unknown witness program as `OP_TRUE`.
+///
+/// A P2SH output without a redeem script is treated as a bare script.
+pub fn output_script<'a>(
+    script_pubkey: &Script<'a>,
+    scripts: OutputScripts<'_, 'a>,
+    flags: VerifyFlags,
+) -> Result<OutputScript<'a>, AnalyzeError> {
+    let err = |err| Err(AnalyzeError::Output(err));
+    let witness = flags.contains(VerifyFlags::WITNESS);
+    let p2sh_hash = match **script_pubkey {
+        [ScriptElem::Op(opcodes::OP_HASH160), ScriptElem::Bytes(hash), ScriptElem::Op(opcodes::OP_EQUAL)]
+            if hash.len() == 20 && flags.contains(VerifyFlags::P2SH) =>
+        {
+            Some(hash)
+        }
+        _ => None,
+    };
+
+    if let Some(script_sig) = scripts.script_sig {
+        if (p2sh_hash.is_some() || flags.contains(VerifyFlags::SIGPUSHONLY))
+            && !script_sig.is_push_only()
+        {
+            return err(ScriptError::SCRIPT_ERR_SIG_PUSHONLY);
+        }
+    }
+
+    if witness {
+        if let Some(program) = WitnessProgram::from_script(script_pubkey) {
+            if scripts.script_sig.is_some_and(|s| !s.is_empty()) {
+                return err(ScriptError::SCRIPT_ERR_WITNESS_MALLEATED);
+            }
+            return witness_program_script(program, scripts.witness_script, flags, false);
+        }
+    }
+
+    if let Some(hash) = p2sh_hash {
+        if let Some(redeem_script) = redeem_script(hash, scripts)? {
+            if witness {
+                if let Some(program) = WitnessProgram::from_script(&redeem_script) {
+                    // the script sig must only push the redeem script
+                    if scripts.script_sig.is_some_and(|s| s.len() != 1) {
+                        return err(ScriptError::SCRIPT_ERR_WITNESS_MALLEATED_P2SH);
+                    }
+                    return witness_program_script(program, scripts.witness_script, flags, true);
+                }
+                if scripts.witness_script.is_some() {
+                    return err(ScriptError::SCRIPT_ERR_WITNESS_UNEXPECTED);
+                }
+            }
+            return Ok(OutputScript {
+                kind: OutputKind::P2sh,
+                script: redeem_script,
+                ctx: ScriptContext::with_flags(ScriptVersion::Legacy, flags),
+            });
+        }
+    }
+
+    if scripts.witness_script.is_some() && witness {
+        return err(ScriptError::SCRIPT_ERR_WITNESS_UNEXPECTED);
+    }
+    Ok(OutputScript {
+        kind: OutputKind::Bare,
+        script: OwnedScript::from(script_pubkey),
+        ctx: ScriptContext::with_flags(ScriptVersion::Legacy, flags),
+    })
+}
+
+/// The redeem script of a P2SH output with this `hash`: the last push of the script sig, or
+/// `scripts.redeem_script` if there is no script sig.
+fn redeem_script<'a>(
+    hash: &[u8],
+    scripts: OutputScripts<'_, 'a>,
+) -> Result<Option<OwnedScript<'a>>, AnalyzeError> {
+    let err = |err| Err(AnalyzeError::Output(err));
+
+    let (script_hash, redeem_script) = match (scripts.script_sig, scripts.redeem_script) {
+        (Some(script_sig), redeem_script) => {
+            let bytes = match script_sig.last() {
+                Some(&ScriptElem::Bytes(bytes)) => bytes,
+                Some(&ScriptElem::Op(opcodes::OP_0)) => &[],
+                // OP_HASH160 of OP_1NEGATE or OP_1..OP_16 with a 20 byte push after it
+                Some(_) => return err(ScriptError::SCRIPT_ERR_EVAL_FALSE),
+                None => return err(ScriptError::SCRIPT_ERR_INVALID_STACK_OPERATION),
+            };
+            // the script is not valid, it fails when it is executed
+            let script = OwnedScript::parse_from_bytes(bytes)
+                .map_err(|_| AnalyzeError::Output(ScriptError::SCRIPT_ERR_BAD_OPCODE))?;
+            if redeem_script.is_some_and(|r| {
+                r.serialize(PushEncoding::Original)
+                    != Script::serialize(&script, PushEncoding::Original)
+            }) {
+                // not the redeem script that is revealed
+                return err(ScriptError::SCRIPT_ERR_EVAL_FALSE);
+            }
+            (hash160::Hash::hash(bytes), script)
+        }
+        (None, Some(redeem_script)) => (
+            hash160::Hash::hash(&redeem_script.serialize(PushEncoding::Original)),
+            OwnedScript::from(redeem_script),
+        ),
+        (None, None) => return Ok(None),
+    };
+
+    if script_hash.to_byte_array()[..] != *hash {
+        return err(ScriptError::SCRIPT_ERR_EVAL_FALSE);
+    }
+    Ok(Some(redeem_script))
+}
+
+/// Like `VerifyWitnessProgram` in Bitcoin Core.
+fn witness_program_script<'a>(
+    WitnessProgram { version, program }: WitnessProgram<'a>,
+    witness_script: Option<&Script<'a>>,
+    flags: VerifyFlags,
+    wrapped: bool,
+) -> Result<OutputScript<'a>, AnalyzeError> {
+    let err = |err| Err(AnalyzeError::Output(err));
+    let output = |kind, script, version| {
+        Ok(OutputScript {
+            kind,
+            script,
+            ctx: ScriptContext::with_flags(version, flags),
+        })
+    };
+    let bytes = |b| ScriptElem::Bytes(b);
+    let op = ScriptElem::Op;
+
+    match (version, program.len()) {
+        (0, 20) => {
+            if witness_script.is_some() {
+                return err(ScriptError::SCRIPT_ERR_WITNESS_PROGRAM_MISMATCH);
+            }
+            let script = [
+                op(opcodes::OP_DUP),
+                op(opcodes::OP_HASH160),
+                bytes(program),
+                op(opcodes::OP_EQUALVERIFY),
+                op(opcodes::OP_CHECKSIG),
+            ];
+            output(
+                OutputKind::P2wpkh { wrapped },
+                OwnedScript::from(Script::new(&script)),
+                ScriptVersion::SegwitV0,
+            )
+        }
+        (0, 32) => {
+            let Some(witness_script) = witness_script else {
+                return err(ScriptError::SCRIPT_ERR_WITNESS_PROGRAM_WITNESS_EMPTY);
+            };
+            // assuming minimal pushes
+            if sha256::Hash::hash(&witness_script.serialize(PushEncoding::Original)).to_byte_array()
+                [..]
+                != *program
+            {
+                return err(ScriptError::SCRIPT_ERR_WITNESS_PROGRAM_MISMATCH);
+            }
+            output(
+                OutputKind::P2wsh { wrapped },
+                OwnedScript::from(witness_script),
+                ScriptVersion::SegwitV0,
+            )
+        }
+        (0, _) => err(ScriptError::SCRIPT_ERR_WITNESS_PROGRAM_WRONG_LENGTH),
+        (1, 32) if !wrapped && flags.contains(VerifyFlags::TAPROOT) => match witness_script {
+            Some(tapscript) => output(
+                OutputKind::P2trScriptPath,
+                OwnedScript::from(tapscript),
+                ScriptVersion::SegwitV1,
+            ),
+            None => {
+                let script = [bytes(program), op(opcodes::OP_CHECKSIG)];
+                output(
+                    OutputKind::P2trKeyPath,
+                    OwnedScript::from(Script::new(&script)),
+                    ScriptVersion::SegwitV1,
+                )
+            }
+        },
+        _ => {
+            if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM) {
+                return err(ScriptError::SCRIPT_ERR_DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM);
+            }
+            output(
+                OutputKind::UnknownWitness { version, wrapped },
+                OwnedScript::from(Script::new(&[op(opcodes::OP_1)])),
+                ScriptVersion::SegwitV0,
+            )
+        }
+    }
+}
+
+/// The script an input reveals when spending an output, found by [`revealed_script`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RevealedScript<'a> {
+    pub kind: OutputKind,
+    /// The scriptPubKey of a bare output, otherwise the redeem script, witness script or
+    /// tapscript. It is not parsed and its hash is not checked, see [`output_script`] for that.
+    pub script: &'a [u8],
+    pub version: ScriptVersion,
+    /// Amount of witness items, or script sig pushes of a P2SH input, before the script. These
+    /// are the initial stack. For bare outputs this is 0, the script sig is a script that creates
+    /// the initial stack.
+    pub stack_items: usize,
+}
+
+/// Error returned by [`revealed_script`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RevealedScriptError {
+    /// The output is spent without a script: P2WPKH outputs, P2TR key path spends, script path
+    /// spends of leaf versions other than tapscript and witness versions without consensus
+    /// rules.
+    NoScript(OutputKind),
+    /// The input does not contain the script of a P2SH, P2WSH or P2TR output, for example
+    /// because the output is not spent yet.
+    MissingScript,
+    /// The script sig of a P2SH input is not a valid script of pushes.
+    InvalidScriptSig,
+}
+
+impl fmt::Display for RevealedScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoScript(kind) => write!(f, "{kind} spends do not reveal a script"),
+            Self::MissingScript => write!(f, "the input does not reveal the script of the output"),
+            Self::InvalidScriptSig => write!(f, "invalid script sig"),
+        }
+    }
+}
+
+impl std::error::Error for RevealedScriptError {}
+
+/// Finds the script that is executed when an input with `script_sig` and `witness` spends an
+/// output with `script_pubkey`. The script type follows from `script_pubkey`, like in Bitcoin
+/// Core, so a P2PKH input is not mistaken for a P2SH one.
+pub fn revealed_script<'a>(
+    script_pubkey: &'a [u8],
+    script_sig: &'a [u8],
+    witness: &[&'a [u8]],
+) -> Result<RevealedScript<'a>, RevealedScriptError> {
+    let is_p2sh = matches!(
+        *script_pubkey,
+        [0xa9, 0x14, .., 0x87] if script_pubkey.len() == 23
+    );
+    if !is_p2sh {
+        return match witness_program(script_pubkey) {
+            Some((version, program)) => witness_script(version, program, witness, false),
+            None => Ok(RevealedScript {
+                kind: OutputKind::Bare,
+                script: script_pubkey,
+                version: ScriptVersion::Legacy,
+                stack_items: 0,
+            }),
+        };
+    }
+
+    let script_sig = OwnedScript::parse_from_bytes(script_sig)
+        .map_err(|_| RevealedScriptError::InvalidScriptSig)?;
+    if !script_sig.is_push_only() {
+        return Err(RevealedScriptError::InvalidScriptSig);
+    }
+    let Some(&ScriptElem::Bytes(redeem_script)) = script_sig.last() else {
+        return Err(RevealedScriptError::MissingScript);
+    };
+    match witness_program(redeem_script) {
+        Some((version, program)) => witness_script(version, program, witness, true),
+        None => Ok(RevealedScript {
+            kind: OutputKind::P2sh,
+            script: redeem_script,
+            version: ScriptVersion::Legacy,
+            stack_items: script_sig.len() - 1,
+        }),
+    }
+}
+
+/// The version and program of a witness program, like `CScript::IsWitnessProgram` in Bitcoin
+/// Core: the program must be a direct push.
+fn witness_program(script: &[u8]) -> Option<(u8, &[u8])> {
+    let [version, len, ref program @ ..] = *script else {
+        return None;
+    };
+    let version = match version {
+        0 => 0,
+        0x51..=0x60 => version - 0x50,
+        _ => return None,
+    };
+    ((2..=40).contains(&len) && program.len() == len as usize).then_some((version, program))
+}
+
+/// The script in the `witness` of an input spending a witness program.
+fn witness_script<'a>(
+    version: u8,
+    program: &[u8],
+    witness: &[&'a [u8]],
+    wrapped: bool,
+) -> Result<RevealedScript<'a>, RevealedScriptError> {
+    let revealed = |kind, script, version, stack_items| {
+        Ok(RevealedScript {
+            kind,
+            script,
+            version,
+            stack_items,
+        })
+    };
+
+    match (version, program.len()) {
+        (0, 20) => Err(RevealedScriptError::NoScript(OutputKind::P2wpkh {
+            wrapped,
+        })),
+        (0, 32) => match *witness {
+            [ref stack @ .., script] => revealed(
+                OutputKind::P2wsh { wrapped },
+                script,
+                ScriptVersion::SegwitV0,
+                stack.len(),
+            ),
+            [] => Err(RevealedScriptError::MissingScript),
+        },
+        (1, 32) if !wrapped => {
+            let mut witness = witness;
+            if let [rest @ .., annex] = witness {
+                // BIP 341: the last item is an annex if there is at least one other item
+                if !rest.is_empty() && annex.first() == Some(&0x50) {
+                    witness = rest;
+                }
+            }
+            match *witness {
+                [ref stack @ .., script, control_block] => {
+                    if !is_tapscript_control_block(control_block) {
+                        return Err(RevealedScriptError::NoScript(OutputKind::P2trScriptPath));
+                    }
+                    revealed(
+                        OutputKind::P2trScriptPath,
+                        script,
+                        ScriptVersion::SegwitV1,
+                        stack.len(),
+                    )
+                }
+                // a single item is a signature of a key path spend
+                [_] => Err(RevealedScriptError::NoScript(OutputKind::P2trKeyPath)),
+                [] => Err(RevealedScriptError::MissingScript),
+            }
+        }
+        _ => Err(RevealedScriptError::NoScript(OutputKind::UnknownWitness {
+            version,
+            wrapped,
+        })),
+    }
+}
+
+/// Checks the size of a control block (BIP 341) and if its leaf version is tapscript (BIP 342).
+fn is_tapscript_control_block(control_block: &[u8]) -> bool {
+    control_block.len() >= 33
+        && (control_block.len() - 33).is_multiple_of(32)
+        && (control_block.len() - 33) / 32 <= 128
+        && control_block[0] & 0xfe == 0xc0
+}
+
+/// Analyzes the script that is executed when spending `script_pubkey`, see [`output_script`].
+pub fn analyze_output<'a>(
+    script_pubkey: &Script<'a>,
+    scripts: OutputScripts<'_, 'a>,
+    flags: VerifyFlags,
+    opts: AnalysisOptions,
+) -> Result<Analysis, AnalyzeError> {
+    output_script(script_pubkey, scripts, flags)?.analyze(opts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        output_script, revealed_script, OutputKind, OutputScripts, RevealedScriptError,
+        WitnessProgram,
+    };
+    use crate::{script_error::ScriptError, AnalyzeError, OwnedScript, ScriptVersion, VerifyFlags};
+    use bitcoin_hashes::{sha256, Hash};
+
+    fn parse(asm: &str) -> OwnedScript<'static> {
+        let buf = asm.as_bytes().to_vec().leak();
+        OwnedScript::parse_from_asm_in_place(buf).unwrap().1
+    }
+
+    #[test]
+    fn test_witness_programs() {
+        let witness_script = parse("OP_SIZE 32 OP_EQUALVERIFY");
+        // OP_SIZE <20> OP_EQUALVERIFY
+        let hash = sha256::Hash::hash(&[0x82, 0x01, 0x20, 0x88]);
+        let p2wsh = parse(&format!("0 <{hash:x}>"));
+        assert_eq!(
+            WitnessProgram::from_script(&p2wsh).unwrap().program,
+            hash.as_byte_array()
+        );
+
+        let scripts = OutputScripts {
+            witness_script: Some(&witness_script),
+            ..Default::default()
+        };
+        let output = output_script(&p2wsh, scripts, VerifyFlags::STANDARD).unwrap();
+        assert_eq!(output.kind, OutputKind::P2wsh { wrapped: false });
+        assert!(output.analyze(Default::default()).is_ok());
+
+        let output_err = |script_pubkey: &str, scripts, flags| {
+            output_script(&parse(script_pubkey), scripts, flags).unwrap_err()
+        };
+        assert_eq!(
+            output_err(
+                &format!("0 <{}>", "00".repeat(32)),
+                scripts,
+                VerifyFlags::STANDARD
+            ),
+            AnalyzeError::Output(ScriptError::SCRIPT_ERR_WITNESS_PROGRAM_MISMATCH)
+        );
+        assert_eq!(
+            output_err(
+                &p2wsh.to_string(),
+                Default::default(),
+                VerifyFlags::STANDARD
+            ),
+            AnalyzeError::Output(ScriptError::SCRIPT_ERR_WITNESS_PROGRAM_WITNESS_EMPTY)
+        );
+        assert_eq!(
+            output_err("0 <0000>", Default::default(), VerifyFlags::STANDARD),
+            AnalyzeError::Output(ScriptError::SCRIPT_ERR_WITNESS_PROGRAM_WRONG_LENGTH)
+        );
+
+        // future witness versions are only discouraged
+        let v2 = parse("2 <0000>");
+        assert_eq!(
+            output_err("2 <0000>", Default::default(), VerifyFlags::STANDARD),
+            AnalyzeError::Output(ScriptError::SCRIPT_ERR_DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM)
+        );
+        let output = output_script(&v2, Default::default(), VerifyFlags::CONSENSUS).unwrap();
+        assert_eq!(
+            output.kind,
+            OutputKind::UnknownWitness {
+                version: 2,
+                wrapped: false
+            }
+        );
+        assert!(output.analyze(Default::default()).unwrap().paths[0].is_anyone_can_spend());
+
+        // taproot can not be wrapped in P2SH
+        let redeem_script = parse(&format!("1 <{}>", "11".repeat(32)));
+        let p2sh = parse("OP_HASH160 <65ba7e5b577d449306a8450ce7c24202c0efcb7c> OP_EQUAL");
+        let scripts = OutputScripts {
+            redeem_script: Some(&redeem_script),
+            ..Default::default()
+        };
+        let output = output_script(&p2sh, scripts, VerifyFlags::CONSENSUS).unwrap();
+        assert_eq!(
+            output.kind,
+            OutputKind::UnknownWitness {
+                version: 1,
+                wrapped: true
+            }
+        );
+        let output = output_script(&redeem_script, Default::default(), VerifyFlags::CONSENSUS);
+        assert_eq!(output.unwrap().kind, OutputKind::P2trKeyPath);
+    }
+
+    #[test]
+    fn test_p2sh() {
+        let key = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        let redeem_script = parse(&format!("1 <{key}> 1 OP_CHECKMULTISIG"));
+        let p2sh = parse("OP_HASH160 <83eebb7d79aa1d388e3b0ac65b98ac580c4da01a> OP_EQUAL");
+        let scripts = OutputScripts {
+            redeem_script: Some(&redeem_script),
+            ..Default::default()
+        };
+        let output = output_script(&p2sh, scripts, VerifyFlags::STANDARD).unwrap();
+        assert_eq!(output.kind, OutputKind::P2sh);
+        assert_eq!(output.ctx.version, ScriptVersion::Legacy);
+        assert!(output.analyze(Default::default()).is_ok());
+
+        // the redeem script is the last push of the script sig
+        let script_sig = parse(&format!("0 <30> <5121{key}51ae>"));
+        let scripts = OutputScripts {
+            script_sig: Some(&script_sig),
+            ..Default::default()
+        };
+        let output = output_script(&p2sh, scripts, VerifyFlags::STANDARD).unwrap();
+        assert_eq!(output.script.to_string(), redeem_script.to_string());
+
+        let other = parse("OP_HASH160 <0000000000000000000000000000000000000000> OP_EQUAL");
+        assert_eq!(
+            output_script(&other, scripts, VerifyFlags::STANDARD).unwrap_err(),
+            AnalyzeError::Output(ScriptError::SCRIPT_ERR_EVAL_FALSE)
+        );
+        let script_sig = parse("0 OP_DUP <51>");
+        let scripts = OutputScripts {
+            script_sig: Some(&script_sig),
+            ..Default::default()
+        };
+        assert_eq!(
+            output_script(&p2sh, scripts, VerifyFlags::CONSENSUS).unwrap_err(),
+            AnalyzeError::Output(ScriptError::SCRIPT_ERR_SIG_PUSHONLY)
+        );
+    }
+
+    #[test]
+    fn test_revealed_script() {
+        let p2tr = [&[0x51, 0x20][..], &[0x11; 32]].concat();
+        let control_block = [0xc0; 33];
+        let tapscript = [0x51];
+        let sig = [0x01; 64];
+
+        let revealed = revealed_script(&p2tr, &[], &[&sig, &tapscript, &control_block]).unwrap();
+        assert_eq!(revealed.kind, OutputKind::P2trScriptPath);
+        assert_eq!(revealed.script, tapscript);
+        assert_eq!(revealed.version, ScriptVersion::SegwitV1);
+        assert_eq!(revealed.stack_items, 1);
+
+        // the annex is removed when at least one other item comes before it
+        let with_annex = revealed_script(&p2tr, &[], &[&tapscript, &control_block, &[0x50]]);
+        assert_eq!(with_annex.unwrap().script, tapscript);
+        assert_eq!(
+            revealed_script(&p2tr, &[], &[&sig, &[0x50]]),
+            Err(RevealedScriptError::NoScript(OutputKind::P2trKeyPath))
+        );
+        assert_eq!(
+            revealed_script(&p2tr, &[], &[]),
+            Err(RevealedScriptError::MissingScript)
+        );
+
+        // nested P2WSH, the script sig pushes the witness program
+        let witness_script = [0x51];
+        let program = [&[0x00, 0x20][..], &[0x22; 32]].concat();
+        let p2sh = [&[0xa9, 0x14][..], &[0x33; 20], &[0x87]].concat();
+        let script_sig = [&[0x22][..], &program].concat();
+        let revealed = revealed_script(&p2sh, &script_sig, &[&[], &witness_script]).unwrap();
+        assert_eq!(revealed.kind, OutputKind::P2wsh { wrapped: true });
+        assert_eq!(revealed.script, witness_script);
+        assert_eq!(revealed.stack_items, 1);
+
+        // the output script of a bare output is executed
+        let bare = [0x51];
+        let revealed = revealed_script(&bare, &[], &[]).unwrap();
+        assert_eq!(revealed.kind, OutputKind::Bare);
+        assert_eq!(revealed.script, bare);
+    }
+}
@@ -0,0 +1,492 @@
+use crate::{
+    expr::{Expr, OpExprArgs, Opcode1, Opcode2, Opcode3},
+    script::{convert::decode_int, MAX_SCRIPT_ELEMENT_SIZE},
+};
+use core::fmt;
+use std::collections::BTreeMap;
+
+/// Largest number that can be used as input for numeric opcodes.
+const INT_MAX: i64 = 0x7fffffff;
+
+/// Inclusive range of script numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumRange {
+    pub min: i64,
+    pub max: i64,
+}
+
+impl NumRange {
+    /// All numbers that fit in 4 bytes.
+    pub const FULL: Self = Self {
+        min: -INT_MAX,
+        max: INT_MAX,
+    };
+
+    pub fn is_empty(&self) -> bool {
+        self.min > self.max
+    }
+
+    pub fn intersect(self, other: Self) -> Self {
+        Self {
+            min: self.min.max(other.min),
+            max: self.max.min(other.max),
+        }
+    }
+
+    fn negate(self) -> Self {
+        Self {
+            min: -self.max,
+            max: -self.min,
+        }
+    }
+
+    fn shift(self, n: i64) -> Self {
+        Self {
+            min: self.min + n,
+            max: self.max + n,
+        }
+    }
+}
+
+impl fmt::Display for NumRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}, {}]", self.min, self.max)
+    }
+}
+
+/// Range of numbers a stack item must be in, see [`item_ranges`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItemRange {
+    pub stack_item: u32,
+    pub range: NumRange,
+}
+
+impl ItemRange {
+    /// The range implied by `condition`, if it is a comparison of a (shifted) stack item and
+    /// constants. These conditions are summarized in
+    /// [`SpendingPath::ranges`](crate::SpendingPath::ranges).
+    pub fn from_condition(condition: &Expr) -> Option<Self> {
+        constraint(condition)
+    }
+}
+
+impl fmt::Display for ItemRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.range.min == self.range.max {
+            write!(
+                f,
+                "stack item #{} must be the number {}",
+                self.stack_item, self.range.min
+            )
+        } else {
+            write!(
+                f,
+                "stack item #{} must be a number in {}",
+                self.stack_item, self.range
+            )
+        }
+    }
+}
+
+/// `<stack item #item> + offset`, or `offset - <stack item #item>` if negative. With `size`,
+/// `OP_SIZE(<stack item #item>)` takes the place of the stack item.
+struct Linear {
+    item: u32,
+    size: bool,
+    negative: bool,
+    offset: i64,
+}
+
+impl Linear {
+    fn from_expr(expr: &Expr) -> Option<Self> {
+        let (op, args) = match expr {
+            Expr::Stack(s) => {
+                return Some(Self {
+                    item: s.pos(),
+                    size: false,
+                    negative: false,
+                    offset: 0,
+                })
+            }
+            Expr::Op(op) => match &op.args {
+                OpExprArgs::Args2(op @ (Opcode2::OP_ADD | Opcode2::OP_SUB), args) => (*op, args),
+                OpExprArgs::Args1(Opcode1::OP_SIZE, arg) => {
+                    let Expr::Stack(s) = &arg[0] else {
+                        return None;
+                    };
+                    return Some(Self {
+                        item: s.pos(),
+                        size: true,
+                        negative: false,
+                        offset: 0,
+                    });
+                }
+                _ => return None,
+            },
+            Expr::Bytes(_) => return None,
+        };
+
+        match (constant(&args[0]), constant(&args[1])) {
+            (None, Some(c)) => {
+                let mut l = Self::from_expr(&args[0])?;
+                l.offset += if op == Opcode2::OP_ADD { c } else { -c };
+                Some(l)
+            }
+            (Some(c), None) => {
+                let mut l = Self::from_expr(&args[1])?;
+                if op == Opcode2::OP_SUB {
+                    l.negative = !l.negative;
+                    l.offset = -l.offset;
+                }
+                l.offset += c;
+                Some(l)
+            }
+            _ => None,
+        }
+    }
+
+    /// Translates a range of the stack item to a range of the value of this expression, the
+    /// inverse of [`item_range`](Self::item_range).
+    fn value_range(&self, item: NumRange) -> NumRange {
+        if self.negative {
+            NumRange {
+                min: self.offset - item.max,
+                max: self.offset - item.min,
+            }
+        } else {
+            item.shift(self.offset)
+        }
+    }
+
+    /// Translates a range of the value of this expression to a range of the stack item.
+    fn item_range(&self, value: NumRange) -> NumRange {
+        // the inputs of numeric opcodes can not be larger than 4 bytes
+        let value = value.intersect(NumRange::FULL);
+        let range = if self.negative {
+            NumRange {
+                min: self.offset - value.max,
+                max: self.offset - value.min,
+            }
+        } else {
+            NumRange {
+                min: value.min - self.offset,
+                max: value.max - self.offset,
+            }
+        };
+        if self.size {
+            range.intersect(NumRange {
+                min: 0,
+                max: MAX_SCRIPT_ELEMENT_SIZE as i64,
+            })
+        } else {
+            range.intersect(NumRange::FULL)
+        }
+    }
+}
+
+fn constant(expr: &Expr) -> Option<i64> {
+    match expr {
+        Expr::Bytes(b) => decode_int(b, 4).ok(),
+        _ => None,
+    }
+}
+
+/// Range constraint on a stack item that is implied by a spending condition, if the condition
+/// is a comparison of a (shifted) stack item and constants.
+pub(crate) fn constraint(condition: &Expr) -> Option<ItemRange> {
+    let (linear, value) = linear_constraint(condition)?;
+    (!linear.size).then(|| ItemRange {
+        stack_item: linear.item,
+        range: linear.item_range(value),
+    })
+}
+
+/// Like [`constraint`], but for the size in bytes of a stack item, implied by a comparison of
+/// `OP_SIZE` of the item and constants.
+fn size_constraint(condition: &Expr) -> Option<ItemRange> {
+    let (linear, value) = linear_constraint(condition)?;
+    linear.size.then(|| ItemRange {
+        stack_item: linear.item,
+        range: linear.item_range(value),
+    })
+}
+
+fn linear_constraint(condition: &Expr) -> Option<(Linear, NumRange)> {
+    let Expr::Op(op) = condition else {
+        return None;
+    };
+
+    let (linear, value) = match &op.args {
+        OpExprArgs::Args2(op, args) => compare(*op, &args[0], &args[1], false)?,
+        OpExprArgs::Args1(Opcode1::OP_NOT | Opcode1::OP_INTERNAL_NOT, arg) => match &arg[0] {
+            Expr::Op(op) => match &op.args {
+                OpExprArgs::Args2(op, args) => compare(*op, &args[0], &args[1], true)?,
+                _ => return None,
+            },
+            _ => return None,
+        },
+        OpExprArgs::Args3(Opcode3::OP_WITHIN, args) => {
+            let linear = Linear::from_expr(&args[0])?;
+            let min = constant(&args[1])?;
+            let max = constant(&args[2])? - 1;
+            (linear, NumRange { min, max })
+        }
+        _ => return None,
+    };
+
+    Some((linear, value))
+}
+
+/// `a op b`, or `!(a op b)` if negated
+fn compare(op: Opcode2, a: &Expr, b: &Expr, negated: bool) -> Option<(Linear, NumRange)> {
+    let (linear, c, linear_first) = match (Linear::from_expr(a), constant(b)) {
+        (Some(l), Some(c)) => (l, c, true),
+        _ => (Linear::from_expr(b)?, constant(a)?, false),
+    };
+
+    let range = |min, max| NumRange { min, max };
+    let value = match (op, linear_first, negated) {
+        (Opcode2::OP_NUMEQUAL, _, false) => range(c, c),
+        // OP_SIZE and arithmetic push minimally encoded numbers
+        (Opcode2::OP_EQUAL, _, false) if linear.size => range(c, c),
+        // x < c
+        (Opcode2::OP_LESSTHAN, true, false) => range(-INT_MAX, c - 1),
+        // !(x < c)
+        (Opcode2::OP_LESSTHAN, true, true) => range(c, INT_MAX),
+        // c < x
+        (Opcode2::OP_LESSTHAN, false, false) => range(c + 1, INT_MAX),
+        // !(c < x)
+        (Opcode2::OP_LESSTHAN, false, true) => range(-INT_MAX, c),
+        // x <= c
+        (Opcode2::OP_LESSTHANOREQUAL, true, false) => range(-INT_MAX, c),
+        // !(x <= c)
+        (Opcode2::OP_LESSTHANOREQUAL, true, true) => range(c + 1, INT_MAX),
+        // c <= x
+        (Opcode2::OP_LESSTHANOREQUAL, false, false) => range(c, INT_MAX),
+        // !(c <= x)
+        (Opcode2::OP_LESSTHANOREQUAL, false, true) => range(-INT_MAX, c - 1),
+        _ => return None,
+    };
+
+    Some((linear, value))
+}
+
+/// Intersects all range constraints on the same stack item. Items are sorted by position, a
+/// contradiction results in an empty range.
+pub fn item_ranges(conditions: &[Expr]) -> Vec<ItemRange> {
+    intersect_all(conditions.iter().filter_map(constraint))
+}
+
+/// Like [`item_ranges`], but for the sizes of stack items, see [`size_constraint`].
+pub(crate) fn item_sizes(conditions: &[Expr]) -> Vec<ItemRange> {
+    intersect_all(conditions.iter().filter_map(size_constraint))
+}
+
+/// The range of the value of `expr` implied by the range constraints among `conditions`, if
+/// `expr` is a (shifted) stack item or its size. `None` if the value is not constrained.
+pub(crate) fn expr_range(expr: &Expr, conditions: &[Expr]) -> Option<NumRange> {
+    let linear = Linear::from_expr(expr)?;
+    let ranges = if linear.size {
+        item_sizes(conditions)
+    } else {
+        item_ranges(conditions)
+    };
+    let item = match ranges.iter().find(|r| r.stack_item == linear.item) {
+        Some(r) => r.range,
+        None if linear.size => NumRange {
+            min: 0,
+            max: MAX_SCRIPT_ELEMENT_SIZE as i64,
+        },
+        None => return None,
+    };
+    Some(linear.value_range(item))
+}
+
+/// `a - b` must be in `range` and not equal to `excluded`, implied by a comparison of
+/// two numbers. `b` is `None` if `a` is compared with a constant.
+struct Difference<'e> {
+    a: &'e Expr,
+    b: Option<&'e Expr>,
+    range: NumRange,
+    excluded: Option<i64>,
+}
+
+impl<'e> Difference<'e> {
+    fn from_condition(condition: &'e Expr) -> Option<Self> {
+        let Expr::Op(op) = condition else {
+            return None;
+        };
+        let (op, args, negated) = match &op.args {
+            OpExprArgs::Args2(op, args) => (*op, args, false),
+            OpExprArgs::Args3(Opcode3::OP_WITHIN, args) => {
+                let a = &args[0];
+                if constant(a).is_some() {
+                    return None;
+                }
+                let min = constant(&args[1])?;
+                let max = constant(&args[2])? - 1;
+                return Some(Self {
+                    a,
+                    b: None,
+                    range: NumRange { min, max },
+                    excluded: None,
+                });
+            }
+            OpExprArgs::Args1(Opcode1::OP_NOT | Opcode1::OP_INTERNAL_NOT, arg) => match &arg[0] {
+                Expr::Op(op) => match &op.args {
+                    OpExprArgs::Args2(op, args) => (*op, args, true),
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        // the difference of two 4 byte numbers
+        let max = 2 * INT_MAX;
+        let range = |min, max| NumRange { min, max };
+        let (range, excluded) = match (op, negated) {
+            (Opcode2::OP_NUMEQUAL, false) | (Opcode2::OP_NUMNOTEQUAL, true) => (range(0, 0), None),
+            (Opcode2::OP_NUMEQUAL, true) | (Opcode2::OP_NUMNOTEQUAL, false) => {
+                (range(-max, max), Some(0))
+            }
+            (Opcode2::OP_LESSTHAN, false) => (range(-max, -1), None),
+            (Opcode2::OP_LESSTHAN, true) => (range(0, max), None),
+            (Opcode2::OP_LESSTHANOREQUAL, false) => (range(-max, 0), None),
+            (Opcode2::OP_LESSTHANOREQUAL, true) => (range(1, max), None),
+            _ => return None,
+        };
+
+        let [a, b] = &**args;
+        let res = match (constant(a), constant(b)) {
+            (None, None) if a < b => Self {
+                a,
+                b: Some(b),
+                range,
+                excluded,
+            },
+            // b - a
+            (None, None) => Self {
+                a: b,
+                b: Some(a),
+                range: range.negate(),
+                excluded,
+            },
+            // a - c
+            (None, Some(c)) => Self {
+                a,
+                b: None,
+                range: range.shift(c).intersect(NumRange::FULL),
+                excluded: excluded.map(|e| e + c),
+            },
+            // c - b
+            (Some(c), None) => Self {
+                a: b,
+                b: None,
+                range: range.negate().shift(c).intersect(NumRange::FULL),
+                excluded: excluded.map(|e| c - e),
+            },
+            (Some(_), Some(_)) => return None,
+        };
+        Some(res)
+    }
+}
+
+/// Returns true if the numeric comparisons among `conditions` can not all be true, like
+/// `a < b` and `b < a`, or `a + b == 3` and `a + b == 4`.
+pub(crate) fn numeric_contradiction(conditions: &[Expr]) -> bool {
+    let mut differences: BTreeMap<_, (NumRange, Vec<i64>)> = BTreeMap::new();
+    for d in conditions.iter().filter_map(Difference::from_condition) {
+        let (range, excluded) = differences
+            .entry((d.a, d.b))
+            .or_insert((d.range, Vec::new()));
+        *range = range.intersect(d.range);
+        excluded.extend(d.excluded);
+    }
+    differences.into_values().any(|(mut range, excluded)| {
+        while excluded.contains(&range.min) && !range.is_empty() {
+            range.min += 1;
+        }
+        while excluded.contains(&range.max) && !range.is_empty() {
+            range.max -= 1;
+        }
+        range.is_empty()
+    })
+}
+
+fn intersect_all(constraints: impl Iterator<Item = ItemRange>) -> Vec<ItemRange> {
+    let mut ranges: Vec<ItemRange> = Vec::new();
+    for c in constraints {
+        match ranges.binary_search_by_key(&c.stack_item, |r| r.stack_item) {
+            Ok(i) => ranges[i].range = ranges[i].range.intersect(c.range),
+            Err(i) => ranges.insert(i, c),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{item_ranges, item_sizes, NumRange};
+    use crate::{analyze_script, OwnedScript, ScriptContext, ScriptRules, ScriptVersion};
+
+    fn analyze(asm: &str) -> Result<crate::Analysis, crate::AnalyzeError> {
+        let ctx = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All);
+        let mut buf = asm.as_bytes().to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        analyze_script(&script, ctx, Default::default())
+    }
+
+    #[test]
+    fn test_item_ranges() {
+        let analysis =
+            analyze("OP_DUP 0 OP_GREATERTHAN OP_VERIFY 1 OP_ADD 102 OP_LESSTHAN").unwrap();
+        let ranges = item_ranges(&analysis.paths[0].spending_conditions);
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].range, NumRange { min: 1, max: 100 });
+        assert_eq!(
+            ranges[0].to_string(),
+            "stack item #0 must be a number in [1, 100]"
+        );
+
+        let analysis = analyze("OP_DUP 1 11 OP_WITHIN OP_VERIFY OP_NEGATE -5 OP_NUMEQUAL").unwrap();
+        let ranges = item_ranges(&analysis.paths[0].spending_conditions);
+        assert_eq!(ranges[0].range, NumRange { min: 5, max: 5 });
+
+        assert!(analyze("OP_DUP 10 OP_LESSTHAN OP_VERIFY 20 OP_GREATERTHAN").is_err());
+    }
+
+    #[test]
+    fn test_numeric_contradiction() {
+        assert!(analyze("OP_2DUP OP_LESSTHAN OP_VERIFY OP_GREATERTHAN").is_err());
+        assert!(analyze("OP_2DUP OP_LESSTHAN OP_VERIFY OP_LESSTHANOREQUAL").is_ok());
+        assert!(analyze("OP_2DUP OP_ADD 3 OP_NUMEQUALVERIFY OP_ADD 4 OP_NUMEQUAL").is_err());
+        assert!(analyze("OP_DUP 3 OP_NUMEQUALVERIFY 3 OP_NUMNOTEQUAL").is_err());
+        assert!(analyze("OP_DUP 3 OP_NUMEQUALVERIFY 4 OP_NUMNOTEQUAL").is_ok());
+        // the only number in the range is excluded
+        assert!(analyze("OP_2DUP OP_MAX 1 2 OP_WITHIN OP_VERIFY OP_MAX 1 OP_NUMNOTEQUAL").is_err());
+    }
+
+    #[test]
+    fn test_item_sizes() {
+        let hash = "ab".repeat(32);
+        let analysis = analyze(&format!(
+            "OP_SIZE 10 21 OP_WITHIN OP_VERIFY OP_SHA256 <{hash}> OP_EQUAL"
+        ))
+        .unwrap();
+        let path = &analysis.paths[0];
+        let sizes = item_sizes(&path.spending_conditions);
+        assert_eq!(sizes[0].range, NumRange { min: 10, max: 20 });
+        // refines the usual preimage size of 32 bytes
+        assert_eq!(path.size.stack_item_sizes, [20]);
+
+        assert!(analyze("OP_SIZE 10 21 OP_WITHIN OP_VERIFY OP_SIZE 30 OP_EQUAL").is_err());
+
+        let pubkey = "02".repeat(33);
+        assert!(analyze(&format!("OP_SIZE 64 OP_EQUALVERIFY <{pubkey}> OP_CHECKSIG")).is_ok());
+        // too large for a DER signature
+        assert!(analyze(&format!("OP_SIZE 80 OP_EQUALVERIFY <{pubkey}> OP_CHECKSIG")).is_err());
+        // neither a compressed nor an uncompressed public key
+        assert!(analyze("OP_SIZE 32 OP_EQUALVERIFY OP_CHECKSIG").is_err());
+    }
+}
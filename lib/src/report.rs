@@ -0,0 +1,323 @@
+use crate::{
+    analyzer::{Analysis, SpendingPath},
+    context::Network,
+    expr::{ExprFormat, Labels},
+    hashlock::PreimageRequirement,
+    known::known_constants,
+    range::constraint,
+    threshold::ThresholdMultisig,
+    timelock::{ChainTip, TxTimelocks},
+    util::locktime::LocktimeFormat,
+};
+use core::fmt::{self, Write};
+
+/// Options for the text report of [`Analysis::report`]. The default is the report of the
+/// [`Display`](fmt::Display) impl of [`Analysis`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReportOptions<'a> {
+    /// Spending conditions like `OP_LESSTHAN(<stack item #0>, <64>)`, otherwise in infix
+    /// notation like `witness[0] < 100`.
+    pub show_raw_exprs: bool,
+    /// The hex encoding of the script. Not part of the report, but respected by frontends that
+    /// show it.
+    pub show_hex: bool,
+    /// Byte strings longer than this are truncated.
+    pub max_bytes_shown: Option<usize>,
+    /// Hashes and public keys are shortened to their first and last 4 bytes.
+    pub abbreviate_hashes: bool,
+    /// Where in the script every spending condition comes from.
+    pub show_origins: bool,
+    /// Estimated input size and signature operations of every path.
+    pub show_sizes: bool,
+    /// Statistics of the analysis itself, like the number of explored paths.
+    pub show_stats: bool,
+    /// Inferred role and size bounds of every stack item.
+    pub show_items: bool,
+    /// Names of public keys, hashes and other byte constants used in spending conditions.
+    pub labels: Option<&'a Labels>,
+    /// Block heights of absolute timelocks get an estimated date on this network.
+    pub network: Option<Network>,
+    /// How the values of timelocks are written.
+    pub locktime_format: LocktimeFormat,
+    /// Whether this transaction satisfies the timelocks of every path, see
+    /// [`SpendingPath::check_timelocks`].
+    pub tx_timelocks: Option<TxTimelocks>,
+    /// When every path can be spent on top of this chain, see [`SpendingPath::spendable_at`].
+    pub chain_tip: Option<ChainTip>,
+}
+
+impl Default for ReportOptions<'_> {
+    fn default() -> Self {
+        Self {
+            show_raw_exprs: true,
+            show_hex: true,
+            max_bytes_shown: None,
+            abbreviate_hashes: false,
+            show_origins: true,
+            show_sizes: true,
+            show_stats: false,
+            show_items: true,
+            labels: None,
+            network: None,
+            locktime_format: LocktimeFormat::default(),
+            tx_timelocks: None,
+            chain_tip: None,
+        }
+    }
+}
+
+impl<'a> ReportOptions<'a> {
+    /// Only the requirements of the spending paths, in infix notation.
+    pub fn quiet() -> Self {
+        Self {
+            show_raw_exprs: false,
+            show_hex: false,
+            max_bytes_shown: Some(32),
+            abbreviate_hashes: true,
+            show_origins: false,
+            show_sizes: false,
+            show_stats: false,
+            show_items: false,
+            labels: None,
+            network: None,
+            locktime_format: LocktimeFormat::default(),
+            tx_timelocks: None,
+            chain_tip: None,
+        }
+    }
+
+    /// Everything, including the statistics of the analysis.
+    pub fn verbose() -> Self {
+        Self {
+            show_stats: true,
+            ..Self::default()
+        }
+    }
+
+    /// How spending conditions are rendered.
+    pub fn expr_format(&self) -> ExprFormat<'a> {
+        let human = !self.show_raw_exprs;
+        ExprFormat {
+            infix: human,
+            witness_labels: human,
+            numbers: human,
+            max_bytes: self.max_bytes_shown,
+            abbreviate_hashes: self.abbreviate_hashes,
+            labels: self.labels,
+        }
+    }
+}
+
+impl SpendingPath {
+    /// The stack item requirements in human readable form, one per line: numeric ranges first,
+    /// then the other spending conditions.
+    pub fn requirements(&self, options: ReportOptions<'_>) -> Vec<String> {
+        let format = options.expr_format();
+        let mut requirements: Vec<String> = self.ranges.iter().map(|r| r.to_string()).collect();
+        for s in &self.spending_conditions {
+            if constraint(s).is_some() {
+                // part of a range
+                continue;
+            }
+            let mut req = if let Some(req) =
+                PreimageRequirement::from_condition(s, &self.spending_conditions)
+            {
+                req.display(format).to_string()
+            } else if let Some(req) = ThresholdMultisig::from_condition(s) {
+                req.display(format).to_string()
+            } else {
+                s.display(format).to_string()
+            };
+            for c in known_constants(s) {
+                write!(req, " (note: {c})").unwrap();
+            }
+            if options.show_origins {
+                if let Some(origin) = s.origin() {
+                    write!(req, " (from {origin})").unwrap();
+                }
+            }
+            requirements.push(req);
+        }
+        requirements
+    }
+
+    pub fn report<'a>(&'a self, options: ReportOptions<'a>) -> PathReport<'a> {
+        PathReport {
+            path: self,
+            options,
+        }
+    }
+}
+
+impl Analysis {
+    pub fn report<'a>(&'a self, options: ReportOptions<'a>) -> AnalysisReport<'a> {
+        AnalysisReport {
+            analysis: self,
+            options,
+        }
+    }
+}
+
+/// Text report of a [`SpendingPath`], returned by [`SpendingPath::report`].
+pub struct PathReport<'a> {
+    path: &'a SpendingPath,
+    options: ReportOptions<'a>,
+}
+
+impl fmt::Display for PathReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self.path;
+
+        write!(f, "Stack size: ")?;
+        if !path.exact_stack_size {
+            write!(f, "at least ")?;
+        }
+        write!(f, "{}", path.stack_size)?;
+        if let Some(limit) = path.stack_size_limit.filter(|&l| path.stack_size > l) {
+            write!(
+                f,
+                " (warning: P2WSH inputs with more than {limit} witness items are not standard)"
+            )?;
+        }
+        write!(f, "\nStack item requirements:")?;
+        let requirements = path.requirements(self.options);
+        if requirements.is_empty() {
+            write!(f, " none")?;
+        }
+        for req in requirements {
+            write!(f, "\n{req}")?;
+        }
+
+        let (network, format) = (self.options.network, self.options.locktime_format);
+        let locktime = path
+            .locktime_req
+            .locktime_requirement_to_string_with_format(false, network, format);
+        let sequence = path
+            .sequence_req
+            .locktime_requirement_to_string_with_format(true, network, format);
+
+        let locktime_str = match &locktime {
+            Some(s) => s,
+            None => "none",
+        };
+        let sequence_str = match (&sequence, &locktime) {
+            (Some(s), _) => s,
+            (None, Some(_)) => "non-final (not 0xffffffff)",
+            (None, None) => "none",
+        };
+        write!(
+            f,
+            "\nLocktime requirement: {locktime_str}\nSequence requirement: {sequence_str}"
+        )?;
+        if path.sequence_req.is_required() {
+            write!(
+                f,
+                " (transaction version {} or higher, disable flag (1 << 31) unset)",
+                path.min_tx_version()
+            )?;
+        }
+        if let Some(tip) = self.options.chain_tip {
+            write!(f, "\nChain tip: {}", path.spendable_at(tip))?;
+        }
+        if let Some(tx) = self.options.tx_timelocks {
+            let mismatches = path.check_timelocks(tx);
+            write!(f, "\nTransaction timelocks: ")?;
+            if mismatches.is_empty() {
+                write!(f, "satisfied")?;
+            } else {
+                write!(f, "not satisfied")?;
+                for mismatch in mismatches {
+                    write!(f, "\n{mismatch}")?;
+                }
+            }
+        }
+
+        if !path.key_sets.is_empty() {
+            write!(f, "\nMultisig key sets:")?;
+            for sets in &path.key_sets {
+                write!(f, "\n{}", sets.display(self.options.expr_format()))?;
+            }
+        }
+
+        if !path.sighash.is_empty() {
+            write!(f, "\nSighash types:")?;
+            for req in &path.sighash {
+                write!(f, "\n{}", req.display(self.options.expr_format()))?;
+            }
+        }
+
+        if path.script_codes.iter().any(|c| c.code_separator.is_some()) {
+            write!(f, "\nScript codes:")?;
+            for script_code in &path.script_codes {
+                write!(f, "\n{script_code}")?;
+            }
+        }
+
+        if self.options.show_items && !path.items.is_empty() {
+            write!(f, "\nStack items:")?;
+            for item in &path.items {
+                write!(f, "\n{item}")?;
+            }
+        }
+
+        if self.options.show_sizes {
+            let (size_label, size) = if path.size.is_script_sig() {
+                ("scriptSig", path.size.script_sig_size)
+            } else {
+                ("witness", path.size.witness_size)
+            };
+            write!(
+                f,
+                "\nEstimated input size: {} vbytes ({size_label}: {} bytes)\n\
+                Signature operations: {}",
+                path.size.vsize, size, path.sigops,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Text report of an [`Analysis`], returned by [`Analysis::report`].
+pub struct AnalysisReport<'a> {
+    analysis: &'a Analysis,
+    options: ReportOptions<'a>,
+}
+
+impl fmt::Display for AnalysisReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let analysis = self.analysis;
+
+        if analysis.paths.iter().any(SpendingPath::is_anyone_can_spend) {
+            write!(
+                f,
+                "WARNING: this script is trivially spendable by anyone, a spending path requires no \
+                signature, hash preimage or timelock\n\n"
+            )?;
+        }
+        if let Some(template) = analysis.template {
+            write!(f, "Template: {template}\n\n")?;
+        }
+        if !analysis.paths.is_empty() {
+            write!(f, "Summary:\n{}\n\n", analysis.summary())?;
+        }
+        write!(f, "Spending paths:")?;
+        if analysis.paths.is_empty() {
+            write!(f, " none")?;
+        }
+        for path in &analysis.paths {
+            write!(f, "\n\n{}", path.report(self.options))?;
+        }
+        if !analysis.failed_paths.is_empty() {
+            write!(f, "\n\nFailed paths:")?;
+            for path in &analysis.failed_paths {
+                write!(f, "\n\n{path}")?;
+            }
+        }
+        if self.options.show_stats {
+            write!(f, "\n\nStatistics:\n{}", analysis.stats)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+use crate::{
+    context::{ScriptContext, ScriptVersion, VerifyFlags},
+    expr::{Expr, OpExprArgs, Opcode1, Opcode2},
+    range::{item_sizes, ItemRange},
+    script::MAX_SCRIPT_ELEMENT_SIZE,
+};
+use core::fmt;
+
+/// What an initial stack item is used for, inferred from how the spending conditions consume it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ItemRole {
+    /// Used as a condition, like the argument of `OP_IF` or `OP_BOOLAND`.
+    Boolean,
+    /// Argument of a numeric opcode.
+    Number,
+    /// Input of a hash function.
+    Preimage,
+    PublicKey,
+    Signature,
+}
+
+impl fmt::Display for ItemRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Boolean => "boolean",
+            Self::Number => "number",
+            Self::Preimage => "preimage",
+            Self::PublicKey => "public key",
+            Self::Signature => "signature",
+        })
+    }
+}
+
+/// Role and size bounds of an initial stack item on a spending path, see
+/// [`SpendingPath::items`](crate::SpendingPath::items).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItemUsage {
+    pub stack_item: u32,
+    /// When an item is used in multiple ways, the most specific role is reported, a public key
+    /// that is also hashed is a [`PublicKey`](ItemRole::PublicKey). `None` if the item is not
+    /// used by any condition.
+    pub role: Option<ItemRole>,
+    /// Inclusive bounds of the size of the item in bytes.
+    pub min_size: usize,
+    pub max_size: usize,
+    /// The item is an ECDSA signature that must have a low S value, with
+    /// [`VerifyFlags::LOW_S`].
+    pub low_s: bool,
+    /// Index and amount of the signatures of the `OP_CHECKMULTISIG` this item is a signature of,
+    /// if there are multiple. The signatures must be in the same order as their public keys.
+    pub multisig: Option<(usize, usize)>,
+}
+
+impl ItemUsage {
+    /// Infers the usage of the first `stack_size` initial stack items. `locktime_exprs` are the
+    /// arguments of locktime checks that are not constant.
+    pub(crate) fn infer<'e>(
+        ctx: ScriptContext,
+        stack_size: u32,
+        conditions: &[Expr],
+        ranges: &[ItemRange],
+        locktime_exprs: impl IntoIterator<Item = &'e Expr>,
+    ) -> Vec<Self> {
+        let mut items: Vec<_> = (0..stack_size)
+            .map(|stack_item| Self {
+                stack_item,
+                role: None,
+                min_size: 0,
+                max_size: MAX_SCRIPT_ELEMENT_SIZE,
+                low_s: false,
+                multisig: None,
+            })
+            .collect();
+
+        for expr in conditions {
+            // a condition that is a stack item itself must be true
+            set_role(expr, ItemRole::Boolean, &mut items);
+            visit(expr, &mut items);
+        }
+        for expr in locktime_exprs {
+            set_role(expr, ItemRole::Number, &mut items);
+            visit(expr, &mut items);
+        }
+
+        for item in &mut items {
+            let (min, max) = match item.role {
+                None | Some(ItemRole::Preimage) => continue,
+                Some(ItemRole::Boolean) => {
+                    let minimal_if = ctx.version == ScriptVersion::SegwitV1
+                        || (ctx.version == ScriptVersion::SegwitV0
+                            && ctx.has(VerifyFlags::MINIMALIF));
+                    if !minimal_if {
+                        continue;
+                    }
+                    (0, 1)
+                }
+                // locktimes can be 5 bytes
+                Some(ItemRole::Number) => (0, 5),
+                Some(ItemRole::PublicKey) if ctx.version == ScriptVersion::SegwitV1 => (32, 32),
+                Some(ItemRole::PublicKey) => (33, 65),
+                Some(ItemRole::Signature) if ctx.version == ScriptVersion::SegwitV1 => (64, 65),
+                // DER encoding and sighash byte
+                Some(ItemRole::Signature) => (9, 73),
+            };
+            item.restrict(min, max);
+        }
+        if ctx.version != ScriptVersion::SegwitV1 && ctx.has(VerifyFlags::LOW_S) {
+            for item in &mut items {
+                item.low_s = item.role == Some(ItemRole::Signature);
+            }
+        }
+        for range in ranges {
+            if let Some(item) = items.get_mut(range.stack_item as usize) {
+                let max = range.range.min.abs().max(range.range.max.abs());
+                item.restrict(0, num_size(max));
+            }
+        }
+        for range in item_sizes(conditions) {
+            if let Some(item) = items.get_mut(range.stack_item as usize) {
+                item.restrict(range.range.min as usize, range.range.max as usize);
+            }
+        }
+        for expr in conditions {
+            exact_size(expr, &mut items);
+        }
+
+        items
+    }
+
+    fn restrict(&mut self, min: usize, max: usize) {
+        self.min_size = self.min_size.max(min);
+        self.max_size = self.max_size.min(max);
+    }
+}
+
+impl fmt::Display for ItemUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "stack item #{}: ", self.stack_item)?;
+        match self.role {
+            Some(role) => write!(f, "{role}")?,
+            None => write!(f, "unused")?,
+        }
+        if self.min_size == 1 && self.max_size == 1 {
+            write!(f, ", 1 byte")?;
+        } else if self.min_size == self.max_size {
+            write!(f, ", {} bytes", self.min_size)?;
+        } else {
+            write!(f, ", {} to {} bytes", self.min_size, self.max_size)?;
+        }
+        if self.low_s {
+            write!(f, ", must be low-S")?;
+        }
+        if let Some((index, count)) = self.multisig {
+            write!(
+                f,
+                ", multisig signature {} of {count}, in the order of the public keys",
+                index + 1
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Size of the minimal encoding of a number with absolute value `n`.
+fn num_size(n: i64) -> usize {
+    let mut size = 0;
+    // the sign bit needs space too
+    let mut n = n << 1;
+    while n != 0 {
+        size += 1;
+        n >>= 8;
+    }
+    size
+}
+
+fn item<'i>(expr: &Expr, items: &'i mut [ItemUsage]) -> Option<&'i mut ItemUsage> {
+    match expr {
+        Expr::Stack(s) => items.get_mut(s.pos() as usize),
+        _ => None,
+    }
+}
+
+fn set_role(expr: &Expr, role: ItemRole, items: &mut [ItemUsage]) {
+    if let Some(item) = item(expr, items) {
+        item.role = item.role.max(Some(role));
+    }
+}
+
+fn visit(expr: &Expr, items: &mut [ItemUsage]) {
+    let Expr::Op(op) = expr else {
+        return;
+    };
+
+    match &op.args {
+        OpExprArgs::Args1(op, args) => match op {
+            Opcode1::OP_RIPEMD160 | Opcode1::OP_SHA1 | Opcode1::OP_SHA256 => {
+                set_role(&args[0], ItemRole::Preimage, items)
+            }
+            Opcode1::OP_NOT | Opcode1::OP_INTERNAL_NOT => {
+                set_role(&args[0], ItemRole::Boolean, items)
+            }
+            Opcode1::OP_SIZE => {}
+            Opcode1::OP_ABS
+            | Opcode1::OP_0NOTEQUAL
+            | Opcode1::OP_CHECKLOCKTIMEVERIFY
+            | Opcode1::OP_CHECKSEQUENCEVERIFY => set_role(&args[0], ItemRole::Number, items),
+        },
+        OpExprArgs::Args2(op, args) => {
+            let roles = match op {
+                Opcode2::OP_CHECKSIG => Some([ItemRole::Signature, ItemRole::PublicKey]),
+                Opcode2::OP_BOOLAND | Opcode2::OP_BOOLOR => Some([ItemRole::Boolean; 2]),
+                Opcode2::OP_EQUAL => {
+                    // with MINIMALIF, `OP_IF` requires its argument to be empty or 1
+                    for [a, b] in [[&args[0], &args[1]], [&args[1], &args[0]]] {
+                        if matches!(b, Expr::Bytes(b) if b.is_false() || b.is_true()) {
+                            set_role(a, ItemRole::Boolean, items);
+                        }
+                    }
+                    None
+                }
+                _ => Some([ItemRole::Number; 2]),
+            };
+            for (arg, role) in args.iter().zip(roles.into_iter().flatten()) {
+                set_role(arg, role, items);
+            }
+        }
+        OpExprArgs::Args3(_, args) => {
+            for arg in args.iter() {
+                set_role(arg, ItemRole::Number, items);
+            }
+        }
+        OpExprArgs::Multisig(m) => {
+            let count = m.sigs().len();
+            for (index, sig) in m.sigs().iter().enumerate() {
+                set_role(sig, ItemRole::Signature, items);
+                if let Some(item) = item(sig, items).filter(|_| count > 1) {
+                    item.multisig = Some((index, count));
+                }
+            }
+            for key in m.keys() {
+                set_role(key, ItemRole::PublicKey, items);
+            }
+        }
+    }
+
+    for arg in op.args() {
+        visit(arg, items);
+    }
+}
+
+/// Sizes that follow from `OP_EQUAL(<stack item>, <bytes>)`.
+fn exact_size(expr: &Expr, items: &mut [ItemUsage]) {
+    let Expr::Op(op) = expr else {
+        return;
+    };
+    let OpExprArgs::Args2(Opcode2::OP_EQUAL, args) = &op.args else {
+        return;
+    };
+    for [a, b] in [[&args[0], &args[1]], [&args[1], &args[0]]] {
+        let Expr::Bytes(b) = b else {
+            continue;
+        };
+        if let Some(item) = item(a, items) {
+            item.min_size = b.len();
+            item.max_size = b.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{num_size, ItemRole};
+    use crate::{
+        analyze_script, AnalysisOptions, OwnedScript, ScriptContext, ScriptRules, ScriptVersion,
+    };
+
+    #[test]
+    fn test_item_roles() {
+        assert_eq!(num_size(0), 0);
+        assert_eq!(num_size(127), 1);
+        assert_eq!(num_size(128), 2);
+
+        let mut buf = format!(
+            "OP_IF OP_SIZE 32 OP_EQUALVERIFY OP_SHA256 <{}> OP_EQUALVERIFY OP_ELSE 10 \
+             OP_LESSTHAN OP_VERIFY OP_ENDIF OP_DUP OP_HASH160 <{}> OP_EQUALVERIFY OP_CHECKSIG",
+            "ab".repeat(32),
+            "cd".repeat(20)
+        )
+        .into_bytes();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let ctx = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All);
+        let analysis = analyze_script(&script, ctx, AnalysisOptions::default()).unwrap();
+
+        let items = &analysis.paths[0].items;
+        let roles: Vec<_> = items.iter().map(|item| item.role).collect();
+        assert_eq!(
+            roles,
+            [
+                Some(ItemRole::Boolean),
+                Some(ItemRole::Preimage),
+                Some(ItemRole::PublicKey),
+                Some(ItemRole::Signature)
+            ]
+        );
+        assert_eq!((items[0].min_size, items[0].max_size), (1, 1));
+        assert_eq!((items[1].min_size, items[1].max_size), (32, 32));
+        assert_eq!((items[3].min_size, items[3].max_size), (9, 73));
+        assert!(items[3].low_s);
+        assert_eq!(
+            items[3].to_string(),
+            "stack item #3: signature, 9 to 73 bytes, must be low-S"
+        );
+
+        let items = &analysis.paths[1].items;
+        assert_eq!(items[1].role, Some(ItemRole::Number));
+        assert_eq!((items[1].min_size, items[1].max_size), (0, 4));
+    }
+}
@@ -0,0 +1,164 @@
+use super::{OwnedScript, ParseAsmScriptError, ParseScriptError, PushEncoding, Script};
+use core::{
+    fmt,
+    hash::{Hash, Hasher},
+    mem,
+};
+
+/// A script that owns its bytes, unlike [`OwnedScript`] that borrows the buffer it is parsed
+/// from. The bytes are always a valid script, the pushes keep the encoding they were parsed
+/// with. The script is parsed once, when it is created.
+pub struct ScriptBuf {
+    /// Parsed from `bytes`. Declared first so it is dropped before `bytes`.
+    script: OwnedScript<'static>,
+    bytes: Vec<u8>,
+}
+
+impl ScriptBuf {
+    /// Checks that `bytes` is a valid script, see [`OwnedScript::parse_from_bytes`].
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, ParseScriptError> {
+        Self::parse(bytes, |bytes| OwnedScript::parse_from_bytes(bytes))
+    }
+
+    /// Parses `bytes` with `parse` and keeps the result next to them.
+    fn parse<E>(
+        bytes: Vec<u8>,
+        parse: impl for<'b> FnOnce(&'b [u8]) -> Result<OwnedScript<'b>, E>,
+    ) -> Result<Self, E> {
+        let script = parse(&bytes)?;
+        // SAFETY: the pushes borrow the heap buffer of `bytes`, which does not move when `bytes`
+        // is moved and is never modified or freed while `script` exists. `as_script` shortens the
+        // lifetime to that of `self`.
+        let script = unsafe { mem::transmute::<OwnedScript<'_>, OwnedScript<'static>>(script) };
+        Ok(Self { script, bytes })
+    }
+
+    /// Parses an asm script, see [`OwnedScript::parse_from_asm`].
+    pub fn from_asm(asm: &str) -> Result<Self, ParseAsmScriptError> {
+        OwnedScript::parse_from_asm(asm).map(|(_, script)| script)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// The elements of the script, borrowing the push data from `self`.
+    pub fn as_script(&self) -> &OwnedScript<'_> {
+        &self.script
+    }
+}
+
+fn parse_lenient(bytes: &[u8]) -> Result<OwnedScript<'_>, ParseScriptError> {
+    OwnedScript::parse_from_bytes_lenient(bytes).map(|(script, _)| script)
+}
+
+impl Clone for ScriptBuf {
+    fn clone(&self) -> Self {
+        Self::parse(self.bytes.clone(), parse_lenient).expect("bytes are parsed")
+    }
+}
+
+impl Default for ScriptBuf {
+    fn default() -> Self {
+        Self {
+            script: OwnedScript {
+                elems: Vec::new(),
+                push_opcodes: Vec::new(),
+            },
+            bytes: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Debug for ScriptBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ScriptBuf").field(&self.bytes).finish()
+    }
+}
+
+impl PartialEq for ScriptBuf {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes == other.bytes
+    }
+}
+
+impl Eq for ScriptBuf {}
+
+impl Hash for ScriptBuf {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bytes.hash(state);
+    }
+}
+
+impl From<&Script<'_>> for ScriptBuf {
+    /// Encodes the pushes of `script` minimally.
+    fn from(script: &Script<'_>) -> Self {
+        // unknown opcodes in `script` are kept
+        Self::parse(script.serialize(PushEncoding::Minimal), parse_lenient)
+            .expect("serialized pushes are complete")
+    }
+}
+
+impl TryFrom<Vec<u8>> for ScriptBuf {
+    type Error = ParseScriptError;
+
+    fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
+        Self::from_bytes(bytes)
+    }
+}
+
+impl AsRef<[u8]> for ScriptBuf {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl fmt::Display for ScriptBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.script.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PushEncoding, ScriptBuf};
+    use crate::{
+        analyze_script, OwnedScript, ScriptContext, ScriptElem, ScriptRules, ScriptVersion,
+    };
+
+    #[test]
+    fn test_script_buf() {
+        let script = ScriptBuf::from_asm("OP_DUP OP_HASH160 <0102> OP_EQUALVERIFY 1000").unwrap();
+        assert_eq!(
+            script.as_bytes(),
+            [0x76, 0xa9, 0x02, 0x01, 0x02, 0x88, 0x02, 0xe8, 0x03]
+        );
+        assert!(matches!(script.as_script()[2], ScriptElem::Bytes([1, 2])));
+
+        // non-minimal pushes are kept
+        let bytes = vec![0x4c, 0x01, 0x07];
+        let script = ScriptBuf::from_bytes(bytes.clone()).unwrap();
+        assert_eq!(script.as_bytes(), bytes);
+        assert_eq!(script.as_script().serialize(PushEncoding::Original), bytes);
+        assert_eq!(ScriptBuf::from(&**script.as_script()).as_bytes(), [0x57]);
+        assert_eq!(script.clone(), script);
+        assert!(ScriptBuf::from_bytes(vec![0x02, 0x01]).is_err());
+
+        // no borrow of a buffer, so it can be moved to another thread
+        let script = ScriptBuf::from_asm("OP_SIZE 32 OP_EQUALVERIFY").unwrap();
+        let analysis = std::thread::spawn(move || {
+            let ctx = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All);
+            analyze_script(&script, ctx, Default::default()).map(|a| a.paths.len())
+        })
+        .join()
+        .unwrap();
+        assert_eq!(analysis.unwrap(), 1);
+
+        let owned: OwnedScript<'_> = OwnedScript::parse_from_bytes(&[0x51]).unwrap();
+        assert_eq!(ScriptBuf::from(&*owned).to_string(), "OP_1");
+    }
+}
@@ -0,0 +1,217 @@
+use super::{convert::decode_int_unchecked, Instructions, OwnedScript, Script, ScriptElem};
+use crate::{
+    opcode::{opcodes, Opcode},
+    util::encode_hex_easy,
+};
+use core::fmt::Write;
+
+/// Flavor of asm script, for parsing and [`Script::to_asm`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AsmDialect {
+    /// Data pushes between `<` and `>`, numbers in decimal, the push opcodes are implied.
+    #[default]
+    Analyzer,
+    /// Like the `asm` of `decodescript` of Bitcoin Core: data pushes in bare hex, pushes of up to
+    /// 4 bytes as decimal numbers. Explicit pushes are written as `OP_PUSHDATA1 <len> <data>`.
+    /// Tokens that are both a number and hex, like `1234`, are numbers.
+    Core,
+    /// Like the [`Display`](core::fmt::Display) of scripts in rust-bitcoin: every data push
+    /// after its opcode, like `OP_PUSHBYTES_2 0102` or `OP_PUSHDATA1 0102`, and `OP_PUSHNUM_1`
+    /// for `OP_1`.
+    RustBitcoin,
+}
+
+impl AsmDialect {
+    /// The push opcode named `name`, if it is written before the data it pushes in this
+    /// dialect.
+    pub(super) fn explicit_push(self, name: &str) -> Option<Opcode> {
+        match self {
+            Self::Analyzer => None,
+            Self::Core | Self::RustBitcoin => match name {
+                "OP_PUSHDATA1" => Some(opcodes::OP_PUSHDATA1),
+                "OP_PUSHDATA2" => Some(opcodes::OP_PUSHDATA2),
+                "OP_PUSHDATA4" => Some(opcodes::OP_PUSHDATA4),
+                _ if self == Self::Core => None,
+                _ => name
+                    .strip_prefix("OP_PUSHBYTES_")?
+                    .parse()
+                    .ok()
+                    .filter(|&n| n <= 75)
+                    .map(|opcode| Opcode { opcode }),
+            },
+        }
+    }
+
+    /// Opcode names only this dialect has.
+    pub(super) fn opcode_from_name(self, name: &str) -> Option<Opcode> {
+        if self != Self::RustBitcoin {
+            return None;
+        }
+        Some(match name {
+            "OP_PUSHNUM_NEG1" => opcodes::OP_1NEGATE,
+            "OP_CLTV" => opcodes::OP_CHECKLOCKTIMEVERIFY,
+            "OP_CSV" => opcodes::OP_CHECKSEQUENCEVERIFY,
+            _ => match name.strip_prefix("OP_PUSHNUM_")?.parse::<u8>().ok()? {
+                n @ 1..=16 => Opcode {
+                    opcode: opcodes::OP_1.opcode + n - 1,
+                },
+                _ => return None,
+            },
+        })
+    }
+
+    /// Whether data pushes can be bare hex, without a push opcode before them.
+    pub(super) fn bare_hex_pushes(self) -> bool {
+        self == Self::Core
+    }
+
+    fn write_opcode(self, s: &mut String, op: Opcode) {
+        match self {
+            Self::Analyzer => write!(s, "{op}"),
+            Self::Core => match op {
+                opcodes::OP_0 => write!(s, "0"),
+                opcodes::OP_1NEGATE => write!(s, "-1"),
+                _ if (opcodes::OP_1..=opcodes::OP_16).contains(&op) => {
+                    write!(s, "{}", op.opcode - opcodes::OP_1.opcode + 1)
+                }
+                _ => write!(s, "{op}"),
+            },
+            Self::RustBitcoin => match op {
+                opcodes::OP_1NEGATE => write!(s, "OP_PUSHNUM_NEG1"),
+                _ if (opcodes::OP_1..=opcodes::OP_16).contains(&op) => {
+                    write!(s, "OP_PUSHNUM_{}", op.opcode - opcodes::OP_1.opcode + 1)
+                }
+                opcodes::OP_CHECKLOCKTIMEVERIFY => write!(s, "OP_CLTV"),
+                opcodes::OP_CHECKSEQUENCEVERIFY => write!(s, "OP_CSV"),
+                _ => write!(s, "{op}"),
+            },
+        }
+        .unwrap();
+    }
+
+    /// Writes a push of `data` that was encoded with `prefix_len` bytes before the data.
+    fn write_push(self, s: &mut String, data: &[u8], prefix_len: usize) {
+        match self {
+            Self::Analyzer => write!(s, "{}", ScriptElem::Bytes(data)),
+            Self::Core if data.len() <= 4 => write!(s, "{}", decode_int_unchecked(data)),
+            Self::Core => write!(s, "{}", encode_hex_easy(data)),
+            Self::RustBitcoin => {
+                match prefix_len {
+                    1 if data.is_empty() => write!(s, "{}", opcodes::OP_0),
+                    1 => write!(s, "OP_PUSHBYTES_{}", data.len()),
+                    2 => write!(s, "{}", opcodes::OP_PUSHDATA1),
+                    3 => write!(s, "{}", opcodes::OP_PUSHDATA2),
+                    _ => write!(s, "{}", opcodes::OP_PUSHDATA4),
+                }
+                .unwrap();
+                if data.is_empty() {
+                    return;
+                }
+                write!(s, " {}", encode_hex_easy(data))
+            }
+        }
+        .unwrap();
+    }
+
+    fn format(self, instructions: Instructions<'_, '_>) -> String {
+        let mut s = String::new();
+        for instruction in instructions {
+            if !s.is_empty() {
+                s.push(' ');
+            }
+            match instruction.elem {
+                ScriptElem::Op(op) => self.write_opcode(&mut s, op),
+                ScriptElem::Bytes(data) => {
+                    self.write_push(&mut s, data, instruction.len - data.len())
+                }
+            }
+        }
+        s
+    }
+}
+
+impl Script<'_> {
+    /// The script as asm in `dialect`, on a single line. The pushes have the shortest length
+    /// prefix, see [`OwnedScript::to_asm`] to keep the original ones.
+    pub fn to_asm(&self, dialect: AsmDialect) -> String {
+        dialect.format(self.instructions())
+    }
+}
+
+impl OwnedScript<'_> {
+    /// Like [`Script::to_asm`], with the length prefixes the pushes were parsed with.
+    pub fn to_asm(&self, dialect: AsmDialect) -> String {
+        dialect.format(self.instructions())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AsmDialect;
+    use crate::{OwnedScript, ParseAsmScriptError};
+
+    fn parse(asm: &str, dialect: AsmDialect) -> Result<Vec<u8>, ParseAsmScriptError> {
+        OwnedScript::parse_from_asm_with_dialect(asm, dialect).map(|(bytes, _)| bytes)
+    }
+
+    #[test]
+    fn test_asm_dialects() {
+        let core = "OP_DUP OP_HASH160 0102030405 OP_EQUALVERIFY 0 -1 16 1000 OP_2DROP OP_2DROP";
+        let bytes = parse(core, AsmDialect::Core).unwrap();
+        assert_eq!(
+            bytes,
+            [
+                0x76, 0xa9, 0x05, 0x01, 0x02, 0x03, 0x04, 0x05, 0x88, 0x00, 0x4f, 0x60, 0x02, 0xe8,
+                0x03, 0x6d, 0x6d
+            ]
+        );
+        let script = OwnedScript::parse_from_bytes(&bytes).unwrap();
+        assert_eq!(script.to_asm(AsmDialect::Core), core);
+        assert_eq!(
+            script.to_asm(AsmDialect::Analyzer),
+            "OP_DUP OP_HASH160 <0102030405> OP_EQUALVERIFY OP_0 OP_1NEGATE OP_16 <e803> \
+            OP_2DROP OP_2DROP"
+        );
+
+        // the length prefix of explicit pushes is kept
+        let bytes = parse("OP_PUSHDATA1 2 0102", AsmDialect::Core).unwrap();
+        assert_eq!(bytes, [0x4c, 0x02, 0x01, 0x02]);
+        assert!(matches!(
+            parse("OP_PUSHDATA1 3 0102", AsmDialect::Core),
+            Err(ParseAsmScriptError::InvalidExplicitPush(_))
+        ));
+
+        let rust_bitcoin = "OP_PUSHNUM_1 OP_PUSHBYTES_2 0102 OP_PUSHDATA1 07 OP_CLTV OP_0 \
+            OP_PUSHNUM_NEG1";
+        let bytes = parse(rust_bitcoin, AsmDialect::RustBitcoin).unwrap();
+        assert_eq!(
+            bytes,
+            [0x51, 0x02, 0x01, 0x02, 0x4c, 0x01, 0x07, 0xb1, 0x00, 0x4f]
+        );
+        let script = OwnedScript::parse_from_bytes(&bytes).unwrap();
+        assert_eq!(script.to_asm(AsmDialect::RustBitcoin), rust_bitcoin);
+        // without the original length prefixes
+        assert_eq!(
+            OwnedScript::from(&*script).to_asm(AsmDialect::RustBitcoin),
+            "OP_PUSHNUM_1 OP_PUSHBYTES_2 0102 OP_PUSHBYTES_1 07 OP_CLTV OP_0 OP_PUSHNUM_NEG1"
+        );
+        assert!(matches!(
+            parse("OP_PUSHBYTES_3 0102", AsmDialect::RustBitcoin),
+            Err(ParseAsmScriptError::InvalidExplicitPush(_))
+        ));
+
+        // bare hex and explicit pushes are only accepted in their dialects
+        assert!(matches!(
+            parse("abcdef", AsmDialect::Analyzer),
+            Err(ParseAsmScriptError::UnknownOpcode(_))
+        ));
+        assert!(matches!(
+            parse("OP_PUSHDATA1 <01>", AsmDialect::Analyzer),
+            Err(ParseAsmScriptError::ExplicitPushdata(_))
+        ));
+        assert!(matches!(
+            parse("OP_PUSHBYTES_1 01", AsmDialect::Core),
+            Err(ParseAsmScriptError::UnknownOpcode(_))
+        ));
+    }
+}
@@ -1,18 +1,59 @@
+mod buf;
 pub mod convert;
+mod dialect;
 pub mod stack;
 
+pub use self::{buf::ScriptBuf, dialect::AsmDialect};
+
 use self::convert::{encode_int, INT_MAX_LEN};
 use crate::{
+    context::VerifyFlags,
     opcode::{opcodes, Opcode},
-    util::{decode_hex_in_place, HexDecodeError},
+    util::{decode_hex_into, HexDecodeError},
 };
 use core::{
     fmt,
     num::IntErrorKind,
-    ops::{Deref, DerefMut},
+    ops::{Deref, DerefMut, Range},
     str,
 };
 
+pub const MAX_SCRIPT_SIZE: usize = 10000;
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+pub const MAX_OPS_PER_SCRIPT: usize = 201;
+pub const MAX_PUBKEYS_PER_MULTISIG: usize = 20;
+/// Limit of the amount of elements on the stack and the altstack together.
+pub const MAX_STACK_SIZE: usize = 1000;
+/// Standardness limit of the amount of witness items of a P2WSH input, not counting the witness
+/// script.
+pub const MAX_STANDARD_P2WSH_STACK_ITEMS: u32 = 100;
+
+/// Size of a minimal push of `len` bytes (not taking OP_1..OP_16 into account).
+pub fn push_len(len: usize) -> usize {
+    len + match len {
+        0..=75 => 1,
+        76..=0xff => 2,
+        0x100..=0xffff => 3,
+        _ => 5,
+    }
+}
+
+/// Checks if `data` is pushed with the smallest possible encoding, like `CheckMinimalPush` in
+/// Bitcoin Core.
+fn is_minimal_push(opcode: Opcode, data: &[u8]) -> bool {
+    match data {
+        [] => opcode == opcodes::OP_0,
+        [1..=16] => false, // should be OP_1..OP_16
+        [0x81] => false,   // should be OP_1NEGATE
+        _ => match data.len() {
+            0..=75 => opcode.opcode as usize == data.len(),
+            76..=0xff => opcode == opcodes::OP_PUSHDATA1,
+            0x100..=0xffff => opcode == opcodes::OP_PUSHDATA2,
+            _ => true,
+        },
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum ScriptElem<'a> {
     Op(Opcode),
@@ -34,15 +75,84 @@ impl<'a> fmt::Display for ScriptElem<'a> {
     }
 }
 
+/// How data pushes are encoded by [`Script::serialize`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PushEncoding {
+    /// Like [`VerifyFlags::MINIMALDATA`] requires: pushes of the empty string, 1 to 16 and -1 as
+    /// `OP_0`, `OP_1`..`OP_16` and `OP_1NEGATE`, other pushes with the shortest length prefix.
+    Minimal,
+    /// Pushes stay pushes with the length prefix they were parsed with, see
+    /// [`OwnedScript::serialize`]. If that is not known, the shortest length prefix is used.
+    #[default]
+    Original,
+}
+
 #[derive(Debug, Clone)]
-pub struct OwnedScript<'a>(Vec<ScriptElem<'a>>);
+pub struct OwnedScript<'a> {
+    elems: Vec<ScriptElem<'a>>,
+    /// The opcode of every data push in `elems` as it was parsed, empty if the script was not
+    /// parsed from bytes.
+    push_opcodes: Vec<Opcode>,
+}
 
 impl<'a> OwnedScript<'a> {
     pub fn parse_from_bytes(bytes: &'a [u8]) -> Result<Self, ParseScriptError> {
+        Self::parse(bytes, |warning| match warning {
+            ParseWarning::UnknownOpcode(opcode, _) => Err(ParseScriptError::Invalid(opcode.opcode)),
+            ParseWarning::NonMinimalPush(_) => Ok(()),
+        })
+    }
+
+    /// Like [`parse_from_bytes`], but bytes that are not an opcode become
+    /// `ScriptElem::Op(UNKNOWN(0x..))` instead of an error, so the script can still be displayed.
+    /// These and non-minimal pushes are returned as warnings. Analyzing a script with unknown
+    /// opcodes fails with [`AnalyzeError::UnknownOpcode`](crate::AnalyzeError::UnknownOpcode).
+    ///
+    /// [`parse_from_bytes`]: Self::parse_from_bytes
+    pub fn parse_from_bytes_lenient(
+        bytes: &'a [u8],
+    ) -> Result<(Self, Vec<ParseWarning>), ParseScriptError> {
+        let mut warnings = Vec::new();
+        let script = Self::parse(bytes, |warning| {
+            warnings.push(warning);
+            Ok(())
+        })?;
+        Ok((script, warnings))
+    }
+
+    /// Like [`parse_from_bytes`], but also checks if all pushes are minimal. Non-minimal pushes
+    /// are an error if `flags` contains [`VerifyFlags::MINIMALDATA`], and are returned as
+    /// warnings otherwise.
+    ///
+    /// [`parse_from_bytes`]: Self::parse_from_bytes
+    pub fn parse_from_bytes_checked(
+        bytes: &'a [u8],
+        flags: VerifyFlags,
+    ) -> Result<(Self, Vec<ParseWarning>), ParseScriptError> {
+        let mut warnings = Vec::new();
+        let script = Self::parse(bytes, |warning| match warning {
+            ParseWarning::UnknownOpcode(opcode, _) => Err(ParseScriptError::Invalid(opcode.opcode)),
+            ParseWarning::NonMinimalPush(offset) if flags.contains(VerifyFlags::MINIMALDATA) => {
+                Err(ParseScriptError::NonMinimalPush(offset))
+            }
+            warning => {
+                warnings.push(warning);
+                Ok(())
+            }
+        })?;
+        Ok((script, warnings))
+    }
+
+    fn parse(
+        bytes: &'a [u8],
+        mut warn: impl FnMut(ParseWarning) -> Result<(), ParseScriptError>,
+    ) -> Result<Self, ParseScriptError> {
         let mut a = Vec::new();
+        let mut push_opcodes = Vec::new();
 
         let mut offset = 0;
         while offset < bytes.len() {
+            let start = offset;
             let b = bytes[offset];
             offset += 1;
             let opcode = Opcode { opcode: b };
@@ -61,7 +171,11 @@ impl<'a> OwnedScript<'a> {
                         return Err(ParseScriptError::UnexpectedEnd(l, bytes.len() - offset));
                     };
                     offset += l;
+                    if !is_minimal_push(opcode, data) {
+                        warn(ParseWarning::NonMinimalPush(start))?;
+                    }
                     a.push(ScriptElem::Bytes(data));
+                    push_opcodes.push(opcode);
                 } else {
                     a.push(ScriptElem::Op(opcode));
                 }
@@ -73,101 +187,214 @@ impl<'a> OwnedScript<'a> {
                     ));
                 };
                 offset += b as usize;
+                if !is_minimal_push(opcode, data) {
+                    warn(ParseWarning::NonMinimalPush(start))?;
+                }
                 a.push(ScriptElem::Bytes(data));
+                push_opcodes.push(opcode);
             } else {
-                return Err(ParseScriptError::Invalid(b));
+                warn(ParseWarning::UnknownOpcode(opcode, start))?;
+                a.push(ScriptElem::Op(opcode));
             }
         }
 
-        Ok(OwnedScript(a))
+        Ok(OwnedScript {
+            elems: a,
+            push_opcodes,
+        })
     }
 
     pub fn parse_from_asm_in_place(
         asm: &'a mut [u8],
+    ) -> Result<(&'a [u8], Self), ParseAsmScriptError> {
+        Self::parse_from_asm_in_place_with_dialect(asm, AsmDialect::Analyzer)
+    }
+
+    /// Like [`parse_from_asm_in_place`](Self::parse_from_asm_in_place), for asm in `dialect`.
+    pub fn parse_from_asm_in_place_with_dialect(
+        asm: &'a mut [u8],
+        dialect: AsmDialect,
     ) -> Result<(&'a [u8], Self), ParseAsmScriptError> {
         // TODO zero alloc
-        let mut ret = Vec::new();
+        let ret = asm_to_bytes(asm, dialect)?;
 
-        let mut i = 0;
-        while i < asm.len() {
-            let mut token_end = i;
-            while token_end < asm.len() {
-                if asm[token_end].is_ascii_whitespace() {
-                    break;
-                }
-                token_end += 1;
-            }
-            if i == token_end {
-                i += 1;
-                continue;
-            }
-            let op = &mut asm[i..token_end];
-            match str::from_utf8(op)
-                .expect("TODO")
-                .parse::<i64>()
-                .map_err(|err| err.kind().clone())
-            {
-                Ok(0) => {
-                    // OP_0
-                    ret.push(0x00);
-                }
-                Ok(n @ -1..=16) => {
-                    // OP_1NEGATE (4f), OP_1 (51) ... OP_16 (60)
-                    ret.push((0x50 + n) as u8);
-                }
-                Ok(n @ -0x7fffffff..=0x7fffffff) => {
-                    let s = &mut [0; INT_MAX_LEN];
-                    let s = encode_int(n, s);
-                    ret.push(s.len() as u8);
-                    ret.extend(s);
-                }
-                Ok(_) | Err(IntErrorKind::PosOverflow | IntErrorKind::NegOverflow) => {
-                    return Err(ParseAsmScriptError::IntegerOutOfRange);
-                }
-                Err(_) => {
-                    if let [b'<', hex @ .., b'>'] = op {
-                        match hex.len() / 2 {
-                            len @ 0..=75 => {
-                                ret.push(len as u8);
-                            }
-                            len @ 76..=255 => {
-                                // OP_PUSHDATA1
-                                ret.push(0x4c);
-                                ret.push(len as u8);
-                            }
-                            len @ 256..=520 => {
-                                // OP_PUSHDATA2
-                                ret.push(0x4d);
-                                ret.extend(u16::to_le_bytes(len as u16));
-                            }
-                            521.. => {
-                                return Err(ParseAsmScriptError::DataPushTooLarge);
-                            }
-                        }
-                        ret.extend(decode_hex_in_place(hex)?);
-                    } else if let Some(opcode) =
-                        Opcode::from_name(str::from_utf8(op).expect("TODO"))
-                    {
-                        if opcode.pushdata_length().is_some() {
-                            return Err(ParseAsmScriptError::ExplicitPushdata);
-                        }
-                        ret.push(opcode.opcode);
-                    } else {
-                        return Err(ParseAsmScriptError::UnknownOpcode);
-                        // throw `Unknown opcode ${op.length > 50 ? op.slice(0, 50) + '..' : op}${
-                        //     /^[0-9a-fA-F]+$/.test(op) ? '. Hex data pushes have to be between < and >' : ''
-                        // }`;
-                    }
+        // only defines make the encoding longer than the asm
+        if ret.len() > asm.len() {
+            return Err(ParseAsmScriptError::TooLong(AsmToken::new(
+                asm,
+                0..asm.len(),
+            )));
+        }
+        let asm = &mut asm[..ret.len()];
+        asm.copy_from_slice(&ret);
+
+        Ok((asm, Self::parse_from_bytes(asm).unwrap()))
+    }
+
+    /// Like [`parse_from_asm_in_place`](Self::parse_from_asm_in_place), but leaves `asm` as it
+    /// is and returns the encoded script in a new buffer, together with a [`ScriptBuf`] of it.
+    pub fn parse_from_asm(asm: &str) -> Result<(Vec<u8>, ScriptBuf), ParseAsmScriptError> {
+        Self::parse_from_asm_with_dialect(asm, AsmDialect::Analyzer)
+    }
+
+    /// Like [`parse_from_asm`](Self::parse_from_asm), for asm in `dialect`.
+    pub fn parse_from_asm_with_dialect(
+        asm: &str,
+        dialect: AsmDialect,
+    ) -> Result<(Vec<u8>, ScriptBuf), ParseAsmScriptError> {
+        let bytes = asm_to_bytes(asm.as_bytes(), dialect)?;
+        let script = ScriptBuf::from_bytes(bytes.clone()).expect("asm encodes a valid script");
+        Ok((bytes, script))
+    }
+
+    /// Like [`Script::instructions`], with the length prefixes the pushes were parsed with, so
+    /// the offsets are the ones in the parsed bytes.
+    pub fn instructions(&self) -> Instructions<'_, 'a> {
+        Instructions {
+            elems: self.elems.iter(),
+            push_opcodes: self.push_opcodes().iter(),
+            offset: 0,
+        }
+    }
+
+    /// The opcodes the pushes were parsed with, empty if the script was not parsed from bytes or
+    /// pushes were added or removed since.
+    fn push_opcodes(&self) -> &[Opcode] {
+        let pushes = self
+            .iter()
+            .filter(|e| matches!(e, ScriptElem::Bytes(_)))
+            .count();
+        if pushes == self.push_opcodes.len() {
+            &self.push_opcodes
+        } else {
+            &[]
+        }
+    }
+
+    /// Like [`Script::serialize`], but with [`PushEncoding::Original`] the pushes get the length
+    /// prefix they were parsed with, so the bytes of a parsed script are returned unchanged.
+    pub fn serialize(&self, encoding: PushEncoding) -> Vec<u8> {
+        if encoding == PushEncoding::Minimal || self.push_opcodes().is_empty() {
+            return Script::serialize(self, encoding);
+        }
+
+        let mut ret = Vec::with_capacity(self.serialized_len());
+        let mut push_opcodes = self.push_opcodes.iter();
+        for &e in &self.elems {
+            match e {
+                ScriptElem::Op(op) => ret.push(op.opcode),
+                ScriptElem::Bytes(bytes) => {
+                    let opcode = *push_opcodes.next().unwrap();
+                    encode_push(&mut ret, bytes, Some(opcode));
                 }
             }
+        }
+        ret
+    }
+}
+
+/// `opcode` if it can push `data`, otherwise the push opcode with the shortest length prefix.
+fn push_opcode(data: &[u8], opcode: Option<Opcode>) -> Opcode {
+    let len = data.len();
+    match opcode {
+        Some(op) if op.opcode as usize == len && len <= 75 => op,
+        Some(opcodes::OP_PUSHDATA1) if len <= 0xff => opcodes::OP_PUSHDATA1,
+        Some(opcodes::OP_PUSHDATA2) if len <= 0xffff => opcodes::OP_PUSHDATA2,
+        Some(opcodes::OP_PUSHDATA4) => opcodes::OP_PUSHDATA4,
+        _ => match len {
+            0..=75 => Opcode { opcode: len as u8 },
+            76..=0xff => opcodes::OP_PUSHDATA1,
+            0x100..=0xffff => opcodes::OP_PUSHDATA2,
+            _ => opcodes::OP_PUSHDATA4,
+        },
+    }
+}
+
+/// Size of the length prefix after a push opcode.
+fn pushdata_len(opcode: Opcode) -> usize {
+    opcode.pushdata_length().unwrap_or(0)
+}
+
+/// Size of a push of `data` with `opcode`, see [`push_opcode`].
+pub(crate) fn encoded_push_len(data: &[u8], opcode: Option<Opcode>) -> usize {
+    1 + pushdata_len(push_opcode(data, opcode)) + data.len()
+}
+
+/// Appends a push of `data` with the length prefix of `opcode`, or the shortest one if `opcode`
+/// is `None` or can not push `data`.
+fn encode_push(buf: &mut Vec<u8>, data: &[u8], opcode: Option<Opcode>) {
+    let len = data.len();
+    let opcode = push_opcode(data, opcode);
+    buf.push(opcode.opcode);
+    match opcode {
+        opcodes::OP_PUSHDATA1 => buf.push(len as u8),
+        opcodes::OP_PUSHDATA2 => buf.extend((len as u16).to_le_bytes()),
+        opcodes::OP_PUSHDATA4 => buf.extend((len as u32).to_le_bytes()),
+        _ => {}
+    }
+    buf.extend(data);
+}
 
-            i = token_end + 1;
+impl<'a> From<&Script<'a>> for OwnedScript<'a> {
+    fn from(script: &Script<'a>) -> Self {
+        Self {
+            elems: script.0.to_vec(),
+            push_opcodes: Vec::new(),
         }
+    }
+}
 
-        let asm = &mut asm[..ret.len()];
-        asm.copy_from_slice(&ret);
+/// A script with the length prefixes of its pushes, so byte offsets in it are the ones in the
+/// parsed bytes. Functions that analyze a script take this, converted from a [`Script`], of which
+/// the pushes are assumed to have the shortest length prefix, or an [`OwnedScript`].
+#[derive(Debug, Clone, Copy)]
+pub struct ScriptRef<'a> {
+    pub script: &'a Script<'a>,
+    /// Push opcodes of the pushes in `script`, empty if they have the shortest length prefix.
+    push_opcodes: &'a [Opcode],
+}
 
-        Ok((asm, Self::parse_from_bytes(asm).unwrap()))
+impl<'a> ScriptRef<'a> {
+    /// See [`OwnedScript::instructions`].
+    pub fn instructions(&self) -> Instructions<'a, 'a> {
+        Instructions {
+            elems: self.script.0.iter(),
+            push_opcodes: self.push_opcodes.iter(),
+            offset: 0,
+        }
+    }
+
+    /// Length of the script in bytes.
+    pub fn serialized_len(&self) -> usize {
+        self.instructions().map(|i| i.len).sum()
+    }
+
+    pub(crate) fn push_opcodes(&self) -> &'a [Opcode] {
+        self.push_opcodes
+    }
+}
+
+impl<'a> From<&'a Script<'a>> for ScriptRef<'a> {
+    fn from(script: &'a Script<'a>) -> Self {
+        Self {
+            script,
+            push_opcodes: &[],
+        }
+    }
+}
+
+impl<'a> From<&'a OwnedScript<'a>> for ScriptRef<'a> {
+    fn from(script: &'a OwnedScript<'a>) -> Self {
+        Self {
+            script,
+            push_opcodes: script.push_opcodes(),
+        }
+    }
+}
+
+impl<'a> From<&'a ScriptBuf> for ScriptRef<'a> {
+    fn from(script: &'a ScriptBuf) -> Self {
+        script.as_script().into()
     }
 }
 
@@ -175,13 +402,13 @@ impl<'a> Deref for OwnedScript<'a> {
     type Target = Script<'a>;
 
     fn deref(&self) -> &Self::Target {
-        Script::new(&self.0)
+        Script::new(&self.elems)
     }
 }
 
 impl<'a> DerefMut for OwnedScript<'a> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        Script::new_mut(&mut self.0)
+        Script::new_mut(&mut self.elems)
     }
 }
 
@@ -191,6 +418,58 @@ impl<'a> fmt::Display for OwnedScript<'a> {
     }
 }
 
+/// An element of a script and where it is in the script bytes, returned by
+/// [`Script::instructions`].
+#[derive(Debug, Clone, Copy)]
+pub struct Instruction<'a> {
+    /// Byte offset in the script.
+    pub offset: usize,
+    /// Size in bytes, including the length prefix of a push.
+    pub len: usize,
+    pub elem: ScriptElem<'a>,
+}
+
+impl Instruction<'_> {
+    /// Byte offsets of the instruction in the script.
+    pub fn span(&self) -> Range<usize> {
+        self.offset..self.offset + self.len
+    }
+}
+
+/// Iterator returned by [`Script::instructions`] and [`OwnedScript::instructions`].
+#[derive(Debug, Clone)]
+pub struct Instructions<'s, 'a> {
+    elems: core::slice::Iter<'s, ScriptElem<'a>>,
+    /// Push opcodes of the pushes in `elems`, empty if they have the shortest length prefix.
+    push_opcodes: core::slice::Iter<'s, Opcode>,
+    offset: usize,
+}
+
+impl<'a> Iterator for Instructions<'_, 'a> {
+    type Item = Instruction<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let elem = *self.elems.next()?;
+        let len = match elem {
+            ScriptElem::Op(_) => 1,
+            ScriptElem::Bytes(data) => encoded_push_len(data, self.push_opcodes.next().copied()),
+        };
+        let instruction = Instruction {
+            offset: self.offset,
+            len,
+            elem,
+        };
+        self.offset += len;
+        Some(instruction)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.elems.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Instructions<'_, '_> {}
+
 #[derive(Debug)]
 #[repr(transparent)]
 pub struct Script<'a>(pub [ScriptElem<'a>]);
@@ -204,6 +483,60 @@ impl<'a> Script<'a> {
         unsafe { &mut *(slice as *mut [ScriptElem<'a>] as *mut Self) }
     }
 
+    /// Length of the script in bytes, assuming minimal push encodings.
+    pub fn serialized_len(&self) -> usize {
+        self.iter()
+            .map(|e| match e {
+                ScriptElem::Op(_) => 1,
+                ScriptElem::Bytes(b) => push_len(b.len()),
+            })
+            .sum()
+    }
+
+    /// The elements of the script with their byte offsets and sizes, assuming the pushes have
+    /// the shortest length prefix. [`OwnedScript::instructions`] knows the length prefixes of a
+    /// parsed script.
+    pub fn instructions(&self) -> Instructions<'_, 'a> {
+        Instructions {
+            elems: self.0.iter(),
+            push_opcodes: [].iter(),
+            offset: 0,
+        }
+    }
+
+    /// Whether the script only pushes data, like `IsPushOnly` in Bitcoin Core, which counts
+    /// `OP_RESERVED` as a push.
+    pub fn is_push_only(&self) -> bool {
+        self.iter().all(|e| match e {
+            ScriptElem::Op(op) => *op <= opcodes::OP_16,
+            ScriptElem::Bytes(_) => true,
+        })
+    }
+
+    /// The script bytes with the data pushes encoded as `encoding` says. Without the length
+    /// prefixes of the original script, [`PushEncoding::Original`] uses the shortest ones, see
+    /// [`OwnedScript::serialize`] to keep them.
+    pub fn serialize(&self, encoding: PushEncoding) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(self.serialized_len());
+
+        for &e in &**self {
+            match e {
+                ScriptElem::Op(op) => ret.push(op.opcode),
+                ScriptElem::Bytes(bytes) if encoding == PushEncoding::Minimal => match bytes {
+                    [] => ret.push(opcodes::OP_0.opcode),
+                    &[n @ 1..=16] => ret.push(opcodes::OP_1.opcode + n - 1),
+                    [0x81] => ret.push(opcodes::OP_1NEGATE.opcode),
+                    _ => encode_push(&mut ret, bytes, None),
+                },
+                ScriptElem::Bytes(bytes) => encode_push(&mut ret, bytes, None),
+            }
+        }
+
+        ret
+    }
+
+    /// The opcodes and push data without length prefixes, these are not valid script bytes, see
+    /// [`serialize`](Self::serialize) for those.
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut ret = Vec::new();
 
@@ -295,6 +628,7 @@ pub enum ParseScriptError {
     Invalid(u8),
     UnexpectedEndPushdataLength(Opcode),
     UnexpectedEnd(usize, usize),
+    NonMinimalPush(usize),
 }
 
 impl fmt::Display for ParseScriptError {
@@ -309,79 +643,600 @@ impl fmt::Display for ParseScriptError {
                 f,
                 "invalid length, expected {expected} but got {actual} (SCRIPT_ERR_BAD_OPCODE)"
             ),
+            Self::NonMinimalPush(offset) => write!(
+                f,
+                "non-minimal push at byte offset {offset} (SCRIPT_ERR_MINIMALDATA)"
+            ),
         }
     }
 }
 
 impl std::error::Error for ParseScriptError {}
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarning {
+    NonMinimalPush(usize),
+    /// A byte that is not an opcode, only a warning with
+    /// [`OwnedScript::parse_from_bytes_lenient`].
+    UnknownOpcode(Opcode, usize),
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NonMinimalPush(offset) => {
+                write!(f, "non-minimal push at byte offset {offset}")
+            }
+            Self::UnknownOpcode(opcode, offset) => {
+                write!(f, "unknown opcode {opcode} at byte offset {offset}")
+            }
+        }
+    }
+}
+
+/// Byte offsets of the whitespace separated tokens of `asm`, without comments.
+fn asm_tokens(asm: &[u8]) -> impl Iterator<Item = Range<usize>> + '_ {
+    let mut i = 0;
+    core::iter::from_fn(move || loop {
+        while i < asm.len() && asm[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if asm[i..].starts_with(b"#") || asm[i..].starts_with(b"//") {
+            // comment until the end of the line
+            while i < asm.len() && asm[i] != b'\n' {
+                i += 1;
+            }
+            continue;
+        }
+        let start = i;
+        while i < asm.len()
+            && !asm[i].is_ascii_whitespace()
+            && !asm[i..].starts_with(b"#")
+            && !asm[i..].starts_with(b"//")
+        {
+            i += 1;
+        }
+        return (start < i).then_some(start..i);
+    })
+}
+
+/// Decodes the hex data push at `span` in `asm`, with or without `<` and `>` around it.
+fn decode_asm_push(asm: &[u8], span: Range<usize>) -> Result<Vec<u8>, ParseAsmScriptError> {
+    let (hex, hex_start) = match &asm[span.clone()] {
+        [b'<', hex @ .., b'>'] => (hex, span.start + 1),
+        hex => (hex, span.start),
+    };
+    if hex.len() / 2 > MAX_SCRIPT_ELEMENT_SIZE {
+        return Err(ParseAsmScriptError::DataPushTooLarge(AsmToken::new(
+            asm, span,
+        )));
+    }
+    let mut data = Vec::new();
+    decode_hex_into(hex, &mut data).map_err(|err| {
+        // offsets in the asm, not in the data push
+        let err = match err {
+            HexDecodeError::InvalidCharacter(pos, char) => {
+                HexDecodeError::InvalidCharacter(hex_start + pos, char)
+            }
+            err => err,
+        };
+        ParseAsmScriptError::HexDecodeError(AsmToken::new(asm, span.clone()), err)
+    })?;
+    Ok(data)
+}
+
+/// Encodes an asm script, see [`OwnedScript::parse_from_asm_in_place`].
+///
+/// Everything after `#` or `//` is a comment until the end of the line.
+/// `$name = value` defines a name for a token, used as `$name`, or `<$name>` for a data push
+/// of a hex value.
+fn asm_to_bytes(asm: &[u8], dialect: AsmDialect) -> Result<Vec<u8>, ParseAsmScriptError> {
+    let mut ret = Vec::new();
+    // names and the spans of their values
+    let mut defines: Vec<(&[u8], Range<usize>)> = Vec::new();
+    let define = |defines: &[(&[u8], Range<usize>)], name: &[u8], span: Range<usize>| {
+        defines
+            .iter()
+            .rfind(|(n, _)| *n == name)
+            .map(|(_, value)| value.clone())
+            .ok_or_else(|| ParseAsmScriptError::UnknownDefine(AsmToken::new(asm, span)))
+    };
+
+    let mut tokens = asm_tokens(asm).peekable();
+    while let Some(span) = tokens.next() {
+        let span = match &asm[span.clone()] {
+            [b'$', name @ ..] if tokens.peek().is_some_and(|t| &asm[t.clone()] == b"=") => {
+                tokens.next();
+                let invalid =
+                    || ParseAsmScriptError::InvalidDefine(AsmToken::new(asm, span.clone()));
+                if name.is_empty() || !name.iter().all(|&c| c.is_ascii_alphanumeric() || c == b'_')
+                {
+                    return Err(invalid());
+                }
+                let value = tokens.next().ok_or_else(invalid)?;
+                let value = match &asm[value.clone()] {
+                    [b'$', value_name @ ..] => define(&defines, value_name, value)?,
+                    _ => value,
+                };
+                defines.push((name, value));
+                continue;
+            }
+            [b'$', name @ ..] => define(&defines, name, span)?,
+            [b'<', b'$', name @ .., b'>'] => {
+                let value = define(&defines, name, span)?;
+                encode_push(&mut ret, &decode_asm_push(asm, value)?, None);
+                continue;
+            }
+            _ => span,
+        };
+        let op = &asm[span.clone()];
+        let token = || AsmToken::new(asm, span.clone());
+        let Ok(op_str) = str::from_utf8(op) else {
+            return Err(ParseAsmScriptError::InvalidUtf8(token()));
+        };
+        let mut number = op_str.parse::<i64>().map_err(|err| *err.kind());
+        if dialect.bare_hex_pushes()
+            && op.iter().all(u8::is_ascii_hexdigit)
+            && !matches!(number, Ok(n @ -0x7fffffff..=0x7fffffff) if n.to_string() == op_str)
+        {
+            // hex of a push longer than 4 bytes, like 0102030405
+            number = Err(IntErrorKind::InvalidDigit);
+        }
+        match number {
+            Ok(0) => {
+                // OP_0
+                ret.push(0x00);
+            }
+            Ok(n @ -1..=16) => {
+                // OP_1NEGATE (4f), OP_1 (51) ... OP_16 (60)
+                ret.push((0x50 + n) as u8);
+            }
+            Ok(n @ -0x7fffffff..=0x7fffffff) => {
+                let s = &mut [0; INT_MAX_LEN];
+                let s = encode_int(n, s);
+                ret.push(s.len() as u8);
+                ret.extend(s);
+            }
+            Ok(_) | Err(IntErrorKind::PosOverflow | IntErrorKind::NegOverflow) => {
+                return Err(ParseAsmScriptError::IntegerOutOfRange(token()));
+            }
+            Err(_) => {
+                if let [b'<', .., b'>'] = op {
+                    encode_push(&mut ret, &decode_asm_push(asm, span.clone())?, None);
+                } else if let Some(opcode) = dialect.explicit_push(op_str) {
+                    let invalid = || ParseAsmScriptError::InvalidExplicitPush(token());
+                    // Core writes the length first
+                    let len = if dialect == AsmDialect::Core {
+                        let len = tokens.next().ok_or_else(invalid)?;
+                        let len = str::from_utf8(&asm[len]).ok().and_then(|l| l.parse().ok());
+                        Some(len.ok_or_else(invalid)?)
+                    } else {
+                        None
+                    };
+                    let data = if opcode.opcode == 0 {
+                        // nothing follows the push of an empty string
+                        Vec::new()
+                    } else {
+                        decode_asm_push(asm, tokens.next().ok_or_else(invalid)?)?
+                    };
+                    if len.is_some_and(|len: usize| len != data.len())
+                        || push_opcode(&data, Some(opcode)) != opcode
+                    {
+                        return Err(invalid());
+                    }
+                    encode_push(&mut ret, &data, Some(opcode));
+                } else if let Some(opcode) =
+                    Opcode::from_name(op_str).or_else(|| dialect.opcode_from_name(op_str))
+                {
+                    if opcode.pushdata_length().is_some() {
+                        return Err(ParseAsmScriptError::ExplicitPushdata(token()));
+                    }
+                    ret.push(opcode.opcode);
+                } else if dialect.bare_hex_pushes() && op.iter().all(u8::is_ascii_hexdigit) {
+                    encode_push(&mut ret, &decode_asm_push(asm, span.clone())?, None);
+                } else {
+                    return Err(ParseAsmScriptError::UnknownOpcode(token()));
+                }
+            }
+        }
+    }
+
+    Ok(ret)
+}
+
+/// Tokens longer than this are truncated in [`AsmToken::text`].
+const MAX_TOKEN_TEXT_LEN: usize = 50;
+
+/// The invalid token of a [`ParseAsmScriptError`] and where it is in the asm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmToken {
+    /// The token, invalid UTF-8 replaced and truncated to 50 characters.
+    pub text: String,
+    /// Byte offsets of the token in the asm.
+    pub span: Range<usize>,
+    /// Line of the start of the token, starting at 1.
+    pub line: usize,
+    /// Column of the start of the token in characters, starting at 1.
+    pub column: usize,
+}
+
+impl AsmToken {
+    fn new(asm: &[u8], span: Range<usize>) -> Self {
+        let line_start = asm[..span.start]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1);
+        let line = asm[..line_start].iter().filter(|&&b| b == b'\n').count() + 1;
+        let column = String::from_utf8_lossy(&asm[line_start..span.start])
+            .chars()
+            .count()
+            + 1;
+
+        let text = String::from_utf8_lossy(&asm[span.clone()]);
+        let text = if text.chars().count() > MAX_TOKEN_TEXT_LEN {
+            let mut text: String = text.chars().take(MAX_TOKEN_TEXT_LEN).collect();
+            text.push_str("..");
+            text
+        } else {
+            text.into_owned()
+        };
+
+        Self {
+            text,
+            span,
+            line,
+            column,
+        }
+    }
+}
+
+/// Error returned by [`OwnedScript::parse_from_asm`] and
+/// [`OwnedScript::parse_from_asm_in_place`]. Every variant has the invalid token and its
+/// position in the asm.
 #[derive(Debug, Clone)]
 pub enum ParseAsmScriptError {
-    IntegerOutOfRange,
-    DataPushTooLarge,
-    UnknownOpcode,
-    ExplicitPushdata,
-    HexDecodeError(HexDecodeError),
+    IntegerOutOfRange(AsmToken),
+    DataPushTooLarge(AsmToken),
+    UnknownOpcode(AsmToken),
+    ExplicitPushdata(AsmToken),
+    /// Invalid data push, positions in the [`HexDecodeError`] are byte offsets in the asm too.
+    HexDecodeError(AsmToken, HexDecodeError),
+    InvalidUtf8(AsmToken),
+    /// A push opcode of an [`AsmDialect`] with explicit pushes is not followed by data it can
+    /// push.
+    InvalidExplicitPush(AsmToken),
+    /// `$name = value` with an invalid name or without a value.
+    InvalidDefine(AsmToken),
+    /// `$name` without a `$name = value` before it.
+    UnknownDefine(AsmToken),
+    /// The script is longer than the asm because of defines, so it can not be parsed in place.
+    TooLong(AsmToken),
 }
 
-impl From<HexDecodeError> for ParseAsmScriptError {
-    fn from(value: HexDecodeError) -> Self {
-        Self::HexDecodeError(value)
+impl ParseAsmScriptError {
+    /// The invalid token.
+    pub fn token(&self) -> &AsmToken {
+        match self {
+            Self::IntegerOutOfRange(token)
+            | Self::DataPushTooLarge(token)
+            | Self::UnknownOpcode(token)
+            | Self::ExplicitPushdata(token)
+            | Self::HexDecodeError(token, _)
+            | Self::InvalidUtf8(token)
+            | Self::InvalidExplicitPush(token)
+            | Self::InvalidDefine(token)
+            | Self::UnknownDefine(token)
+            | Self::TooLong(token) => token,
+        }
+    }
+
+    /// Byte offsets of the invalid part of the asm: the invalid character of a data push or the
+    /// whole token.
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            Self::HexDecodeError(token, err) => err.span().unwrap_or_else(|| token.span.clone()),
+            _ => self.token().span.clone(),
+        }
+    }
+
+    /// How to fix the token.
+    pub fn hint(&self) -> &'static str {
+        match self {
+            Self::IntegerOutOfRange(_) => {
+                "numbers are at most 4 bytes, write larger numbers as a <hex> data push"
+            }
+            Self::DataPushTooLarge(_) => "data pushes are at most 520 bytes",
+            Self::UnknownOpcode(token) => {
+                let text = token.text.as_bytes();
+                if text.iter().all(u8::is_ascii_hexdigit) {
+                    "hex pushes must be wrapped in <>"
+                } else if text.first() == Some(&b'<') || text.last() == Some(&b'>') {
+                    "data pushes can not contain whitespace"
+                } else {
+                    "expected an opcode name, a number or a <hex> data push"
+                }
+            }
+            Self::ExplicitPushdata(_) => {
+                "write only the data between <>, the push opcode is chosen automatically"
+            }
+            Self::HexDecodeError(_, HexDecodeError::OddAmountOfHexCharacters(_)) => {
+                "every byte of a data push takes 2 hex characters"
+            }
+            Self::HexDecodeError(_, HexDecodeError::InvalidCharacter(..)) => {
+                "data pushes can only contain the hex characters 0-9, a-f and A-F"
+            }
+            Self::InvalidUtf8(_) => "asm must be valid UTF-8",
+            Self::InvalidExplicitPush(_) => {
+                "a push opcode is followed by the data it pushes, in the Core dialect by its \
+                length first"
+            }
+            Self::InvalidDefine(_) => {
+                "define names like $name = value with letters, digits and underscores"
+            }
+            Self::UnknownDefine(_) => "define names with $name = value before using them",
+            Self::TooLong(_) => "parse it with OwnedScript::parse_from_asm instead",
+        }
     }
 }
 
 impl fmt::Display for ParseAsmScriptError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::IntegerOutOfRange => write!(f, "integer out of range"),
-            Self::DataPushTooLarge => write!(f, "data push too large"),
-            Self::UnknownOpcode => write!(f, "unknown opcode"),
-            Self::ExplicitPushdata => {
+            Self::IntegerOutOfRange(_) => write!(f, "integer out of range"),
+            Self::DataPushTooLarge(_) => write!(f, "data push too large"),
+            Self::UnknownOpcode(_) => write!(f, "unknown opcode"),
+            Self::ExplicitPushdata(_) => {
                 write!(f, "OP_PUSHDATA opcodes are not allowed in asm script")
             }
-            Self::HexDecodeError(err) => write!(f, "hex decode error: {err}"),
-        }
+            Self::HexDecodeError(_, err) => write!(f, "hex decode error: {err}"),
+            Self::InvalidUtf8(_) => write!(f, "invalid UTF-8"),
+            Self::InvalidExplicitPush(_) => write!(f, "invalid explicit push"),
+            Self::InvalidDefine(_) => write!(f, "invalid define"),
+            Self::UnknownDefine(_) => write!(f, "unknown define"),
+            Self::TooLong(_) => write!(f, "script does not fit in the asm"),
+        }?;
+        let token = self.token();
+        write!(
+            f,
+            " at line {}, column {}: `{}` (hint: {})",
+            token.line,
+            token.column,
+            token.text,
+            self.hint()
+        )
     }
 }
 
 impl std::error::Error for ParseAsmScriptError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         Some(match self {
-            Self::HexDecodeError(err) => err,
+            Self::HexDecodeError(_, err) => err,
             _ => return None,
         })
     }
 }
 
-/*
+#[cfg(test)]
+mod tests {
+    use super::{
+        OwnedScript, ParseAsmScriptError, ParseScriptError, ParseWarning, PushEncoding, Script,
+        ScriptElem,
+    };
+    use crate::context::VerifyFlags;
+
+    #[test]
+    fn test_asm_error_span() {
+        let span = |asm: &str| {
+            let mut buf = asm.as_bytes().to_vec();
+            OwnedScript::parse_from_asm_in_place(&mut buf)
+                .unwrap_err()
+                .span()
+        };
 
-TODO maybe flags from bitcoin core
+        assert_eq!(span("OP_DUP OP_FOO OP_DROP"), 7..13);
+        assert_eq!(span("1 <0102zz>"), 7..8);
+        assert_eq!(span("<012>"), 0..5);
+        assert_eq!(span("OP_PUSHDATA1 <01>"), 0..12);
+        let mut buf = b"  9999999999".to_vec();
+        assert!(matches!(
+            OwnedScript::parse_from_asm_in_place(&mut buf),
+            Err(ParseAsmScriptError::IntegerOutOfRange(token)) if token.span == (2..12)
+        ));
 
-MANDATORY_SCRIPT_VERIFY_FLAGS = SCRIPT_VERIFY_P2SH
+        let err = |asm: &[u8]| {
+            let mut buf = asm.to_vec();
+            OwnedScript::parse_from_asm_in_place(&mut buf).unwrap_err()
+        };
 
-consensus:
-MANDATORY_SCRIPT_VERIFY_FLAGS
+        let e = err(b"OP_DUP\n  OP_HASH160 abcd");
+        assert!(matches!(e, ParseAsmScriptError::UnknownOpcode(_)));
+        assert_eq!((e.token().line, e.token().column), (2, 14));
+        assert_eq!(e.hint(), "hex pushes must be wrapped in <>");
+        assert_eq!(
+            e.to_string(),
+            "unknown opcode at line 2, column 14: `abcd` (hint: hex pushes must be wrapped in <>)"
+        );
 
-relay:
-MANDATORY_SCRIPT_VERIFY_FLAGS
-SCRIPT_VERIFY_DERSIG
-SCRIPT_VERIFY_STRICTENC
-SCRIPT_VERIFY_MINIMALDATA
-SCRIPT_VERIFY_NULLDUMMY
-SCRIPT_VERIFY_DISCOURAGE_UPGRADABLE_NOPS
-SCRIPT_VERIFY_CLEANSTACK
-SCRIPT_VERIFY_MINIMALIF
-SCRIPT_VERIFY_NULLFAIL
-SCRIPT_VERIFY_CHECKLOCKTIMEVERIFY
-SCRIPT_VERIFY_CHECKSEQUENCEVERIFY
-SCRIPT_VERIFY_LOW_S
-SCRIPT_VERIFY_WITNESS
-SCRIPT_VERIFY_DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM
-SCRIPT_VERIFY_WITNESS_PUBKEYTYPE
-SCRIPT_VERIFY_CONST_SCRIPTCODE
-SCRIPT_VERIFY_TAPROOT
-SCRIPT_VERIFY_DISCOURAGE_UPGRADABLE_TAPROOT_VERSION
-SCRIPT_VERIFY_DISCOURAGE_OP_SUCCESS
-SCRIPT_VERIFY_DISCOURAGE_UPGRADABLE_PUBKEYTYPE
+        let e = err(format!("<{}>", "ab".repeat(600)).as_bytes());
+        assert!(matches!(e, ParseAsmScriptError::DataPushTooLarge(_)));
+        assert_eq!(e.token().text.len(), 52);
+        assert!(e.token().text.ends_with(".."));
+
+        // no panic on invalid UTF-8
+        let e = err(b"OP_1 \xff\xfe OP_2");
+        assert!(matches!(e, ParseAsmScriptError::InvalidUtf8(_)));
+        assert_eq!(e.span(), 5..7);
+        assert_eq!(e.token().text, "\u{fffd}\u{fffd}");
+    }
 
-*/
+    #[test]
+    fn test_parse_from_asm() {
+        let asm = "OP_DUP OP_HASH160 <0102> OP_EQUALVERIFY 1000 -1";
+        let (bytes, script) = OwnedScript::parse_from_asm(asm).unwrap();
+        assert_eq!(
+            bytes,
+            [0x76, 0xa9, 0x02, 0x01, 0x02, 0x88, 0x02, 0xe8, 0x03, 0x4f]
+        );
+        assert_eq!(script.as_bytes(), bytes);
+
+        let mut buf = asm.as_bytes().to_vec();
+        assert_eq!(
+            OwnedScript::parse_from_asm_in_place(&mut buf).unwrap().0,
+            bytes
+        );
+
+        assert_eq!(
+            OwnedScript::parse_from_asm("OP_1 <0102zz>")
+                .unwrap_err()
+                .span(),
+            10..11
+        );
+    }
+
+    #[test]
+    fn test_asm_comments_and_defines() {
+        let asm = "\
+            # 2-of-2 with a timeout
+            $alice_pk = 02aa
+            $bob_pk = <02bb> // a define can be any token
+            $timeout = 144
+            $carol_pk = $alice_pk
+            OP_IF
+                2 <$alice_pk> $bob_pk 2 OP_CHECKMULTISIG
+            OP_ELSE
+                $timeout OP_CHECKSEQUENCEVERIFY OP_DROP <$carol_pk> OP_CHECKSIG#no space needed
+            OP_ENDIF
+        ";
+        let (bytes, _) = OwnedScript::parse_from_asm(asm).unwrap();
+        let (expected, _) = OwnedScript::parse_from_asm(
+            "OP_IF 2 <02aa> <02bb> 2 OP_CHECKMULTISIG OP_ELSE 144 OP_CHECKSEQUENCEVERIFY OP_DROP \
+            <02aa> OP_CHECKSIG OP_ENDIF",
+        )
+        .unwrap();
+        assert_eq!(bytes, expected);
+
+        let err = |asm: &str| OwnedScript::parse_from_asm(asm).unwrap_err();
+        assert!(matches!(
+            err("OP_DUP <$pk>"),
+            ParseAsmScriptError::UnknownDefine(token) if token.span == (7..12)
+        ));
+        assert!(matches!(
+            err("$pk ="),
+            ParseAsmScriptError::InvalidDefine(_)
+        ));
+        assert!(matches!(
+            err("$p-k = 01"),
+            ParseAsmScriptError::InvalidDefine(_)
+        ));
+        // errors in the value point at the define
+        assert_eq!(err("$x = <0g>\n<$x>").span(), 7..8);
+
+        // references to a long value make the script longer than the asm
+        let asm = format!("$x = {} <$x> <$x> <$x>", "ab".repeat(33));
+        let mut buf = asm.clone().into_bytes();
+        assert!(matches!(
+            OwnedScript::parse_from_asm_in_place(&mut buf),
+            Err(ParseAsmScriptError::TooLong(_))
+        ));
+        assert_eq!(OwnedScript::parse_from_asm(&asm).unwrap().0.len(), 102);
+    }
+
+    #[test]
+    fn test_minimal_push() {
+        let warnings = |bytes: &[u8]| {
+            OwnedScript::parse_from_bytes_checked(bytes, VerifyFlags::CONSENSUS)
+                .unwrap()
+                .1
+        };
+
+        assert!(warnings(&[0x00, 0x51, 0x4f, 0x01, 0x11, 0x02, 0x01, 0x00]).is_empty());
+        assert_eq!(
+            warnings(&[0x51, 0x01, 0x05]),
+            [ParseWarning::NonMinimalPush(1)]
+        );
+        assert_eq!(
+            warnings(&[0x4c, 0x00, 0x01, 0x81, 0x4c, 0x01, 0x20]),
+            [
+                ParseWarning::NonMinimalPush(0),
+                ParseWarning::NonMinimalPush(2),
+                ParseWarning::NonMinimalPush(4),
+            ]
+        );
+
+        let mut bytes = vec![0x4c, 76];
+        bytes.extend([0; 76]);
+        assert!(warnings(&bytes).is_empty());
+
+        assert!(matches!(
+            OwnedScript::parse_from_bytes_checked(&[0x51, 0x01, 0x05], VerifyFlags::STANDARD),
+            Err(ParseScriptError::NonMinimalPush(1))
+        ));
+    }
+
+    #[test]
+    fn test_lenient_parse() {
+        let bytes = [0x51, 0xbb, 0x01, 0x05, 0xfe];
+        assert!(matches!(
+            OwnedScript::parse_from_bytes(&bytes),
+            Err(ParseScriptError::Invalid(0xbb))
+        ));
+        let (script, warnings) = OwnedScript::parse_from_bytes_lenient(&bytes).unwrap();
+        assert_eq!(
+            script.to_string(),
+            "OP_1\nUNKNOWN(0xbb)\n<05>\nUNKNOWN(0xfe)"
+        );
+        assert_eq!(script.serialize(PushEncoding::Original), bytes);
+        assert_eq!(
+            warnings.iter().map(|w| w.to_string()).collect::<Vec<_>>(),
+            [
+                "unknown opcode UNKNOWN(0xbb) at byte offset 1",
+                "non-minimal push at byte offset 2",
+                "unknown opcode UNKNOWN(0xfe) at byte offset 4",
+            ]
+        );
+        // truncated pushes are still an error
+        assert!(OwnedScript::parse_from_bytes_lenient(&[0xbb, 0x02, 0x01]).is_err());
+    }
+
+    #[test]
+    fn test_serialize() {
+        // OP_PUSHDATA1 <07>, <01>, <>, OP_PUSHDATA2 <0102>, OP_1
+        let bytes = [
+            0x4c, 0x01, 0x07, 0x01, 0x01, 0x4c, 0x00, 0x4d, 0x02, 0x00, 0x01, 0x02, 0x51,
+        ];
+        let script = OwnedScript::parse_from_bytes(&bytes).unwrap();
+        assert_eq!(script.serialize(PushEncoding::Original), bytes);
+        assert_eq!(
+            script.serialize(PushEncoding::Minimal),
+            [0x57, 0x51, 0x00, 0x02, 0x01, 0x02, 0x51]
+        );
+        // the script elements alone do not know the original length prefixes
+        assert_eq!(
+            OwnedScript::from(&*script).serialize(PushEncoding::Original),
+            [0x01, 0x07, 0x01, 0x01, 0x00, 0x02, 0x01, 0x02, 0x51]
+        );
+
+        let data = [0xab; 300];
+        let mut buf = format!("<{}> OP_DROP", "ab".repeat(300)).into_bytes();
+        let (bytes, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        assert_eq!(script.serialize(PushEncoding::Minimal), bytes);
+        assert_eq!(&bytes[3..303], data);
+    }
+
+    #[test]
+    fn test_instructions() {
+        // OP_DUP OP_PUSHDATA1 <0102> OP_DROP
+        let bytes = [0x76, 0x4c, 0x02, 0x01, 0x02, 0x75];
+        let script = OwnedScript::parse_from_bytes(&bytes).unwrap();
+        let spans = |instructions: super::Instructions<'_, '_>| {
+            instructions.map(|i| i.span()).collect::<Vec<_>>()
+        };
+        assert_eq!(spans(script.instructions()), [0..1, 1..5, 5..6]);
+        // without the original length prefix
+        assert_eq!(spans(Script::instructions(&script)), [0..1, 1..4, 4..5]);
+
+        let instruction = script.instructions().nth(1).unwrap();
+        assert!(matches!(instruction.elem, ScriptElem::Bytes([1, 2])));
+        assert_eq!(script.instructions().len(), 3);
+    }
+}
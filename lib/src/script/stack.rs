@@ -5,6 +5,8 @@ use core::{array, cmp::max};
 pub struct Stack {
     elements: Vec<Expr>,
     next_element_id: u32,
+    /// There are no unknown items below `elements`.
+    complete: bool,
 }
 
 impl Stack {
@@ -12,6 +14,28 @@ impl Stack {
         Self {
             elements: Vec::new(),
             next_element_id: 0,
+            complete: false,
+        }
+    }
+
+    /// Creates a stack with known initial elements, `elements[0]` being the bottom of the stack.
+    pub fn from_elements(elements: Vec<Expr>) -> Self {
+        Self {
+            elements,
+            next_element_id: 0,
+            complete: true,
+        }
+    }
+
+    /// Creates a stack where the initial stack items `0..known.len()` are given, `known[0]` being
+    /// the top of the stack. Items below are unknown.
+    pub fn with_known_items(mut known: Vec<Expr>) -> Self {
+        let next_element_id = known.len() as u32;
+        known.reverse();
+        Self {
+            elements: known,
+            next_element_id,
+            complete: false,
         }
     }
 
@@ -19,6 +43,11 @@ impl Stack {
         self.elements.len()
     }
 
+    /// The amount of elements on the stack, `None` if there are unknown initial items.
+    pub fn depth(&self) -> Option<usize> {
+        self.complete.then_some(self.elements.len())
+    }
+
     pub fn items_used(&self) -> u32 {
         self.next_element_id
     }
@@ -67,6 +96,19 @@ impl Stack {
         self.elements.swap(last - a, last - b);
     }
 
+    /// The top element, if any element was pushed.
+    pub fn last_mut(&mut self) -> Option<&mut Expr> {
+        self.elements.last_mut()
+    }
+
+    pub fn elements(&self) -> &[Expr] {
+        &self.elements
+    }
+
+    pub fn elements_mut(&mut self) -> &mut [Expr] {
+        &mut self.elements
+    }
+
     pub fn pop<const N: usize>(&mut self) -> [Expr; N] {
         self.grow_to(N);
 
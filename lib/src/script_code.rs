@@ -0,0 +1,41 @@
+use crate::expr::Origin;
+use core::fmt;
+
+/// The part of the script a signature check signs, which starts after the last executed
+/// `OP_CODESEPARATOR`, see [`SpendingPath::script_codes`](crate::SpendingPath::script_codes).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptCode {
+    /// The `OP_CHECKSIG`, `OP_CHECKSIGVERIFY`, `OP_CHECKSIGADD`, `OP_CHECKMULTISIG` or
+    /// `OP_CHECKMULTISIGVERIFY`.
+    pub checksig: Origin,
+    /// The last `OP_CODESEPARATOR` executed before it.
+    pub code_separator: Option<Origin>,
+}
+
+impl ScriptCode {
+    /// Byte offset of the start of the scriptCode, assuming minimal pushes.
+    pub fn start(&self) -> usize {
+        self.code_separator.map_or(0, |sep| sep.offset + 1)
+    }
+
+    /// The `codesep_pos` signed by tapscript signatures, the opcode position of the
+    /// `OP_CODESEPARATOR` or `0xffffffff` if there is none.
+    pub fn codesep_pos(&self) -> u32 {
+        self.code_separator.map_or(u32::MAX, |sep| sep.index as u32)
+    }
+}
+
+impl fmt::Display for ScriptCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} signs the script from byte {}",
+            self.checksig,
+            self.start()
+        )?;
+        if let Some(sep) = self.code_separator {
+            write!(f, ", after {sep} (opcode position {})", sep.index)?;
+        }
+        Ok(())
+    }
+}
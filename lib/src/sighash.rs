@@ -0,0 +1,237 @@
+use crate::{
+    context::{ScriptContext, ScriptVersion, VerifyFlags},
+    expr::{Expr, ExprFormat, OpExprArgs, Opcode1, Opcode2},
+    roles::ItemUsage,
+    util::checksig::{SIGHASH_ALL, SIGHASH_ANYONECANPAY, SIGHASH_NONE, SIGHASH_SINGLE},
+};
+use core::fmt;
+
+/// The sighash types a signature can use, see [`SighashRequirement`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SighashTypes {
+    /// The signature is a constant with this sighash type, 0 is `SIGHASH_DEFAULT`.
+    Fixed(u8),
+    /// A 64 byte Schnorr signature, it can only use `SIGHASH_DEFAULT`.
+    Default,
+    /// `SIGHASH_ALL`, `SIGHASH_NONE` or `SIGHASH_SINGLE`, with or without
+    /// `SIGHASH_ANYONECANPAY`. In tapscript, `SIGHASH_DEFAULT` is allowed if the signature can be
+    /// 64 bytes.
+    Standard { default: bool },
+    /// Any byte, without [`VerifyFlags::STRICTENC`].
+    Any,
+}
+
+impl fmt::Display for SighashTypes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fixed(sighash_type) => write!(f, "{}", sighash_type_name(*sighash_type)),
+            Self::Default => write!(f, "SIGHASH_DEFAULT only"),
+            Self::Standard { default } => {
+                if *default {
+                    write!(f, "SIGHASH_DEFAULT, ")?;
+                }
+                write!(f, "ALL, NONE or SINGLE, with or without ANYONECANPAY")
+            }
+            Self::Any => write!(f, "any, the last byte is not checked"),
+        }
+    }
+}
+
+/// Name of a sighash type, like `SIGHASH_NONE|SIGHASH_ANYONECANPAY`.
+pub fn sighash_type_name(sighash_type: u8) -> String {
+    let base = match sighash_type & !SIGHASH_ANYONECANPAY {
+        0 if sighash_type == 0 => return "SIGHASH_DEFAULT".to_owned(),
+        SIGHASH_ALL => "SIGHASH_ALL".to_owned(),
+        SIGHASH_NONE => "SIGHASH_NONE".to_owned(),
+        SIGHASH_SINGLE => "SIGHASH_SINGLE".to_owned(),
+        _ => format!("0x{sighash_type:02x}"),
+    };
+    if sighash_type & SIGHASH_ANYONECANPAY != 0 && base.starts_with("SIGHASH") {
+        format!("{base}|SIGHASH_ANYONECANPAY")
+    } else {
+        base
+    }
+}
+
+/// The sighash types a signature of a spending path can use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SighashRequirement {
+    pub sig: Expr,
+    /// `None` for the signatures of `OP_CHECKMULTISIG`, they are not tied to one key.
+    pub key: Option<Expr>,
+    pub types: SighashTypes,
+}
+
+impl SighashRequirement {
+    /// A warning if the sighash type leaves parts of the transaction unsigned.
+    pub fn warning(&self) -> Option<&'static str> {
+        let SighashTypes::Fixed(sighash_type) = self.types else {
+            return None;
+        };
+        match sighash_type & !SIGHASH_ANYONECANPAY {
+            SIGHASH_NONE => Some("no outputs are signed, anyone can send the funds elsewhere"),
+            SIGHASH_SINGLE => Some("only the output at the same index is signed"),
+            _ if sighash_type & SIGHASH_ANYONECANPAY != 0 => {
+                Some("only this input is signed, inputs can be added or removed")
+            }
+            _ => None,
+        }
+    }
+
+    pub fn display<'a>(&'a self, format: ExprFormat<'a>) -> SighashDisplay<'a> {
+        SighashDisplay { req: self, format }
+    }
+}
+
+/// Returned by [`SighashRequirement::display`].
+pub struct SighashDisplay<'a> {
+    req: &'a SighashRequirement,
+    format: ExprFormat<'a>,
+}
+
+impl fmt::Display for SighashDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.req.sig.display(self.format))?;
+        if let Some(key) = &self.req.key {
+            write!(f, " for {}", key.display(self.format))?;
+        }
+        write!(f, ": {}", self.req.types)?;
+        if let Some(warning) = self.req.warning() {
+            write!(f, " (warning: {warning})")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SighashRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display(ExprFormat::default()))
+    }
+}
+
+/// Sighash types of a signature that is not constant, with size bounds from `items`.
+fn sighash_types(sig: &Expr, ctx: ScriptContext, items: &[ItemUsage]) -> SighashTypes {
+    let (min_size, max_size) = match sig {
+        Expr::Stack(s) => items
+            .get(s.pos() as usize)
+            .map_or((0, usize::MAX), |i| (i.min_size, i.max_size)),
+        _ => (0, usize::MAX),
+    };
+    if ctx.version == ScriptVersion::SegwitV1 {
+        if max_size <= 64 {
+            SighashTypes::Default
+        } else {
+            SighashTypes::Standard {
+                default: min_size <= 64,
+            }
+        }
+    } else if ctx.has(VerifyFlags::STRICTENC) {
+        SighashTypes::Standard { default: false }
+    } else {
+        SighashTypes::Any
+    }
+}
+
+/// Finds the signatures of the signature checks in `conditions` that must succeed, and the
+/// sighash types they can use.
+pub(crate) fn sighash_requirements(
+    ctx: ScriptContext,
+    conditions: &[Expr],
+    items: &[ItemUsage],
+) -> Vec<SighashRequirement> {
+    fn visit(
+        expr: &Expr,
+        ctx: ScriptContext,
+        items: &[ItemUsage],
+        out: &mut Vec<SighashRequirement>,
+    ) {
+        let Expr::Op(op) = expr else {
+            return;
+        };
+        let mut push = |sig: &Expr, key: Option<&Expr>| {
+            let types = match sig {
+                // the last byte, a 64 byte Schnorr signature has none
+                Expr::Bytes(b) if b.len() == 64 && ctx.version == ScriptVersion::SegwitV1 => {
+                    SighashTypes::Fixed(0)
+                }
+                Expr::Bytes(b) => match b.as_ref().last() {
+                    Some(&last) => SighashTypes::Fixed(last),
+                    None => return,
+                },
+                sig => sighash_types(sig, ctx, items),
+            };
+            let req = SighashRequirement {
+                sig: sig.clone(),
+                key: key.cloned(),
+                types,
+            };
+            if !out.contains(&req) {
+                out.push(req);
+            }
+        };
+        match &op.args {
+            // a failing signature check does not need a signature
+            OpExprArgs::Args1(Opcode1::OP_NOT | Opcode1::OP_INTERNAL_NOT, _) => return,
+            OpExprArgs::Args2(Opcode2::OP_CHECKSIG, args) => push(&args[0], Some(&args[1])),
+            OpExprArgs::Multisig(m) => {
+                for sig in m.sigs() {
+                    push(sig, None);
+                }
+            }
+            _ => {}
+        }
+        for arg in op.args() {
+            visit(arg, ctx, items, out);
+        }
+    }
+
+    let mut out = Vec::new();
+    for expr in conditions {
+        visit(expr, ctx, items, &mut out);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sighash_type_name, SighashTypes};
+    use crate::{
+        analyze_script, OwnedScript, ScriptAnalyzer, ScriptContext, ScriptRules, ScriptVersion,
+    };
+
+    #[test]
+    fn test_sighash_requirements() {
+        assert_eq!(sighash_type_name(0x82), "SIGHASH_NONE|SIGHASH_ANYONECANPAY");
+        assert_eq!(sighash_type_name(0x05), "0x05");
+
+        let key = format!(
+            "<{}>",
+            "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"
+        );
+        let tapscript = ScriptContext::new(ScriptVersion::SegwitV1, ScriptRules::All);
+        let mut buf = format!("OP_SIZE 64 OP_EQUALVERIFY {key} OP_CHECKSIG").into_bytes();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let analysis = analyze_script(&script, tapscript, Default::default()).unwrap();
+        assert_eq!(analysis.paths[0].sighash[0].types, SighashTypes::Default);
+
+        let mut buf = format!("{key} OP_CHECKSIG").into_bytes();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let analysis = analyze_script(&script, tapscript, Default::default()).unwrap();
+        assert_eq!(
+            analysis.paths[0].sighash[0].types,
+            SighashTypes::Standard { default: true }
+        );
+
+        // a constant signature that anyone can reuse for other outputs
+        let mut sig = vec![0x11; 64];
+        sig.push(0x82);
+        let analysis = ScriptAnalyzer::builder(&script)
+            .stack_item(0, crate::expr::Expr::bytes(&sig))
+            .build()
+            .analyze(tapscript, Default::default())
+            .unwrap();
+        let req = &analysis.paths[0].sighash[0];
+        assert_eq!(req.types, SighashTypes::Fixed(0x82));
+        assert!(req.warning().unwrap().starts_with("no outputs are signed"));
+    }
+}
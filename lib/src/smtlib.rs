@@ -0,0 +1,371 @@
+use crate::{
+    analyzer::{Analysis, SpendingPath},
+    expr::{Expr, OpExprArgs, Opcode1, Opcode2, Opcode3},
+    script::{
+        convert::{decode_bool, decode_int},
+        MAX_SCRIPT_ELEMENT_SIZE,
+    },
+};
+use std::{collections::BTreeSet, fmt::Write};
+
+/// Largest number that can be used as input for numeric opcodes.
+const INT_MAX: i64 = 0x7fffffff;
+
+/// Declarations every script starts with. Byte strings are an uninterpreted sort, `num` is their
+/// value as a script number and `truthy` their value as a boolean. Hash functions and signature
+/// checks are uninterpreted functions.
+const PRELUDE: &str = "(set-logic ALL)
+(declare-sort Bytes 0)
+(declare-fun num (Bytes) (_ BitVec 64))
+(declare-fun size (Bytes) (_ BitVec 64))
+(declare-fun truthy (Bytes) Bool)
+(declare-fun encode ((_ BitVec 64)) Bytes)
+(declare-fun ripemd160 (Bytes) Bytes)
+(declare-fun sha1 (Bytes) Bytes)
+(declare-fun sha256 (Bytes) Bytes)
+(declare-fun checksig (Bytes Bytes) Bool)
+";
+
+fn bv(n: i64) -> String {
+    if n < 0 {
+        format!("(bvneg (_ bv{} 64))", n.unsigned_abs())
+    } else {
+        format!("(_ bv{n} 64)")
+    }
+}
+
+fn constant_name(bytes: &[u8]) -> String {
+    let mut name = "b_".to_owned();
+    for b in bytes {
+        write!(name, "{b:02x}").unwrap();
+    }
+    name
+}
+
+/// Translates the expressions of one spending path, collecting the declarations and the
+/// assertions the terms rely on.
+#[derive(Default)]
+struct Translator {
+    items: BTreeSet<u32>,
+    constants: BTreeSet<Vec<u8>>,
+    /// Multisig functions by number of signatures and keys.
+    multisigs: BTreeSet<(usize, usize)>,
+    /// Facts about the terms, like the range of numeric inputs.
+    facts: BTreeSet<String>,
+}
+
+impl Translator {
+    /// The expression as a byte string.
+    fn bytes(&mut self, expr: &Expr) -> String {
+        match expr {
+            Expr::Stack(s) => {
+                self.items.insert(s.pos());
+                format!("item{}", s.pos())
+            }
+            Expr::Bytes(b) => {
+                self.constants.insert(b.as_ref().to_vec());
+                constant_name(b.as_ref())
+            }
+            Expr::Op(op) => match &op.args {
+                OpExprArgs::Args1(
+                    hash @ (Opcode1::OP_RIPEMD160 | Opcode1::OP_SHA1 | Opcode1::OP_SHA256),
+                    args,
+                ) => {
+                    let (name, len) = match hash {
+                        Opcode1::OP_RIPEMD160 => ("ripemd160", 20),
+                        Opcode1::OP_SHA1 => ("sha1", 20),
+                        _ => ("sha256", 32),
+                    };
+                    let term = format!("({name} {})", self.bytes(&args[0]));
+                    self.facts.insert(format!("(= (size {term}) {})", bv(len)));
+                    term
+                }
+                _ if op.opcode().returns_boolean() => {
+                    let cond = self.bool(expr);
+                    format!(
+                        "(ite {cond} {} {})",
+                        self.bytes(&Expr::bytes(&[1])),
+                        self.bytes(&Expr::bytes(&[]))
+                    )
+                }
+                _ => {
+                    // arithmetic results are minimally encoded numbers
+                    let value = self.num(expr);
+                    let term = format!("(encode {value})");
+                    self.facts.insert(format!("(= (num {term}) {value})"));
+                    self.facts
+                        .insert(format!("(bvule (size {term}) {})", bv(5)));
+                    term
+                }
+            },
+        }
+    }
+
+    /// The expression as a script number.
+    fn num(&mut self, expr: &Expr) -> String {
+        if let Expr::Bytes(b) = expr {
+            if let Ok(n) = decode_int(b, 4) {
+                return bv(n);
+            }
+        }
+        let Expr::Op(op) = expr else {
+            return self.decode(expr);
+        };
+        if op.opcode().returns_boolean() {
+            return format!("(ite {} {} {})", self.bool(expr), bv(1), bv(0));
+        }
+        match &op.args {
+            OpExprArgs::Args1(Opcode1::OP_SIZE, args) => format!("(size {})", self.bytes(&args[0])),
+            OpExprArgs::Args1(Opcode1::OP_ABS, args) => {
+                let a = self.num_input(&args[0]);
+                format!("(ite (bvslt {a} {}) (bvneg {a}) {a})", bv(0))
+            }
+            OpExprArgs::Args2(op @ (Opcode2::OP_ADD | Opcode2::OP_SUB), args) => {
+                let a = self.num_input(&args[0]);
+                let b = self.num_input(&args[1]);
+                let f = if *op == Opcode2::OP_ADD {
+                    "bvadd"
+                } else {
+                    "bvsub"
+                };
+                format!("({f} {a} {b})")
+            }
+            OpExprArgs::Args2(op @ (Opcode2::OP_MIN | Opcode2::OP_MAX), args) => {
+                let a = self.num_input(&args[0]);
+                let b = self.num_input(&args[1]);
+                let (lt, ge) = if *op == Opcode2::OP_MIN {
+                    (&a, &b)
+                } else {
+                    (&b, &a)
+                };
+                format!("(ite (bvslt {a} {b}) {lt} {ge})")
+            }
+            _ => self.decode(expr),
+        }
+    }
+
+    /// An argument of a numeric opcode, which has to be a number of at most 4 bytes.
+    fn num_input(&mut self, expr: &Expr) -> String {
+        if let Expr::Bytes(b) = expr {
+            if let Ok(n) = decode_int(b, 4) {
+                return bv(n);
+            }
+        }
+        if matches!(expr, Expr::Op(op) if op.opcode().returns_number()) {
+            let term = self.num(expr);
+            self.facts.insert(format!(
+                "(and (bvsle {} {term}) (bvsle {term} {}))",
+                bv(-INT_MAX),
+                bv(INT_MAX)
+            ));
+            return term;
+        }
+        self.decode(expr)
+    }
+
+    /// A byte string of at most 4 bytes as a number.
+    fn decode(&mut self, expr: &Expr) -> String {
+        let bytes = self.bytes(expr);
+        self.facts
+            .insert(format!("(bvule (size {bytes}) {})", bv(4)));
+        let term = format!("(num {bytes})");
+        self.facts.insert(format!(
+            "(and (bvsle {} {term}) (bvsle {term} {}))",
+            bv(-INT_MAX),
+            bv(INT_MAX)
+        ));
+        term
+    }
+
+    /// The expression as a boolean, true if the script would consider it true.
+    fn bool(&mut self, expr: &Expr) -> String {
+        let zero = bv(0);
+        let Expr::Op(op) = expr else {
+            if let Expr::Bytes(b) = expr {
+                return decode_bool(b).to_string();
+            }
+            let bytes = self.bytes(expr);
+            // a number is true if it is not zero
+            self.facts.insert(format!(
+                "(=> (bvule (size {bytes}) {}) (= (truthy {bytes}) (not (= (num {bytes}) {zero}))))",
+                bv(4)
+            ));
+            return format!("(truthy {bytes})");
+        };
+        match &op.args {
+            OpExprArgs::Args1(Opcode1::OP_NOT, args) => {
+                format!("(= {} {zero})", self.num_input(&args[0]))
+            }
+            OpExprArgs::Args1(Opcode1::OP_0NOTEQUAL, args) => {
+                format!("(not (= {} {zero}))", self.num_input(&args[0]))
+            }
+            OpExprArgs::Args1(Opcode1::OP_INTERNAL_NOT, args) => {
+                format!("(not {})", self.bool(&args[0]))
+            }
+            OpExprArgs::Args2(op, args) => {
+                let f = match op {
+                    Opcode2::OP_EQUAL => {
+                        return format!("(= {} {})", self.bytes(&args[0]), self.bytes(&args[1]))
+                    }
+                    Opcode2::OP_CHECKSIG => {
+                        return format!(
+                            "(checksig {} {})",
+                            self.bytes(&args[0]),
+                            self.bytes(&args[1])
+                        )
+                    }
+                    Opcode2::OP_BOOLAND | Opcode2::OP_BOOLOR => {
+                        let a = self.num_input(&args[0]);
+                        let b = self.num_input(&args[1]);
+                        let f = if *op == Opcode2::OP_BOOLAND {
+                            "and"
+                        } else {
+                            "or"
+                        };
+                        return format!("({f} (not (= {a} {zero})) (not (= {b} {zero})))");
+                    }
+                    Opcode2::OP_NUMEQUAL => "=",
+                    Opcode2::OP_NUMNOTEQUAL => "distinct",
+                    Opcode2::OP_LESSTHAN => "bvslt",
+                    Opcode2::OP_LESSTHANOREQUAL => "bvsle",
+                    _ => return format!("(not (= {} {zero}))", self.num(expr)),
+                };
+                let a = self.num_input(&args[0]);
+                let b = self.num_input(&args[1]);
+                format!("({f} {a} {b})")
+            }
+            OpExprArgs::Args3(Opcode3::OP_WITHIN, args) => {
+                let x = self.num_input(&args[0]);
+                let min = self.num_input(&args[1]);
+                let max = self.num_input(&args[2]);
+                format!("(and (bvsle {min} {x}) (bvslt {x} {max}))")
+            }
+            OpExprArgs::Multisig(m) => {
+                self.multisigs.insert((m.sigs().len(), m.keys().len()));
+                let mut term = format!("(checkmultisig_{}_{}", m.sigs().len(), m.keys().len());
+                for arg in m.sigs().iter().chain(m.keys()) {
+                    write!(term, " {}", self.bytes(arg)).unwrap();
+                }
+                term.push(')');
+                term
+            }
+            _ => format!("(not (= {} {zero}))", self.num(expr)),
+        }
+    }
+}
+
+impl SpendingPath {
+    /// Translates the spending conditions of this path into an SMT-LIB 2 script, for an SMT
+    /// solver like Z3 or cvc5. Script numbers are 64 bit bitvectors, hash functions and signature
+    /// checks are uninterpreted functions, so an unsatisfiable script proves that the path can
+    /// not be spent, but a model of a satisfiable one is not necessarily a valid witness.
+    /// Locktime requirements are not part of the script.
+    pub fn to_smtlib(&self) -> String {
+        let mut out = self.smtlib_assertions();
+        out.push_str("(check-sat)\n(get-model)\n");
+        out
+    }
+
+    /// The declarations and assertions of [`to_smtlib`](Self::to_smtlib), without commands.
+    pub(crate) fn smtlib_assertions(&self) -> String {
+        let mut translator = Translator::default();
+        translator.items.extend(0..self.stack_size);
+        let assertions: Vec<_> = self
+            .spending_conditions
+            .iter()
+            .map(|condition| (condition, translator.bool(condition)))
+            .collect();
+
+        let mut out = PRELUDE.to_owned();
+        for &(sigs, keys) in &translator.multisigs {
+            writeln!(
+                out,
+                "(declare-fun checkmultisig_{sigs}_{keys} ({}) Bool)",
+                vec!["Bytes"; sigs + keys].join(" ")
+            )
+            .unwrap();
+        }
+        for item in &translator.items {
+            writeln!(out, "(declare-const item{item} Bytes)").unwrap();
+            writeln!(
+                out,
+                "(assert (bvule (size item{item}) {}))",
+                bv(MAX_SCRIPT_ELEMENT_SIZE as i64)
+            )
+            .unwrap();
+        }
+        for bytes in &translator.constants {
+            let name = constant_name(bytes);
+            writeln!(out, "(declare-const {name} Bytes)").unwrap();
+            writeln!(out, "(assert (= (size {name}) {}))", bv(bytes.len() as i64)).unwrap();
+            writeln!(out, "(assert (= (truthy {name}) {}))", decode_bool(bytes)).unwrap();
+            if let Ok(n) = decode_int(bytes, 4) {
+                writeln!(out, "(assert (= (num {name}) {}))", bv(n)).unwrap();
+            }
+        }
+        if translator.constants.len() > 1 {
+            out.push_str("(assert (distinct");
+            for bytes in &translator.constants {
+                write!(out, " {}", constant_name(bytes)).unwrap();
+            }
+            out.push_str("))\n");
+        }
+        for fact in &translator.facts {
+            writeln!(out, "(assert {fact})").unwrap();
+        }
+        for (condition, assertion) in assertions {
+            writeln!(out, "; {condition}\n(assert {assertion})").unwrap();
+        }
+        out
+    }
+}
+
+impl Analysis {
+    /// The [SMT-LIB 2 scripts](SpendingPath::to_smtlib) of all spending paths, separated by
+    /// `(reset)`.
+    pub fn to_smtlib(&self) -> String {
+        let mut out = String::new();
+        for (i, path) in self.paths.iter().enumerate() {
+            if i != 0 {
+                out.push_str("(reset)\n");
+            }
+            writeln!(out, "; spending path #{}", i + 1).unwrap();
+            out.push_str(&path.to_smtlib());
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        analyze_script, AnalysisOptions, OwnedScript, ScriptContext, ScriptRules, ScriptVersion,
+    };
+
+    #[test]
+    fn test_smtlib() {
+        let digest = format!("aabb{}", "00".repeat(30));
+        let mut buf = format!(
+            "OP_2DUP OP_ADD 10 OP_LESSTHAN OP_VERIFY OP_DROP OP_SHA256 <{digest}> OP_EQUAL"
+        )
+        .into_bytes();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let ctx = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All);
+        let smt = analyze_script(&script, ctx, AnalysisOptions::default())
+            .unwrap()
+            .to_smtlib();
+
+        for line in smt.lines().filter(|line| !line.starts_with(';')) {
+            assert_eq!(
+                line.matches('(').count(),
+                line.matches(')').count(),
+                "{line}"
+            );
+        }
+        assert!(smt.contains("(declare-const item1 Bytes)"));
+        assert!(smt.contains(&format!("(assert (= (size b_{digest}) (_ bv32 64)))")));
+        assert!(smt.contains("(assert (bvslt (bvadd (num item0) (num item1)) (_ bv10 64)))"));
+        assert!(smt.contains(&format!("(assert (= (sha256 item1) b_{digest}))")));
+        assert!(smt.ends_with("(check-sat)\n(get-model)\n"));
+    }
+}
@@ -0,0 +1,244 @@
+use crate::{
+    analyzer::{cmp_branches, Analysis, FailedPath, SpendingPath},
+    script_error::ScriptError,
+};
+use std::{
+    fmt::Write as _,
+    io::{self, Write as _},
+    process::{Command, Stdio},
+};
+
+/// An external SMT solver that reads SMT-LIB 2 from stdin, see [`Analysis::solve`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Solver {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl Solver {
+    /// Z3, found in `PATH`.
+    pub fn z3() -> Self {
+        Self {
+            program: "z3".to_owned(),
+            args: vec!["-in".to_owned(), "-smt2".to_owned()],
+        }
+    }
+
+    /// cvc5, found in `PATH`.
+    pub fn cvc5() -> Self {
+        Self {
+            program: "cvc5".to_owned(),
+            args: vec!["--lang=smt2".to_owned(), "--produce-models".to_owned()],
+        }
+    }
+
+    /// Checks whether the spending conditions of `path` can all be true.
+    pub fn check(&self, path: &SpendingPath) -> io::Result<Satisfiability> {
+        let mut script = path.smtlib_assertions();
+        script.push_str("(check-sat)\n");
+        if path.stack_size != 0 {
+            script.push_str("(get-value (");
+            for item in 0..path.stack_size {
+                write!(script, "(size item{item}) (num item{item}) ").unwrap();
+            }
+            script.push_str("))\n");
+        }
+
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        child.stdin.take().unwrap().write_all(script.as_bytes())?;
+        let output = child.wait_with_output()?;
+        let output = String::from_utf8_lossy(&output.stdout);
+
+        let (result, values) = output
+            .trim_start()
+            .split_once('\n')
+            .unwrap_or((&output, ""));
+        match result.trim() {
+            "sat" => Ok(Satisfiability::Sat(Model::parse(values, path.stack_size)?)),
+            "unsat" => Ok(Satisfiability::Unsat),
+            "unknown" => Ok(Satisfiability::Unknown),
+            _ => Err(invalid_output(&output)),
+        }
+    }
+}
+
+fn invalid_output(output: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected solver output: {}", output.trim()),
+    )
+}
+
+/// Result of [`Solver::check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Satisfiability {
+    /// The conditions can be true, with example stack items.
+    Sat(Model),
+    /// The path can not be spent.
+    Unsat,
+    /// The solver gave up.
+    Unknown,
+}
+
+/// Example stack items that satisfy the spending conditions of a path. Hashes and signatures
+/// are not modeled, only sizes and numbers are known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Model {
+    pub items: Vec<ItemValue>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItemValue {
+    pub stack_item: u32,
+    pub size: usize,
+    /// The value as a number, if the item is small enough to be one. The solver may pick a
+    /// number that does not fit in `size` bytes for items that are not used as one, then this is
+    /// `None` as well.
+    pub number: Option<i64>,
+}
+
+impl Model {
+    /// Parses the response to `get-value` of the size and number of every stack item.
+    fn parse(values: &str, stack_size: u32) -> io::Result<Self> {
+        // bitvector literals in the order they were requested
+        let mut literals = values
+            .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+            .filter_map(|token| {
+                if let Some(hex) = token.strip_prefix("#x") {
+                    u64::from_str_radix(hex, 16).ok()
+                } else if let Some(bin) = token.strip_prefix("#b") {
+                    u64::from_str_radix(bin, 2).ok()
+                } else {
+                    token.strip_prefix("bv")?.parse().ok()
+                }
+            });
+
+        let items = (0..stack_size)
+            .map(|stack_item| {
+                let (Some(size), Some(number)) = (literals.next(), literals.next()) else {
+                    return Err(invalid_output(values));
+                };
+                let size = size as usize;
+                let number = number as i64;
+                // the sign takes a bit of a number's encoding
+                let in_range = match size {
+                    0 => number == 0,
+                    1..=4 => number.unsigned_abs() < 1 << (8 * size - 1),
+                    _ => false,
+                };
+                Ok(ItemValue {
+                    stack_item,
+                    size,
+                    number: in_range.then_some(number),
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self { items })
+    }
+}
+
+impl Analysis {
+    /// Checks every spending path with `solver`. Paths that are proven unsatisfiable are moved
+    /// to [`failed_paths`](Self::failed_paths), the results of the remaining paths are returned
+    /// in the same order, `None` if the solver gave up.
+    pub fn solve(&mut self, solver: &Solver) -> io::Result<Vec<Option<Model>>> {
+        let mut models = Vec::new();
+        let mut paths = Vec::new();
+        for path in self.paths.drain(..) {
+            match solver.check(&path)? {
+                Satisfiability::Sat(model) => {
+                    paths.push(path);
+                    models.push(Some(model));
+                }
+                Satisfiability::Unknown => {
+                    paths.push(path);
+                    models.push(None);
+                }
+                Satisfiability::Unsat => self.failed_paths.push(FailedPath {
+                    branches: path.branches,
+                    // like contradictions found by the analyzer itself, see `FailedPath::error`
+                    error: ScriptError::SCRIPT_ERR_UNKNOWN_ERROR,
+                }),
+            }
+        }
+        self.paths = paths;
+        self.failed_paths
+            .sort_by(|a, b| cmp_branches(&a.branches, &b.branches));
+        Ok(models)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ItemValue, Model, Solver};
+    use crate::{
+        analyze_script, AnalysisOptions, OwnedScript, ScriptContext, ScriptRules, ScriptVersion,
+    };
+
+    #[test]
+    fn test_parse_model() {
+        let z3 = "(((size item0) #x0000000000000001)\n ((num item0) #x0000000000000005)\n \
+                  ((size item1) #x0000000000000021)\n ((num item1) #x0000000000000000))";
+        let cvc5 = "(((size item0) (_ bv1 64)) ((num item0) (_ bv5 64)) ((size item1) (_ bv33 \
+                    64)) ((num item1) (_ bv0 64)))";
+        for output in [z3, cvc5] {
+            let model = Model::parse(output, 2).unwrap();
+            assert_eq!(
+                model.items,
+                [
+                    ItemValue {
+                        stack_item: 0,
+                        size: 1,
+                        number: Some(5)
+                    },
+                    ItemValue {
+                        stack_item: 1,
+                        size: 33,
+                        number: None
+                    }
+                ]
+            );
+        }
+        assert!(Model::parse("()", 1).is_err());
+
+        // numbers that do and do not fit in the size of the item
+        let output = "(((size item0) #x0000000000000000) ((num item0) #x0000000000000000) \
+                      ((size item1) #x0000000000000001) ((num item1) #xffffffffffffff81) \
+                      ((size item2) #x0000000000000001) ((num item2) #x0000000000000080) \
+                      ((size item3) #x0000000000000002) ((num item3) #x0000000000000080) \
+                      ((size item4) #x0000000000000000) ((num item4) #x0000000000000001))";
+        let numbers: Vec<_> = Model::parse(output, 5)
+            .unwrap()
+            .items
+            .iter()
+            .map(|item| item.number)
+            .collect();
+        assert_eq!(numbers, [Some(0), Some(-127), None, Some(128), None]);
+    }
+
+    #[test]
+    fn test_solve() {
+        let mut buf = b"OP_IF OP_2DUP OP_LESSTHAN OP_VERIFY OP_ENDIF OP_DROP".to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let ctx = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All);
+        let mut analysis = analyze_script(&script, ctx, AnalysisOptions::default()).unwrap();
+        assert_eq!(analysis.paths.len(), 2);
+
+        // stands in for a solver, proves the path with the comparison unsatisfiable
+        let solver = Solver {
+            program: "sh".to_owned(),
+            args: vec![
+                "-c".to_owned(),
+                "if grep -q bvslt; then echo unsat; else echo unknown; fi".to_owned(),
+            ],
+        };
+        let models = analysis.solve(&solver).unwrap();
+        assert_eq!(models, [None]);
+        assert_eq!(analysis.paths.len(), 1);
+        assert_eq!(analysis.failed_paths.len(), 1);
+    }
+}
@@ -0,0 +1,53 @@
+use crate::script_error::ScriptError;
+use core::{fmt, time::Duration};
+
+/// Counters of an analysis run, to find out why a script is slow to analyze. See
+/// [`Analysis::stats`](crate::Analysis::stats).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnalysisStats {
+    /// Paths analyzed to the end, including failed ones.
+    pub paths_explored: usize,
+    /// Failed paths per error, most common first.
+    pub paths_pruned: Vec<(ScriptError, usize)>,
+    /// Amount of times execution forked on an unknown value.
+    pub forks: usize,
+    /// Amount of spending conditions that were rewritten or removed while simplifying.
+    pub simplifications: usize,
+    /// Highest amount of items on the stack of any path.
+    pub peak_stack_depth: usize,
+    pub wall_time: Duration,
+}
+
+impl AnalysisStats {
+    /// Total amount of failed paths.
+    pub fn pruned(&self) -> usize {
+        self.paths_pruned.iter().map(|&(_, n)| n).sum()
+    }
+
+    pub(crate) fn count_pruned(&mut self, error: ScriptError) {
+        match self.paths_pruned.iter_mut().find(|(e, _)| *e == error) {
+            Some((_, n)) => *n += 1,
+            None => self.paths_pruned.push((error, 1)),
+        }
+    }
+
+    pub(crate) fn sort_pruned(&mut self) {
+        // stable, errors with the same count stay in the order they first occurred
+        self.paths_pruned.sort_by(|(_, a), (_, b)| b.cmp(a));
+    }
+}
+
+impl fmt::Display for AnalysisStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Paths explored: {}", self.paths_explored)?;
+        write!(f, "Paths pruned: {}", self.pruned())?;
+        for (error, n) in &self.paths_pruned {
+            write!(f, "\n  {error}: {n}")?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Forks: {}", self.forks)?;
+        writeln!(f, "Simplifications: {}", self.simplifications)?;
+        writeln!(f, "Peak stack depth: {}", self.peak_stack_depth)?;
+        write!(f, "Wall time: {:?}", self.wall_time)
+    }
+}
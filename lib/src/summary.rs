@@ -0,0 +1,263 @@
+use crate::{
+    analyzer::SpendingPath,
+    expr::{BytesExprBox, Expr, OpExprArgs, Opcode1, Opcode2},
+    hashlock::PreimageRequirement,
+    threshold::ThresholdMultisig,
+    util::locktime::locktime_to_string,
+};
+use core::fmt;
+
+/// Aggregate over all spending paths of a script, see [`Analysis::summary`].
+///
+/// [`Analysis::summary`]: crate::Analysis::summary
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary {
+    /// Lowest number of signatures any path requires.
+    pub min_signatures: u32,
+    /// Earliest possible spend, `None` if a path has no timelock.
+    pub earliest_spend: Option<EarliestSpend>,
+    /// Whether a path can be spent with hash preimages only, without signatures.
+    pub hash_only: bool,
+    /// Public keys that must sign in every path.
+    pub common_keys: Vec<BytesExprBox>,
+}
+
+/// Timelocks of the path that can be spent first. Paths are compared by their known minimum
+/// locktime, then by their known minimum sequence. Values are compared numerically, so block
+/// heights come before times.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EarliestSpend {
+    pub locktime: Option<u32>,
+    pub sequence: Option<u32>,
+    /// The path also has timelocks on stack items without a known minimum, these may allow an
+    /// earlier spend than `locktime` and `sequence` say.
+    pub unknown_values: bool,
+}
+
+impl EarliestSpend {
+    fn new(path: &SpendingPath) -> Self {
+        Self {
+            locktime: path.locktime_req.req,
+            sequence: path.sequence_req.req,
+            unknown_values: !path.locktime_req.exprs.is_empty()
+                || !path.sequence_req.exprs.is_empty(),
+        }
+    }
+}
+
+impl Summary {
+    pub fn new(paths: &[SpendingPath]) -> Self {
+        let signers: Vec<_> = paths
+            .iter()
+            .map(|p| signers(&p.spending_conditions))
+            .collect();
+
+        let min_signatures = signers.iter().map(|s| s.count).min().unwrap_or_default();
+
+        let timelock_free = paths.iter().any(|p| {
+            p.locktime_req.req.is_none()
+                && p.locktime_req.exprs.is_empty()
+                && p.sequence_req.req.is_none()
+                && p.sequence_req.exprs.is_empty()
+        });
+        // the locktime and sequence of different paths can not be combined
+        let earliest_spend = if timelock_free {
+            None
+        } else {
+            paths.iter().map(EarliestSpend::new).min_by_key(|e| {
+                (
+                    e.locktime.unwrap_or(0),
+                    e.sequence.unwrap_or(0),
+                    e.unknown_values,
+                )
+            })
+        };
+
+        let hash_only = paths.iter().zip(&signers).any(|(p, s)| {
+            s.count == 0
+                && p.spending_conditions.iter().any(|c| {
+                    PreimageRequirement::from_condition(c, &p.spending_conditions).is_some()
+                })
+        });
+
+        let mut common_keys = signers.first().map_or_else(Vec::new, |s| s.keys.clone());
+        common_keys.retain(|key| signers.iter().all(|s| s.keys.contains(key)));
+        common_keys.sort_unstable_by(|a, b| a[..].cmp(&b[..]));
+        common_keys.dedup();
+
+        Self {
+            min_signatures,
+            earliest_spend,
+            hash_only,
+            common_keys,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Signers {
+    count: u32,
+    /// Constant keys of signature checks that must succeed.
+    keys: Vec<BytesExprBox>,
+}
+
+fn signers(conditions: &[Expr]) -> Signers {
+    fn visit(expr: &Expr, signers: &mut Signers) {
+        if let Some(threshold) = ThresholdMultisig::from_condition(expr) {
+            signers.count += threshold.required;
+            if threshold.required as usize == threshold.keys.len() {
+                signers
+                    .keys
+                    .extend(threshold.keys.iter().filter_map(|k| match k {
+                        Expr::Bytes(key) => Some(key.clone()),
+                        _ => None,
+                    }));
+            }
+            return;
+        }
+        let Expr::Op(op) = expr else {
+            return;
+        };
+        match &op.args {
+            // a failing signature check does not need a signature
+            OpExprArgs::Args1(Opcode1::OP_NOT | Opcode1::OP_INTERNAL_NOT, _) => return,
+            OpExprArgs::Args2(Opcode2::OP_CHECKSIG, args) => {
+                signers.count += 1;
+                if let Expr::Bytes(key) = &args[1] {
+                    signers.keys.push(key.clone());
+                }
+            }
+            OpExprArgs::Multisig(m) => {
+                signers.count += m.sigs().len() as u32;
+                if m.sigs().len() == m.keys().len() {
+                    // every key has to sign
+                    signers.keys.extend(m.keys().iter().filter_map(|k| match k {
+                        Expr::Bytes(key) => Some(key.clone()),
+                        _ => None,
+                    }));
+                }
+            }
+            _ => {}
+        }
+        for arg in op.args() {
+            visit(arg, signers);
+        }
+    }
+
+    let mut signers = Signers::default();
+    for c in conditions {
+        visit(c, &mut signers);
+    }
+    signers
+}
+
+impl fmt::Display for EarliestSpend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.locktime, self.sequence) {
+            (Some(locktime), Some(sequence)) => write!(
+                f,
+                "locktime {}, sequence {}",
+                locktime_to_string(locktime, false),
+                locktime_to_string(sequence, true)
+            ),
+            (Some(locktime), None) => {
+                write!(f, "locktime {}", locktime_to_string(locktime, false))
+            }
+            (None, Some(sequence)) => {
+                write!(f, "sequence {}", locktime_to_string(sequence, true))
+            }
+            (None, None) => return write!(f, "unknown"),
+        }?;
+        if self.unknown_values {
+            write!(f, " (or earlier, depending on stack items)")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Minimum signatures: {}", self.min_signatures)?;
+        match &self.earliest_spend {
+            Some(earliest) => writeln!(f, "Earliest spend: {earliest}")?,
+            None => writeln!(f, "Earliest spend: immediately")?,
+        }
+        writeln!(
+            f,
+            "Hash-only path: {}",
+            if self.hash_only { "yes" } else { "no" }
+        )?;
+        write!(f, "Keys in every path:")?;
+        if self.common_keys.is_empty() {
+            write!(f, " none")?;
+        }
+        for key in &self.common_keys {
+            write!(f, "\n{key}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EarliestSpend, Summary};
+    use crate::{OwnedScript, ScriptContext, ScriptRules, ScriptVersion};
+
+    fn summarize(asm: &str) -> Summary {
+        let ctx = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All);
+        let mut buf = asm.as_bytes().to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        crate::analyze_script(&script, ctx, Default::default())
+            .unwrap()
+            .summary()
+    }
+
+    #[test]
+    fn test_summary() {
+        let ctx = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All);
+        // x coordinates of points on the curve
+        let key_a = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        let key_b = "03c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+        let digest = "ab".repeat(32);
+        let asm = format!(
+            "<{key_a}> OP_CHECKSIGVERIFY OP_IF <{key_b}> OP_CHECKSIG OP_ELSE \
+            OP_SHA256 <{digest}> OP_EQUALVERIFY 144 OP_CHECKSEQUENCEVERIFY OP_ENDIF"
+        );
+        let mut buf = asm.into_bytes();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let summary = crate::analyze_script(&script, ctx, Default::default())
+            .unwrap()
+            .summary();
+
+        assert_eq!(summary.min_signatures, 1);
+        assert!(summary.earliest_spend.is_none());
+        assert!(!summary.hash_only);
+        assert_eq!(summary.common_keys.len(), 1);
+        assert_eq!(summary.common_keys[0].to_string(), format!("<{key_a}>"));
+    }
+
+    #[test]
+    fn test_earliest_spend() {
+        // the lowest locktime and the lowest sequence are on different paths
+        let summary = summarize(
+            "OP_IF 500 OP_CHECKLOCKTIMEVERIFY OP_DROP 10 OP_CHECKSEQUENCEVERIFY OP_ELSE \
+            100 OP_CHECKLOCKTIMEVERIFY OP_DROP 144 OP_CHECKSEQUENCEVERIFY OP_ENDIF",
+        );
+        assert_eq!(
+            summary.earliest_spend,
+            Some(EarliestSpend {
+                locktime: Some(100),
+                sequence: Some(144),
+                unknown_values: false,
+            })
+        );
+
+        // a locktime chosen by the spender can be lower than 100
+        let summary =
+            summarize("OP_IF 100 OP_CHECKLOCKTIMEVERIFY OP_ELSE OP_CHECKLOCKTIMEVERIFY OP_ENDIF");
+        let earliest = summary.earliest_spend.unwrap();
+        assert_eq!(earliest.locktime, None);
+        assert!(earliest.unknown_values);
+        assert_eq!(earliest.to_string(), "unknown");
+    }
+}
@@ -1,42 +1,318 @@
 #[cfg(feature = "threads")]
+use core::fmt;
+#[cfg(feature = "threads")]
 use std::{
-    sync::{
-        mpsc::{channel, Sender},
-        Arc, Mutex,
-    },
-    thread::Scope,
+    any::Any,
+    cell::Cell,
+    collections::VecDeque,
+    marker::PhantomData,
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+    sync::{Arc, Condvar, Mutex, OnceLock},
+    thread::{self, JoinHandle},
+    time::Duration,
 };
 
+#[cfg(feature = "threads")]
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+#[cfg(feature = "threads")]
+struct Shared {
+    /// Jobs submitted from outside the pool.
+    injector: Mutex<VecDeque<Job>>,
+    /// Jobs submitted by each worker. A worker takes its newest job first, idle workers steal the
+    /// oldest jobs of other workers.
+    local: Box<[Mutex<VecDeque<Job>>]>,
+    /// Amount of queued jobs and whether the pool is shutting down.
+    state: Mutex<(usize, bool)>,
+    work_available: Condvar,
+}
+
+#[cfg(feature = "threads")]
+thread_local! {
+    /// The pool and index of the worker running on this thread.
+    static WORKER: Cell<Option<(*const Shared, usize)>> = const { Cell::new(None) };
+}
+
+#[cfg(feature = "threads")]
+impl Shared {
+    /// Index of the worker running on this thread, if it is one of this pool.
+    fn current_worker(&self) -> Option<usize> {
+        WORKER
+            .get()
+            .filter(|&(pool, _)| core::ptr::eq(pool, self))
+            .map(|(_, i)| i)
+    }
+
+    fn push(&self, job: Job) {
+        // counted before it is queued, it can be taken (and uncounted) right after
+        self.state.lock().unwrap().0 += 1;
+
+        match self.current_worker() {
+            Some(i) => self.local[i].lock().unwrap().push_back(job),
+            None => self.injector.lock().unwrap().push_back(job),
+        }
+
+        self.work_available.notify_one();
+    }
+
+    /// Takes a job, `worker` is `None` for threads outside the pool.
+    fn take(&self, worker: Option<usize>) -> Option<Job> {
+        let own = worker.and_then(|i| self.local[i].lock().unwrap().pop_back());
+        let first_victim = worker.map_or(0, |i| i + 1);
+        let job = own
+            .or_else(|| self.injector.lock().unwrap().pop_front())
+            .or_else(|| {
+                (0..self.local.len()).find_map(|i| {
+                    let victim = (first_victim + i) % self.local.len();
+                    self.local[victim].lock().unwrap().pop_front()
+                })
+            })?;
+
+        self.state.lock().unwrap().0 -= 1;
+        Some(job)
+    }
+
+    fn run_worker(&self, worker: usize) {
+        WORKER.set(Some((self, worker)));
+        loop {
+            if let Some(job) = self.take(Some(worker)) {
+                job();
+                continue;
+            }
+
+            let mut state = self.state.lock().unwrap();
+            while state.0 == 0 && !state.1 {
+                state = self.work_available.wait(state).unwrap();
+            }
+            if state.1 {
+                break;
+            }
+        }
+    }
+}
+
+/// A work-stealing thread pool. The pool can be reused for many analyses, see
+/// [`AnalysisOptions::pool`](crate::AnalysisOptions::pool).
+#[cfg(feature = "threads")]
+pub struct ThreadPool {
+    shared: Arc<Shared>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+#[cfg(feature = "threads")]
+impl ThreadPool {
+    /// Creates a pool with `worker_threads` threads. Without threads, jobs run on the thread that
+    /// submits them.
+    pub fn new(worker_threads: usize) -> Self {
+        let shared = Arc::new(Shared {
+            injector: Mutex::new(VecDeque::new()),
+            local: (0..worker_threads)
+                .map(|_| Mutex::new(VecDeque::new()))
+                .collect(),
+            state: Mutex::new((0, false)),
+            work_available: Condvar::new(),
+        });
+
+        let threads = (0..worker_threads)
+            .map(|i| {
+                let shared = shared.clone();
+                thread::spawn(move || shared.run_worker(i))
+            })
+            .collect();
+
+        Self { shared, threads }
+    }
+
+    /// A pool shared by the whole process, with a thread for every CPU.
+    pub fn global() -> &'static Self {
+        static POOL: OnceLock<ThreadPool> = OnceLock::new();
+        POOL.get_or_init(|| Self::new(thread::available_parallelism().map_or(1, |n| n.get())))
+    }
+
+    pub fn worker_threads(&self) -> usize {
+        self.threads.len()
+    }
+
+    /// Runs `f`, jobs spawned on the [`PoolScope`] may borrow anything that outlives the call.
+    /// Returns after all jobs are done.
+    pub(crate) fn scope<'env, R>(&'env self, f: impl FnOnce(&PoolScope<'env>) -> R) -> R {
+        let scope = PoolScope {
+            pool: self,
+            state: Arc::new(ScopeState {
+                pending: Mutex::new(0),
+                done: Condvar::new(),
+                panic: Mutex::new(None),
+            }),
+            _env: PhantomData,
+        };
+
+        let res = catch_unwind(AssertUnwindSafe(|| f(&scope)));
+
+        // jobs borrow from the environment, they have to be done before returning (or unwinding).
+        // scopes can be nested in jobs, so help with other jobs instead of blocking a worker
+        let worker = self.shared.current_worker();
+        loop {
+            if *scope.state.pending.lock().unwrap() == 0 {
+                break;
+            }
+            if let Some(job) = self.shared.take(worker) {
+                job();
+                continue;
+            }
+            let pending = scope.state.pending.lock().unwrap();
+            if *pending == 0 {
+                break;
+            }
+            // new jobs are not signaled on `done`, check for them every now and then
+            let _ = scope
+                .state
+                .done
+                .wait_timeout(pending, Duration::from_millis(1))
+                .unwrap();
+        }
+
+        if let Some(panic) = scope.state.panic.lock().unwrap().take() {
+            resume_unwind(panic);
+        }
+        res.unwrap_or_else(|panic| resume_unwind(panic))
+    }
+
+    /// Calls `f` on every item, the items are split in a chunk per worker. Returns after all calls.
+    pub(crate) fn for_each_mut<T: Send>(&self, items: &mut [T], f: impl Fn(&mut T) + Sync) {
+        let chunk_size = items.len().div_ceil(self.worker_threads().max(1)).max(1);
+        self.scope(|scope| {
+            for chunk in items.chunks_mut(chunk_size) {
+                let f = &f;
+                scope.spawn(move || chunk.iter_mut().for_each(f));
+            }
+        });
+    }
+}
+
+/// Stands in for the pool if the `threads` feature is disabled, so `Option<&ThreadPool>` is
+/// always `None`.
+#[cfg(not(feature = "threads"))]
+pub(crate) enum ThreadPool {}
+
+#[cfg(not(feature = "threads"))]
+impl ThreadPool {
+    pub fn worker_threads(&self) -> usize {
+        match *self {}
+    }
+
+    pub(crate) fn for_each_mut<T>(&self, _items: &mut [T], _f: impl Fn(&mut T)) {
+        match *self {}
+    }
+}
+
+#[cfg(feature = "threads")]
+impl fmt::Debug for ThreadPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ThreadPool")
+            .field("worker_threads", &self.worker_threads())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "threads")]
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().1 = true;
+        self.shared.work_available.notify_all();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(feature = "threads")]
+struct ScopeState {
+    pending: Mutex<usize>,
+    done: Condvar,
+    panic: Mutex<Option<Box<dyn Any + Send>>>,
+}
+
+/// Handle to spawn jobs that borrow from the environment, see [`ThreadPool::scope`].
 #[cfg(feature = "threads")]
 #[derive(Clone)]
-pub struct ThreadPool<'a> {
-    sender: Sender<Box<dyn FnOnce() + Send + 'a>>,
-}
-
-#[cfg(feature = "threads")]
-impl<'a> ThreadPool<'a> {
-    pub fn new(scope: &'a Scope<'a, '_>, worker_threads: usize) -> Self {
-        let (sender, receiver) = channel::<Box<dyn FnOnce() + Send + 'a>>();
-        let receiver = Arc::new(Mutex::new(receiver));
-        for _ in 0..worker_threads {
-            let receiver = receiver.clone();
-
-            scope.spawn(move || {
-                while let Ok(f) = {
-                    let guard = receiver.lock().unwrap();
-                    let f = guard.recv();
-                    drop(guard);
-                    f
-                } {
-                    f();
-                }
-            });
+pub(crate) struct PoolScope<'env> {
+    pool: &'env ThreadPool,
+    state: Arc<ScopeState>,
+    /// Invariant, so the scope can not be used for jobs with a shorter lifetime.
+    _env: PhantomData<&'env mut &'env ()>,
+}
+
+#[cfg(feature = "threads")]
+impl<'env> PoolScope<'env> {
+    pub fn has_workers(&self) -> bool {
+        self.pool.worker_threads() != 0
+    }
+
+    pub fn spawn<F: FnOnce() + Send + 'env>(&self, job: F) {
+        if !self.has_workers() {
+            job();
+            return;
         }
 
-        Self { sender }
+        *self.state.pending.lock().unwrap() += 1;
+        let state = self.state.clone();
+        let job: Box<dyn FnOnce() + Send + 'env> = Box::new(move || {
+            if let Err(panic) = catch_unwind(AssertUnwindSafe(job)) {
+                state.panic.lock().unwrap().get_or_insert(panic);
+            }
+            let mut pending = state.pending.lock().unwrap();
+            *pending -= 1;
+            if *pending == 0 {
+                state.done.notify_all();
+            }
+        });
+        // SAFETY: `ThreadPool::scope` does not return before all jobs of the scope are done, so
+        // nothing the job borrows is dropped while it runs
+        let job: Job = unsafe { core::mem::transmute(job) };
+        self.pool.shared.push(job);
     }
+}
 
-    pub fn submit_job<F: FnOnce() + Send + 'a>(&self, job: F) {
-        self.sender.send(Box::new(job)).unwrap();
+#[cfg(all(test, feature = "threads"))]
+mod tests {
+    use super::ThreadPool;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_nested_scope() {
+        let pool = ThreadPool::new(2);
+        let mut items = vec![0; 8];
+        pool.scope(|scope| {
+            for item in &mut items {
+                let pool = &pool;
+                scope.spawn(move || {
+                    let mut parts = [1; 4];
+                    pool.for_each_mut(&mut parts, |p| *p *= 2);
+                    *item = parts.iter().sum();
+                });
+            }
+        });
+        assert_eq!(items, [8; 8]);
+    }
+
+    #[test]
+    fn test_scope() {
+        let pool = ThreadPool::new(4);
+        let count = AtomicUsize::new(0);
+        for _ in 0..2 {
+            pool.scope(|scope| {
+                for _ in 0..10 {
+                    let scope_ = scope.clone();
+                    let count = &count;
+                    scope.spawn(move || {
+                        count.fetch_add(1, Ordering::Relaxed);
+                        scope_.spawn(move || {
+                            count.fetch_add(1, Ordering::Relaxed);
+                        });
+                    });
+                }
+            });
+        }
+        assert_eq!(count.load(Ordering::Relaxed), 40);
     }
 }
@@ -0,0 +1,146 @@
+use crate::{
+    expr::{Expr, ExprFormat, OpExprArgs, Opcode2},
+    script::convert::decode_int,
+};
+use core::fmt;
+
+/// A tapscript threshold multisig: `<pk1> OP_CHECKSIG <pk2> OP_CHECKSIGADD .. <k> OP_NUMEQUAL`,
+/// which becomes the spending condition `OP_NUMEQUAL(OP_ADD(..OP_CHECKSIG(..)..), <k>)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThresholdMultisig {
+    /// Amount of keys that must sign.
+    pub required: u32,
+    /// Every key has its own signature, signatures of keys that do not sign must be empty.
+    pub sigs: Vec<Expr>,
+    pub keys: Vec<Expr>,
+}
+
+impl ThresholdMultisig {
+    /// Detects a threshold multisig in `condition`.
+    pub fn from_condition(condition: &Expr) -> Option<Self> {
+        let Expr::Op(op) = condition else {
+            return None;
+        };
+        let OpExprArgs::Args2(Opcode2::OP_NUMEQUAL, args) = &op.args else {
+            return None;
+        };
+        let (sum, required) = [(&args[0], &args[1]), (&args[1], &args[0])]
+            .into_iter()
+            .find_map(|(sum, required)| match required {
+                Expr::Bytes(required) => Some((sum, decode_int(required, 4).ok()?)),
+                _ => None,
+            })?;
+
+        let mut sigs = Vec::new();
+        let mut keys = Vec::new();
+        if !checksigs(sum, &mut sigs, &mut keys) || keys.len() < 2 {
+            return None;
+        }
+        if !(1..=keys.len() as i64).contains(&required) {
+            return None;
+        }
+
+        Some(Self {
+            required: required as u32,
+            sigs,
+            keys,
+        })
+    }
+
+    pub fn display<'a>(&'a self, format: ExprFormat<'a>) -> ThresholdDisplay<'a> {
+        ThresholdDisplay { req: self, format }
+    }
+}
+
+/// Collects the arguments of the `OP_CHECKSIG`s added together in `expr`, returns false if
+/// anything else is added.
+fn checksigs(expr: &Expr, sigs: &mut Vec<Expr>, keys: &mut Vec<Expr>) -> bool {
+    let Expr::Op(op) = expr else {
+        return false;
+    };
+    match &op.args {
+        OpExprArgs::Args2(Opcode2::OP_ADD, args) => {
+            checksigs(&args[0], sigs, keys) && checksigs(&args[1], sigs, keys)
+        }
+        OpExprArgs::Args2(Opcode2::OP_CHECKSIG, args) => {
+            sigs.push(args[0].clone());
+            keys.push(args[1].clone());
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Returned by [`ThresholdMultisig::display`].
+pub struct ThresholdDisplay<'a> {
+    req: &'a ThresholdMultisig,
+    format: ExprFormat<'a>,
+}
+
+impl fmt::Display for ThresholdDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let req = self.req;
+        write!(f, "{}-of-{} multisig over [", req.required, req.keys.len())?;
+        for (i, key) in req.keys.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", key.display(self.format))?;
+        }
+        write!(f, "], signed by ")?;
+        for (i, sig) in req.sigs.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", sig.display(self.format))?;
+        }
+        write!(f, " in the same order")?;
+        if req.required as usize != req.keys.len() {
+            write!(f, ", the signatures of other keys must be empty")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ThresholdMultisig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display(ExprFormat::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ThresholdMultisig;
+    use crate::{analyze_script, OwnedScript, ScriptContext, ScriptRules, ScriptVersion};
+
+    #[test]
+    fn test_threshold_multisig() {
+        // x coordinates of points on the curve
+        let key = |x: &str| format!("<{x}>");
+        let asm = format!(
+            "{} OP_CHECKSIG {} OP_CHECKSIGADD {} OP_CHECKSIGADD 2 OP_NUMEQUAL",
+            key("79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"),
+            key("c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5"),
+            key("f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9")
+        );
+        let mut buf = asm.into_bytes();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let ctx = ScriptContext::new(ScriptVersion::SegwitV1, ScriptRules::All);
+        let analysis = analyze_script(&script, ctx, Default::default()).unwrap();
+
+        let conditions = &analysis.paths[0].spending_conditions;
+        let req = ThresholdMultisig::from_condition(&conditions[0]).unwrap();
+        assert_eq!((req.required, req.keys.len()), (2, 3));
+        assert_eq!(
+            req.to_string(),
+            format!(
+                "2-of-3 multisig over [{}, {}, {}], signed by <stack item #0>, <stack item #1>, \
+                 <stack item #2> in the same order, the signatures of other keys must be empty",
+                key("79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"),
+                key("c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5"),
+                key("f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9")
+            )
+        );
+        assert_eq!(analysis.summary().min_signatures, 2);
+    }
+}
@@ -0,0 +1,341 @@
+use crate::{
+    analyzer::{Analysis, SpendingPath},
+    util::locktime::{
+        approximate_duration_to_string, locktime_to_string, locktime_type_equals, LocktimeType,
+        SEQUENCE_LOCKTIME_DISABLE_FLAG, SEQUENCE_LOCKTIME_MASK, SEQUENCE_LOCKTIME_TYPE_FLAG,
+    },
+};
+use core::fmt;
+
+const SEQUENCE_FINAL: u32 = 0xffffffff;
+
+/// The fields of the spending transaction that timelocks are checked against, see
+/// [`SpendingPath::check_timelocks`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxTimelocks {
+    pub version: u32,
+    /// nLockTime of the transaction.
+    pub lock_time: u32,
+    /// nSequence of the input spending the script.
+    pub sequence: u32,
+}
+
+impl Default for TxTimelocks {
+    /// Version 2, no locktime and a final sequence, like a transaction that does not use
+    /// timelocks.
+    fn default() -> Self {
+        Self {
+            version: 2,
+            lock_time: 0,
+            sequence: SEQUENCE_FINAL,
+        }
+    }
+}
+
+/// Why a transaction does not satisfy the timelocks of a spending path, returned by
+/// [`SpendingPath::check_timelocks`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimelockMismatch {
+    /// The locktime is lower than `required` or a time where a height is required, or the other
+    /// way around.
+    LockTime { required: u32 },
+    /// `OP_CHECKLOCKTIMEVERIFY` fails on inputs with a final sequence (0xffffffff).
+    FinalSequence,
+    /// Sequence requirements only apply to transactions with version 2 or higher.
+    Version,
+    /// `OP_CHECKSEQUENCEVERIFY` fails on inputs with the disable flag set in their sequence.
+    SequenceDisabled,
+    /// The relative locktime of the sequence is lower than `required` or a time where a height is
+    /// required, or the other way around.
+    Sequence { required: u32 },
+}
+
+impl fmt::Display for TimelockMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LockTime { required } => {
+                let type_ = match LocktimeType::new(*required, false) {
+                    LocktimeType::Height => "block height",
+                    LocktimeType::Time => "time",
+                };
+                write!(
+                    f,
+                    "locktime must be a {type_} of at least {required} (spendable {})",
+                    locktime_to_string(*required, false)
+                )
+            }
+            Self::FinalSequence => write!(f, "sequence must be non-final (not 0xffffffff)"),
+            Self::Version => write!(f, "transaction version must be 2 or higher"),
+            Self::SequenceDisabled => {
+                write!(f, "sequence must have the disable flag (1 << 31) unset")
+            }
+            Self::Sequence { required } => {
+                let type_ = match LocktimeType::new(*required, true) {
+                    LocktimeType::Height => "block",
+                    LocktimeType::Time => "time",
+                };
+                write!(
+                    f,
+                    "sequence must be a relative {type_} lock of at least {} (spendable {})",
+                    required & SEQUENCE_LOCKTIME_MASK,
+                    locktime_to_string(*required, true)
+                )
+            }
+        }
+    }
+}
+
+/// The chain the spending transaction is mined on, see [`SpendingPath::spendable_at`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainTip {
+    pub height: u32,
+    pub median_time_past: u32,
+    /// Height of the block that confirmed the spent output and the median time past of the block
+    /// before it, relative timelocks start there. If `None`, the output is assumed to confirm in
+    /// the next block.
+    pub confirmed_at: Option<(u32, u32)>,
+}
+
+/// When a spending path can be spent, returned by [`SpendingPath::spendable_at`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpendableAt {
+    /// Blocks to wait for height based timelocks, 0 if the next block can include the spend.
+    pub blocks: u32,
+    /// Seconds the median time past has to advance for time based timelocks.
+    pub seconds: u32,
+}
+
+impl SpendableAt {
+    /// Assumed time between blocks.
+    pub const BLOCK_INTERVAL: u32 = 600;
+
+    /// Returns true if the next block can include the spend.
+    pub fn is_now(&self) -> bool {
+        self.blocks == 0 && self.seconds == 0
+    }
+
+    /// Estimated seconds until the path can be spent, with a block every
+    /// [`BLOCK_INTERVAL`](Self::BLOCK_INTERVAL) seconds.
+    pub fn estimated_seconds(&self) -> u64 {
+        (self.blocks as u64 * Self::BLOCK_INTERVAL as u64).max(self.seconds as u64)
+    }
+}
+
+impl fmt::Display for SpendableAt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_now() {
+            return write!(f, "spendable now");
+        }
+        write!(
+            f,
+            "spendable in {}",
+            approximate_duration_to_string(self.estimated_seconds())
+        )?;
+        if self.blocks != 0 {
+            write!(f, " ({} blocks)", self.blocks)?;
+        }
+        Ok(())
+    }
+}
+
+impl SpendingPath {
+    /// Checks the locktime and sequence requirements of this path against the spending
+    /// transaction, like `OP_CHECKLOCKTIMEVERIFY` and `OP_CHECKSEQUENCEVERIFY` would. Returns
+    /// everything `tx` has to change to satisfy them, an empty list if it already does. Only the
+    /// known minimum values are checked, arguments that could not be evaluated are assumed to be
+    /// chosen by the spender.
+    pub fn check_timelocks(&self, tx: TxTimelocks) -> Vec<TimelockMismatch> {
+        let mut mismatches = Vec::new();
+
+        if self.locktime_req.is_required() {
+            if let Some(required) = self.locktime_req.req {
+                if !locktime_type_equals(required, tx.lock_time, false) || tx.lock_time < required {
+                    mismatches.push(TimelockMismatch::LockTime { required });
+                }
+            }
+            if tx.sequence == SEQUENCE_FINAL {
+                mismatches.push(TimelockMismatch::FinalSequence);
+            }
+        }
+
+        if self.sequence_req.is_required() {
+            if tx.version < self.min_tx_version() {
+                mismatches.push(TimelockMismatch::Version);
+            }
+            if tx.sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+                mismatches.push(TimelockMismatch::SequenceDisabled);
+            }
+            if let Some(required) = self.sequence_req.req {
+                let mask = SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK;
+                let sequence = tx.sequence & mask;
+                let required_masked = required & mask;
+                if LocktimeType::new(sequence, true) != LocktimeType::new(required_masked, true)
+                    || sequence < required_masked
+                {
+                    mismatches.push(TimelockMismatch::Sequence { required });
+                }
+            }
+        }
+
+        mismatches
+    }
+
+    /// How long until this path can be spent on top of `tip`, following the locktime rules of
+    /// BIP 65, 68, 112 and 113. Like [`check_timelocks`](Self::check_timelocks), arguments that
+    /// could not be evaluated are ignored.
+    pub fn spendable_at(&self, tip: ChainTip) -> SpendableAt {
+        let next_height = tip.height + 1;
+        let (coin_height, coin_time) = tip
+            .confirmed_at
+            .unwrap_or((next_height, tip.median_time_past));
+
+        let mut spendable = SpendableAt {
+            blocks: 0,
+            seconds: 0,
+        };
+        let mut wait_for = |type_, min_height: u32, min_time: u32| match type_ {
+            LocktimeType::Height => {
+                spendable.blocks = spendable.blocks.max(min_height.saturating_sub(next_height))
+            }
+            LocktimeType::Time => {
+                spendable.seconds = spendable
+                    .seconds
+                    .max(min_time.saturating_sub(tip.median_time_past))
+            }
+        };
+
+        if let Some(req) = self.locktime_req.req {
+            // the locktime must be lower than the height of the block, or the median time past
+            // of the block before it
+            let min = req.saturating_add(1);
+            wait_for(LocktimeType::new(req, false), min, min);
+        }
+        if let Some(req) = self.sequence_req.req {
+            let value = req & SEQUENCE_LOCKTIME_MASK;
+            wait_for(
+                LocktimeType::new(req, true),
+                coin_height.saturating_add(value),
+                coin_time.saturating_add(value << 9),
+            );
+        }
+
+        spendable
+    }
+}
+
+impl Analysis {
+    /// The paths of which `tx` satisfies the timelocks, see [`SpendingPath::check_timelocks`].
+    pub fn satisfiable_paths(&self, tx: TxTimelocks) -> impl Iterator<Item = &SpendingPath> {
+        self.paths
+            .iter()
+            .filter(move |path| path.check_timelocks(tx).is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChainTip, SpendableAt, TimelockMismatch, TxTimelocks};
+    use crate::{analyze_script, OwnedScript, ScriptContext, ScriptRules, ScriptVersion};
+
+    #[test]
+    fn test_check_timelocks() {
+        let mut buf = b"OP_IF 800000 OP_CHECKLOCKTIMEVERIFY OP_ELSE 144 OP_CHECKSEQUENCEVERIFY \
+                        OP_ENDIF OP_DROP"
+            .to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let ctx = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All);
+        let analysis = analyze_script(&script, ctx, Default::default()).unwrap();
+        let (cltv, csv) = (&analysis.paths[0], &analysis.paths[1]);
+        assert_eq!(cltv.locktime_req.req, Some(800000));
+        assert_eq!(csv.sequence_req.req, Some(144));
+
+        let tx = TxTimelocks::default();
+        assert_eq!(
+            cltv.check_timelocks(tx),
+            [
+                TimelockMismatch::LockTime { required: 800000 },
+                TimelockMismatch::FinalSequence
+            ]
+        );
+        assert_eq!(
+            csv.check_timelocks(tx),
+            [
+                TimelockMismatch::SequenceDisabled,
+                TimelockMismatch::Sequence { required: 144 }
+            ]
+        );
+        assert_eq!(analysis.satisfiable_paths(tx).count(), 0);
+
+        let tx = TxTimelocks {
+            version: 1,
+            lock_time: 800000,
+            sequence: 144,
+        };
+        assert!(cltv.check_timelocks(tx).is_empty());
+        assert_eq!(csv.check_timelocks(tx), [TimelockMismatch::Version]);
+
+        // a time does not satisfy a height
+        let tx = TxTimelocks {
+            version: 2,
+            lock_time: 1_700_000_000,
+            sequence: 144 | (1 << 22),
+        };
+        assert_eq!(
+            cltv.check_timelocks(tx),
+            [TimelockMismatch::LockTime { required: 800000 }]
+        );
+        assert_eq!(
+            csv.check_timelocks(tx),
+            [TimelockMismatch::Sequence { required: 144 }]
+        );
+
+        let tx = TxTimelocks {
+            version: 2,
+            lock_time: 800001,
+            sequence: 200,
+        };
+        assert_eq!(analysis.satisfiable_paths(tx).count(), 2);
+    }
+
+    #[test]
+    fn test_spendable_at() {
+        let mut buf = b"OP_IF 800000 OP_CHECKLOCKTIMEVERIFY OP_ELSE 144 OP_CHECKSEQUENCEVERIFY \
+                        OP_ENDIF OP_DROP"
+            .to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let ctx = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All);
+        let analysis = analyze_script(&script, ctx, Default::default()).unwrap();
+        let (cltv, csv) = (&analysis.paths[0], &analysis.paths[1]);
+
+        let tip = ChainTip {
+            height: 799568,
+            median_time_past: 1_700_000_000,
+            confirmed_at: None,
+        };
+        let spendable = cltv.spendable_at(tip);
+        assert_eq!(
+            spendable,
+            SpendableAt {
+                blocks: 432,
+                seconds: 0
+            }
+        );
+        assert_eq!(spendable.to_string(), "spendable in ~3 days (432 blocks)");
+        assert_eq!(
+            cltv.spendable_at(ChainTip {
+                height: 800000,
+                ..tip
+            })
+            .to_string(),
+            "spendable now"
+        );
+
+        // an unconfirmed output confirms in the next block at the earliest
+        assert_eq!(csv.spendable_at(tip).blocks, 144);
+        let confirmed = ChainTip {
+            confirmed_at: Some((799500, 1_699_990_000)),
+            ..tip
+        };
+        assert_eq!(csv.spendable_at(confirmed).blocks, 75);
+    }
+}
@@ -0,0 +1,518 @@
+//! Bitcoin addresses: base58check (P2PKH, P2SH), bech32 (segwit v0) and bech32m (segwit v1+).
+
+pub use crate::context::Network;
+use crate::{
+    opcode::opcodes,
+    script::{PushEncoding, Script},
+};
+use bitcoin_hashes::{hash160, sha256, sha256d, Hash};
+use core::fmt;
+
+impl Network {
+    /// Human readable part of segwit addresses.
+    pub const fn bech32_hrp(self) -> &'static str {
+        match self {
+            Self::Mainnet => "bc",
+            Self::Testnet | Self::Signet => "tb",
+            Self::Regtest => "bcrt",
+        }
+    }
+
+    const fn p2pkh_prefix(self) -> u8 {
+        match self {
+            Self::Mainnet => 0x00,
+            Self::Testnet | Self::Signet | Self::Regtest => 0x6f,
+        }
+    }
+
+    const fn p2sh_prefix(self) -> u8 {
+        match self {
+            Self::Mainnet => 0x05,
+            Self::Testnet | Self::Signet | Self::Regtest => 0xc4,
+        }
+    }
+}
+
+/// Error returned when decoding an address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressError {
+    /// A character that is not in the base58 or bech32 alphabet, at this byte offset.
+    InvalidCharacter(usize),
+    /// A bech32 string with both upper and lower case characters.
+    MixedCase,
+    InvalidChecksum,
+    /// Unknown base58 version byte or bech32 human readable part.
+    UnknownPrefix,
+    /// The decoded data has the wrong length for its address type.
+    InvalidLength,
+    /// A witness version above 16, or a witness version encoded with the wrong checksum
+    /// (bech32 for v0, bech32m otherwise).
+    InvalidWitnessVersion,
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidCharacter(pos) => write!(f, "invalid character at position {pos}"),
+            Self::MixedCase => write!(f, "mixed case"),
+            Self::InvalidChecksum => write!(f, "invalid checksum"),
+            Self::UnknownPrefix => write!(f, "unknown address prefix"),
+            Self::InvalidLength => write!(f, "invalid length"),
+            Self::InvalidWitnessVersion => write!(f, "invalid witness version"),
+        }
+    }
+}
+
+impl std::error::Error for AddressError {}
+
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Base58 encoding of `payload` followed by the first 4 bytes of its double SHA256.
+pub fn encode_base58check(payload: &[u8]) -> String {
+    let mut data = payload.to_vec();
+    data.extend_from_slice(&sha256d::Hash::hash(payload).to_byte_array()[..4]);
+
+    // little endian base 58 digits
+    let mut digits: Vec<u8> = Vec::new();
+    for &byte in &data {
+        let mut carry = byte as u32;
+        for digit in &mut digits {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry != 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    let zeros = data.iter().take_while(|&&b| b == 0).count();
+    core::iter::repeat_n('1', zeros)
+        .chain(
+            digits
+                .iter()
+                .rev()
+                .map(|&d| BASE58_ALPHABET[d as usize] as char),
+        )
+        .collect()
+}
+
+/// Decodes a base58check string, the returned payload does not include the checksum.
+pub fn decode_base58check(s: &str) -> Result<Vec<u8>, AddressError> {
+    // little endian bytes
+    let mut bytes: Vec<u8> = Vec::new();
+    for (i, c) in s.bytes().enumerate() {
+        let mut carry = BASE58_ALPHABET
+            .iter()
+            .position(|&a| a == c)
+            .ok_or(AddressError::InvalidCharacter(i))? as u32;
+        for byte in &mut bytes {
+            carry += (*byte as u32) * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry != 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    let zeros = s.bytes().take_while(|&c| c == b'1').count();
+    bytes.extend(core::iter::repeat_n(0, zeros));
+    bytes.reverse();
+
+    if bytes.len() < 4 {
+        return Err(AddressError::InvalidLength);
+    }
+    let (payload, checksum) = bytes.split_at(bytes.len() - 4);
+    if sha256d::Hash::hash(payload).to_byte_array()[..4] != *checksum {
+        return Err(AddressError::InvalidChecksum);
+    }
+    Ok(payload.to_vec())
+}
+
+const BECH32_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Checksum constants of BIP 173 and BIP 350.
+const BECH32_CONST: u32 = 1;
+const BECH32M_CONST: u32 = 0x2bc8_30a3;
+
+fn bech32_polymod(values: impl Iterator<Item = u8>) -> u32 {
+    const GEN: [u32; 5] = [
+        0x3b6a_57b2,
+        0x2650_8e6d,
+        0x1ea1_19fa,
+        0x3d42_33dd,
+        0x2a14_62b3,
+    ];
+    let mut chk = 1u32;
+    for v in values {
+        let top = chk >> 25;
+        chk = ((chk & 0x1ff_ffff) << 5) ^ v as u32;
+        for (i, g) in GEN.iter().enumerate() {
+            if (top >> i) & 1 != 0 {
+                chk ^= g;
+            }
+        }
+    }
+    chk
+}
+
+fn hrp_expand(hrp: &str) -> impl Iterator<Item = u8> + '_ {
+    hrp.bytes()
+        .map(|c| c >> 5)
+        .chain([0])
+        .chain(hrp.bytes().map(|c| c & 31))
+}
+
+/// Regroups bits, like `convertbits` of the BIP 173 reference implementation.
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Option<Vec<u8>> {
+    let mut acc = 0u32;
+    let mut bits = 0;
+    let max = (1 << to) - 1;
+    let mut ret = Vec::new();
+    for &value in data {
+        acc = (acc << from) | value as u32;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            ret.push(((acc >> bits) & max) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            ret.push(((acc << (to - bits)) & max) as u8);
+        }
+    } else if bits >= from || ((acc << (to - bits)) & max) != 0 {
+        return None;
+    }
+    Some(ret)
+}
+
+/// Encodes a witness program as a segwit address with human readable part `hrp`, with a bech32
+/// checksum for version 0 and bech32m otherwise.
+pub fn encode_segwit_address(hrp: &str, version: u8, program: &[u8]) -> String {
+    let mut data = vec![version];
+    data.extend(convert_bits(program, 8, 5, true).unwrap());
+    let constant = if version == 0 {
+        BECH32_CONST
+    } else {
+        BECH32M_CONST
+    };
+    let polymod =
+        bech32_polymod(hrp_expand(hrp).chain(data.iter().copied()).chain([0; 6])) ^ constant;
+    data.extend((0..6).map(|i| ((polymod >> (5 * (5 - i))) & 31) as u8));
+
+    let mut ret = format!("{hrp}1");
+    ret.extend(data.iter().map(|&d| BECH32_CHARSET[d as usize] as char));
+    ret
+}
+
+/// Decodes a segwit address, returns the human readable part (in lower case), witness version
+/// and witness program.
+pub fn decode_segwit_address(s: &str) -> Result<(String, u8, Vec<u8>), AddressError> {
+    if s.bytes().any(|c| c.is_ascii_lowercase()) && s.bytes().any(|c| c.is_ascii_uppercase()) {
+        return Err(AddressError::MixedCase);
+    }
+    let s = s.to_ascii_lowercase();
+    let sep = s.rfind('1').ok_or(AddressError::UnknownPrefix)?;
+    if sep == 0 || sep + 7 > s.len() || s.len() > 90 {
+        return Err(AddressError::InvalidLength);
+    }
+    let (hrp, data) = (&s[..sep], &s[sep + 1..]);
+    if let Some(i) = hrp.bytes().position(|c| !(33..=126).contains(&c)) {
+        return Err(AddressError::InvalidCharacter(i));
+    }
+    let data = data
+        .bytes()
+        .enumerate()
+        .map(|(i, c)| {
+            BECH32_CHARSET
+                .iter()
+                .position(|&a| a == c)
+                .map(|d| d as u8)
+                .ok_or(AddressError::InvalidCharacter(sep + 1 + i))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let constant = match bech32_polymod(hrp_expand(hrp).chain(data.iter().copied())) {
+        BECH32_CONST => BECH32_CONST,
+        BECH32M_CONST => BECH32M_CONST,
+        _ => return Err(AddressError::InvalidChecksum),
+    };
+    let data = &data[..data.len() - 6];
+    let (&version, program) = data.split_first().ok_or(AddressError::InvalidLength)?;
+    if version > 16 || (version == 0) != (constant == BECH32_CONST) {
+        return Err(AddressError::InvalidWitnessVersion);
+    }
+    let program = convert_bits(program, 5, 8, false).ok_or(AddressError::InvalidLength)?;
+    if !(2..=40).contains(&program.len()) || (version == 0 && !matches!(program.len(), 20 | 32)) {
+        return Err(AddressError::InvalidLength);
+    }
+    Ok((hrp.to_owned(), version, program))
+}
+
+fn witness_program_script(version: u8, program: &[u8]) -> Vec<u8> {
+    let version = match version {
+        0 => opcodes::OP_0.opcode,
+        v => opcodes::OP_1.opcode + v - 1,
+    };
+    let mut script = vec![version, program.len() as u8];
+    script.extend_from_slice(program);
+    script
+}
+
+/// Decodes an address to the scriptPubKey it pays to, with its network. Testnet and signet use
+/// the same addresses, these are returned as [`Network::Testnet`]. Base58 addresses of regtest
+/// are also returned as testnet.
+pub fn address_to_script_pubkey(address: &str) -> Result<(Vec<u8>, Network), AddressError> {
+    if let Ok(payload) = decode_base58check(address) {
+        let [prefix, ref hash @ ..] = payload[..] else {
+            return Err(AddressError::InvalidLength);
+        };
+        if hash.len() != 20 {
+            return Err(AddressError::InvalidLength);
+        }
+        let mut script = Vec::with_capacity(25);
+        let network = match prefix {
+            0x00 | 0x6f => {
+                script.extend([opcodes::OP_DUP.opcode, opcodes::OP_HASH160.opcode, 20]);
+                script.extend_from_slice(hash);
+                script.extend([opcodes::OP_EQUALVERIFY.opcode, opcodes::OP_CHECKSIG.opcode]);
+                prefix == 0x00
+            }
+            0x05 | 0xc4 => {
+                script.extend([opcodes::OP_HASH160.opcode, 20]);
+                script.extend_from_slice(hash);
+                script.push(opcodes::OP_EQUAL.opcode);
+                prefix == 0x05
+            }
+            _ => return Err(AddressError::UnknownPrefix),
+        };
+        let network = if network {
+            Network::Mainnet
+        } else {
+            Network::Testnet
+        };
+        return Ok((script, network));
+    }
+
+    let (hrp, version, program) = decode_segwit_address(address)?;
+    let network = [Network::Mainnet, Network::Testnet, Network::Regtest]
+        .into_iter()
+        .find(|n| n.bech32_hrp() == hrp)
+        .ok_or(AddressError::UnknownPrefix)?;
+    Ok((witness_program_script(version, &program), network))
+}
+
+/// The address of a scriptPubKey, `None` if it has no address, like bare multisig.
+pub fn script_pubkey_address(script_pubkey: &[u8], network: Network) -> Option<String> {
+    match *script_pubkey {
+        [0x76, 0xa9, 20, ref hash @ .., 0x88, 0xac] if hash.len() == 20 => {
+            Some(base58_address(network.p2pkh_prefix(), hash))
+        }
+        [0xa9, 20, ref hash @ .., 0x87] if hash.len() == 20 => {
+            Some(base58_address(network.p2sh_prefix(), hash))
+        }
+        [version, len, ref program @ ..]
+            if (version == 0 || (0x51..=0x60).contains(&version))
+                && len as usize == program.len()
+                && (2..=40).contains(&program.len()) =>
+        {
+            let version = version.saturating_sub(0x50);
+            Some(encode_segwit_address(
+                network.bech32_hrp(),
+                version,
+                program,
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn base58_address(prefix: u8, hash: &[u8]) -> String {
+    let mut payload = vec![prefix];
+    payload.extend_from_slice(hash);
+    encode_base58check(&payload)
+}
+
+/// The P2SH address of a redeem script, assuming minimal pushes.
+pub fn p2sh_address(redeem_script: &Script<'_>, network: Network) -> String {
+    base58_address(
+        network.p2sh_prefix(),
+        hash160::Hash::hash(&redeem_script.serialize(PushEncoding::Original)).as_byte_array(),
+    )
+}
+
+/// The P2WSH address of a witness script, assuming minimal pushes.
+pub fn p2wsh_address(witness_script: &Script<'_>, network: Network) -> String {
+    encode_segwit_address(
+        network.bech32_hrp(),
+        0,
+        sha256::Hash::hash(&witness_script.serialize(PushEncoding::Original)).as_byte_array(),
+    )
+}
+
+/// The P2TR address of `internal_key` (x-only) with `tapscript` as its only leaf, or without a
+/// script path if it is `None`, assuming minimal pushes. `None` if the key is not on the curve.
+#[cfg(feature = "secp256k1")]
+pub fn p2tr_address(
+    internal_key: &[u8; 32],
+    tapscript: Option<&Script<'_>>,
+    network: Network,
+) -> Option<String> {
+    use bitcoin_hashes::HashEngine;
+    use secp256k1::{ffi::types::AlignedType, Scalar, Secp256k1, XOnlyPublicKey};
+
+    fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+        let tag = sha256::Hash::hash(tag.as_bytes());
+        let mut engine = sha256::Hash::engine();
+        engine.input(tag.as_byte_array());
+        engine.input(tag.as_byte_array());
+        for d in data {
+            engine.input(d);
+        }
+        sha256::Hash::from_engine(engine).to_byte_array()
+    }
+
+    let mut tweak_data = vec![&internal_key[..]];
+    let leaf_hash;
+    if let Some(tapscript) = tapscript {
+        let script = tapscript.serialize(PushEncoding::Original);
+        // leaf version and compact size of the script length
+        let mut prefix = vec![0xc0];
+        match script.len() {
+            len @ 0..=0xfc => prefix.push(len as u8),
+            len @ 0xfd..=0xffff => {
+                prefix.push(0xfd);
+                prefix.extend((len as u16).to_le_bytes());
+            }
+            len => {
+                prefix.push(0xfe);
+                prefix.extend((len as u32).to_le_bytes());
+            }
+        }
+        leaf_hash = tagged_hash("TapLeaf", &[&prefix, &script]);
+        tweak_data.push(&leaf_hash);
+    }
+    let tweak = Scalar::from_be_bytes(tagged_hash("TapTweak", &tweak_data)).ok()?;
+
+    let mut buf = vec![AlignedType::zeroed(); Secp256k1::preallocate_verification_size()];
+    let secp = Secp256k1::preallocated_verification_only(&mut buf).ok()?;
+    let (output_key, _) = XOnlyPublicKey::from_slice(internal_key)
+        .ok()?
+        .add_tweak(&secp, &tweak)
+        .ok()?;
+    Some(encode_segwit_address(
+        network.bech32_hrp(),
+        1,
+        &output_key.serialize(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        address_to_script_pubkey, p2wsh_address, script_pubkey_address, AddressError, Network,
+    };
+    use crate::{
+        util::{decode_hex_in_place_easy, encode_hex_easy},
+        OwnedScript, ScriptContext, ScriptRules, ScriptVersion, VerifyFlags,
+    };
+
+    #[test]
+    fn test_addresses() {
+        // BIP 173, BIP 350 and the compressed public key of private key 1
+        for (address, script_pubkey, network) in [
+            (
+                "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+                "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+                Network::Mainnet,
+            ),
+            (
+                "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7",
+                "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262",
+                Network::Testnet,
+            ),
+            (
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+                "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+                Network::Mainnet,
+            ),
+            (
+                "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH",
+                "76a914751e76e8199196d454941c45d1b3a323f1433bd688ac",
+                Network::Mainnet,
+            ),
+        ] {
+            let (script, n) = address_to_script_pubkey(address).unwrap();
+            assert_eq!(encode_hex_easy(&script), script_pubkey);
+            assert_eq!(n, network);
+            assert_eq!(
+                script_pubkey_address(&script, network).as_deref(),
+                Some(address)
+            );
+        }
+
+        assert_eq!(
+            address_to_script_pubkey("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4x"),
+            Err(AddressError::InvalidChecksum)
+        );
+        assert_eq!(
+            address_to_script_pubkey(
+                "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+                    .to_ascii_uppercase()
+                    .as_str()
+            )
+            .map(|(_, n)| n),
+            Ok(Network::Mainnet)
+        );
+        assert_eq!(
+            address_to_script_pubkey("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7KV8F3T4"),
+            Err(AddressError::MixedCase)
+        );
+
+        // BIP 173
+        let witness_script = decode_hex_in_place_easy(
+            "210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798ac".to_owned(),
+        )
+        .unwrap();
+        let witness_script = OwnedScript::parse_from_bytes(&witness_script).unwrap();
+        let address = "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7";
+        assert_eq!(p2wsh_address(&witness_script, Network::Testnet), address);
+        let ctx = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All)
+            .with_network(Network::Testnet);
+        assert_eq!(ctx.flags, VerifyFlags::STANDARD);
+        assert_eq!(ctx.address(&witness_script).as_deref(), Some(address));
+
+        let bare_multisig = decode_hex_in_place_easy("5121".to_owned() + &"02".repeat(33) + "51ae");
+        assert_eq!(
+            script_pubkey_address(&bare_multisig.unwrap(), Network::Mainnet),
+            None
+        );
+    }
+
+    #[cfg(feature = "secp256k1")]
+    #[test]
+    fn test_p2tr_address() {
+        use super::p2tr_address;
+
+        // BIP 341 wallet test vectors
+        let mut internal_key = [0; 32];
+        internal_key.copy_from_slice(
+            &decode_hex_in_place_easy(
+                "187791b6f712a8ea41c8ecdd0ee77fab3e85263b37e1ec18a3651926b3a6cf27".to_owned(),
+            )
+            .unwrap(),
+        );
+        let script = decode_hex_in_place_easy(
+            "20d85a959b0290bf19bb89ed43c916be835475d013da4b362117393e25a48229b8ac".to_owned(),
+        )
+        .unwrap();
+        let script = OwnedScript::parse_from_bytes(&script).unwrap();
+        assert_eq!(
+            p2tr_address(&internal_key, Some(&script), Network::Mainnet).unwrap(),
+            "bc1pz37fc4cn9ah8anwm4xqqhvxygjf9rjf2resrw8h8w4tmvcs0863sa2e586"
+        );
+    }
+}
@@ -1,3 +1,8 @@
+use crate::{
+    context::{ScriptContext, ScriptVersion, VerifyFlags},
+    script_error::ScriptError,
+};
+
 pub const SIGHASH_DEFAULT: u8 = 0;
 pub const SIGHASH_ALL: u8 = 1;
 pub const SIGHASH_NONE: u8 = 2;
@@ -14,6 +19,18 @@ pub const SIG_HASH_TYPES: [u8; 6] = [
     SIGHASH_SINGLE | SIGHASH_ANYONECANPAY,
 ];
 
+/// Half the order of the secp256k1 curve, the largest S value of a low-S signature.
+const HALF_CURVE_ORDER: [u8; 32] = [
+    0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0x5d, 0x57, 0x6e, 0x73, 0x57, 0xa4, 0x50, 0x1d, 0xdf, 0xe9, 0x2f, 0x46, 0x68, 0x1b, 0x20, 0xa0,
+];
+
+/// The order of the secp256k1 curve.
+const CURVE_ORDER: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+    0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+];
+
 pub enum PubKeyCheckResult {
     Invalid,
     Valid { compressed: bool },
@@ -31,6 +48,21 @@ pub fn check_pub_key<T: AsRef<[u8]>>(pub_key: T) -> PubKeyCheckResult {
     }
 }
 
+/// Returns true if `pub_key` can be a point on the curve. Only checked with the `secp256k1`
+/// feature, without it every key of the right length is accepted. Keys of 32 bytes are x-only
+/// keys, used in tapscript.
+pub fn is_on_curve<T: AsRef<[u8]>>(pub_key: T) -> bool {
+    let pub_key = pub_key.as_ref();
+    #[cfg(feature = "secp256k1")]
+    if pub_key.len() == 32 {
+        secp256k1::XOnlyPublicKey::from_slice(pub_key).is_ok()
+    } else {
+        secp256k1::PublicKey::from_slice(pub_key).is_ok()
+    }
+    #[cfg(not(feature = "secp256k1"))]
+    matches!(pub_key.len(), 32 | 33 | 65)
+}
+
 // The following function was copied from the Bitcoin Core source code,
 // src/script/interpreter (lines 97-170) at commit b92d609fb25637ccda000e182da854d4b762eee9
 // Modified for use in this software
@@ -143,3 +175,143 @@ pub fn is_valid_signature_encoding<T: AsRef<[u8]>>(sig: T) -> bool {
 
     true
 }
+
+/// Checks whether the S value of `sig` is at most half the curve order, like
+/// `IsLowDERSignature` in Bitcoin Core. `sig` must be encoded correctly, see
+/// [`is_valid_signature_encoding`]. Like the lax DER parser of libsecp256k1, S values that do not
+/// fit the curve order are treated as 0, these signatures fail verification instead.
+pub fn is_low_s<T: AsRef<[u8]>>(sig: T) -> bool {
+    let sig = sig.as_ref();
+    let len_r = sig[3] as usize;
+    let len_s = sig[len_r + 5] as usize;
+    let mut s = &sig[len_r + 6..len_r + 6 + len_s];
+    while let [0, rest @ ..] = s {
+        s = rest;
+    }
+    if s.len() > 32 {
+        return true;
+    }
+
+    let mut padded = [0; 32];
+    padded[32 - s.len()..].copy_from_slice(s);
+    // big endian, compared byte by byte
+    padded <= HALF_CURVE_ORDER || padded >= CURVE_ORDER
+}
+
+/// Checks the encoding of an ECDSA signature with its sighash byte, like
+/// `CheckSignatureEncoding` in Bitcoin Core. The empty signature is allowed, it makes a signature
+/// check fail without failing the script.
+pub fn check_signature_encoding<T: AsRef<[u8]>>(
+    sig: T,
+    flags: VerifyFlags,
+) -> Result<(), ScriptError> {
+    let sig = sig.as_ref();
+    if sig.is_empty() {
+        return Ok(());
+    }
+    if flags.intersects(
+        VerifyFlags::DERSIG
+            .union(VerifyFlags::LOW_S)
+            .union(VerifyFlags::STRICTENC),
+    ) && !is_valid_signature_encoding(sig)
+    {
+        Err(ScriptError::SCRIPT_ERR_SIG_DER)
+    } else if flags.contains(VerifyFlags::LOW_S) && !is_low_s(sig) {
+        Err(ScriptError::SCRIPT_ERR_SIG_HIGH_S)
+    } else if flags.contains(VerifyFlags::STRICTENC)
+        && !SIG_HASH_TYPES.contains(&sig[sig.len() - 1])
+    {
+        Err(ScriptError::SCRIPT_ERR_SIG_HASHTYPE)
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks the encoding of a public key used with an ECDSA signature, like `CheckPubKeyEncoding`
+/// in Bitcoin Core. Returns false if the key is invalid but allowed by the flags, or not on the
+/// curve (see [`is_on_curve`]), no signature is valid for it.
+pub fn check_pub_key_encoding<T: AsRef<[u8]>>(
+    pub_key: T,
+    ctx: ScriptContext,
+) -> Result<bool, ScriptError> {
+    let pub_key = pub_key.as_ref();
+    let result = check_pub_key(pub_key);
+    if matches!(result, PubKeyCheckResult::Invalid) && ctx.has(VerifyFlags::STRICTENC) {
+        Err(ScriptError::SCRIPT_ERR_PUBKEYTYPE)
+    } else if !matches!(result, PubKeyCheckResult::Valid { compressed: true })
+        && ctx.version == ScriptVersion::SegwitV0
+        && ctx.has(VerifyFlags::WITNESS_PUBKEYTYPE)
+    {
+        Err(ScriptError::SCRIPT_ERR_WITNESS_PUBKEYTYPE)
+    } else {
+        Ok(matches!(result, PubKeyCheckResult::Valid { .. }) && is_on_curve(pub_key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_signature_encoding, is_low_s};
+    use crate::{context::VerifyFlags, script_error::ScriptError};
+
+    #[cfg(feature = "secp256k1")]
+    #[test]
+    fn test_on_curve() {
+        use super::{check_pub_key_encoding, is_on_curve};
+        use crate::{ScriptContext, ScriptRules, ScriptVersion};
+
+        let g = [
+            0x79, 0xbe, 0x66, 0x7e, 0xf9, 0xdc, 0xbb, 0xac, 0x55, 0xa0, 0x62, 0x95, 0xce, 0x87,
+            0x0b, 0x07, 0x02, 0x9b, 0xfc, 0xdb, 0x2d, 0xce, 0x28, 0xd9, 0x59, 0xf2, 0x81, 0x5b,
+            0x16, 0xf8, 0x17, 0x98,
+        ];
+        assert!(is_on_curve(g));
+        assert!(is_on_curve([&[0x02][..], &g].concat()));
+        // larger than the field size
+        assert!(!is_on_curve([0xff; 32]));
+        let key = [&[0x02][..], &[0xff; 32]].concat();
+        assert!(!is_on_curve(&key));
+
+        // correctly encoded, but no signature is valid for it
+        let ctx = ScriptContext::new(ScriptVersion::SegwitV0, ScriptRules::All);
+        assert_eq!(check_pub_key_encoding(&key, ctx), Ok(false));
+    }
+
+    #[test]
+    fn test_low_s() {
+        let low = [0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x01, 0x01];
+        assert!(is_low_s(low));
+
+        let mut high = vec![0x30, 0x25, 0x02, 0x01, 0x01, 0x02, 0x20, 0x7f];
+        high.extend([0xff; 31]);
+        high.push(0x01);
+        assert!(!is_low_s(&high));
+        assert_eq!(
+            check_signature_encoding(&high, VerifyFlags::LOW_S),
+            Err(ScriptError::SCRIPT_ERR_SIG_HIGH_S)
+        );
+        assert_eq!(check_signature_encoding(&high, VerifyFlags::DERSIG), Ok(()));
+
+        let sig = |s: &[u8]| {
+            let mut sig = vec![
+                0x30,
+                s.len() as u8 + 5,
+                0x02,
+                0x01,
+                0x01,
+                0x02,
+                s.len() as u8,
+            ];
+            sig.extend(s);
+            sig.push(0x01);
+            sig
+        };
+        let mut s = super::HALF_CURVE_ORDER;
+        assert!(is_low_s(sig(&s)));
+        s[31] += 1;
+        assert!(!is_low_s(sig(&s)));
+        // larger than the curve order, with a padding byte
+        let mut s = vec![0x00];
+        s.extend([0xff; 32]);
+        assert!(is_low_s(sig(&s)));
+    }
+}
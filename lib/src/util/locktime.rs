@@ -1,9 +1,13 @@
+use crate::context::Network;
 use time::{format_description, OffsetDateTime};
 
+/// BIP 68: relative locktimes do not apply to an input with this bit set in its sequence, and
+/// BIP 112: `OP_CHECKSEQUENCEVERIFY` with this bit set in its argument is a NOP.
+pub const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
 pub const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
 pub const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000ffff;
 
-#[derive(PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LocktimeType {
     Height,
     Time,
@@ -23,6 +27,24 @@ impl LocktimeType {
             Self::Time
         }
     }
+
+    /// The type of every value in `min..=max`, `None` if the range has values of both types.
+    pub fn of_range(min: i64, max: i64, relative: bool) -> Option<Self> {
+        if min < 0 || min > max {
+            return None;
+        }
+        if relative {
+            // the type flag is the same for all values if they only differ in the lower bits
+            let shift = SEQUENCE_LOCKTIME_TYPE_FLAG.trailing_zeros();
+            (min >> shift == max >> shift).then(|| Self::new((min as u32) & (1 << shift), true))
+        } else if max < 500000000 {
+            Some(Self::Height)
+        } else if min >= 500000000 {
+            Some(Self::Time)
+        } else {
+            None
+        }
+    }
 }
 
 pub fn locktime_type_equals(a: u32, b: u32, relative: bool) -> bool {
@@ -36,6 +58,29 @@ pub fn absolute_timelock_height_to_string(n: u32) -> String {
     format!("at block {n}")
 }
 
+/// Block 840000 on mainnet and its timestamp, used to estimate when later blocks are mined.
+const MAINNET_REFERENCE_BLOCK: (u32, i64) = (840_000, 1_713_571_767);
+
+/// Estimated unix timestamp of block `height`, with a block every 10 minutes. Only for mainnet
+/// blocks after block 840000, the block times of the test networks are not predictable.
+pub fn estimated_block_time(height: u32, network: Network) -> Option<i64> {
+    let (reference_height, reference_time) = MAINNET_REFERENCE_BLOCK;
+    (network == Network::Mainnet && height > reference_height)
+        .then(|| reference_time + (height - reference_height) as i64 * 600)
+}
+
+/// Like [`absolute_timelock_height_to_string`], with the estimated date of the block if there is
+/// one, see [`estimated_block_time`].
+pub fn absolute_timelock_height_to_string_with_network(n: u32, network: Network) -> String {
+    let mut s = absolute_timelock_height_to_string(n);
+    if let Some(time) = estimated_block_time(n, network) {
+        let date = OffsetDateTime::from_unix_timestamp(time).unwrap();
+        let format = format_description::parse_borrowed::<2>("[year]-[month]-[day]").unwrap();
+        s += &format!(" (around {})", date.format(&format).unwrap());
+    }
+    s
+}
+
 pub fn absolute_timelock_time_to_string(n: u32) -> String {
     const DATE_FORMAT_STR: &str =
         "on [year]-[month]-[day] [hour]:[minute]:[second] ([unix_timestamp] seconds since unix epoch)";
@@ -51,18 +96,28 @@ pub fn relative_timelock_height_to_string(n: u32) -> String {
 }
 
 pub fn relative_timelock_time_to_string(n: u32) -> String {
-    let mut t = (n & SEQUENCE_LOCKTIME_MASK) * 512;
-    let mut output = format!("in {}s", t % 60);
-    let mut prev = 60;
-    for unit in [('m', 60), ('h', 24), ('d', 999)] {
-        t /= prev;
-        if t == 0 {
-            break;
-        }
-        output.insert_str(3, &format!("{}{} ", t % unit.1, unit.0));
-        prev = unit.1;
-    }
-    output
+    let seconds = (n & SEQUENCE_LOCKTIME_MASK) as u64 * 512;
+    format!(
+        "in {}",
+        duration_to_string(seconds, TimeFormat::Human, false)
+    )
+}
+
+/// Rounds `seconds` to the largest unit that fits, like "~3 days" or "~1 hour".
+pub fn approximate_duration_to_string(seconds: u64) -> String {
+    const UNITS: [(&str, u64); 4] = [
+        ("day", 86400),
+        ("hour", 3600),
+        ("minute", 60),
+        ("second", 1),
+    ];
+
+    let (unit, size) = UNITS
+        .into_iter()
+        .find(|&(_, size)| seconds >= size)
+        .unwrap_or(UNITS[3]);
+    let n = (seconds + size / 2) / size;
+    format!("~{n} {unit}{}", if n == 1 { "" } else { "s" })
 }
 
 pub fn locktime_to_string_unchecked(n: u32, relative: bool, type_: LocktimeType) -> String {
@@ -77,3 +132,188 @@ pub fn locktime_to_string_unchecked(n: u32, relative: bool, type_: LocktimeType)
 pub fn locktime_to_string(n: u32, relative: bool) -> String {
     locktime_to_string_unchecked(n, relative, LocktimeType::new(n, relative))
 }
+
+/// Like [`locktime_to_string`], block heights of absolute timelocks get an estimated date on
+/// `network`.
+pub fn locktime_to_string_with_network(n: u32, relative: bool, network: Option<Network>) -> String {
+    locktime_to_string_with_format(n, relative, network, LocktimeFormat::default())
+}
+
+/// How times and durations of timelocks are written, see [`LocktimeFormat`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimeFormat {
+    /// `on 2023-11-14 22:13:20 (1700000000 seconds since unix epoch)` and `in 1d 2h 3m 4s`.
+    #[default]
+    Human,
+    /// ISO 8601, `on 2023-11-14T22:13:20Z` and `in P1DT2H3M4S`.
+    Iso8601,
+    /// Seconds only, `at unix time 1700000000` and `in 93784 seconds`.
+    Seconds,
+}
+
+/// Options of [`locktime_to_string_with_format`]. The default is the format of
+/// [`locktime_to_string`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LocktimeFormat {
+    pub time: TimeFormat,
+    /// Relative block heights also get an approximate duration, assuming a block every this many
+    /// seconds.
+    pub seconds_per_block: Option<u32>,
+}
+
+/// A duration in `format`, `approximate` rounds it to a single unit.
+fn duration_to_string(seconds: u64, format: TimeFormat, approximate: bool) -> String {
+    match format {
+        TimeFormat::Human if approximate => approximate_duration_to_string(seconds),
+        TimeFormat::Human => {
+            let mut output = format!("{}s", seconds % 60);
+            for (unit, size, next) in [('m', 60, 60), ('h', 3600, 24), ('d', 86400, u64::MAX)] {
+                if seconds < size {
+                    break;
+                }
+                output.insert_str(0, &format!("{}{unit} ", seconds / size % next));
+            }
+            output
+        }
+        TimeFormat::Iso8601 => {
+            let (days, hours, minutes, secs) = (
+                seconds / 86400,
+                seconds / 3600 % 24,
+                seconds / 60 % 60,
+                seconds % 60,
+            );
+            let mut output = String::from("P");
+            if days != 0 {
+                output += &format!("{days}D");
+            }
+            if hours != 0 || minutes != 0 || secs != 0 || days == 0 {
+                output.push('T');
+                for (n, unit) in [(hours, 'H'), (minutes, 'M'), (secs, 'S')] {
+                    if n != 0 {
+                        output += &format!("{n}{unit}");
+                    }
+                }
+                if output.ends_with('T') {
+                    output += "0S";
+                }
+            }
+            if approximate {
+                output.insert(0, '~');
+            }
+            output
+        }
+        TimeFormat::Seconds => format!("{}{seconds} seconds", if approximate { "~" } else { "" }),
+    }
+}
+
+/// Like [`locktime_to_string_with_network`], in `format`.
+pub fn locktime_to_string_with_format(
+    n: u32,
+    relative: bool,
+    network: Option<Network>,
+    format: LocktimeFormat,
+) -> String {
+    match (relative, LocktimeType::new(n, relative), format.time) {
+        (false, LocktimeType::Height, TimeFormat::Seconds) => {
+            let mut s = absolute_timelock_height_to_string(n);
+            if let Some(time) = network.and_then(|network| estimated_block_time(n, network)) {
+                s += &format!(" (around unix time {time})");
+            }
+            s
+        }
+        (false, LocktimeType::Height, _) => match network {
+            Some(network) => absolute_timelock_height_to_string_with_network(n, network),
+            None => absolute_timelock_height_to_string(n),
+        },
+        (false, LocktimeType::Time, TimeFormat::Human) => absolute_timelock_time_to_string(n),
+        (false, LocktimeType::Time, TimeFormat::Iso8601) => {
+            let date = OffsetDateTime::from_unix_timestamp(n as i64).unwrap();
+            let format = format_description::parse_borrowed::<2>(
+                "on [year]-[month]-[day]T[hour]:[minute]:[second]Z",
+            )
+            .unwrap();
+            date.format(&format).unwrap()
+        }
+        (false, LocktimeType::Time, TimeFormat::Seconds) => format!("at unix time {n}"),
+        (true, LocktimeType::Height, time) => {
+            let mut s = relative_timelock_height_to_string(n);
+            if let Some(interval) = format.seconds_per_block {
+                let seconds = (n & SEQUENCE_LOCKTIME_MASK) as u64 * interval as u64;
+                s += &format!(" ({})", duration_to_string(seconds, time, true));
+            }
+            s
+        }
+        (true, LocktimeType::Time, time) => {
+            let seconds = (n & SEQUENCE_LOCKTIME_MASK) as u64 * 512;
+            format!("in {}", duration_to_string(seconds, time, false))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{locktime_to_string_with_format, LocktimeFormat, TimeFormat};
+
+    #[test]
+    fn test_locktime_formats() {
+        let format = |time, seconds_per_block| LocktimeFormat {
+            time,
+            seconds_per_block,
+        };
+        let relative_time = (1 << 22) | 183;
+        assert_eq!(
+            locktime_to_string_with_format(relative_time, true, None, Default::default()),
+            "in 1d 2h 1m 36s"
+        );
+        assert_eq!(
+            locktime_to_string_with_format(
+                relative_time,
+                true,
+                None,
+                format(TimeFormat::Iso8601, None)
+            ),
+            "in P1DT2H1M36S"
+        );
+        assert_eq!(
+            locktime_to_string_with_format(
+                relative_time,
+                true,
+                None,
+                format(TimeFormat::Seconds, None)
+            ),
+            "in 93696 seconds"
+        );
+
+        assert_eq!(
+            locktime_to_string_with_format(
+                1700000000,
+                false,
+                None,
+                format(TimeFormat::Iso8601, None)
+            ),
+            "on 2023-11-14T22:13:20Z"
+        );
+        assert_eq!(
+            locktime_to_string_with_format(
+                1700000000,
+                false,
+                None,
+                format(TimeFormat::Seconds, None)
+            ),
+            "at unix time 1700000000"
+        );
+
+        assert_eq!(
+            locktime_to_string_with_format(144, true, None, Default::default()),
+            "in 144 blocks"
+        );
+        assert_eq!(
+            locktime_to_string_with_format(144, true, None, format(TimeFormat::Human, Some(600))),
+            "in 144 blocks (~1 day)"
+        );
+        assert_eq!(
+            locktime_to_string_with_format(144, true, None, format(TimeFormat::Iso8601, Some(600))),
+            "in 144 blocks (~P1D)"
+        );
+    }
+}
@@ -1,5 +1,6 @@
-use core::{fmt, hint::unreachable_unchecked};
+use core::{fmt, hint::unreachable_unchecked, ops::Range};
 
+pub mod address;
 pub mod checksig;
 pub mod locktime;
 
@@ -72,13 +73,29 @@ pub fn decode_hex_in_place(v: &mut [u8]) -> Result<&[u8], HexDecodeError> {
         }
     }
 
-    if v.len() % 2 == 0 {
+    if v.len().is_multiple_of(2) {
         Ok(&v[..result_len])
     } else {
         Err(HexDecodeError::OddAmountOfHexCharacters(v.len()))
     }
 }
 
+/// Like [`decode_hex_in_place`], but appends the bytes to `out` and leaves `v` untouched.
+pub fn decode_hex_into(v: &[u8], out: &mut Vec<u8>) -> Result<(), HexDecodeError> {
+    if !v.len().is_multiple_of(2) {
+        return Err(HexDecodeError::OddAmountOfHexCharacters(v.len()));
+    }
+    out.reserve(v.len() / 2);
+    for (i, pair) in v.chunks_exact(2).enumerate() {
+        let high =
+            decode_hex_digit(pair[0]).ok_or(HexDecodeError::InvalidCharacter(2 * i, pair[0]))?;
+        let low = decode_hex_digit(pair[1])
+            .ok_or(HexDecodeError::InvalidCharacter(2 * i + 1, pair[1]))?;
+        out.push((high << 4) | low);
+    }
+    Ok(())
+}
+
 pub fn decode_hex_in_place_ignore_whitespace_easy(v: String) -> Result<Vec<u8>, HexDecodeError> {
     let mut bytes = v.into_bytes();
     let len = decode_hex_in_place_ignore_whitespace(&mut bytes)?.len();
@@ -119,12 +136,34 @@ pub fn decode_hex_in_place_ignore_whitespace(v: &mut [u8]) -> Result<&[u8], HexD
     }
 }
 
+/// Copies `s`, replacing the characters for which `replacement` returns a string.
+pub(crate) fn escape_with(s: &str, replacement: impl Fn(char) -> Option<&'static str>) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match replacement(c) {
+            Some(r) => out.push_str(r),
+            None => out.push(c),
+        }
+    }
+    out
+}
+
 #[derive(Debug, Clone)]
 pub enum HexDecodeError {
     OddAmountOfHexCharacters(usize),
     InvalidCharacter(usize, u8),
 }
 
+impl HexDecodeError {
+    /// Byte offsets of the invalid character in the input, if the error is caused by one.
+    pub fn span(&self) -> Option<Range<usize>> {
+        match *self {
+            Self::OddAmountOfHexCharacters(_) => None,
+            Self::InvalidCharacter(pos, _) => Some(pos..pos + 1),
+        }
+    }
+}
+
 impl fmt::Display for HexDecodeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
@@ -0,0 +1,357 @@
+use crate::{
+    context::{ScriptContext, ScriptVersion},
+    expr::{Expr, OpExprArgs, Opcode1, Opcode2},
+    range::item_sizes,
+    script::{convert::decode_int, push_len, ScriptRef},
+    script_error::ScriptError,
+};
+use core::fmt;
+
+/// DER encoded signature with sighash byte, 72 bytes in the worst case
+const ECDSA_SIG_SIZE: usize = 72;
+/// Schnorr signature with explicit sighash byte
+const SCHNORR_SIG_SIZE: usize = 65;
+const PUBKEY_SIZE: usize = 33;
+const XONLY_PUBKEY_SIZE: usize = 32;
+/// Hashlocks are almost always used with 32 byte preimages
+const PREIMAGE_SIZE: usize = 32;
+const LOCKTIME_SIZE: usize = 4;
+/// Outpoint (36), nSequence (4) and scriptSig length (1)
+const INPUT_BASE_SIZE: usize = 41;
+/// Leaf version and internal key, assumes the script is the only leaf
+const CONTROL_BLOCK_SIZE: usize = 33;
+const WITNESS_SCALE_FACTOR: usize = 4;
+const VALIDATION_WEIGHT_PER_SIGOP_PASSED: u32 = 50;
+const VALIDATION_WEIGHT_OFFSET: u32 = 50;
+const MAX_BLOCK_WEIGHT: usize = 4_000_000;
+
+/// Estimated size of the data needed to spend a path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeEstimate {
+    /// Estimated size of every initial stack item, indexed by stack item number.
+    pub stack_item_sizes: Vec<usize>,
+    /// Size of the scriptSig, 0 for segwit scripts. For P2SH the redeem script push has to be
+    /// added.
+    pub script_sig_size: usize,
+    /// Size of the serialized witness, 0 for legacy scripts.
+    pub witness_size: usize,
+    /// Virtual size of the spending input.
+    pub vsize: usize,
+    script_sig: bool,
+}
+
+impl SizeEstimate {
+    pub(crate) fn new<'e>(
+        script: ScriptRef<'_>,
+        ctx: ScriptContext,
+        stack_size: u32,
+        conditions: &[Expr],
+        locktime_exprs: impl IntoIterator<Item = &'e Expr>,
+    ) -> Self {
+        let mut items = vec![ItemSize::default(); stack_size as usize];
+
+        for expr in conditions {
+            visit_condition(expr, ctx, &mut items);
+        }
+        for expr in locktime_exprs {
+            visit(expr, ctx, &mut items);
+            set_role(expr, LOCKTIME_SIZE, &mut items);
+        }
+
+        let mut stack_item_sizes: Vec<_> = items.iter().map(ItemSize::size).collect();
+        // comparisons of OP_SIZE of an item
+        for r in item_sizes(conditions) {
+            if let Some(size) = stack_item_sizes.get_mut(r.stack_item as usize) {
+                *size = (*size).clamp(r.range.min as usize, r.range.max as usize);
+            }
+        }
+
+        let (script_sig_size, witness_size, weight) = if ctx.version == ScriptVersion::Legacy {
+            let script_sig_size: usize = stack_item_sizes.iter().map(|&s| push_len(s)).sum();
+            let size = INPUT_BASE_SIZE - 1 + compact_size_len(script_sig_size) + script_sig_size;
+            (script_sig_size, 0, size * WITNESS_SCALE_FACTOR)
+        } else {
+            let script_size = script.serialized_len();
+            let mut elements = stack_item_sizes.clone();
+            elements.push(script_size);
+            if ctx.version == ScriptVersion::SegwitV1 {
+                elements.push(CONTROL_BLOCK_SIZE);
+            }
+            let witness_size = compact_size_len(elements.len())
+                + elements
+                    .iter()
+                    .map(|&s| compact_size_len(s) + s)
+                    .sum::<usize>();
+            (
+                0,
+                witness_size,
+                INPUT_BASE_SIZE * WITNESS_SCALE_FACTOR + witness_size,
+            )
+        };
+
+        Self {
+            stack_item_sizes,
+            script_sig_size,
+            witness_size,
+            vsize: weight.div_ceil(WITNESS_SCALE_FACTOR),
+            script_sig: ctx.version == ScriptVersion::Legacy,
+        }
+    }
+
+    /// True if the stack items are pushed by the scriptSig instead of a witness.
+    pub fn is_script_sig(&self) -> bool {
+        self.script_sig
+    }
+
+    /// Estimated fee of the input in satoshis at a feerate in sat/vB.
+    pub fn fee(&self, feerate: f64) -> u64 {
+        (self.vsize as f64 * feerate).ceil() as u64
+    }
+}
+
+/// Signature operations executed on a spending path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigOps {
+    /// Legacy and segwit v0 scripts, sigops count against the block sigop limit. Legacy sigops
+    /// cost 4 times as much as witness sigops, and a legacy `OP_CHECKMULTISIG` counts as 20
+    /// sigops no matter how many keys it has.
+    Cost { count: u32, cost: u32 },
+    /// Tapscript, every signature check with a non-empty signature uses validation weight from
+    /// a budget of 50 plus the witness size.
+    ValidationWeight {
+        count: u32,
+        weight: u32,
+        /// Smallest witness that gives enough budget. The spender can pad the witness, for
+        /// example with an annex, when the items it needs are smaller.
+        min_witness_size: u32,
+    },
+}
+
+impl SigOps {
+    pub(crate) fn new(ctx: ScriptContext, count: u32) -> Result<Self, ScriptError> {
+        Ok(match ctx.version {
+            ScriptVersion::Legacy => Self::Cost {
+                count,
+                cost: count * WITNESS_SCALE_FACTOR as u32,
+            },
+            ScriptVersion::SegwitV0 => Self::Cost { count, cost: count },
+            ScriptVersion::SegwitV1 => {
+                let weight = count * VALIDATION_WEIGHT_PER_SIGOP_PASSED;
+                let min_witness_size = weight.saturating_sub(VALIDATION_WEIGHT_OFFSET);
+                // the witness has to fit in a block next to the rest of the transaction
+                if min_witness_size as usize
+                    > MAX_BLOCK_WEIGHT - INPUT_BASE_SIZE * WITNESS_SCALE_FACTOR
+                {
+                    return Err(ScriptError::SCRIPT_ERR_TAPSCRIPT_VALIDATION_WEIGHT);
+                }
+                Self::ValidationWeight {
+                    count,
+                    weight,
+                    min_witness_size,
+                }
+            }
+        })
+    }
+
+    pub fn count(&self) -> u32 {
+        match *self {
+            Self::Cost { count, .. } | Self::ValidationWeight { count, .. } => count,
+        }
+    }
+}
+
+impl fmt::Display for SigOps {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Cost { count, cost } => write!(f, "{count} (sigop cost: {cost})"),
+            Self::ValidationWeight {
+                count,
+                weight,
+                min_witness_size,
+            } => write!(
+                f,
+                "{count} (validation weight: {weight}, needs a witness of at least \
+                 {min_witness_size} bytes)"
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct ItemSize {
+    exact: Option<usize>,
+    role: Option<usize>,
+    used: bool,
+}
+
+impl ItemSize {
+    fn size(&self) -> usize {
+        // unused items can be empty, used ones are most likely small numbers or booleans
+        self.exact.or(self.role).unwrap_or(self.used as usize)
+    }
+}
+
+fn item<'i>(expr: &Expr, items: &'i mut [ItemSize]) -> Option<&'i mut ItemSize> {
+    match expr {
+        Expr::Stack(s) => items.get_mut(s.pos() as usize),
+        _ => None,
+    }
+}
+
+fn set_role(expr: &Expr, size: usize, items: &mut [ItemSize]) {
+    if let Some(item) = item(expr, items) {
+        item.role = Some(item.role.map_or(size, |s| s.max(size)));
+    }
+}
+
+/// Sizes that follow from a condition that must be true.
+fn visit_condition(expr: &Expr, ctx: ScriptContext, items: &mut [ItemSize]) {
+    if let Expr::Op(op) = expr {
+        match &op.args {
+            OpExprArgs::Args1(Opcode1::OP_NOT | Opcode1::OP_INTERNAL_NOT, args) => {
+                if let Some(item) = item(&args[0], items) {
+                    item.exact = Some(0);
+                }
+            }
+            OpExprArgs::Args2(Opcode2::OP_EQUAL, args) => {
+                for [a, b] in [[&args[0], &args[1]], [&args[1], &args[0]]] {
+                    let Expr::Bytes(b) = b else {
+                        continue;
+                    };
+                    if let Some(item) = item(a, items) {
+                        item.exact = Some(b.len());
+                    } else if let Expr::Op(a) = a {
+                        if let OpExprArgs::Args1(Opcode1::OP_SIZE, args) = &a.args {
+                            if let (Some(item), Ok(len)) = (item(&args[0], items), decode_int(b, 4))
+                            {
+                                item.exact = Some(len as usize);
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    visit(expr, ctx, items);
+}
+
+/// Sizes that follow from how an item is used.
+fn visit(expr: &Expr, ctx: ScriptContext, items: &mut [ItemSize]) {
+    let Expr::Op(op) = expr else {
+        if let Some(item) = item(expr, items) {
+            item.used = true;
+        }
+        return;
+    };
+
+    let (sig_size, pubkey_size) = if ctx.version == ScriptVersion::SegwitV1 {
+        (SCHNORR_SIG_SIZE, XONLY_PUBKEY_SIZE)
+    } else {
+        (ECDSA_SIG_SIZE, PUBKEY_SIZE)
+    };
+
+    match &op.args {
+        OpExprArgs::Args2(Opcode2::OP_CHECKSIG, args) => {
+            set_role(&args[0], sig_size, items);
+            set_role(&args[1], pubkey_size, items);
+        }
+        OpExprArgs::Multisig(m) => {
+            for sig in m.sigs() {
+                set_role(sig, sig_size, items);
+            }
+            for key in m.keys() {
+                set_role(key, pubkey_size, items);
+            }
+        }
+        OpExprArgs::Args1(Opcode1::OP_RIPEMD160 | Opcode1::OP_SHA1 | Opcode1::OP_SHA256, args) => {
+            set_role(&args[0], PREIMAGE_SIZE, items);
+        }
+        OpExprArgs::Args1(
+            Opcode1::OP_CHECKLOCKTIMEVERIFY | Opcode1::OP_CHECKSEQUENCEVERIFY,
+            args,
+        ) => {
+            set_role(&args[0], LOCKTIME_SIZE, items);
+        }
+        _ => {}
+    }
+
+    for arg in op.args() {
+        visit(arg, ctx, items);
+    }
+}
+
+fn compact_size_len(n: usize) -> usize {
+    match n {
+        0..=0xfc => 1,
+        0xfd..=0xffff => 3,
+        0x10000..=0xffff_ffff => 5,
+        _ => 9,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compact_size_len, SizeEstimate};
+    use crate::{
+        analyze_script, script::push_len, OwnedScript, ScriptContext, ScriptRules, ScriptVersion,
+    };
+
+    fn size(asm: &str, version: ScriptVersion) -> SizeEstimate {
+        let mut buf = asm.as_bytes().to_vec();
+        let (_, script) = OwnedScript::parse_from_asm_in_place(&mut buf).unwrap();
+        let ctx = ScriptContext::new(version, ScriptRules::All);
+        let analysis = analyze_script(&script, ctx, Default::default()).unwrap();
+        analysis.paths[0].size.clone()
+    }
+
+    #[test]
+    fn test_serialized_sizes() {
+        assert_eq!(compact_size_len(0), 1);
+        assert_eq!(compact_size_len(252), 1);
+        assert_eq!(compact_size_len(253), 3);
+        assert_eq!(compact_size_len(0x10000), 5);
+
+        assert_eq!(push_len(0), 1);
+        assert_eq!(push_len(33), 34);
+        assert_eq!(push_len(76), 78);
+        assert_eq!(push_len(520), 523);
+    }
+
+    #[test]
+    fn test_size_estimate() {
+        // scriptSig with pushes of a signature and a public key, 148 vbytes like P2PKH
+        let legacy = size("OP_CHECKSIG", ScriptVersion::Legacy);
+        assert!(legacy.is_script_sig());
+        assert_eq!(legacy.stack_item_sizes, [33, 72]);
+        assert_eq!(legacy.script_sig_size, 34 + 73);
+        assert_eq!(legacy.witness_size, 0);
+        assert_eq!(legacy.vsize, 148);
+
+        // the same items in a witness, with the witness script, are discounted. the weight of
+        // 274 is rounded up to 69 vbytes
+        let segwit = size("OP_CHECKSIG", ScriptVersion::SegwitV0);
+        assert!(!segwit.is_script_sig());
+        assert_eq!(segwit.stack_item_sizes, [33, 72]);
+        assert_eq!(segwit.script_sig_size, 0);
+        assert_eq!(segwit.witness_size, 1 + 34 + 73 + 2);
+        assert_eq!(segwit.vsize, 69);
+
+        // schnorr signature, x-only key and a control block
+        let tapscript = size("OP_CHECKSIG", ScriptVersion::SegwitV1);
+        assert_eq!(tapscript.stack_item_sizes, [32, 65]);
+        assert_eq!(tapscript.witness_size, 1 + 33 + 66 + 2 + 34);
+        assert_eq!(tapscript.vsize, 75);
+    }
+
+    #[test]
+    fn test_fee() {
+        let segwit = size("OP_CHECKSIG", ScriptVersion::SegwitV0);
+        assert_eq!(segwit.fee(0.0), 0);
+        assert_eq!(segwit.fee(1.0), 69);
+        // rounded up to whole satoshis
+        assert_eq!(segwit.fee(1.5), 104);
+        assert_eq!(segwit.fee(0.01), 1);
+    }
+}
@@ -0,0 +1,89 @@
+//! Functions for other web apps that embed the analyzer without the bundled UI.
+
+use crate::analysis_options;
+use bitcoin_script_analyzer::{
+    analyze_script, util::decode_hex_in_place_ignore_whitespace, ScriptContext, ScriptRules,
+    ScriptVersion, SpendingPath,
+};
+use js_sys::{Array, Object, Reflect};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(typescript_custom_section)]
+const JS_PATH: &str = r#"
+export interface JsPath {
+    stackSize: number;
+    conditions: string[];
+    locktime: number | null;
+    sequence: number | null;
+}
+"#;
+
+/// Result of [`analyze`].
+#[wasm_bindgen]
+pub struct JsAnalysis {
+    template: Option<String>,
+    paths: Array,
+    report: String,
+}
+
+#[wasm_bindgen]
+impl JsAnalysis {
+    /// Name of the recognized script template, if any.
+    #[wasm_bindgen(getter)]
+    pub fn template(&self) -> Option<String> {
+        self.template.clone()
+    }
+
+    /// The spending paths, as `JsPath` objects.
+    #[wasm_bindgen(getter)]
+    pub fn paths(&self) -> Array {
+        self.paths.clone()
+    }
+
+    /// The human readable report, as shown by the bundled UI.
+    #[wasm_bindgen(getter)]
+    pub fn report(&self) -> String {
+        self.report.clone()
+    }
+}
+
+/// Analyzes the script `hex`. `version` is 0 for legacy, 1 for segwit v0 and 2 for tapscript,
+/// `rules` is 0 for consensus rules only and 1 for all (standardness) rules.
+#[wasm_bindgen]
+pub fn analyze(hex: &str, version: u32, rules: u32) -> Result<JsAnalysis, JsError> {
+    let version = ScriptVersion::from_u32(version)
+        .ok_or_else(|| JsError::new(&format!("invalid version {version}")))?;
+    let rules = ScriptRules::from_u32(rules)
+        .ok_or_else(|| JsError::new(&format!("invalid rules {rules}")))?;
+    let ctx = ScriptContext::new(version, rules);
+
+    let mut hex = hex.as_bytes().to_vec();
+    let bytes = decode_hex_in_place_ignore_whitespace(&mut hex)?;
+    let script = ctx.parse_script(bytes)?;
+    let analysis = analyze_script(&script, ctx, analysis_options())?;
+
+    Ok(JsAnalysis {
+        template: analysis.template.map(|t| t.to_string()),
+        paths: analysis.paths.iter().map(path_object).collect(),
+        report: analysis.to_string(),
+    })
+}
+
+fn path_object(path: &SpendingPath) -> Object {
+    let obj = Object::new();
+    let set = |key: &str, value: JsValue| {
+        Reflect::set(&obj, &key.into(), &value).unwrap();
+    };
+    set("stackSize", path.stack_size.into());
+    set(
+        "conditions",
+        path.spending_conditions
+            .iter()
+            .map(|c| JsValue::from(c.to_string()))
+            .collect::<Array>()
+            .into(),
+    );
+    set("locktime", path.locktime_req.req.into());
+    set("sequence", path.sequence_req.req.into());
+    obj
+}
@@ -0,0 +1,92 @@
+//! Imports scripts from an esplora (mempool.space) API.
+
+use bitcoin_script_analyzer::{
+    revealed_script,
+    util::{decode_hex_in_place_easy, encode_hex_easy},
+    ScriptVersion,
+};
+use js_sys::{Array, Reflect};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::Response;
+
+pub struct ImportedScript {
+    pub hex: String,
+    pub version: ScriptVersion,
+}
+
+/// Finds a script revealed on chain. `query` is a txid, of which the first input that reveals a
+/// script is used, or an address, of which the most recent spend is used.
+pub async fn get_script(api_url: &str, query: &str) -> Result<ImportedScript, String> {
+    let api_url = api_url.trim_end_matches('/');
+
+    if query.len() == 64 && query.bytes().all(|b| b.is_ascii_hexdigit()) {
+        let tx = fetch_json(&format!("{api_url}/api/tx/{query}")).await?;
+        Array::from(&get(&tx, "vin"))
+            .iter()
+            .find_map(|vin| input_script(&vin))
+            .ok_or_else(|| "no input of this transaction reveals a script".to_owned())
+    } else {
+        let txs = fetch_json(&format!("{api_url}/api/address/{query}/txs")).await?;
+        Array::from(&txs)
+            .iter()
+            .flat_map(|tx| Array::from(&get(&tx, "vin")).to_vec())
+            .filter(|vin| {
+                get(&get(vin, "prevout"), "scriptpubkey_address")
+                    .as_string()
+                    .as_deref()
+                    == Some(query)
+            })
+            .find_map(|vin| input_script(&vin))
+            .ok_or_else(|| "no transaction spending from this address found".to_owned())
+    }
+}
+
+async fn fetch_json(url: &str) -> Result<JsValue, String> {
+    let window = web_sys::window().ok_or("no window")?;
+    let response: Response = JsFuture::from(window.fetch_with_str(url))
+        .await
+        .map_err(js_error)?
+        .dyn_into()
+        .map_err(js_error)?;
+
+    if !response.ok() {
+        return Err(format!(
+            "request to {url} failed: {} {}",
+            response.status(),
+            response.status_text()
+        ));
+    }
+
+    JsFuture::from(response.json().map_err(js_error)?)
+        .await
+        .map_err(js_error)
+}
+
+fn js_error(err: JsValue) -> String {
+    err.as_string()
+        .or_else(|| err.dyn_ref::<js_sys::Error>().map(|e| e.message().into()))
+        .unwrap_or_else(|| format!("{err:?}"))
+}
+
+fn get(obj: &JsValue, key: &str) -> JsValue {
+    Reflect::get(obj, &key.into()).unwrap_or(JsValue::UNDEFINED)
+}
+
+/// The script executed when spending the input `vin` (esplora format), if it reveals one.
+fn input_script(vin: &JsValue) -> Option<ImportedScript> {
+    let hex = |value: JsValue| decode_hex_in_place_easy(value.as_string()?).ok();
+    let script_pubkey = hex(get(&get(vin, "prevout"), "scriptpubkey"))?;
+    let script_sig = hex(get(vin, "scriptsig"))?;
+    let witness = Array::from(&get(vin, "witness"))
+        .iter()
+        .map(hex)
+        .collect::<Option<Vec<_>>>()?;
+    let witness: Vec<&[u8]> = witness.iter().map(Vec::as_slice).collect();
+
+    let revealed = revealed_script(&script_pubkey, &script_sig, &witness).ok()?;
+    Some(ImportedScript {
+        hex: encode_hex_easy(revealed.script),
+        version: revealed.version,
+    })
+}
@@ -1,14 +1,48 @@
 use bitcoin_script_analyzer::{
     analyze_script,
+    expr::Expr,
     util::{decode_hex_in_place_ignore_whitespace, encode_hex_easy},
-    OwnedScript, ScriptContext, ScriptRules, ScriptVersion,
+    Analysis, AnalysisOptions, AnalyzeError, ItemRange, OwnedScript, PreimageRequirement,
+    ReportOptions, ScriptContext, ScriptRules, ScriptVersion, SpendingPath,
+};
+use std::{
+    cell::{Cell, RefCell},
+    ops::Range,
+    rc::Rc,
+    time::Duration,
 };
-use std::{cell::RefCell, rc::Rc};
 use wasm_bindgen::prelude::*;
-use web_sys::{Document, Event, HtmlElement, HtmlInputElement, HtmlSelectElement};
+use web_sys::{Document, Element, Event, HtmlElement, HtmlInputElement, HtmlSelectElement, Node};
 
+mod api;
+mod chain_import;
 mod util;
 
+/// Pushes can be up to 520 bytes, they are truncated in the rendered spending conditions.
+fn report_options() -> ReportOptions<'static> {
+    ReportOptions {
+        max_bytes_shown: Some(64),
+        ..Default::default()
+    }
+}
+
+/// Keeps the page responsive on scripts with many branches.
+fn analysis_options() -> AnalysisOptions {
+    AnalysisOptions {
+        max_paths: Some(10_000),
+        max_expr_nodes: Some(100_000),
+        timeout: Some(Duration::from_secs(5)),
+        // `Instant` is not available on wasm
+        clock: || {
+            let now = web_sys::window()
+                .and_then(|window| window.performance())
+                .map_or(0.0, |performance| performance.now());
+            Duration::from_secs_f64(now / 1000.0)
+        },
+        ..Default::default()
+    }
+}
+
 macro_rules! html_element_or {
     () => {
         HtmlElement
@@ -73,13 +107,376 @@ impl HtmlElements {
     fn get_script_context(&self) -> ScriptContext {
         ScriptContext::new(self.get_script_version(), self.get_script_rules())
     }
+
+    /// Stores the script and settings in the URL, so the analysis can be shared with a link.
+    fn update_url_hash(&self) {
+        let hex: String = self
+            .hex
+            .inner_text()
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        let hash = format!(
+            "#hex={hex}&version={}&rules={}",
+            self.script_version.selected_index(),
+            self.script_rules.selected_index()
+        );
+        // replace instead of push, so not every keystroke is a history entry
+        if let Some(history) = web_sys::window().and_then(|window| window.history().ok()) {
+            let _ = history.replace_state_with_url(&JsValue::NULL, "", Some(&hash));
+        }
+    }
+
+    /// Restores the script and settings stored by [`Self::update_url_hash`]. Returns false if the
+    /// URL has none.
+    fn restore_url_hash(&self) -> bool {
+        let Some(hash) = web_sys::window().and_then(|window| window.location().hash().ok()) else {
+            return false;
+        };
+        let mut restored = false;
+        for (key, value) in hash
+            .trim_start_matches('#')
+            .split('&')
+            .filter_map(|kv| kv.split_once('='))
+        {
+            match key {
+                "hex" => self.hex.set_inner_text(value),
+                "version" => self
+                    .script_version
+                    .set_selected_index(value.parse().unwrap_or(1)),
+                "rules" => self
+                    .script_rules
+                    .set_selected_index(value.parse().unwrap_or(0)),
+                _ => continue,
+            }
+            restored = true;
+        }
+        restored
+    }
+
+    /// Analyzes the script in the hex field again with the selected settings.
+    fn rerun_analysis(&self) {
+        for (element, ev_type) in [
+            (self.script_version.unchecked_ref::<HtmlElement>(), "change"),
+            (&self.hex, "keyup"),
+        ] {
+            let event = Event::new(ev_type).expect("can't create event");
+            element
+                .dispatch_event(&event)
+                .expect("can't dispatch_event");
+        }
+    }
+}
+
+/// Creates an element with an optional class and text.
+fn create_element(document: &Document, tag: &str, class: &str, text: Option<&str>) -> Element {
+    let element = document.create_element(tag).expect("can't create_element");
+    if !class.is_empty() {
+        element.set_class_name(class);
+    }
+    element.set_text_content(text);
+    element
+}
+
+fn append(parent: &Element, child: &Element) {
+    parent.append_child(child).expect("can't append_child");
+}
+
+/// Shows the analysis result in `container`, with a collapsible section for every spending path.
+fn render_analysis(container: &HtmlElement, res: &Result<Analysis, AnalyzeError>) {
+    let document = container.owner_document().expect("element has no document");
+    container.set_text_content(None);
+
+    let analysis = match res {
+        Ok(analysis) => analysis,
+        Err(err) => {
+            container.set_inner_text(&err.to_string());
+            return;
+        }
+    };
+
+    if analysis.paths.iter().any(SpendingPath::is_anyone_can_spend) {
+        append(
+            container,
+            &create_element(
+                &document,
+                "p",
+                "warning",
+                Some(
+                    "WARNING: this script is trivially spendable by anyone, a spending path \
+                    requires no signature, hash preimage or timelock",
+                ),
+            ),
+        );
+    }
+    if let Some(template) = analysis.template {
+        let text = format!("Template: {template}");
+        append(container, &create_element(&document, "p", "", Some(&text)));
+    }
+    if !analysis.paths.is_empty() {
+        let summary = create_element(&document, "details", "", None);
+        append(
+            &summary,
+            &create_element(&document, "summary", "", Some("Summary")),
+        );
+        let text = analysis.summary().to_string();
+        append(&summary, &create_element(&document, "pre", "", Some(&text)));
+        append(container, &summary);
+    }
+
+    if analysis.paths.is_empty() {
+        append(
+            container,
+            &create_element(&document, "p", "", Some("Spending paths: none")),
+        );
+    }
+    for (i, path) in analysis.paths.iter().enumerate() {
+        append(container, &render_path(&document, i, path));
+    }
+}
+
+fn render_path(document: &Document, index: usize, path: &SpendingPath) -> Element {
+    let details = create_element(document, "details", "path", None);
+    details
+        .set_attribute("open", "")
+        .expect("can't set_attribute");
+    let title = format!(
+        "Spending path #{} (stack size: {}, {} vbytes)",
+        index + 1,
+        path.stack_size,
+        path.size.vsize
+    );
+    append(
+        &details,
+        &create_element(document, "summary", "", Some(&title)),
+    );
+
+    let list = create_element(document, "ul", "", None);
+    for (label, req, relative) in [
+        ("Locktime requirement", &path.locktime_req, false),
+        ("Sequence requirement", &path.sequence_req, true),
+    ] {
+        let Some(text) = req.locktime_requirement_to_string(relative) else {
+            continue;
+        };
+        let item = create_element(
+            document,
+            "li",
+            "timelock",
+            Some(&format!("{label}: {text}")),
+        );
+        if !req.exprs.is_empty() {
+            let exprs = create_element(document, "ul", "", None);
+            for expr in &req.exprs {
+                append(&exprs, &render_expr(document, expr));
+            }
+            append(&item, &exprs);
+        }
+        append(&list, &item);
+    }
+
+    let conditions = create_element(document, "li", "", Some("Stack item requirements:"));
+    let condition_list = create_element(document, "ul", "", None);
+    for range in &path.ranges {
+        let text = range.to_string();
+        append(
+            &condition_list,
+            &create_element(document, "li", "", Some(&text)),
+        );
+    }
+    for condition in &path.spending_conditions {
+        if ItemRange::from_condition(condition).is_some() {
+            // part of a range
+            continue;
+        }
+        let item = match PreimageRequirement::from_condition(condition, &path.spending_conditions) {
+            Some(req) => {
+                let text = req.display(report_options().expr_format()).to_string();
+                let item = create_element(document, "li", "hashlock", Some(&text));
+                let exprs = create_element(document, "ul", "", None);
+                append(&exprs, &render_expr(document, condition));
+                append(&item, &exprs);
+                item
+            }
+            None => render_expr(document, condition),
+        };
+        if let Some(origin) = condition.origin() {
+            item.set_attribute("title", &format!("from {origin}"))
+                .expect("can't set_attribute");
+        }
+        append(&condition_list, &item);
+    }
+    if path.ranges.is_empty() && path.spending_conditions.is_empty() {
+        append(
+            &condition_list,
+            &create_element(document, "li", "", Some("none")),
+        );
+    }
+    append(&conditions, &condition_list);
+    append(&list, &conditions);
+
+    let sigops = format!("Signature operations: {}", path.sigops);
+    append(&list, &create_element(document, "li", "", Some(&sigops)));
+    append(&details, &list);
+    details
+}
+
+/// Renders an expression as a list item, with the arguments of an operation as a nested list.
+fn render_expr(document: &Document, expr: &Expr) -> Element {
+    let Expr::Op(op) = expr else {
+        let text = expr.display(report_options().expr_format()).to_string();
+        return create_element(document, "li", "", Some(&text));
+    };
+
+    let item = create_element(document, "li", "", None);
+    let opcode = op.opcode().to_string();
+    append(
+        &item,
+        &create_element(document, "span", "opcode", Some(&opcode)),
+    );
+    let args = create_element(document, "ul", "", None);
+    for arg in op.args() {
+        append(&args, &render_expr(document, arg));
+    }
+    append(&item, &args);
+    item
+}
+
+/// Marks the bytes `span` of `text` in the editor `element` that contains `text`, or removes the
+/// mark if `span` is `None`. The caret stays at the same position.
+fn highlight_error(element: &HtmlElement, text: &str, span: Option<Range<usize>>) {
+    let has_mark = matches!(element.query_selector("mark"), Ok(Some(_)));
+    if span.is_none() && !has_mark {
+        return;
+    }
+
+    let document = element.owner_document().expect("element has no document");
+    let caret = caret_offset(element);
+
+    element.set_text_content(None);
+    match span {
+        Some(span) => {
+            let start = floor_char_boundary(text, span.start);
+            // mark at least one character
+            let end = ceil_char_boundary(text, span.end.max(start + 1));
+            let mark = create_element(&document, "mark", "", Some(&text[start..end]));
+            element
+                .append_with_str_1(&text[..start])
+                .expect("can't append");
+            element.append_with_node_1(&mark).expect("can't append");
+            element
+                .append_with_str_1(&text[end..])
+                .expect("can't append");
+        }
+        None => element.set_inner_text(text),
+    }
+
+    if let Some(caret) = caret {
+        set_caret(&document, element, caret);
+    }
+}
+
+fn floor_char_boundary(s: &str, mut i: usize) -> usize {
+    i = i.min(s.len());
+    while !s.is_char_boundary(i) {
+        i -= 1;
+    }
+    i
+}
+
+fn ceil_char_boundary(s: &str, mut i: usize) -> usize {
+    i = i.min(s.len());
+    while !s.is_char_boundary(i) {
+        i += 1;
+    }
+    i
+}
+
+/// Position of the caret in `element`, in UTF-16 code units.
+fn caret_offset(element: &HtmlElement) -> Option<u32> {
+    let selection = web_sys::window()?.get_selection().ok()??;
+    if selection.range_count() == 0 {
+        return None;
+    }
+    let range = selection.get_range_at(0).ok()?;
+    let container = range.end_container().ok()?;
+    if !element.contains(Some(&container)) {
+        return None;
+    }
+
+    // text from the start of the element to the caret. this ignores line breaks that are not
+    // in text nodes, but those are gone after the first highlight
+    let before = range.clone_range();
+    before.select_node_contents(element).ok()?;
+    before.set_end(&container, range.end_offset().ok()?).ok()?;
+    Some(before.to_string().length())
+}
+
+/// Moves the caret to `offset` UTF-16 code units in the text of `element`.
+fn set_caret(document: &Document, element: &HtmlElement, offset: u32) -> Option<()> {
+    fn find_text_node(node: &Node, offset: &mut u32) -> Option<Node> {
+        if node.node_type() == Node::TEXT_NODE {
+            let len = node.text_content()?.encode_utf16().count() as u32;
+            if *offset <= len {
+                return Some(node.clone());
+            }
+            *offset -= len;
+            return None;
+        }
+        let mut child = node.first_child();
+        while let Some(node) = child {
+            if let Some(found) = find_text_node(&node, offset) {
+                return Some(found);
+            }
+            child = node.next_sibling();
+        }
+        None
+    }
+
+    let mut offset = offset;
+    let node = find_text_node(element, &mut offset)?;
+    let range = document.create_range().ok()?;
+    range.set_start(&node, offset).ok()?;
+    range.collapse();
+    let selection = web_sys::window()?.get_selection().ok()??;
+    selection.remove_all_ranges().ok()?;
+    selection.add_range(&range).ok()
+}
+
+/// Time without input events after which the script in an editor is analyzed.
+const DEBOUNCE_DELAY_MS: i32 = 200;
+
+/// Amount of analyses kept in [`GlobalMutableState::analysis_cache`].
+const ANALYSIS_CACHE_SIZE: usize = 8;
+
+type AnalysisResult = Rc<Result<Analysis, AnalyzeError>>;
+
+/// Returns an event listener that calls `f` once events stop coming in for
+/// [`DEBOUNCE_DELAY_MS`], so partially typed scripts are not analyzed.
+fn debounce(f: &Closure<dyn Fn()>) -> Closure<dyn Fn(Event)> {
+    let f: js_sys::Function = f.as_ref().unchecked_ref::<js_sys::Function>().clone();
+    let timeout = Cell::new(None);
+    Closure::wrap(Box::new(move |_| {
+        let window = web_sys::window().expect("web_sys::window() returned None");
+        if let Some(handle) = timeout.take() {
+            window.clear_timeout_with_handle(handle);
+        }
+        timeout.set(
+            window
+                .set_timeout_with_callback_and_timeout_and_arguments_0(&f, DEBOUNCE_DELAY_MS)
+                .ok(),
+        );
+    }) as Box<dyn Fn(Event)>)
 }
 
 struct GlobalMutableState {
     script_context: Option<ScriptContext>,
-    last_script_bytes: Option<Vec<u8>>,
     // last_asm_inner_text: Option<String>,
     // last_hex_inner_text: Option<String>,
+    /// Recent analyses by script bytes and context, newest last.
+    analysis_cache: Vec<(Vec<u8>, ScriptContext, AnalysisResult)>,
+    /// Script bytes and context of the analysis that is shown.
+    shown_analysis: Option<(Vec<u8>, ScriptContext)>,
     error: bool,
 }
 
@@ -87,12 +484,55 @@ impl GlobalMutableState {
     fn new() -> Self {
         Self {
             script_context: None,
-            last_script_bytes: None,
             // last_asm_inner_text: None,
             // last_hex_inner_text: None,
+            analysis_cache: Vec::new(),
+            shown_analysis: None,
             error: false,
         }
     }
+
+    /// Analyzes `script`, parsed from `bytes`, or takes the analysis from the cache. Returns
+    /// `None` if the analysis is already shown.
+    fn analyze(
+        &mut self,
+        bytes: &[u8],
+        script: &OwnedScript,
+        ctx: ScriptContext,
+    ) -> Option<AnalysisResult> {
+        if self
+            .shown_analysis
+            .as_ref()
+            .is_some_and(|(b, c)| b == bytes && *c == ctx)
+        {
+            return None;
+        }
+
+        let res = match self
+            .analysis_cache
+            .iter()
+            .position(|(b, c, _)| b == bytes && *c == ctx)
+        {
+            Some(i) => {
+                // move to the end, it is the most recently used now
+                let entry = self.analysis_cache.remove(i);
+                let res = entry.2.clone();
+                self.analysis_cache.push(entry);
+                res
+            }
+            None => {
+                let res = Rc::new(analyze_script(script, ctx, analysis_options()));
+                if self.analysis_cache.len() == ANALYSIS_CACHE_SIZE {
+                    self.analysis_cache.remove(0);
+                }
+                self.analysis_cache.push((bytes.to_vec(), ctx, res.clone()));
+                res
+            }
+        };
+
+        self.shown_analysis = Some((bytes.to_vec(), ctx));
+        Some(res)
+    }
 }
 
 struct GlobalState {
@@ -146,24 +586,27 @@ fn main() {
             }
 
             let s = elements.hex.inner_text();
-            let mut hex = s.into_bytes();
+            let mut hex = s.clone().into_bytes();
             match decode_hex_in_place_ignore_whitespace(&mut hex)
-                .map_err(|err| err.to_string())
+                .map_err(|err| (err.to_string(), err.span()))
                 .and_then(|bytes| {
-                    OwnedScript::parse_from_bytes(bytes).map_err(|err| err.to_string())
+                    OwnedScript::parse_from_bytes(bytes)
+                        .map(|script| (bytes, script))
+                        .map_err(|err| (err.to_string(), None))
                 }) {
-                Ok(script) => {
-                    let res = match analyze_script(&script, ctx, 0) {
-                        Ok(res) | Err(res) => res,
-                    };
-
+                Ok((bytes, script)) => {
                     elements.hex_error.set_text_content(None);
-                    elements.analysis.set_inner_text(&res);
+                    highlight_error(&elements.hex, &s, None);
+                    if let Some(res) = m.analyze(bytes, &script, ctx) {
+                        render_analysis(&elements.analysis, &res);
+                        elements.update_url_hash();
+                    }
 
                     // m.error = false;
                 }
-                Err(err) => {
+                Err((err, span)) => {
                     elements.hex_error.set_inner_text(&err);
+                    highlight_error(&elements.hex, &s, span);
 
                     m.error = true;
                 }
@@ -173,7 +616,7 @@ fn main() {
 
     let hex_input_callback = {
         let global_state = global_state.clone();
-        Closure::wrap(Box::new(move |_| {
+        Closure::wrap(Box::new(move || {
             let elements = &global_state.elements;
 
             let Ok(mut m) = global_state.mutable_state.try_borrow_mut() else {
@@ -182,41 +625,43 @@ fn main() {
             };
 
             let s = elements.hex.inner_text();
-            let mut hex = s.into_bytes();
+            let mut hex = s.clone().into_bytes();
             match decode_hex_in_place_ignore_whitespace(&mut hex)
-                .map_err(|err| err.to_string())
+                .map_err(|err| (err.to_string(), err.span()))
                 .and_then(|bytes| {
-                    OwnedScript::parse_from_bytes(bytes).map_err(|err| err.to_string())
+                    OwnedScript::parse_from_bytes(bytes)
+                        .map(|script| (bytes, script))
+                        .map_err(|err| (err.to_string(), None))
                 }) {
-                Ok(script) => {
-                    let res = match analyze_script(
-                        &script,
-                        *m.script_context
-                            .get_or_insert_with(|| elements.get_script_context()),
-                        0,
-                    ) {
-                        Ok(res) | Err(res) => res,
-                    };
+                Ok((bytes, script)) => {
+                    let ctx = *m
+                        .script_context
+                        .get_or_insert_with(|| elements.get_script_context());
 
                     elements.hex_error.set_text_content(None);
+                    highlight_error(&elements.hex, &s, None);
                     elements.asm_error.set_text_content(None);
                     elements.asm.set_inner_text(&script.to_string());
-                    elements.analysis.set_inner_text(&res);
+                    if let Some(res) = m.analyze(bytes, &script, ctx) {
+                        render_analysis(&elements.analysis, &res);
+                        elements.update_url_hash();
+                    }
 
                     m.error = false;
                 }
-                Err(err) => {
+                Err((err, span)) => {
                     elements.hex_error.set_inner_text(&err);
+                    highlight_error(&elements.hex, &s, span);
 
                     m.error = true;
                 }
             }
-        }) as Box<dyn Fn(Event)>)
+        }) as Box<dyn Fn()>)
     };
 
     let asm_input_callback = {
         let global_state = global_state.clone();
-        Closure::wrap(Box::new(move |_| {
+        Closure::wrap(Box::new(move || {
             let elements = &global_state.elements;
 
             let Ok(mut m) = global_state.mutable_state.try_borrow_mut() else {
@@ -225,42 +670,73 @@ fn main() {
             };
 
             let asm = elements.asm.inner_text();
-            let mut buf = asm.into_bytes();
+            let mut buf = asm.clone().into_bytes();
             match OwnedScript::parse_from_asm_in_place(&mut buf) {
                 Ok((bytes, script)) => {
-                    if Some(bytes) == m.last_script_bytes.as_deref() {
-                        // no change
-                        return;
-                    }
-                    // bytes to hex TODO
-                    let res = match analyze_script(
-                        &script,
-                        *m.script_context
-                            .get_or_insert_with(|| elements.get_script_context()),
-                        0,
-                    ) {
-                        Ok(res) | Err(res) => res,
-                    };
+                    let ctx = *m
+                        .script_context
+                        .get_or_insert_with(|| elements.get_script_context());
 
                     elements.hex_error.set_text_content(None);
                     elements.asm_error.set_text_content(None);
+                    highlight_error(&elements.asm, &asm, None);
                     elements.hex.set_inner_text(&encode_hex_easy(bytes));
-                    elements.analysis.set_inner_text(&res);
+                    if let Some(res) = m.analyze(bytes, &script, ctx) {
+                        render_analysis(&elements.analysis, &res);
+                        elements.update_url_hash();
+                    }
 
                     m.error = false;
                 }
                 Err(err) => {
                     elements.asm_error.set_inner_text(&err.to_string());
+                    highlight_error(&elements.asm, &asm, Some(err.span()));
 
                     m.error = true;
                 }
             }
+        }) as Box<dyn Fn()>)
+    };
+
+    let chain_import_callback = {
+        let global_state = global_state.clone();
+        Closure::wrap(Box::new(move |_| {
+            let global_state = global_state.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let elements = &global_state.elements;
+
+                let script = match chain_import::get_script(
+                    &elements.chain_import_url.value(),
+                    elements.chain_import.value().trim(),
+                )
+                .await
+                {
+                    Ok(script) => script,
+                    Err(err) => {
+                        elements.chain_import_error.set_inner_text(&err);
+                        return;
+                    }
+                };
+
+                elements.chain_import_error.set_text_content(None);
+                elements.hex.set_inner_text(&script.hex);
+                elements
+                    .script_version
+                    .set_selected_index(match script.version {
+                        ScriptVersion::Legacy => 0,
+                        ScriptVersion::SegwitV0 => 1,
+                        ScriptVersion::SegwitV1 => 2,
+                    });
+                elements.rerun_analysis();
+            });
         }) as Box<dyn Fn(Event)>)
     };
 
     let options_callback_ref = options_callback.as_ref().unchecked_ref();
-    let hex_input_callback_ref = hex_input_callback.as_ref().unchecked_ref();
-    let asm_input_callback_ref = asm_input_callback.as_ref().unchecked_ref();
+    let hex_input_listener = debounce(&hex_input_callback);
+    let asm_input_listener = debounce(&asm_input_callback);
+    let hex_input_listener_ref = hex_input_listener.as_ref().unchecked_ref();
+    let asm_input_listener_ref = asm_input_listener.as_ref().unchecked_ref();
 
     let elements = &global_state.elements;
 
@@ -276,34 +752,27 @@ fn main() {
     for ev_type in ["keydown", "keypress", "keyup"] {
         elements
             .asm
-            .add_event_listener_with_callback(ev_type, asm_input_callback_ref)
+            .add_event_listener_with_callback(ev_type, asm_input_listener_ref)
             .expect("can't add_event_listener");
         elements
             .hex
-            .add_event_listener_with_callback(ev_type, hex_input_callback_ref)
+            .add_event_listener_with_callback(ev_type, hex_input_listener_ref)
             .expect("can't add_event_listener");
     }
 
+    elements
+        .chain_import_button
+        .add_event_listener_with_callback("click", chain_import_callback.as_ref().unchecked_ref())
+        .expect("can't add_event_listener");
+
+    if elements.restore_url_hash() {
+        elements.rerun_analysis();
+    }
+
     options_callback.forget();
     hex_input_callback.forget();
     asm_input_callback.forget();
+    hex_input_listener.forget();
+    asm_input_listener.forget();
+    chain_import_callback.forget();
 }
-
-/*
-TODO from js
-
-html.chainImportButton.addEventListener('click', async () => {
-    const address = html.chainImport.value;
-    const apiURL = html.chainImportURL.value;
-    let script: Awaited<ReturnType<typeof getScript>>;
-    try {
-        script = await getScript(apiURL, address);
-    } catch (e) {
-        html.chainImportError.innerText = e instanceof Error ? e.message : String(e);
-        return;
-    }
-    html.chainImportError.innerText = '';
-    html.hex.innerText = script.hex;
-    html.scriptVersion.selectedIndex = script.version;
-    hexUpdate();
-}); */